@@ -0,0 +1,40 @@
+//! run a command and send its output to the daemon, keeping its exit status
+//!
+//! `cargo run --example producer -- make test`
+
+use std::env;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+use spellhold::client::producer::Producer;
+use spellhold::transport::Endpoint;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let (program, rest) = args.split_first().ok_or("usage: producer CMD")?;
+
+    let endpoint = Endpoint::Unix("/tmp/spellholdd_socket".into());
+    let mut handle = Producer::new(endpoint)
+        .with_name(program.as_str())
+        .with_tags(vec!["example"])
+        .with_token(env::var("SPELLHOLD_TOKEN").ok())
+        .connect()?;
+
+    let mut child = Command::new(program)
+        .args(rest)
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            handle.send(&line?)?;
+        }
+    }
+
+    let status = child.wait()?;
+    handle.set_exit(status.code().unwrap_or(-1));
+    handle.close()?;
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! whatever a connection sends, cut into lines and read the ways the
+//! daemon and the viewers read them
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use spellhold::daemon::SendEvt;
+use spellhold::protocol::{self, RecordLine, ViewerLine};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Cursor::new(data);
+    let mut buf = Vec::new();
+
+    while let Ok(Some(line)) = protocol::read_frame(&mut reader, &mut buf) {
+        assert!(!line.contains('\n'));
+
+        if let Some((id, record)) = protocol::parse_producer_line(&line) {
+            let RecordLine { line, attrs } = record;
+            let wire = protocol::record_line(id, &line, &attrs);
+            let frame = protocol::decode_frame(wire.as_bytes());
+
+            assert_eq!(
+                protocol::parse_producer_line(&frame),
+                Some((id, RecordLine { line, attrs }))
+            );
+        }
+
+        if let Some(parsed) = ViewerLine::parse(&line) {
+            let _ = ViewerLine::parse(&parsed.to_line());
+        }
+
+        let _ = protocol::exit_status(&line);
+        let _ = protocol::parse_reply(&line);
+        let _ = SendEvt::new(line).session();
+    }
+});
@@ -0,0 +1,15 @@
+//! the first line of a connection, anything let in has to be sent back out
+//! as the same handshake
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use spellhold::protocol::Handshake;
+
+fuzz_target!(|data: &[u8]| {
+    let line = String::from_utf8_lossy(data);
+
+    if let Ok(handshake) = Handshake::parse(&line) {
+        assert_eq!(Handshake::parse(&handshake.to_line()), Ok(handshake));
+    }
+});
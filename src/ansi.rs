@@ -0,0 +1,347 @@
+use std::borrow::Cow;
+use std::cmp;
+
+/// a colour from an SGR sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// 0 to 7 are the normal colours, 8 to 15 the bright ones, then the
+    /// 256 colour cube and greys
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// as `#rrggbb`, using the xterm palette for indexed colours
+    pub fn to_hex(self) -> String {
+        let (r, g, b) = self.to_rgb();
+
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        const BASE: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 49, 49),
+            (13, 188, 121),
+            (229, 229, 16),
+            (36, 114, 200),
+            (188, 63, 188),
+            (17, 168, 205),
+            (229, 229, 229),
+            (102, 102, 102),
+            (241, 76, 76),
+            (35, 209, 139),
+            (245, 245, 67),
+            (59, 142, 234),
+            (214, 112, 214),
+            (41, 184, 219),
+            (255, 255, 255),
+        ];
+
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(index) if index < 16 => BASE[index as usize],
+            Color::Indexed(index) if index < 232 => {
+                let cube = index - 16;
+                let level =
+                    |step: u8| if step == 0 { 0 } else { 55 + step * 40 };
+
+                (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+            }
+            Color::Indexed(index) => {
+                let grey = 8 + (index - 232) * 10;
+                (grey, grey, grey)
+            }
+        }
+    }
+}
+
+/// the look of a run of text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// apply the parameters of one SGR sequence, `ESC [ ... m`
+    pub fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+
+        let mut params = params.iter().copied();
+
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                30..=37 => self.fg = Some(Color::Indexed((param - 30) as u8)),
+                38 => self.fg = extended(&mut params),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((param - 40) as u8)),
+                48 => self.bg = extended(&mut params),
+                49 => self.bg = None,
+                90..=97 => {
+                    self.fg = Some(Color::Indexed((param - 90 + 8) as u8))
+                }
+                100..=107 => {
+                    self.bg = Some(Color::Indexed((param - 100 + 8) as u8))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// the colour after a 38 or 48, `5;n` or `2;r;g;b`
+fn extended<I>(params: &mut I) -> Option<Color>
+where
+    I: Iterator<Item = u16>,
+{
+    let byte = |val: Option<u16>| val.map(|val| val.min(255) as u8);
+
+    match params.next() {
+        Some(5) => byte(params.next()).map(Color::Indexed),
+        Some(2) => {
+            let (r, g, b) = (params.next(), params.next(), params.next());
+            Some(Color::Rgb(byte(r)?, byte(g)?, byte(b)?))
+        }
+        _ => None,
+    }
+}
+
+/// a run of text in one style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub style: Style,
+    pub text: String,
+}
+
+/// a piece of a line, a char to draw or something that changes how the
+/// chars after it are drawn
+pub(crate) enum Token {
+    Char(char),
+    /// a control char other than a tab, like \r or a backspace
+    Control(char),
+    /// a CSI sequence, its body and final byte
+    Csi(String, char),
+}
+
+/// the chars, controls and CSI sequences of a line, OSC sequences and any
+/// other escapes are dropped
+pub(crate) fn tokens(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            if !c.is_control() || c == '\t' {
+                tokens.push(Token::Char(c));
+            } else {
+                tokens.push(Token::Control(c));
+            }
+            continue;
+        }
+
+        match chars.next() {
+            // CSI, parameters then a final byte
+            Some('[') => {
+                let mut body = String::new();
+
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        tokens.push(Token::Csi(std::mem::take(&mut body), c));
+                        break;
+                    }
+                    body.push(c);
+                }
+            }
+            // OSC, ended by BEL or ESC backslash
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+/// split a line into styled spans, starting from style
+///
+/// SGR sequences set the style, every other escape sequence is dropped and
+/// the style at the end of the line is returned so the next line can carry
+/// on from it
+pub fn parse(line: &str, mut style: Style) -> (Vec<Span>, Style) {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+
+    for token in tokens(line) {
+        match token {
+            Token::Char(c) => text.push(c),
+            Token::Csi(body, 'm') => {
+                if !text.is_empty() {
+                    spans.push(Span {
+                        style,
+                        text: std::mem::take(&mut text),
+                    });
+                }
+
+                style.apply(&params(&body));
+            }
+            _ => {}
+        }
+    }
+
+    if !text.is_empty() {
+        spans.push(Span { style, text });
+    }
+
+    (spans, style)
+}
+
+/// like parse, but a line that moves the cursor along itself comes out the
+/// way a terminal would have drawn it
+///
+/// a \r goes back to the start so a progress bar shows as where it got to,
+/// a backspace or `ESC [ n D` goes back, `ESC [ n C` forward, `ESC [ n G` to
+/// a column and `ESC [ K` erases. moves to other lines are dropped
+pub fn draw(line: &str, mut style: Style) -> (Vec<Span>, Style) {
+    let blank = (' ', Style::default());
+    let mut cells: Vec<(char, Style)> = Vec::new();
+    let mut cursor = 0;
+
+    for token in tokens(line) {
+        match token {
+            Token::Char(c) => {
+                if cursor < cells.len() {
+                    cells[cursor] = (c, style);
+                } else {
+                    cells.resize(cursor, blank);
+                    cells.push((c, style));
+                }
+
+                cursor += 1;
+            }
+            Token::Control('\r') => cursor = 0,
+            Token::Control('\x08') => cursor = cursor.saturating_sub(1),
+            Token::Csi(body, last) => {
+                let params = params(&body);
+                let first = params.first().copied().unwrap_or(0) as usize;
+                let count = first.max(1);
+
+                match last {
+                    'm' => style.apply(&params),
+                    'C' => cursor += count,
+                    'D' => cursor = cursor.saturating_sub(count),
+                    'G' => cursor = count - 1,
+                    'K' if first == 0 => cells.truncate(cursor),
+                    'K' if first == 1 => {
+                        let end = cmp::min(cursor + 1, cells.len());
+                        cells[..end].fill(blank);
+                    }
+                    'K' => cells.clear(),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut spans: Vec<Span> = Vec::new();
+
+    for (c, style) in cells {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(c),
+            _ => spans.push(Span {
+                style,
+                text: c.to_string(),
+            }),
+        }
+    }
+
+    (spans, style)
+}
+
+/// the numbers in a CSI body, empty ones are 0
+pub(crate) fn params(body: &str) -> Vec<u16> {
+    if body.is_empty() {
+        return Vec::new();
+    }
+
+    body.split([';', ':'])
+        .map(|param| param.parse().unwrap_or(0))
+        .collect()
+}
+
+/// the text of a line with every escape sequence taken out
+pub fn strip(line: &str) -> String {
+    parse(line, Style::default())
+        .0
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+/// the text of a line as a terminal would show it, without its line break
+pub fn plain(line: &str) -> Cow<'_, str> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+
+    if !line.chars().any(|c| c.is_control() && c != '\t') {
+        return Cow::Borrowed(line);
+    }
+
+    let (spans, _) = draw(line, Style::default());
+
+    Cow::Owned(spans.into_iter().map(|span| span.text).collect())
+}
+
+/// a line with its escapes and other control chars made visible, like
+/// `^[[31m` for the start of something red
+pub fn escape(line: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in line.chars() {
+        match c {
+            '\t' => escaped.push(c),
+            '\x7f' => escaped.push_str("^?"),
+            c if (c as u32) < 0x20 => {
+                escaped.push('^');
+                escaped.push((c as u8 + 0x40) as char);
+            }
+            c if c.is_control() => {
+                escaped.extend(c.escape_unicode());
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -1,32 +1,238 @@
 extern crate clap;
 extern crate rand;
 
+use std::cmp;
+use std::io::{self, Write};
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::error::Error;
 use std::path::PathBuf;
 
-use clap::{Arg, App, SubCommand};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
 use spellhold::daemon::main_loop::Daemon;
 use spellhold::client::stdin_handle::StdinHandle;
+use spellhold::client::keymap::Keymap;
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::client::theme::{self, Theme};
+use spellhold::client::timeline::{self, Entry, Timeline};
 use spellhold::client::tui::TuiApp;
+use spellhold::client::stats::{fetch_compact, fetch_gc, fetch_stats};
+use spellhold::config::{DaemonConfig, StorageConfig, TuiConfig, DEFAULT_LOG_ROOT};
+use spellhold::protocol;
+use spellhold::storage::export::{export, utc, Format, Range};
+use spellhold::storage::fsck::fsck;
+use spellhold::storage::import::{guess_format, import};
+use spellhold::storage::reader::SessionReader;
+use spellhold::transport::{ClientTls, Endpoint, TlsListener};
 
 const MAIN_SOCKET: &str = "/tmp/spellholdd_socket";
 
 enum AppAction {
     None,
     Tui,
+    Tail,
     Daemon,
     Stdin,
+    Stats,
+    Gc,
+    Compact,
+    Cat,
+    Fsck,
+    Export(ExportArgs),
+    Import(ImportArgs),
+}
+
+/// what to export and where to, checked as the args are read
+struct ExportArgs {
+    session: String,
+    format: Format,
+    range: Range,
+    /// stdout when not given
+    output: Option<PathBuf>,
+}
+
+impl ExportArgs {
+    fn from(sub: &ArgMatches) -> Result<ExportArgs, String> {
+        let format = sub.value_of("format").unwrap_or("txt").parse()?;
+
+        let range = Range {
+            from_seq: number_arg(sub.value_of("from"), "from")?,
+            to_seq: number_arg(sub.value_of("to"), "to")?,
+            since: number_arg(sub.value_of("since"), "since")?
+                .map(|secs| secs * 1000),
+            // the whole of the last second
+            until: number_arg(sub.value_of("until"), "until")?
+                .map(|secs| secs * 1000 + 999),
+        };
+
+        Ok(ExportArgs {
+            session: sub.value_of("session").unwrap_or_default().to_string(),
+            format,
+            range,
+            output: sub.value_of("output").map(PathBuf::from),
+        })
+    }
+}
+
+/// the file to import and how, checked as the args are read
+struct ImportArgs {
+    file: PathBuf,
+    name: String,
+    format: Format,
+    segment_bytes: u64,
+}
+
+impl ImportArgs {
+    fn from(sub: &ArgMatches) -> Result<ImportArgs, String> {
+        let file = PathBuf::from(sub.value_of("file").unwrap_or_default());
+
+        let format = match sub.value_of("format") {
+            Some(val) => val.parse()?,
+            None => guess_format(&file),
+        };
+
+        let segment_bytes = match sub.value_of("config") {
+            Some(path) => {
+                DaemonConfig::load(&PathBuf::from(path))
+                    .map_err(|err| err.to_string())?
+                    .storage
+                    .segment_bytes
+            }
+            None => StorageConfig::default().segment_bytes,
+        };
+
+        Ok(ImportArgs {
+            file,
+            name: sub.value_of("name").unwrap_or_default().to_string(),
+            format,
+            segment_bytes,
+        })
+    }
 }
 
 struct AppArgs {
     quite: bool,
     action: AppAction,
     optional_values: Vec<Option<String>>,
+    endpoint: Endpoint,
+    token: Option<String>,
+    tcp: Option<TlsListener>,
+    config: Option<PathBuf>,
+    log_root: PathBuf,
+}
+
+/// the args every producer and viewer takes to reach a remote daemon
+fn remote_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("remote")
+            .short("r")
+            .long("remote")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .help("connect to a daemon over tls instead of the socket"),
+        Arg::with_name("tls ca")
+            .long("tls-ca")
+            .value_name("CA_PEM")
+            .takes_value(true)
+            .help("the ca to check the remote daemon with"),
+        Arg::with_name("tls cert")
+            .long("tls-cert")
+            .value_name("CERT_PEM")
+            .takes_value(true)
+            .help("a client cert for the remote daemon"),
+        Arg::with_name("tls key")
+            .long("tls-key")
+            .value_name("KEY_PEM")
+            .takes_value(true)
+            .help("the key for the client cert"),
+        Arg::with_name("token")
+            .long("token")
+            .value_name("TOKEN")
+            .env("SPELLHOLD_TOKEN")
+            .takes_value(true)
+            .help("the token to show the daemon"),
+    ]
+}
+
+/// the args for commands that read the log root directly
+fn log_root_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("CONFIG_TOML")
+            .takes_value(true)
+            .help("take the log root from the daemon config"),
+        Arg::with_name("log root")
+            .long("log-root")
+            .value_name("DIR")
+            .takes_value(true)
+            .help("the log root, over the one in the config"),
+    ]
+}
+
+/// the log root from --log-root, then the config, then the default
+fn log_root_from(matches: &ArgMatches) -> Result<PathBuf, String> {
+    if let Some(root) = matches.value_of("log root") {
+        return Ok(PathBuf::from(root));
+    }
+
+    match matches.value_of("config") {
+        Some(path) => DaemonConfig::load(&PathBuf::from(path))
+            .map(|config| config.log_root)
+            .map_err(|err| err.to_string()),
+        None => Ok(PathBuf::from(DEFAULT_LOG_ROOT)),
+    }
+}
+
+/// get a unix or remote endpoint from a subcommands args
+fn endpoint_from(
+    matches: Option<&ArgMatches>,
+    socket: Option<&str>,
+) -> Endpoint {
+    let remote = matches.and_then(|sub| sub.value_of("remote"));
+
+    match (remote, matches) {
+        (Some(addr), Some(sub)) => Endpoint::Remote {
+            addr: addr.to_string(),
+            tls: ClientTls {
+                ca: sub.value_of("tls ca").map(PathBuf::from),
+                cert: sub.value_of("tls cert").map(PathBuf::from),
+                key: sub.value_of("tls key").map(PathBuf::from),
+            },
+        },
+        _ => Endpoint::Unix(PathBuf::from(socket.unwrap_or(MAIN_SOCKET))),
+    }
+}
+
+/// get the tls listener for the daemon if one was asked for
+fn tcp_from(matches: &ArgMatches) -> Result<Option<TlsListener>, String> {
+    let addr = match matches.value_of("tcp") {
+        Some(val) => val.to_string(),
+        None => return Ok(None),
+    };
+
+    let (cert, key) =
+        match (matches.value_of("tls cert"), matches.value_of("tls key")) {
+            (Some(cert), Some(key)) => {
+                (PathBuf::from(cert), PathBuf::from(key))
+            }
+            _ => return Err("--tcp needs --tls-cert and --tls-key".to_string()),
+        };
+
+    Ok(Some(TlsListener {
+        addr,
+        cert,
+        key,
+        client_ca: matches.value_of("tls client ca").map(PathBuf::from),
+    }))
 }
 
 impl AppArgs {
-    fn new() -> AppArgs {
+    fn new() -> Result<AppArgs, String> {
         let matches = App::new("spellcli")
             .arg(
                 Arg::with_name("quite")
@@ -47,6 +253,42 @@ impl AppArgs {
                             .value_name("DAEMON_PATH")
                             .takes_value(true)
                             .help("the daemon path"),
+                    )
+                    .arg(
+                        Arg::with_name("config")
+                            .short("c")
+                            .long("config")
+                            .value_name("CONFIG_TOML")
+                            .takes_value(true)
+                            .help("the daemon config, tokens reload from it"),
+                    )
+                    .arg(
+                        Arg::with_name("tcp")
+                            .long("tcp")
+                            .value_name("ADDR:PORT")
+                            .takes_value(true)
+                            .help("also listen for tls connections on addr"),
+                    )
+                    .arg(
+                        Arg::with_name("tls cert")
+                            .long("tls-cert")
+                            .value_name("CERT_PEM")
+                            .takes_value(true)
+                            .help("the servers cert chain"),
+                    )
+                    .arg(
+                        Arg::with_name("tls key")
+                            .long("tls-key")
+                            .value_name("KEY_PEM")
+                            .takes_value(true)
+                            .help("the servers private key"),
+                    )
+                    .arg(
+                        Arg::with_name("tls client ca")
+                            .long("tls-client-ca")
+                            .value_name("CA_PEM")
+                            .takes_value(true)
+                            .help("only let in clients with a cert from ca"),
                     ),
             )
             .subcommand(
@@ -68,12 +310,234 @@ impl AppArgs {
                             .value_name("SOCKET_PATH")
                             .takes_value(true)
                             .help("the stdin socket if changed from default"),
-                    ),
+                    )
+                    .arg(
+                        Arg::with_name("tag")
+                            .long("tag")
+                            .value_name("TAG")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("a tag to keep with the session"),
+                    )
+                    .args(&remote_args()),
             )
             .subcommand(
                 SubCommand::with_name("tui")
                     .help("run the tui")
-                    .visible_alias("t"),
+                    .visible_alias("t")
+                    .arg(
+                        Arg::with_name("history")
+                            .long("history")
+                            .value_name("LINES")
+                            .takes_value(true)
+                            .default_value("1000")
+                            .help("lines of each running session to load"),
+                    )
+                    .arg(
+                        Arg::with_name("finished")
+                            .long("finished")
+                            .help("load finished and imported sessions too"),
+                    )
+                    .arg(
+                        Arg::with_name("screen")
+                            .long("screen")
+                            .help("show sessions as a terminal drew them"),
+                    )
+                    .arg(
+                        Arg::with_name("layout")
+                            .long("layout")
+                            .value_name("FILE")
+                            .takes_value(true)
+                            .help("where the panes are saved and restored"),
+                    )
+                    .arg(
+                        Arg::with_name("config")
+                            .short("c")
+                            .long("config")
+                            .value_name("CONFIG_TOML")
+                            .takes_value(true)
+                            .help("the keymap and theme"),
+                    )
+                    .args(&remote_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("tail")
+                    .help("print the lines of running sessions as they come")
+                    .arg(
+                        Arg::with_name("sessions")
+                            .value_name("SESSION")
+                            .multiple(true)
+                            .help("globs of the sessions, every one if none"),
+                    )
+                    .arg(
+                        Arg::with_name("merge").long("merge").help(
+                            "order the lines by when the daemon got them",
+                        ),
+                    )
+                    .arg(
+                        Arg::with_name("history")
+                            .long("history")
+                            .value_name("LINES")
+                            .takes_value(true)
+                            .default_value("10")
+                            .help(
+                                "lines of each running session to start with",
+                            ),
+                    )
+                    .args(&remote_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("stats")
+                    .help("show the daemons queue depths and drops")
+                    .args(&remote_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("gc")
+                    .help("run the daemons retention rules now")
+                    .arg(
+                        Arg::with_name("dry run")
+                            .long("dry-run")
+                            .help("only show what would be removed"),
+                    )
+                    .args(&remote_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("compact")
+                    .help("compress finished sessions now")
+                    .arg(
+                        Arg::with_name("session")
+                            .value_name("SESSION")
+                            .help("only compress this session"),
+                    )
+                    .args(&remote_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("cat")
+                    .help("print a session from the log root")
+                    .arg(
+                        Arg::with_name("session")
+                            .value_name("SESSION")
+                            .required(true)
+                            .help("the session to print"),
+                    )
+                    .arg(
+                        Arg::with_name("from")
+                            .long("from")
+                            .value_name("SEQ")
+                            .takes_value(true)
+                            .help("start at this line number"),
+                    )
+                    .arg(
+                        Arg::with_name("since")
+                            .long("since")
+                            .value_name("UNIX_SECS")
+                            .takes_value(true)
+                            .help("start at the first line from this time"),
+                    )
+                    .args(&log_root_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("fsck")
+                    .help("check the checksums of every session on disk")
+                    .arg(
+                        Arg::with_name("repair")
+                            .long("repair")
+                            .help("drop damaged records and rebuild indexes"),
+                    )
+                    .args(&log_root_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("export")
+                    .help("write a session out for sharing")
+                    .arg(
+                        Arg::with_name("session")
+                            .value_name("SESSION")
+                            .required(true)
+                            .help("the session to export"),
+                    )
+                    .arg(
+                        Arg::with_name("format")
+                            .short("f")
+                            .long("format")
+                            .value_name("FORMAT")
+                            .takes_value(true)
+                            .possible_values(&[
+                                "txt",
+                                "jsonl",
+                                "html",
+                                "asciicast",
+                                "screen",
+                            ])
+                            .default_value("txt")
+                            .help("what to write it as"),
+                    )
+                    .arg(
+                        Arg::with_name("from")
+                            .long("from")
+                            .value_name("SEQ")
+                            .takes_value(true)
+                            .help("the first line number to export"),
+                    )
+                    .arg(
+                        Arg::with_name("to")
+                            .long("to")
+                            .value_name("SEQ")
+                            .takes_value(true)
+                            .help("the last line number to export"),
+                    )
+                    .arg(
+                        Arg::with_name("since")
+                            .long("since")
+                            .value_name("UNIX_SECS")
+                            .takes_value(true)
+                            .help("only lines from this time on"),
+                    )
+                    .arg(
+                        Arg::with_name("until")
+                            .long("until")
+                            .value_name("UNIX_SECS")
+                            .takes_value(true)
+                            .help("only lines up to this time"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .short("o")
+                            .long("output")
+                            .value_name("FILE")
+                            .takes_value(true)
+                            .help("write here instead of stdout"),
+                    )
+                    .args(&log_root_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .help("add a log file or recording as a finished session")
+                    .arg(
+                        Arg::with_name("file")
+                            .value_name("FILE")
+                            .required(true)
+                            .help("the file to import"),
+                    )
+                    .arg(
+                        Arg::with_name("name")
+                            .short("n")
+                            .long("name")
+                            .value_name("SESSION")
+                            .takes_value(true)
+                            .required(true)
+                            .help("the session to make"),
+                    )
+                    .arg(
+                        Arg::with_name("format")
+                            .short("f")
+                            .long("format")
+                            .value_name("FORMAT")
+                            .takes_value(true)
+                            .possible_values(&["txt", "jsonl", "asciicast"])
+                            .help("what the file is, by its extension if not"),
+                    )
+                    .args(&log_root_args()),
             )
             .get_matches();
 
@@ -88,82 +552,310 @@ impl AppArgs {
             _ => true,
         };
 
-        let (action, optional_values) = if matches.is_present("daemon") {
-            let opt = matches.value_of("stdin-name").map(String::from);
+        let mut tcp = None;
+        let mut config = None;
+        let mut token = None;
+        let mut log_root = PathBuf::from(DEFAULT_LOG_ROOT);
 
-            (AppAction::Daemon, vec![opt])
-        } else if matches.is_present("stdin") {
-            let socket = matches.value_of("stdin-socket").map(String::from);
-            let name = matches.value_of("stdin-name").map(String::from);
+        let (action, optional_values, endpoint) =
+            if let Some(sub) = matches.subcommand_matches("daemon") {
+                let socket = sub.value_of("daemon socket").map(String::from);
 
-            (AppAction::Stdin, vec![socket, name])
-        } else if matches.is_present("tui") {
-            (AppAction::Tui, vec![None])
-        } else {
-            (AppAction::None, vec![None])
-        };
+                tcp = tcp_from(sub)?;
+                config = sub.value_of("config").map(PathBuf::from);
+
+                (AppAction::Daemon, vec![socket], endpoint_from(None, None))
+            } else if let Some(sub) = matches.subcommand_matches("stdin") {
+                let socket = sub.value_of("stdin socket");
+                let name = sub.value_of("stdin name").map(String::from);
+                token = sub.value_of("token").map(String::from);
+
+                let tags = sub
+                    .values_of("tag")
+                    .map(|tags| tags.collect::<Vec<&str>>().join(","));
+
+                (
+                    AppAction::Stdin,
+                    vec![name, tags],
+                    endpoint_from(Some(sub), socket),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("tui") {
+                token = sub.value_of("token").map(String::from);
+
+                let history = sub.value_of("history").map(String::from);
+                let screen = Some(sub.is_present("screen").to_string());
+                let layout = sub.value_of("layout").map(String::from);
+                let finished = Some(sub.is_present("finished").to_string());
+                config = sub.value_of("config").map(PathBuf::from);
+
+                (
+                    AppAction::Tui,
+                    vec![history, screen, layout, finished],
+                    endpoint_from(Some(sub), None),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("tail") {
+                token = sub.value_of("token").map(String::from);
+
+                let history = sub.value_of("history").map(String::from);
+                let merge = Some(sub.is_present("merge").to_string());
+                let sessions = sub
+                    .values_of("sessions")
+                    .map(|globs| globs.collect::<Vec<&str>>().join(" "));
+
+                (
+                    AppAction::Tail,
+                    vec![history, merge, sessions],
+                    endpoint_from(Some(sub), None),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("stats") {
+                token = sub.value_of("token").map(String::from);
+
+                (AppAction::Stats, vec![None], endpoint_from(Some(sub), None))
+            } else if let Some(sub) = matches.subcommand_matches("gc") {
+                token = sub.value_of("token").map(String::from);
 
-        AppArgs {
+                let dry_run = Some(sub.is_present("dry run").to_string());
+
+                (AppAction::Gc, vec![dry_run], endpoint_from(Some(sub), None))
+            } else if let Some(sub) = matches.subcommand_matches("compact") {
+                token = sub.value_of("token").map(String::from);
+
+                let session = sub.value_of("session").map(String::from);
+
+                (
+                    AppAction::Compact,
+                    vec![session],
+                    endpoint_from(Some(sub), None),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("cat") {
+                log_root = log_root_from(sub)?;
+
+                let session = sub.value_of("session").map(String::from);
+                let from = sub.value_of("from").map(String::from);
+                let since = sub.value_of("since").map(String::from);
+
+                (
+                    AppAction::Cat,
+                    vec![session, from, since],
+                    endpoint_from(None, None),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("fsck") {
+                log_root = log_root_from(sub)?;
+
+                let repair = Some(sub.is_present("repair").to_string());
+
+                (AppAction::Fsck, vec![repair], endpoint_from(None, None))
+            } else if let Some(sub) = matches.subcommand_matches("export") {
+                log_root = log_root_from(sub)?;
+
+                (
+                    AppAction::Export(ExportArgs::from(sub)?),
+                    vec![None],
+                    endpoint_from(None, None),
+                )
+            } else if let Some(sub) = matches.subcommand_matches("import") {
+                log_root = log_root_from(sub)?;
+
+                (
+                    AppAction::Import(ImportArgs::from(sub)?),
+                    vec![None],
+                    endpoint_from(None, None),
+                )
+            } else {
+                (AppAction::None, vec![None], endpoint_from(None, None))
+            };
+
+        Ok(AppArgs {
             quite,
             action,
             optional_values,
-        }
+            endpoint,
+            token,
+            tcp,
+            config,
+            log_root,
+        })
     }
 }
 
 fn main() {
-    let app = AppArgs::new();
+    let app = match AppArgs::new() {
+        Ok(val) => val,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
 
     match app.action {
         AppAction::Stdin => {
-            let (socket, name) = (
-                app.optional_values[0].to_owned(),
-                app.optional_values[1].to_owned(),
-            );
-            if let Err(err) = stdin_runner(socket, app.quite, name) {
+            let name = app.optional_values[0].to_owned();
+            let tags = app.optional_values[1]
+                .as_deref()
+                .map(|tags| tags.split(',').map(String::from).collect())
+                .unwrap_or_default();
+
+            if let Err(err) =
+                stdin_runner(app.endpoint, app.token, app.quite, name, tags)
+            {
                 eprintln!("Cli Intake Error: {}", err);
             }
         }
         AppAction::Daemon => {
-            if let Err(err) =
-                daemon_runner(app.optional_values[0].to_owned(), app.quite)
-            {
+            if let Err(err) = daemon_runner(
+                app.optional_values[0].to_owned(),
+                app.config,
+                app.tcp,
+                app.quite,
+            ) {
                 eprintln!("Daemon Error: {}", err)
             }
         }
         AppAction::Tui => {
-            if let Err(err) = tui_runner() {
+            let history = match number_arg(
+                app.optional_values[0].as_deref(),
+                "history",
+            ) {
+                Ok(lines) => lines.filter(|lines| *lines > 0),
+                Err(err) => {
+                    eprintln!("Daemon Error: {}", err);
+                    return;
+                }
+            };
+            let screen = app.optional_values[1].as_deref() == Some("true");
+            let layout = app.optional_values[2].as_ref().map(PathBuf::from);
+            let finished = app.optional_values[3].as_deref() == Some("true");
+
+            if let Err(err) = tui_runner(
+                app.endpoint,
+                app.token,
+                history,
+                finished,
+                screen,
+                layout,
+                app.config,
+            ) {
                 eprintln!("Daemon Error: {}", err)
             } else {
                 println!("Good bye")
             }
         }
+        AppAction::Tail => {
+            let history = match number_arg(
+                app.optional_values[0].as_deref(),
+                "history",
+            ) {
+                Ok(lines) => lines.filter(|lines| *lines > 0),
+                Err(err) => {
+                    eprintln!("Tail Error: {}", err);
+                    return;
+                }
+            };
+            let merge = app.optional_values[1].as_deref() == Some("true");
+            let sessions = app.optional_values[2]
+                .as_deref()
+                .map(|globs| globs.split(' ').map(String::from).collect())
+                .unwrap_or_default();
+
+            if let Err(err) =
+                tail_runner(app.endpoint, app.token, history, merge, sessions)
+            {
+                eprintln!("Tail Error: {}", err)
+            }
+        }
+        AppAction::Stats => match fetch_stats(&app.endpoint, app.token) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(err) => eprintln!("Stats Error: {}", err),
+        },
+        AppAction::Gc => {
+            let dry_run = app.optional_values[0].as_deref() == Some("true");
+
+            match fetch_gc(&app.endpoint, app.token, dry_run) {
+                Ok(lines) if lines.is_empty() => println!("nothing to remove"),
+                Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                Err(err) => eprintln!("Gc Error: {}", err),
+            }
+        }
+        AppAction::Compact => {
+            let session = app.optional_values[0].to_owned();
+
+            match fetch_compact(&app.endpoint, app.token, session) {
+                Ok(lines) if lines.is_empty() => {
+                    println!("nothing to compress")
+                }
+                Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                Err(err) => eprintln!("Compact Error: {}", err),
+            }
+        }
+        AppAction::Cat => {
+            let session = app.optional_values[0].to_owned().unwrap_or_default();
+            let start = (
+                app.optional_values[1].to_owned(),
+                app.optional_values[2].to_owned(),
+            );
+
+            if let Err(err) = cat_runner(app.log_root, &session, start) {
+                eprintln!("Cat Error: {}", err)
+            }
+        }
+        AppAction::Fsck => {
+            let repair = app.optional_values[0].as_deref() == Some("true");
+
+            match fsck_runner(app.log_root, repair) {
+                Ok(true) => {}
+                Ok(false) => process::exit(1),
+                Err(err) => {
+                    eprintln!("Fsck Error: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        AppAction::Export(args) => {
+            if let Err(err) = export_runner(app.log_root, args) {
+                eprintln!("Export Error: {}", err);
+                process::exit(1);
+            }
+        }
+        AppAction::Import(args) => {
+            if let Err(err) = import_runner(app.log_root, args) {
+                eprintln!("Import Error: {}", err);
+                process::exit(1);
+            }
+        }
         AppAction::None => eprintln!("No or bad cli args given"),
     }
 }
 
 fn stdin_runner(
-    socket: Option<String>,
+    endpoint: Endpoint,
+    token: Option<String>,
     quite: bool,
     name: Option<String>,
+    tags: Vec<String>,
 ) -> Result<(), Box<dyn Error>> {
-    let socket: PathBuf = if socket.is_some() {
-        PathBuf::from(socket.unwrap())
-    } else {
-        PathBuf::from(MAIN_SOCKET)
-    };
-
-    let stdin_handle = StdinHandle::new(socket, quite);
+    let stdin_handle = StdinHandle::new(endpoint, quite)
+        .with_token(token)
+        .with_tags(tags);
 
     stdin_handle.run(name)
 }
 
 fn daemon_runner(
     socket: Option<String>,
+    config: Option<PathBuf>,
+    tcp: Option<TlsListener>,
     quite: bool,
 ) -> Result<(), Box<dyn Error>> {
     let mut da = Daemon::new(socket, quite);
 
+    if let Some(config) = config {
+        da = da.with_config(config)?;
+    }
+
+    if let Some(tcp) = tcp {
+        da = da.with_tcp(tcp);
+    }
+
     let mut loop_break = true;
 
     while loop_break {
@@ -173,8 +865,289 @@ fn daemon_runner(
     Ok(())
 }
 
-fn tui_runner() -> Result<(), Box<dyn Error>> {
-    let mut tui = TuiApp::new(PathBuf::from(MAIN_SOCKET));
+fn tui_runner(
+    endpoint: Endpoint,
+    token: Option<String>,
+    history: Option<u64>,
+    finished: bool,
+    screen: bool,
+    layout: Option<PathBuf>,
+    config: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let config = match config {
+        Some(path) => TuiConfig::load(&path)?,
+        None => TuiConfig::default(),
+    };
+
+    let keymap = Keymap::from_config(&config.keymap)?;
+    let theme = Theme::from_config(&config.theme, theme::no_color())?;
+
+    let mut tui = TuiApp::new(endpoint)
+        .with_token(token)
+        .with_history(history)
+        .with_finished(finished)
+        .with_screens(screen)
+        .with_layout(layout)
+        .with_keymap(keymap)
+        .with_theme(theme);
 
     tui.run()
 }
+
+/// print lines as the daemon sends them, each after its session. merged
+/// they have the time the daemon got them and the history is put in order,
+/// lines after it already come in order
+fn tail_runner(
+    endpoint: Endpoint,
+    token: Option<String>,
+    history: Option<u64>,
+    merge: bool,
+    sessions: Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    let events = Subscriber::new(endpoint)
+        .with_token(token)
+        .with_history(history)
+        .with_sessions(sessions)
+        .with_times(true)
+        .connect()?;
+
+    let (send, recv) = mpsc::channel();
+
+    thread::spawn(move || {
+        for event in events {
+            if send.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let colour = termion::is_tty(&io::stdout()) && !theme::no_color();
+    let mut timeline = Timeline::new().with_colour(colour);
+    let mut width = 0;
+    let mut newest = 0;
+
+    // the history comes a session at a time, it is held until the daemon
+    // goes quiet or it has been held too long
+    let mut held = Vec::new();
+    let holding_since = Instant::now();
+    let mut holding = merge;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    loop {
+        let event = match recv.recv_timeout(Duration::from_millis(200)) {
+            Ok(val) => Some(val),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        let (session, at, text) = match event {
+            Some(Event::Stamped { session, at, text }) => (session, at, text),
+            Some(Event::Line { session, text }) => (session, newest, text),
+            Some(Event::Gap { session, dropped }) => {
+                (session, newest, format!("-- {} lines dropped --", dropped))
+            }
+            Some(Event::Ended { session, exit }) => {
+                let text = match exit {
+                    Some(code) => format!("-- ended, exited with {} --", code),
+                    None => "-- ended --".to_string(),
+                };
+
+                (session, newest, text)
+            }
+            Some(Event::Error(err)) => return Err(Box::from(err)),
+            Some(Event::Started { .. })
+            | Some(Event::Opened { .. })
+            | Some(Event::Tagged { .. }) => continue,
+            None => {
+                holding = false;
+                (String::new(), 0, String::new())
+            }
+        };
+
+        newest = cmp::max(newest, at);
+
+        if !session.is_empty() {
+            held.push(Entry { at, session, text });
+        }
+
+        if holding && holding_since.elapsed() < Duration::from_secs(2) {
+            continue;
+        }
+
+        holding = false;
+
+        if merge {
+            // stable, lines got at the same time stay in the order sent
+            held.sort_by_key(|entry| entry.at);
+        }
+
+        // pad to the longest name held so the first lines line up too
+        let held_width = held
+            .iter()
+            .map(|entry| entry.session.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        if held_width > width {
+            width = held_width;
+            timeline = timeline.with_width(width);
+        }
+
+        for entry in held.drain(..) {
+            if merge {
+                writeln!(out, "{}", timeline.line(&entry))?;
+            } else {
+                let label = timeline::label(&entry.session, width, colour);
+
+                writeln!(out, "{} | {}", label, entry.text)?;
+            }
+        }
+
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// parse an optional number arg, naming the flag if it is bad
+fn number_arg(value: Option<&str>, flag: &str) -> Result<Option<u64>, String> {
+    match value {
+        Some(val) => val
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("bad --{}: {}", flag, val)),
+        None => Ok(None),
+    }
+}
+
+/// print a session, from a line number or a time if one was given
+fn cat_runner(
+    log_root: PathBuf,
+    session: &str,
+    (from, since): (Option<String>, Option<String>),
+) -> Result<(), Box<dyn Error>> {
+    protocol::check_id(session)?;
+
+    let mut reader = SessionReader::open(&log_root.join(session))
+        .map_err(|err| format!("cant open {}: {}", session, err))?;
+
+    let range = Range {
+        from_seq: number_arg(from.as_deref(), "from")?,
+        since: number_arg(since.as_deref(), "since")?.map(|secs| secs * 1000),
+        ..Range::default()
+    };
+
+    // the second seek replaces the first, so the records are checked
+    // against both bounds like export does
+    if let Some(from) = range.from_seq {
+        reader.seek_seq(from)?;
+    }
+
+    if let Some(since) = range.since {
+        reader.seek_ts(since)?;
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+
+    for record in reader {
+        let record = record?;
+
+        if !range.contains(&record) {
+            continue;
+        }
+
+        out.write_all(&record.payload)?;
+        out.write_all(b"\n")?;
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+/// export a session to stdout or the output file
+fn export_runner(
+    log_root: PathBuf,
+    args: ExportArgs,
+) -> Result<(), Box<dyn Error>> {
+    let session = args.session.as_str();
+    protocol::check_id(session)?;
+
+    let path = log_root.join(session);
+
+    if !path.exists() {
+        return Err(Box::from(format!("no session: {}", session)));
+    }
+
+    match &args.output {
+        Some(output) => {
+            let mut out = io::BufWriter::new(std::fs::File::create(output)?);
+            let lines = export(&path, args.format, &args.range, &mut out)?;
+
+            eprintln!("wrote {} lines to {}", lines, output.display());
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = io::BufWriter::new(stdout.lock());
+
+            export(&path, args.format, &args.range, &mut out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// import a file as a finished session
+fn import_runner(
+    log_root: PathBuf,
+    args: ImportArgs,
+) -> Result<(), Box<dyn Error>> {
+    let imported = import(
+        &log_root,
+        args.segment_bytes,
+        &args.file,
+        &args.name,
+        args.format,
+    )?;
+
+    println!(
+        "imported {} lines as {}, {} to {}",
+        imported.lines,
+        imported.name,
+        utc(imported.first),
+        utc(imported.last)
+    );
+
+    Ok(())
+}
+
+/// check the log root, false if damage was left behind
+fn fsck_runner(
+    log_root: PathBuf,
+    repair: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let report = fsck(&log_root, repair)?;
+
+    for check in &report.damaged {
+        println!("{}", check);
+    }
+
+    for session in &report.skipped {
+        println!("{}: open, skipped", session);
+    }
+
+    let repaired = report.damaged.iter().filter(|check| check.repaired);
+
+    println!(
+        "checked {} sessions, {} segments, {} damaged, {} repaired",
+        report.sessions,
+        report.segments,
+        report.damaged.len(),
+        repaired.count()
+    );
+
+    Ok(!report.has_damage())
+}
@@ -0,0 +1,156 @@
+use regex::Regex;
+
+use crate::ansi;
+
+/// a regex a line has to match to be shown, or has to miss if it excludes
+///
+/// lines are matched as they are drawn, without their escapes or trailing \n
+#[derive(Debug, Clone)]
+pub struct Filter {
+    regex: Regex,
+    exclude: bool,
+}
+
+impl Filter {
+    pub fn new(pattern: &str, exclude: bool) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|err| err.to_string())?;
+
+        Ok(Filter { regex, exclude })
+    }
+
+    /// a filter as typed, `!pattern` excludes and anything else includes
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.strip_prefix('!') {
+            Some(pattern) => Filter::new(pattern, true),
+            None => Filter::new(text, false),
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn excludes(&self) -> bool {
+        self.exclude
+    }
+
+    pub fn keeps(&self, line: &str) -> bool {
+        self.regex.is_match(&ansi::plain(line)) != self.exclude
+    }
+}
+
+/// the filters stacked on one tab, a line is shown if every one keeps it
+///
+/// the lines shown are kept as they come in, so a filter on a long session
+/// only looks at each line once
+#[derive(Debug, Clone)]
+pub struct Filters {
+    filters: Vec<Filter>,
+    on: bool,
+    shown: Vec<String>,
+    /// how many of the tabs lines have been looked at
+    seen: usize,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Filters::new()
+    }
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Filters {
+            filters: Vec::new(),
+            on: true,
+            shown: Vec::new(),
+            seen: 0,
+        }
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// if lines are being hidden, there are filters and they are on
+    pub fn active(&self) -> bool {
+        self.on && !self.filters.is_empty()
+    }
+
+    /// add a filter and turn them on
+    pub fn push(&mut self, filter: Filter) {
+        self.filters.push(filter);
+        self.on = true;
+        self.reset();
+    }
+
+    pub fn pop(&mut self) -> Option<Filter> {
+        self.reset();
+        self.filters.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+        self.reset();
+    }
+
+    /// hide or show the lines the filters leave out, keeping the filters
+    pub fn toggle(&mut self) {
+        self.on = !self.on;
+    }
+
+    pub fn keeps(&self, line: &str) -> bool {
+        self.filters.iter().all(|filter| filter.keeps(line))
+    }
+
+    /// look at the lines that came in since the last update
+    pub fn update(&mut self, lines: &[String]) {
+        if self.seen > lines.len() {
+            self.reset();
+        }
+
+        for line in &lines[self.seen..] {
+            if self.keeps(line) {
+                self.shown.push(line.clone());
+            }
+        }
+
+        self.seen = lines.len();
+    }
+
+    /// the lines kept, as of the last update
+    pub fn shown(&self) -> &[String] {
+        &self.shown
+    }
+
+    /// how many lines are shown and what filters them, like
+    /// `12 of 300 lines, +error -debug`
+    pub fn status(&self, total: usize) -> String {
+        if self.filters.is_empty() {
+            return String::new();
+        }
+
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| {
+                let sign = if filter.excludes() { '-' } else { '+' };
+                format!("{}{}", sign, filter.pattern())
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        if self.on {
+            format!("{} of {} lines, {}", self.shown.len(), total, filters)
+        } else {
+            format!("filters off, {}", filters)
+        }
+    }
+
+    /// forget the lines shown so the next update looks at every line, for
+    /// when lines were put in between rather than added at the end
+    pub fn reset(&mut self) {
+        self.shown.clear();
+        self.seen = 0;
+    }
+}
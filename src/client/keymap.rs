@@ -0,0 +1,455 @@
+use std::error::Error;
+
+use serde::Deserialize;
+use termion::event::Key;
+
+use crate::config::KeymapConfig;
+
+/// what a key does in the tui, outside the prompt, the sidebar and the
+/// keys after the pane key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    Sidebar,
+    SidebarFocus,
+    NextTab,
+    PreviousTab,
+    Panes,
+    SearchForward,
+    SearchBackward,
+    SearchAll,
+    NextMatch,
+    PreviousMatch,
+    ClearSearch,
+    Filter,
+    ToggleFilters,
+    DropFilter,
+    View,
+    Screen,
+    PickMerge,
+    ShowMerged,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    HalfUp,
+    HalfDown,
+    Top,
+    Bottom,
+}
+
+impl Action {
+    /// every action, in the order the help shows them
+    pub const ALL: [Action; 28] = [
+        Action::Quit,
+        Action::Help,
+        Action::Sidebar,
+        Action::SidebarFocus,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::Panes,
+        Action::SearchForward,
+        Action::SearchBackward,
+        Action::SearchAll,
+        Action::NextMatch,
+        Action::PreviousMatch,
+        Action::ClearSearch,
+        Action::Filter,
+        Action::ToggleFilters,
+        Action::DropFilter,
+        Action::View,
+        Action::Screen,
+        Action::PickMerge,
+        Action::ShowMerged,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::HalfUp,
+        Action::HalfDown,
+        Action::Top,
+        Action::Bottom,
+    ];
+
+    /// the name the config binds keys to it by
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Sidebar => "sidebar",
+            Action::SidebarFocus => "sidebar_focus",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::Panes => "panes",
+            Action::SearchForward => "search_forward",
+            Action::SearchBackward => "search_backward",
+            Action::SearchAll => "search_all",
+            Action::NextMatch => "next_match",
+            Action::PreviousMatch => "previous_match",
+            Action::ClearSearch => "clear_search",
+            Action::Filter => "filter",
+            Action::ToggleFilters => "toggle_filters",
+            Action::DropFilter => "drop_filter",
+            Action::View => "view",
+            Action::Screen => "screen",
+            Action::PickMerge => "pick_merge",
+            Action::ShowMerged => "show_merged",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::HalfUp => "half_up",
+            Action::HalfDown => "half_down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    /// what the help says it does
+    pub fn about(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "show these keys",
+            Action::Sidebar => "open or close the sessions sidebar",
+            Action::SidebarFocus => "go to the open sidebar",
+            Action::NextTab => "next tab",
+            Action::PreviousTab => "previous tab",
+            Action::Panes => "the next key splits, moves or closes panes",
+            Action::SearchForward => "search down",
+            Action::SearchBackward => "search up",
+            Action::SearchAll => "search every tab or just this one",
+            Action::NextMatch => "next match",
+            Action::PreviousMatch => "previous match",
+            Action::ClearSearch => "stop searching",
+            Action::Filter => "filter the tab by a regex, !regex leaves out",
+            Action::ToggleFilters => "turn the tabs filters off or on",
+            Action::DropFilter => "drop the last filter",
+            Action::View => "colours, no colours or raw escapes",
+            Action::Screen => "the tab as lines or as a terminal screen",
+            Action::PickMerge => "pick the session for the merged timeline",
+            Action::ShowMerged => "open the merged timeline",
+            Action::ScrollUp => "up a line",
+            Action::ScrollDown => "down a line",
+            Action::PageUp => "up a page",
+            Action::PageDown => "down a page",
+            Action::HalfUp => "up half a page",
+            Action::HalfDown => "down half a page",
+            Action::Top => "the first line",
+            Action::Bottom => "the last line, and follow",
+        }
+    }
+}
+
+/// keymaps to start from, the config binds keys over them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// less like, with ? for help
+    #[default]
+    Default,
+    /// ? searches up, h and l change tab, help is on f1
+    Vim,
+    /// ctrl-s and ctrl-r search, ctrl-n ctrl-p ctrl-v alt-v scroll and
+    /// ctrl-x is the pane key
+    Emacs,
+}
+
+impl Preset {
+    pub fn keymap(self) -> Keymap {
+        let mut keymap = Keymap::empty();
+
+        let defaults: [(Action, &[Key]); 28] = [
+            (Action::Quit, &[Key::Char('q')]),
+            (Action::Help, &[Key::Char('?'), Key::F(1)]),
+            (Action::Sidebar, &[Key::Char('s')]),
+            (Action::SidebarFocus, &[Key::Char('\t')]),
+            (Action::NextTab, &[Key::Right]),
+            (Action::PreviousTab, &[Key::Left]),
+            (Action::Panes, &[Key::Ctrl('w')]),
+            (Action::SearchForward, &[Key::Char('/')]),
+            (Action::SearchBackward, &[Key::Ctrl('r')]),
+            (Action::SearchAll, &[Key::Char('a')]),
+            (Action::NextMatch, &[Key::Char('n')]),
+            (Action::PreviousMatch, &[Key::Char('N')]),
+            (Action::ClearSearch, &[Key::Esc]),
+            (Action::Filter, &[Key::Char('&')]),
+            (Action::ToggleFilters, &[Key::Char('f')]),
+            (Action::DropFilter, &[Key::Char('F')]),
+            (Action::View, &[Key::Char('v')]),
+            (Action::Screen, &[Key::Char('t')]),
+            (Action::PickMerge, &[Key::Char('m')]),
+            (Action::ShowMerged, &[Key::Char('M')]),
+            (Action::ScrollUp, &[Key::Up, Key::Char('k')]),
+            (Action::ScrollDown, &[Key::Down, Key::Char('j')]),
+            (Action::PageUp, &[Key::PageUp, Key::Ctrl('b')]),
+            (
+                Action::PageDown,
+                &[Key::PageDown, Key::Ctrl('f'), Key::Char(' ')],
+            ),
+            (Action::HalfUp, &[Key::Ctrl('u')]),
+            (Action::HalfDown, &[Key::Ctrl('d')]),
+            (Action::Top, &[Key::Home, Key::Char('g')]),
+            (Action::Bottom, &[Key::End, Key::Char('G')]),
+        ];
+
+        for (action, keys) in defaults.iter() {
+            keymap.bind(*action, keys);
+        }
+
+        let changes: &[(Action, &[Key])] = match self {
+            Preset::Default => &[],
+            Preset::Vim => &[
+                (Action::SearchBackward, &[Key::Char('?')]),
+                (Action::Help, &[Key::F(1)]),
+                (Action::NextTab, &[Key::Right, Key::Char('l')]),
+                (Action::PreviousTab, &[Key::Left, Key::Char('h')]),
+                (Action::ScrollUp, &[Key::Up, Key::Char('k'), Key::Ctrl('y')]),
+                (
+                    Action::ScrollDown,
+                    &[Key::Down, Key::Char('j'), Key::Ctrl('e')],
+                ),
+            ],
+            Preset::Emacs => &[
+                (Action::SearchForward, &[Key::Ctrl('s'), Key::Char('/')]),
+                (Action::SearchBackward, &[Key::Ctrl('r')]),
+                (Action::ClearSearch, &[Key::Esc, Key::Ctrl('g')]),
+                (Action::Panes, &[Key::Ctrl('x')]),
+                (Action::NextTab, &[Key::Right, Key::Ctrl('f')]),
+                (Action::PreviousTab, &[Key::Left, Key::Ctrl('b')]),
+                (Action::ScrollUp, &[Key::Up, Key::Ctrl('p')]),
+                (Action::ScrollDown, &[Key::Down, Key::Ctrl('n')]),
+                (Action::PageUp, &[Key::PageUp, Key::Alt('v')]),
+                (
+                    Action::PageDown,
+                    &[Key::PageDown, Key::Ctrl('v'), Key::Char(' ')],
+                ),
+                (Action::Top, &[Key::Home, Key::Alt('<')]),
+                (Action::Bottom, &[Key::End, Key::Alt('>')]),
+            ],
+        };
+
+        for (action, keys) in changes {
+            keymap.bind(*action, keys);
+        }
+
+        keymap
+    }
+}
+
+/// which key does what, and the key that quits from anywhere
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    exit: Key,
+    /// in the order they were bound, a key is only ever in here once
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Preset::Default.keymap()
+    }
+}
+
+impl Keymap {
+    /// nothing bound, ctrl-c still quits
+    pub fn empty() -> Self {
+        Keymap {
+            exit: Key::Ctrl('c'),
+            bindings: Vec::new(),
+        }
+    }
+
+    /// the preset with the configs keys bound over it
+    pub fn from_config(config: &KeymapConfig) -> Result<Self, Box<dyn Error>> {
+        let mut keymap = config.preset.keymap();
+
+        if let Some(exit) = &config.exit {
+            keymap.set_exit(parse_key(exit)?)?;
+        }
+
+        for (name, keys) in &config.bind {
+            let action = Action::from_name(name)
+                .ok_or_else(|| format!("No action called {}", name))?;
+
+            let keys = keys
+                .iter()
+                .map(|key| parse_key(key))
+                .collect::<Result<Vec<Key>, String>>()?;
+
+            keymap.bind(action, &keys);
+        }
+
+        Ok(keymap)
+    }
+
+    /// the key that quits even while a search is typed, so it cant be one
+    /// that types something
+    pub fn set_exit(&mut self, key: Key) -> Result<(), Box<dyn Error>> {
+        if let Key::Char(_) = key {
+            return Err(Box::from(format!(
+                "The exit key {} would quit while typing",
+                key_name(key)
+            )));
+        }
+
+        self.exit = key;
+        Ok(())
+    }
+
+    pub fn exit(&self) -> Key {
+        self.exit
+    }
+
+    /// give an action these keys instead of the ones it had, taking them
+    /// from whatever had them. no keys unbinds it
+    pub fn bind(&mut self, action: Action, keys: &[Key]) {
+        self.bindings
+            .retain(|(key, bound)| *bound != action && !keys.contains(key));
+        self.bindings.extend(keys.iter().map(|key| (*key, action)));
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// a line for each action with its keys, what it does and the name
+    /// the config binds it by, the keys padded to line up. an action
+    /// without keys has a -
+    pub fn help(&self) -> Vec<String> {
+        let mut rows =
+            vec![(key_name(self.exit), "quit from anywhere", "exit")];
+
+        for action in Action::ALL.iter() {
+            let names = self
+                .keys(*action)
+                .into_iter()
+                .map(key_name)
+                .collect::<Vec<String>>()
+                .join(" ");
+
+            let names = if names.is_empty() {
+                "-".to_string()
+            } else {
+                names
+            };
+
+            rows.push((names, action.about(), action.name()));
+        }
+
+        let width = rows
+            .iter()
+            .map(|(keys, _, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        rows.into_iter()
+            .map(|(keys, about, name)| {
+                format!("{:<width$}  {} ({})", keys, about, name, width = width)
+            })
+            .collect()
+    }
+}
+
+/// a key as the config writes it, like `q`, `N`, `ctrl-w`, `alt-v`, `esc`,
+/// `pagedown` or `f1`
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let bad = || format!("No key called {:?}", name);
+
+    let single = |name: &str| {
+        let mut chars = name.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(c) = single(name) {
+        return Ok(Key::Char(c));
+    }
+
+    let lower = name.to_lowercase();
+
+    if let Some(rest) = lower.strip_prefix("ctrl-") {
+        return single(rest).map(Key::Ctrl).ok_or_else(bad);
+    }
+
+    if let Some(rest) = name.get(4..).filter(|_| lower.starts_with("alt-")) {
+        return single(rest).map(Key::Alt).ok_or_else(bad);
+    }
+
+    if let Some(number) = lower.strip_prefix('f') {
+        return match number.parse() {
+            Ok(number) if (1..=12).contains(&number) => Ok(Key::F(number)),
+            _ => Err(bad()),
+        };
+    }
+
+    let key = match lower.as_str() {
+        "space" => Key::Char(' '),
+        "tab" => Key::Char('\t'),
+        "enter" => Key::Char('\n'),
+        "esc" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => return Err(bad()),
+    };
+
+    Ok(key)
+}
+
+/// a key the way parse_key reads it
+pub fn key_name(key: Key) -> String {
+    let name = match key {
+        Key::Char(' ') => "space",
+        Key::Char('\t') => "tab",
+        Key::Char('\n') => "enter",
+        Key::Char(c) => return c.to_string(),
+        Key::Ctrl(c) => return format!("ctrl-{}", c),
+        Key::Alt(c) => return format!("alt-{}", c),
+        Key::F(number) => return format!("f{}", number),
+        Key::Esc => "esc",
+        Key::Backspace => "backspace",
+        Key::Delete => "delete",
+        Key::Insert => "insert",
+        Key::Up => "up",
+        Key::Down => "down",
+        Key::Left => "left",
+        Key::Right => "right",
+        Key::Home => "home",
+        Key::End => "end",
+        Key::PageUp => "pageup",
+        Key::PageDown => "pagedown",
+        _ => "unknown",
+    };
+
+    name.to_string()
+}
@@ -1,2 +1,14 @@
+pub mod filter;
+pub mod keymap;
+pub mod pager;
+pub mod panes;
+pub mod producer;
+pub mod search;
+pub mod sidebar;
+pub mod sink;
+pub mod stats;
 pub mod stdin_handle;
+pub mod subscriber;
+pub mod theme;
+pub mod timeline;
 pub mod tui;
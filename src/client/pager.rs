@@ -0,0 +1,109 @@
+use std::cmp;
+
+/// a way to move through the lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scroll {
+    Up(usize),
+    Down(usize),
+    PageUp,
+    PageDown,
+    HalfUp,
+    HalfDown,
+    Top,
+    Bottom,
+    /// put a line at the top, or as near as it goes
+    To(usize),
+}
+
+/// where a view of a growing list of lines is scrolled to
+///
+/// it follows the newest lines until it is scrolled up, and follows again
+/// once it is back at the bottom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pager {
+    /// the first line shown when not following
+    top: usize,
+    follow: bool,
+}
+
+impl Default for Pager {
+    fn default() -> Self {
+        Pager::new()
+    }
+}
+
+impl Pager {
+    pub fn new() -> Self {
+        Pager {
+            top: 0,
+            follow: true,
+        }
+    }
+
+    pub fn following(&self) -> bool {
+        self.follow
+    }
+
+    /// the first line shown of total, with height lines to show them in
+    pub fn top(&self, total: usize, height: usize) -> usize {
+        let last_top = total.saturating_sub(height);
+
+        if self.follow {
+            last_top
+        } else {
+            cmp::min(self.top, last_top)
+        }
+    }
+
+    /// the range of lines shown
+    pub fn visible(
+        &self,
+        total: usize,
+        height: usize,
+    ) -> std::ops::Range<usize> {
+        let top = self.top(total, height);
+
+        top..cmp::min(top + height, total)
+    }
+
+    pub fn scroll(&mut self, scroll: Scroll, total: usize, height: usize) {
+        let top = self.top(total, height);
+        let page = cmp::max(height, 1);
+        let half = cmp::max(height / 2, 1);
+
+        let top = match scroll {
+            Scroll::Up(lines) => top.saturating_sub(lines),
+            Scroll::Down(lines) => top + lines,
+            Scroll::PageUp => top.saturating_sub(page),
+            Scroll::PageDown => top + page,
+            Scroll::HalfUp => top.saturating_sub(half),
+            Scroll::HalfDown => top + half,
+            Scroll::Top => 0,
+            Scroll::Bottom => usize::MAX,
+            Scroll::To(line) => line,
+        };
+
+        let last_top = total.saturating_sub(height);
+
+        self.top = cmp::min(top, last_top);
+        self.follow = self.top == last_top;
+    }
+
+    /// where the view is, like `line 40 of 200, 20%`, counting to the last
+    /// line shown
+    pub fn status(&self, total: usize, height: usize) -> String {
+        if total == 0 {
+            return "no lines".to_string();
+        }
+
+        let shown = self.visible(total, height).end;
+        let status =
+            format!("line {} of {}, {}%", shown, total, shown * 100 / total);
+
+        if self.follow {
+            status + ", following"
+        } else {
+            status
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::cmp;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tui::layout::Rect;
+
+/// which way a pane is cut in two
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// side by side, the first on the left
+    Horizontal,
+    /// one above the other, the first on top
+    Vertical,
+}
+
+/// a side of a pane, to move the focus to the pane next to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// a tree of panes, each leaf a pane and each branch a split of its area
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Node<T> {
+    Pane(T),
+    Split {
+        split: Split,
+        /// how much of the area the first side gets
+        percent: u16,
+        first: Box<Node<T>>,
+        second: Box<Node<T>>,
+    },
+}
+
+impl<T> Node<T> {
+    /// the panes from the top left
+    pub fn panes(&self) -> Vec<&T> {
+        match self {
+            Node::Pane(pane) => vec![pane],
+            Node::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+
+    pub fn map<U, F>(&self, f: &mut F) -> Node<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        match self {
+            Node::Pane(pane) => Node::Pane(f(pane)),
+            Node::Split {
+                split,
+                percent,
+                first,
+                second,
+            } => Node::Split {
+                split: *split,
+                percent: *percent,
+                first: Box::new(first.map(f)),
+                second: Box::new(second.map(f)),
+            },
+        }
+    }
+}
+
+impl Node<usize> {
+    fn contains(&self, id: usize) -> bool {
+        self.panes().contains(&&id)
+    }
+
+    fn split_pane(&mut self, id: usize, split: Split, new: usize) {
+        match self {
+            Node::Pane(pane) if *pane == id => {
+                *self = Node::Split {
+                    split,
+                    percent: 50,
+                    first: Box::new(Node::Pane(id)),
+                    second: Box::new(Node::Pane(new)),
+                }
+            }
+            Node::Pane(_) => {}
+            Node::Split { first, second, .. } => {
+                first.split_pane(id, split, new);
+                second.split_pane(id, split, new);
+            }
+        }
+    }
+
+    /// the first pane on the other side of the split id is in
+    fn sibling(&self, id: usize) -> Option<usize> {
+        let (first, second) = match self {
+            Node::Pane(_) => return None,
+            Node::Split { first, second, .. } => (first, second),
+        };
+
+        if **first == Node::Pane(id) {
+            second.panes().first().copied().copied()
+        } else if **second == Node::Pane(id) {
+            first.panes().first().copied().copied()
+        } else {
+            first.sibling(id).or_else(|| second.sibling(id))
+        }
+    }
+
+    /// take a pane out, the other side of its split gets the space
+    fn remove(&mut self, id: usize) {
+        let kept = match self {
+            Node::Pane(_) => return,
+            Node::Split { first, second, .. } => {
+                if **first == Node::Pane(id) {
+                    second
+                } else if **second == Node::Pane(id) {
+                    first
+                } else {
+                    first.remove(id);
+                    second.remove(id);
+                    return;
+                }
+            }
+        };
+
+        let kept = std::mem::replace(&mut **kept, Node::Pane(id));
+        *self = kept;
+    }
+
+    /// move the nearest split of that way around id, true once one moved
+    fn resize(&mut self, id: usize, way: Split, by: i16) -> bool {
+        let (split, percent, first, second) = match self {
+            Node::Pane(_) => return false,
+            Node::Split {
+                split,
+                percent,
+                first,
+                second,
+            } => (split, percent, first, second),
+        };
+
+        let in_first = first.contains(id);
+
+        if !in_first && !second.contains(id) {
+            return false;
+        }
+
+        let inner = if in_first { first } else { second };
+
+        if inner.resize(id, way, by) {
+            return true;
+        }
+
+        if *split != way {
+            return false;
+        }
+
+        let by = if in_first { by } else { -by };
+        *percent = (*percent as i16 + by).clamp(10, 90) as u16;
+
+        true
+    }
+
+    fn even(&mut self) {
+        if let Node::Split {
+            percent,
+            first,
+            second,
+            ..
+        } = self
+        {
+            *percent = 50;
+            first.even();
+            second.even();
+        }
+    }
+
+    fn rects(&self, area: Rect, out: &mut Vec<(usize, Rect)>) {
+        let (split, percent, first, second) = match self {
+            Node::Pane(pane) => return out.push((*pane, area)),
+            Node::Split {
+                split,
+                percent,
+                first,
+                second,
+            } => (split, percent, first, second),
+        };
+
+        let (mut one, mut two) = (area, area);
+
+        match split {
+            Split::Horizontal => {
+                one.width = (area.width as u32 * *percent as u32 / 100) as u16;
+                two.x = area.x + one.width;
+                two.width = area.width - one.width;
+            }
+            Split::Vertical => {
+                one.height =
+                    (area.height as u32 * *percent as u32 / 100) as u16;
+                two.y = area.y + one.height;
+                two.height = area.height - one.height;
+            }
+        }
+
+        first.rects(one, out);
+        second.rects(two, out);
+    }
+}
+
+/// the panes the text area is split into, and the one with the focus
+#[derive(Debug, Clone)]
+pub struct Panes {
+    root: Node<usize>,
+    focus: usize,
+    /// the id the next pane gets
+    next: usize,
+}
+
+impl Default for Panes {
+    fn default() -> Self {
+        Panes::new()
+    }
+}
+
+impl Panes {
+    /// one pane, with id 0
+    pub fn new() -> Self {
+        Panes {
+            root: Node::Pane(0),
+            focus: 0,
+            next: 1,
+        }
+    }
+
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    /// the pane ids from the top left
+    pub fn ids(&self) -> Vec<usize> {
+        self.root.panes().into_iter().copied().collect()
+    }
+
+    /// focus a pane, if it is one
+    pub fn focus_on(&mut self, id: usize) {
+        if self.root.contains(id) {
+            self.focus = id;
+        }
+    }
+
+    /// the pane after the focused one, or before it, going round
+    pub fn cycle(&self, reverse: bool) -> usize {
+        let ids = self.ids();
+        let at = ids.iter().position(|id| *id == self.focus).unwrap_or(0);
+
+        let at = if reverse {
+            (at + ids.len() - 1) % ids.len()
+        } else {
+            (at + 1) % ids.len()
+        };
+
+        ids[at]
+    }
+
+    /// cut the focused pane in two, the new pane is second and gets the
+    /// focus
+    pub fn split(&mut self, split: Split) -> usize {
+        let id = self.next;
+        self.next += 1;
+
+        self.root.split_pane(self.focus, split, id);
+        self.focus = id;
+
+        id
+    }
+
+    /// close the focused pane, unless it is the last one. the focus goes
+    /// to the first pane of what took its space
+    pub fn close(&mut self) -> Option<usize> {
+        let closed = self.focus;
+
+        self.focus = self.root.sibling(closed)?;
+        self.root.remove(closed);
+
+        Some(closed)
+    }
+
+    /// close every pane but the focused one, giving the ones closed
+    pub fn only(&mut self) -> Vec<usize> {
+        let closed = self
+            .ids()
+            .into_iter()
+            .filter(|id| *id != self.focus)
+            .collect();
+
+        self.root = Node::Pane(self.focus);
+        closed
+    }
+
+    /// grow the focused pane by a percent of the split around it that
+    /// goes that way, or shrink it with a negative
+    pub fn resize(&mut self, way: Split, by: i16) {
+        self.root.resize(self.focus, way, by);
+    }
+
+    /// put every split back in the middle
+    pub fn even(&mut self) {
+        self.root.even();
+    }
+
+    /// where each pane goes in area
+    pub fn rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.root.rects(area, &mut rects);
+        rects
+    }
+
+    /// the pane on a side of the focused one in area, the nearest and then
+    /// the one lined up with it the most
+    pub fn neighbour(&self, area: Rect, side: Side) -> Option<usize> {
+        let rects = self.rects(area);
+        let (_, at) = *rects.iter().find(|(id, _)| *id == self.focus)?;
+
+        let overlap = |a: u16, a_len: u16, b: u16, b_len: u16| {
+            cmp::min(a + a_len, b + b_len) as i32 - cmp::max(a, b) as i32
+        };
+
+        rects
+            .iter()
+            .filter_map(|(id, rect)| {
+                let (gap, lined_up) = match side {
+                    Side::Left => (
+                        at.x as i32 - (rect.x + rect.width) as i32,
+                        overlap(at.y, at.height, rect.y, rect.height),
+                    ),
+                    Side::Right => (
+                        rect.x as i32 - (at.x + at.width) as i32,
+                        overlap(at.y, at.height, rect.y, rect.height),
+                    ),
+                    Side::Up => (
+                        at.y as i32 - (rect.y + rect.height) as i32,
+                        overlap(at.x, at.width, rect.x, rect.width),
+                    ),
+                    Side::Down => (
+                        rect.y as i32 - (at.y + at.height) as i32,
+                        overlap(at.x, at.width, rect.x, rect.width),
+                    ),
+                };
+
+                if *id == self.focus || gap < 0 || lined_up <= 0 {
+                    return None;
+                }
+
+                Some((gap, -lined_up, *id))
+            })
+            .min()
+            .map(|(_, _, id)| id)
+    }
+
+    /// the panes as a layout to save, with the session name each is
+    /// showing
+    pub fn layout<F>(&self, mut session: F) -> Layout
+    where
+        F: FnMut(usize) -> Option<String>,
+    {
+        Layout {
+            focus: self.ids().iter().position(|id| *id == self.focus),
+            root: self.root.map(&mut |id| session(*id)),
+        }
+    }
+
+    /// panes laid out as saved, with the session name each pane wants by
+    /// its new id
+    pub fn restore(layout: &Layout) -> (Self, Vec<(usize, Option<String>)>) {
+        let mut next = 0;
+        let mut wanted = Vec::new();
+
+        let root = layout.root.map(&mut |session| {
+            wanted.push((next, session.clone()));
+            next += 1;
+            next - 1
+        });
+
+        let focus = cmp::min(layout.focus.unwrap_or(0), next - 1);
+
+        (Panes { root, focus, next }, wanted)
+    }
+}
+
+/// panes as they are saved to a file, with the name of the session each
+/// one was showing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    /// which pane had the focus, counted from the top left
+    pub focus: Option<usize>,
+    pub root: Node<Option<String>>,
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let layout = serde_json::from_str(&text)?;
+
+        Ok(layout)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+
+        Ok(())
+    }
+}
+
+/// a session id without the time the producer put on the end, so a layout
+/// finds the next run of a session with the same name
+pub fn session_name(id: &str) -> &str {
+    match id.rsplit_once('_') {
+        Some((name, time))
+            if !name.is_empty()
+                && !time.is_empty()
+                && time.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => id,
+    }
+}
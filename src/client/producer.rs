@@ -0,0 +1,621 @@
+use std::iter;
+use std::error::Error;
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::{Rng, thread_rng};
+use rand::distributions::Alphanumeric;
+
+use crate::protocol::{self, Handshake};
+use crate::storage::record::Attrs;
+use crate::transport::{BoxConnection, Endpoint};
+
+/// how many times close tries to reach the daemon with lines still spooled
+const CLOSE_ATTEMPTS: u32 = 5;
+
+/// what to connect to and as, `connect` gives the handle to send with
+///
+/// ```no_run
+/// use std::io::Write;
+///
+/// use spellhold::client::producer::Producer;
+/// use spellhold::transport::Endpoint;
+///
+/// let endpoint = Endpoint::Unix("/tmp/spellholdd_socket".into());
+/// let mut handle = Producer::new(endpoint)
+///     .with_name("nightly")
+///     .with_tags(vec!["ci"])
+///     .connect()?;
+///
+/// handle.send("building")?;
+/// writeln!(handle, "{} tests passed", 12)?;
+///
+/// handle.set_exit(0);
+/// handle.close()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Producer {
+    endpoint: Endpoint,
+    token: Option<String>,
+    name: Option<String>,
+    tags: Vec<String>,
+    spool: usize,
+    retry: Duration,
+}
+
+impl Producer {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Producer {
+            endpoint,
+            token: None,
+            name: None,
+            tags: Vec::new(),
+            spool: 10_000,
+            retry: Duration::from_secs(1),
+        }
+    }
+
+    /// the token to show the daemon, if it wants one
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// the start of the session id, the connect time is added on. a random
+    /// one is made if not given
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// tags kept with the session, they cant have commas or spaces
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// lines to hold while the daemon cant be reached, the oldest are
+    /// dropped past this
+    pub fn with_spool(mut self, lines: usize) -> Self {
+        self.spool = lines.max(1);
+        self
+    }
+
+    /// how long to wait between tries to reconnect
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// the errors connecting would give that dont need the daemon
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        protocol::check_tags(&self.tags)?;
+        make_id_string(self.name.clone())?;
+
+        Ok(())
+    }
+
+    /// connect and say which session this is
+    ///
+    /// an error here is final, a refused token or no daemon. once connected
+    /// the handle reconnects on its own
+    pub fn connect(self) -> Result<ProducerHandle, Box<dyn Error>> {
+        self.check()?;
+
+        let id = make_id_string(self.name.clone())?;
+        let conn = self.handshake(&id)?;
+
+        Ok(ProducerHandle {
+            spool: Spool::new(self.spool),
+            producer: self,
+            id,
+            conn: Some(conn),
+            partial: Vec::new(),
+            exit: None,
+            next_try: Instant::now(),
+            closed: false,
+        })
+    }
+
+    /// like connect but a daemon that cant be reached yet isnt an error,
+    /// lines are spooled until it can be
+    pub fn connect_lazily(self) -> Result<ProducerHandle, Box<dyn Error>> {
+        self.check()?;
+
+        let id = make_id_string(self.name.clone())?;
+        let conn = self.handshake(&id).ok();
+        let next_try = Instant::now() + self.retry;
+
+        Ok(ProducerHandle {
+            spool: Spool::new(self.spool),
+            producer: self,
+            id,
+            conn,
+            partial: Vec::new(),
+            exit: None,
+            next_try,
+            closed: false,
+        })
+    }
+
+    /// the handshake saying which session this is
+    fn connect_line(&self, id: &str) -> String {
+        Handshake::Connect {
+            id: id.to_string(),
+            token: self.token.clone(),
+            tags: self.tags.clone(),
+        }
+        .to_line()
+    }
+
+    fn handshake(&self, id: &str) -> Result<BoxConnection, Box<dyn Error>> {
+        let mut reader = BufReader::new(self.endpoint.connect()?);
+
+        reader
+            .get_mut()
+            .write_all(self.connect_line(id).as_bytes())?;
+        reader.get_mut().flush()?;
+
+        protocol::read_reply(&mut reader)?;
+
+        // the daemon sends nothing after its reply, so nothing is lost here
+        Ok(reader.into_inner())
+    }
+}
+
+/// a connected session, lines go out with `send` or through `Write`
+///
+/// lines sent while the daemon cant be reached are spooled and go out once
+/// it is back. dropping the handle closes it
+pub struct ProducerHandle {
+    producer: Producer,
+    id: String,
+    conn: Option<BoxConnection>,
+    spool: Spool,
+    /// written bytes that have no \n yet
+    partial: Vec<u8>,
+    exit: Option<i32>,
+    next_try: Instant,
+    closed: bool,
+}
+
+impl ProducerHandle {
+    /// the session id the daemon knows this by
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// send a line, a line with \n in it is sent as several
+    pub fn send(&mut self, line: &str) -> io::Result<()> {
+        self.send_with(line, &Attrs::new())
+    }
+
+    /// send a line with attributes kept apart from its text, like a log
+    /// level. each line of a multi line text gets them all
+    pub fn send_with(&mut self, line: &str, attrs: &Attrs) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::other("producer is closed"));
+        }
+
+        for line in wire_lines(&self.id, line, attrs) {
+            self.spool.push(line);
+        }
+
+        self.drain();
+
+        Ok(())
+    }
+
+    /// how the command being sent for exited, kept with the session
+    pub fn set_exit(&mut self, code: i32) {
+        self.exit = Some(code);
+    }
+
+    /// lines waiting for the daemon
+    pub fn spooled(&self) -> usize {
+        self.spool.lines.len()
+    }
+
+    /// send what is left and tell the daemon the session is done
+    ///
+    /// an error says how many lines never made it
+    pub fn close(mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        if !self.partial.is_empty() {
+            let partial = std::mem::take(&mut self.partial);
+            self.send(&String::from_utf8_lossy(&partial))?;
+        }
+
+        self.closed = true;
+
+        for _ in 0..CLOSE_ATTEMPTS {
+            if self.drain() {
+                break;
+            }
+
+            thread::sleep(
+                self.next_try.saturating_duration_since(Instant::now()),
+            );
+        }
+
+        let lost = self.spool.lost();
+
+        if let (Some(conn), true) =
+            (self.conn.as_mut(), self.spool.lines.is_empty())
+        {
+            let end = protocol::end_line(self.exit);
+
+            conn.write_all(end.as_bytes())?;
+            conn.flush()?;
+        }
+
+        self.conn = None;
+
+        Spool::check_lost(lost)
+    }
+
+    /// send the spool, reconnecting if it is time to. true once it is empty
+    fn drain(&mut self) -> bool {
+        if self.conn.is_none() {
+            if Instant::now() < self.next_try {
+                return false;
+            }
+
+            match self.producer.handshake(&self.id) {
+                Ok(conn) => self.conn = Some(conn),
+                Err(_) => {
+                    self.next_try = Instant::now() + self.producer.retry;
+                    return false;
+                }
+            }
+
+            self.spool.reconnected(&self.id);
+        }
+
+        while let Some(line) = self.spool.lines.front() {
+            let sent = match self.conn.as_mut() {
+                Some(conn) => {
+                    conn.write_all(line.as_bytes()).and_then(|_| conn.flush())
+                }
+                None => return false,
+            };
+
+            if sent.is_err() {
+                self.conn = None;
+                self.next_try = Instant::now() + self.producer.retry;
+                return false;
+            }
+
+            self.spool.lines.pop_front();
+        }
+
+        true
+    }
+}
+
+/// bytes are cut into lines, a line without its \n waits for the rest
+impl Write for ProducerHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+
+        while let Some(end) = self.partial.iter().position(|b| *b == b'\n') {
+            let line = self.partial.drain(..=end).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line[..end]).into_owned();
+
+            self.send(&line)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain();
+        Ok(())
+    }
+}
+
+impl Drop for ProducerHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Error closing {}: {}", self.id, err);
+        }
+    }
+}
+
+/// lines waiting for the daemon, the oldest are dropped once it is full
+struct Spool {
+    lines: VecDeque<String>,
+    capacity: usize,
+    /// lines pushed out since the last reconnect
+    dropped: u64,
+}
+
+impl Spool {
+    fn new(capacity: usize) -> Self {
+        Spool {
+            lines: VecDeque::new(),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        self.lines.push_back(line);
+
+        while self.lines.len() > self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// put a line saying what was dropped in front of what is left
+    fn reconnected(&mut self, id: &str) {
+        if self.dropped > 0 {
+            let marker = format!(
+                "{} -ENDID- -- {} lines dropped while disconnected --\n",
+                id, self.dropped
+            );
+
+            self.lines.push_front(marker);
+            self.dropped = 0;
+        }
+    }
+
+    /// lines that will never be sent once the connection is given up
+    fn lost(&self) -> u64 {
+        self.lines.len() as u64 + self.dropped
+    }
+
+    fn check_lost(lost: u64) -> io::Result<()> {
+        if lost > 0 {
+            return Err(io::Error::other(format!(
+                "{} lines never reached the daemon",
+                lost
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// the protocol lines for a text, a line each
+fn wire_lines<'a>(
+    id: &'a str,
+    text: &'a str,
+    attrs: &'a Attrs,
+) -> impl Iterator<Item = String> + 'a {
+    text.split('\n').map(move |line| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if attrs.is_empty() {
+            format!("{} -ENDID- {}\n", id, line)
+        } else {
+            protocol::record_line(id, line, attrs)
+        }
+    })
+}
+
+fn make_id_string(name: Option<String>) -> Result<String, Box<dyn Error>> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs()
+        .to_string();
+
+    let log_file = match name {
+        Some(val) => val,
+        None => iter::repeat_with(|| thread_rng().sample(Alphanumeric))
+            .take(10)
+            .collect::<String>(),
+    };
+
+    let id = format!("{}_{}", log_file, since_epoch);
+
+    protocol::check_id(&id).map_err(|_| format!("bad name: {}", log_file))?;
+
+    Ok(id)
+}
+
+#[cfg(feature = "async")]
+pub use self::asynchronous::AsyncProducerHandle;
+
+/// the producer for tokio
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::error::Error;
+    use std::io;
+    use std::time::Instant;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::{make_id_string, wire_lines, Producer, Spool, CLOSE_ATTEMPTS};
+    use crate::protocol;
+    use crate::storage::record::Attrs;
+    use crate::transport::BoxAsyncConnection;
+
+    type AsyncError = Box<dyn Error + Send + Sync>;
+
+    impl Producer {
+        /// connect without holding up the thread, like connect
+        pub async fn connect_async(
+            self,
+        ) -> Result<AsyncProducerHandle, AsyncError> {
+            self.check().map_err(|err| err.to_string())?;
+
+            let id = make_id_string(self.name.clone())
+                .map_err(|err| err.to_string())?;
+            let conn = self.handshake_async(&id).await?;
+
+            Ok(AsyncProducerHandle {
+                spool: Spool::new(self.spool),
+                producer: self,
+                id,
+                conn: Some(conn),
+                exit: None,
+                next_try: Instant::now(),
+                closed: false,
+            })
+        }
+
+        async fn handshake_async(
+            &self,
+            id: &str,
+        ) -> Result<BoxAsyncConnection, AsyncError> {
+            let mut reader =
+                BufReader::new(self.endpoint.connect_async().await?);
+
+            let stream = reader.get_mut();
+            stream.write_all(self.connect_line(id).as_bytes()).await?;
+            stream.flush().await?;
+
+            let mut reply = String::new();
+            reader.read_line(&mut reply).await?;
+            protocol::parse_reply(&reply)?;
+
+            // the daemon sends nothing after its reply, so nothing is lost here
+            Ok(reader.into_inner())
+        }
+    }
+
+    /// a connected session for tokio, reconnecting and spooling like
+    /// ProducerHandle
+    ///
+    /// close it to send what is left and the exit status, dropping it only
+    /// drops the connection
+    pub struct AsyncProducerHandle {
+        producer: Producer,
+        id: String,
+        conn: Option<BoxAsyncConnection>,
+        spool: Spool,
+        exit: Option<i32>,
+        next_try: Instant,
+        closed: bool,
+    }
+
+    impl AsyncProducerHandle {
+        /// the session id the daemon knows this by
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        /// send a line, a line with \n in it is sent as several
+        pub async fn send(&mut self, line: &str) -> io::Result<()> {
+            self.send_with(line, &Attrs::new()).await
+        }
+
+        /// send a line with attributes kept apart from its text
+        pub async fn send_with(
+            &mut self,
+            line: &str,
+            attrs: &Attrs,
+        ) -> io::Result<()> {
+            if self.closed {
+                return Err(io::Error::other("producer is closed"));
+            }
+
+            for line in wire_lines(&self.id, line, attrs) {
+                self.spool.push(line);
+            }
+
+            self.drain().await;
+
+            Ok(())
+        }
+
+        /// how the command being sent for exited, kept with the session
+        pub fn set_exit(&mut self, code: i32) {
+            self.exit = Some(code);
+        }
+
+        /// lines waiting for the daemon
+        pub fn spooled(&self) -> usize {
+            self.spool.lines.len()
+        }
+
+        /// send what is left and tell the daemon the session is done
+        ///
+        /// an error says how many lines never made it
+        pub async fn close(mut self) -> io::Result<()> {
+            self.closed = true;
+
+            for _ in 0..CLOSE_ATTEMPTS {
+                if self.drain().await {
+                    break;
+                }
+
+                tokio::time::sleep(
+                    self.next_try.saturating_duration_since(Instant::now()),
+                )
+                .await;
+            }
+
+            let lost = self.spool.lost();
+
+            if let (Some(conn), true) =
+                (self.conn.as_mut(), self.spool.lines.is_empty())
+            {
+                let end = protocol::end_line(self.exit);
+
+                conn.write_all(end.as_bytes()).await?;
+                conn.flush().await?;
+            }
+
+            Spool::check_lost(lost)
+        }
+
+        /// send the spool, reconnecting if it is time to. true once it is
+        /// empty
+        async fn drain(&mut self) -> bool {
+            if self.conn.is_none() {
+                if Instant::now() < self.next_try {
+                    return false;
+                }
+
+                match self.producer.handshake_async(&self.id).await {
+                    Ok(conn) => self.conn = Some(conn),
+                    Err(_) => {
+                        self.next_try = Instant::now() + self.producer.retry;
+                        return false;
+                    }
+                }
+
+                self.spool.reconnected(&self.id);
+            }
+
+            while let Some(line) = self.spool.lines.front() {
+                let conn = match self.conn.as_mut() {
+                    Some(conn) => conn,
+                    None => return false,
+                };
+
+                let sent = match conn.write_all(line.as_bytes()).await {
+                    Ok(()) => conn.flush().await,
+                    Err(err) => Err(err),
+                };
+
+                if sent.is_err() {
+                    self.conn = None;
+                    self.next_try = Instant::now() + self.producer.retry;
+                    return false;
+                }
+
+                self.spool.lines.pop_front();
+            }
+
+            true
+        }
+    }
+}
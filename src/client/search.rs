@@ -0,0 +1,102 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::ansi;
+
+/// which way a search goes, `/` is forward and `?` backward
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// a regex looked for in the lines of one tab or of every tab
+///
+/// lines are matched as they are drawn, without their escapes or trailing \n
+#[derive(Debug, Clone)]
+pub struct Search {
+    regex: Regex,
+    direction: Direction,
+}
+
+impl Search {
+    pub fn new(pattern: &str, direction: Direction) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|err| err.to_string())?;
+
+        Ok(Search { regex, direction })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.regex.is_match(&ansi::plain(line))
+    }
+
+    /// where it matches in a line as byte ranges into `ansi::plain(line)`,
+    /// empty matches left out
+    pub fn ranges(&self, line: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(&ansi::plain(line))
+            .map(|found| found.range())
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    /// how many of the lines match
+    pub fn count(&self, lines: &[String]) -> usize {
+        lines.iter().filter(|line| self.is_match(line)).count()
+    }
+
+    /// the next matching line after from, as a tab and line index into
+    /// tabs. the rest of the tab from is in goes first, then the other tabs
+    /// in order, then the start of the first one. reverse goes against the
+    /// direction of the search, like N
+    pub fn next_hit(
+        &self,
+        tabs: &[&[String]],
+        from: (usize, usize),
+        reverse: bool,
+    ) -> Option<(usize, usize)> {
+        let backward = (self.direction == Direction::Backward) != reverse;
+        let (tab, line) = from;
+
+        let count = tabs.len();
+        let len = tabs.get(tab)?.len();
+        let line = line.min(len);
+
+        let lines =
+            move |tab: usize| (0..tabs[tab].len()).map(move |l| (tab, l));
+
+        let order: Box<dyn Iterator<Item = (usize, usize)>> = if backward {
+            Box::new(
+                (0..line)
+                    .rev()
+                    .map(move |l| (tab, l))
+                    .chain((1..count).flat_map(move |i| {
+                        lines((tab + count - i) % count).rev()
+                    }))
+                    .chain((line..len).rev().map(move |l| (tab, l))),
+            )
+        } else {
+            Box::new(
+                (line + 1..len)
+                    .map(move |l| (tab, l))
+                    .chain(
+                        (1..count).flat_map(move |i| lines((tab + i) % count)),
+                    )
+                    .chain((0..(line + 1).min(len)).map(move |l| (tab, l))),
+            )
+        };
+
+        order
+            .into_iter()
+            .find(|&(tab, line)| self.is_match(&tabs[tab][line]))
+    }
+}
@@ -0,0 +1,309 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+/// how a session is doing, as far as a viewer has heard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// ended and its command exited 0
+    Ok,
+    /// ended and its command exited with this
+    Failed(i32),
+    /// ended without saying how its command exited
+    Ended,
+    /// was running when the viewer lost the daemon
+    Disconnected,
+}
+
+impl State {
+    /// how a session ended, from its exit if it said
+    pub fn ended(exit: Option<i32>) -> Self {
+        match exit {
+            Some(0) => State::Ok,
+            Some(code) => State::Failed(code),
+            None => State::Ended,
+        }
+    }
+
+    pub fn icon(self) -> char {
+        match self {
+            State::Running => '▶',
+            State::Ok => '✓',
+            State::Failed(_) => '✗',
+            State::Ended => '■',
+            State::Disconnected => '?',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Running => "running",
+            State::Ok => "ok",
+            State::Failed(_) => "failed",
+            State::Ended => "ended",
+            State::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// what the sidebar knows of a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub name: String,
+    pub state: State,
+    pub tags: Vec<String>,
+    pub lines: usize,
+    /// the unix millis the daemon opened it at, or the newest time heard
+    /// when it was first heard of if the daemon didnt say
+    pub started: u64,
+    /// when it was last heard of, counted in events
+    pub active: u64,
+}
+
+impl Session {
+    pub fn new(name: &str, at: u64) -> Self {
+        Session {
+            name: name.to_string(),
+            state: State::Running,
+            tags: Vec::new(),
+            lines: 0,
+            started: at,
+            active: at,
+        }
+    }
+}
+
+/// what sessions are grouped under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    /// the start of the name, up to the first `_`, `-` or `.`
+    Prefix,
+    /// each of its tags, a session with two tags is under both
+    Tag,
+    State,
+}
+
+impl Group {
+    pub fn name(self) -> &'static str {
+        match self {
+            Group::Prefix => "prefix",
+            Group::Tag => "tag",
+            Group::State => "state",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Group::Prefix => Group::Tag,
+            Group::Tag => Group::State,
+            Group::State => Group::Prefix,
+        }
+    }
+
+    /// the groups a session goes under
+    fn of(self, session: &Session) -> Vec<String> {
+        match self {
+            Group::Prefix => vec![prefix(&session.name).to_string()],
+            Group::Tag if session.tags.is_empty() => vec!["untagged".into()],
+            Group::Tag => session.tags.clone(),
+            Group::State => vec![session.state.name().to_string()],
+        }
+    }
+}
+
+/// the order of the sessions in a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sort {
+    /// the last heard of first
+    Recent,
+    /// the first started first
+    Started,
+    Name,
+}
+
+impl Sort {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sort::Recent => "recent",
+            Sort::Started => "started",
+            Sort::Name => "name",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Sort::Recent => Sort::Started,
+            Sort::Started => Sort::Name,
+            Sort::Name => Sort::Recent,
+        }
+    }
+}
+
+/// a line of the sidebar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    Group {
+        name: String,
+        sessions: usize,
+        collapsed: bool,
+    },
+    /// a session, with the group it is shown under
+    Session { group: String, name: String },
+}
+
+impl Row {
+    /// if it is the same line of the sidebar, though its count or folding
+    /// may have changed
+    pub fn is(&self, other: &Row) -> bool {
+        match (self, other) {
+            (Row::Group { name, .. }, Row::Group { name: other, .. }) => {
+                name == other
+            }
+            (Row::Session { .. }, Row::Session { .. }) => self == other,
+            _ => false,
+        }
+    }
+}
+
+/// a list of the sessions grouped and sorted, with groups that can be
+/// collapsed
+#[derive(Debug, Clone)]
+pub struct Sidebar {
+    pub group: Group,
+    pub sort: Sort,
+    collapsed: HashSet<(&'static str, String)>,
+}
+
+impl Default for Sidebar {
+    fn default() -> Self {
+        Sidebar::new()
+    }
+}
+
+impl Sidebar {
+    pub fn new() -> Self {
+        Sidebar {
+            group: Group::Prefix,
+            sort: Sort::Recent,
+            collapsed: HashSet::new(),
+        }
+    }
+
+    /// collapsing is kept per way of grouping, so switching back finds the
+    /// groups as they were
+    pub fn is_collapsed(&self, group: &str) -> bool {
+        self.collapsed.contains(&self.key(group))
+    }
+
+    pub fn toggle(&mut self, group: &str) {
+        let key = self.key(group);
+
+        if !self.collapsed.remove(&key) {
+            self.collapsed.insert(key);
+        }
+    }
+
+    /// each group then its sessions, unless it is collapsed
+    pub fn rows(&self, sessions: &[Session]) -> Vec<Row> {
+        let mut groups: BTreeMap<String, Vec<&Session>> = BTreeMap::new();
+
+        for session in sessions {
+            for group in self.group.of(session) {
+                groups.entry(group).or_default().push(session);
+            }
+        }
+
+        let mut rows = Vec::new();
+
+        for (group, mut members) in groups {
+            match self.sort {
+                Sort::Recent => members.sort_by_key(|s| Reverse(s.active)),
+                Sort::Started => members.sort_by_key(|s| s.started),
+                Sort::Name => members.sort_by(|a, b| a.name.cmp(&b.name)),
+            }
+
+            let collapsed = self.is_collapsed(&group);
+
+            rows.push(Row::Group {
+                name: group.clone(),
+                sessions: members.len(),
+                collapsed,
+            });
+
+            if collapsed {
+                continue;
+            }
+
+            rows.extend(members.into_iter().map(|session| Row::Session {
+                group: group.clone(),
+                name: session.name.clone(),
+            }));
+        }
+
+        rows
+    }
+
+    fn key(&self, group: &str) -> (&'static str, String) {
+        (self.group.name(), group.to_string())
+    }
+}
+
+/// the start of a session name, up to the first `_`, `-` or `.`
+pub fn prefix(name: &str) -> &str {
+    match name.find(['_', '-', '.']) {
+        Some(0) | None => name,
+        Some(end) => &name[..end],
+    }
+}
+
+/// how well query matches name, none if its chars arent all in name in
+/// order. higher is better, runs of chars and matches at the start of
+/// words count for more
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let name = name.chars().collect::<Vec<char>>();
+    let mut score = 0;
+    let mut at = 0;
+    let mut last: Option<usize> = None;
+
+    for wanted in query.chars() {
+        let wanted = wanted.to_lowercase().next().unwrap_or(wanted);
+
+        let found = (at..name.len()).find(|&i| {
+            name[i].to_lowercase().next().unwrap_or(name[i]) == wanted
+        })?;
+
+        score += 1;
+
+        if last.is_some_and(|last| last + 1 == found) {
+            score += 5;
+        }
+
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+
+        score -= (found - at) as i64;
+        last = Some(found);
+        at = found + 1;
+    }
+
+    Some(score)
+}
+
+/// the session that best matches query, the first one on a tie
+pub fn fuzzy_jump<'a, I>(query: &str, names: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut best: Option<(i64, &str)> = None;
+
+    for name in names {
+        if let Some(score) = fuzzy_score(query, name) {
+            if best.is_none_or(|(top, _)| score > top) {
+                best = Some((score, name));
+            }
+        }
+    }
+
+    best.map(|(_, name)| name)
+}
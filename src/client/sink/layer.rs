@@ -0,0 +1,162 @@
+use std::fmt;
+use std::error::Error;
+use std::time::SystemTime;
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::{span, Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::client::producer::Producer;
+use crate::client::sink::{time_attr, Shipper};
+use crate::storage::record::Attrs;
+
+/// a tracing_subscriber::Layer that ships each event into a session
+///
+/// the message is the line. the level, target and time, the events other
+/// fields and the fields of the spans it is in go with it as attributes,
+/// span fields named `<span>.<field>` and the span names in `spans`
+///
+/// ```no_run
+/// use tracing_subscriber::prelude::*;
+///
+/// use spellhold::client::producer::Producer;
+/// use spellhold::client::sink::layer::SpellholdLayer;
+/// use spellhold::transport::Endpoint;
+///
+/// let producer = Producer::new(Endpoint::Unix("/tmp/spellholdd_socket".into()))
+///     .with_name("worker");
+///
+/// tracing_subscriber::registry()
+///     .with(SpellholdLayer::new(producer)?)
+///     .init();
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct SpellholdLayer {
+    shipper: Shipper,
+}
+
+impl SpellholdLayer {
+    /// ship to the session producer makes, holding up to 1024 events while
+    /// the daemon is slow or away
+    pub fn new(producer: Producer) -> Result<Self, Box<dyn Error>> {
+        SpellholdLayer::with_capacity(producer, 1024)
+    }
+
+    pub fn with_capacity(
+        producer: Producer,
+        capacity: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(SpellholdLayer {
+            shipper: Shipper::spawn(producer, capacity)?,
+        })
+    }
+
+    /// events dropped because the shipper was behind
+    pub fn dropped(&self) -> u64 {
+        self.shipper.dropped()
+    }
+}
+
+/// the fields of a span, kept in its extensions
+struct SpanFields(Fields);
+
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: Vec<(String, String)>,
+}
+
+impl Fields {
+    fn set(&mut self, name: &str, value: String) {
+        if name == "message" {
+            self.message = Some(value);
+            return;
+        }
+
+        match self.values.iter_mut().find(|(key, _)| key == name) {
+            Some(field) => field.1 = value,
+            None => self.values.push((name.to_string(), value)),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field.name(), format!("{:?}", value));
+    }
+}
+
+impl<S> Layer<S> for SpellholdLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &span::Id,
+        values: &span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+            {
+                values.record(&mut fields.0);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let meta = event.metadata();
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let mut attrs = Attrs::new();
+
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut names = Vec::new();
+
+            for span in scope.from_root() {
+                names.push(span.name());
+
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in &fields.0.values {
+                        let key = format!("{}.{}", span.name(), key);
+                        attrs.insert(key, value.clone());
+                    }
+                }
+            }
+
+            attrs.insert("spans".into(), names.join(":"));
+        }
+
+        // the events own fields win over its spans
+        for (key, value) in fields.values {
+            attrs.insert(key, value);
+        }
+
+        attrs.insert("level".into(), meta.level().to_string());
+        attrs.insert("target".into(), meta.target().to_string());
+        attrs.insert("time".into(), time_attr(SystemTime::now()));
+
+        self.shipper.ship(fields.message.unwrap_or_default(), attrs);
+    }
+}
@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::time::SystemTime;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::client::producer::Producer;
+use crate::client::sink::{time_attr, Shipper};
+use crate::storage::record::Attrs;
+
+/// a log::Log that ships each record into a session
+///
+/// the message is the line, the level, target, module and time go with it
+/// as attributes
+///
+/// ```no_run
+/// use spellhold::client::producer::Producer;
+/// use spellhold::client::sink::logger::SpellholdLogger;
+/// use spellhold::transport::Endpoint;
+///
+/// let producer = Producer::new(Endpoint::Unix("/tmp/spellholdd_socket".into()))
+///     .with_name("api");
+///
+/// SpellholdLogger::new(producer)?
+///     .with_level(log::LevelFilter::Info)
+///     .init()?;
+///
+/// log::info!(target: "api::db", "connected");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct SpellholdLogger {
+    shipper: Shipper,
+    level: LevelFilter,
+}
+
+impl SpellholdLogger {
+    /// ship to the session producer makes, holding up to 1024 records while
+    /// the daemon is slow or away
+    pub fn new(producer: Producer) -> Result<Self, Box<dyn Error>> {
+        SpellholdLogger::with_capacity(producer, 1024)
+    }
+
+    pub fn with_capacity(
+        producer: Producer,
+        capacity: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(SpellholdLogger {
+            shipper: Shipper::spawn(producer, capacity)?,
+            level: LevelFilter::Trace,
+        })
+    }
+
+    /// only ship records at least this important
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// records dropped because the shipper was behind
+    pub fn dropped(&self) -> u64 {
+        self.shipper.dropped()
+    }
+
+    /// make this the global logger
+    pub fn init(self) -> Result<(), Box<dyn Error>> {
+        let level = self.level;
+
+        log::set_boxed_logger(Box::new(self))
+            .map_err(|err| format!("cant set logger: {}", err))?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+}
+
+impl Log for SpellholdLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut attrs = Attrs::new();
+
+        attrs.insert("level".into(), record.level().to_string());
+        attrs.insert("target".into(), record.target().to_string());
+        attrs.insert("time".into(), time_attr(SystemTime::now()));
+
+        if let Some(module) = record.module_path() {
+            attrs.insert("module".into(), module.to_string());
+        }
+
+        self.shipper.ship(record.args().to_string(), attrs);
+    }
+
+    fn flush(&self) {
+        self.shipper.flush();
+    }
+}
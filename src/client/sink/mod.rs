@@ -0,0 +1,167 @@
+#[cfg(feature = "tracing")]
+pub mod layer;
+#[cfg(feature = "log")]
+pub mod logger;
+
+use std::thread;
+use std::error::Error;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::producer::Producer;
+use crate::storage::record::Attrs;
+
+/// how often the shipper thread tries to send what is spooled when nothing
+/// new comes in
+const IDLE_FLUSH: Duration = Duration::from_millis(500);
+
+/// how long closing waits for the thread to send the last lines, a daemon
+/// that stopped reading cant hold the program up past this
+const CLOSE_WAIT: Duration = Duration::from_secs(2);
+
+enum Msg {
+    Line(String, Attrs),
+    Flush,
+}
+
+/// sends lines to a session from a thread of its own
+///
+/// shipping never waits on the daemon, when the queue to the thread is full
+/// the line is dropped and counted instead
+pub struct Shipper {
+    sender: Option<SyncSender<Msg>>,
+    dropped: Arc<AtomicU64>,
+    thread: Option<thread::JoinHandle<()>>,
+    /// hangs up when the thread ends, the mutex only makes the shipper Sync
+    finished: Mutex<Receiver<()>>,
+}
+
+impl Shipper {
+    /// start the thread, it connects when it can and holds up to capacity
+    /// lines for it
+    pub fn spawn(
+        producer: Producer,
+        capacity: usize,
+    ) -> Result<Shipper, Box<dyn Error>> {
+        // the thread can only print what went wrong
+        producer.check()?;
+
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let (done, finished) = mpsc::channel::<()>();
+
+        let thread = thread::Builder::new()
+            .name("spellhold-shipper".to_string())
+            .spawn(move || {
+                let _done = done;
+
+                let mut handle = match producer.connect_lazily() {
+                    Ok(val) => val,
+                    Err(err) => {
+                        eprintln!("Shipper Error: {}", err);
+                        return;
+                    }
+                };
+
+                loop {
+                    match receiver.recv_timeout(IDLE_FLUSH) {
+                        Ok(Msg::Line(text, attrs)) => {
+                            let _ = handle.send_with(&text, &attrs);
+                        }
+                        Ok(Msg::Flush) | Err(RecvTimeoutError::Timeout) => {
+                            let _ = handle.flush();
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+
+                if let Err(err) = handle.close() {
+                    eprintln!("Shipper Error: {}", err);
+                }
+            })?;
+
+        Ok(Shipper {
+            sender: Some(sender),
+            dropped: Arc::new(AtomicU64::new(0)),
+            thread: Some(thread),
+            finished: Mutex::new(finished),
+        })
+    }
+
+    /// queue a line for the session, never blocks
+    pub fn ship(&self, text: String, attrs: Attrs) {
+        self.try_send(Msg::Line(text, attrs));
+    }
+
+    /// ask the thread to send what it has now
+    pub fn flush(&self) {
+        self.try_send(Msg::Flush);
+    }
+
+    /// lines dropped because the thread was behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// send everything queued and close the session, waiting a while for the
+    /// thread. one stuck on the daemon is left behind
+    pub fn close(mut self) {
+        self.stop();
+    }
+
+    fn try_send(&self, msg: Msg) {
+        let sender = match &self.sender {
+            Some(val) => val,
+            None => return,
+        };
+
+        match sender.try_send(msg) {
+            Ok(()) => {}
+            Err(TrySendError::Full(Msg::Line(..))) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn stop(&mut self) {
+        self.sender = None;
+
+        let thread = match self.thread.take() {
+            Some(val) => val,
+            None => return,
+        };
+
+        let finished = self
+            .finished
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner());
+
+        match finished.recv_timeout(CLOSE_WAIT) {
+            Err(RecvTimeoutError::Timeout) => {
+                eprintln!(
+                    "Shipper Error: the daemon isnt taking lines, gave up"
+                );
+            }
+            _ => {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for Shipper {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// unix millis as an attribute value
+pub fn time_attr(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis())
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+
+use crate::transport::Endpoint;
+use crate::protocol::{self, Handshake};
+
+/// ask the daemon for its queue depths and drop counts, a line per queue
+pub fn fetch_stats(
+    endpoint: &Endpoint,
+    token: Option<String>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    fetch_lines(endpoint, Handshake::Stats { token })
+}
+
+/// ask the daemon to run its retention rules now, a line per thing removed
+/// or that would be removed with dry_run
+pub fn fetch_gc(
+    endpoint: &Endpoint,
+    token: Option<String>,
+    dry_run: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    fetch_lines(endpoint, Handshake::Gc { token, dry_run })
+}
+
+/// ask the daemon to compress finished sessions now, a line per session
+pub fn fetch_compact(
+    endpoint: &Endpoint,
+    token: Option<String>,
+    session: Option<String>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    fetch_lines(endpoint, Handshake::Compact { token, session })
+}
+
+/// send a handshake and read every line the daemon sends back
+fn fetch_lines(
+    endpoint: &Endpoint,
+    handshake: Handshake,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut reader = BufReader::new(endpoint.connect()?);
+
+    reader.get_mut().write_all(handshake.to_line().as_bytes())?;
+    reader.get_mut().flush()?;
+
+    protocol::read_reply(&mut reader)?;
+
+    let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+
+    Ok(lines)
+}
@@ -1,67 +1,61 @@
-use std::iter;
 use std::error::Error;
-use std::path::PathBuf;
-use std::os::unix::net::UnixStream;
-use std::io::{stdin, BufRead, Write};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{stdin, BufRead};
 
-use rand::{Rng, thread_rng};
-use rand::distributions::Alphanumeric;
+use crate::client::producer::Producer;
+use crate::transport::Endpoint;
 
 pub struct StdinHandle {
     quite: bool,
-    socket: PathBuf,
+    endpoint: Endpoint,
+    token: Option<String>,
+    tags: Vec<String>,
 }
 
 impl StdinHandle {
-    pub fn new(socket: PathBuf, quite: bool) -> Self {
-        StdinHandle { socket, quite }
+    pub fn new(endpoint: Endpoint, quite: bool) -> Self {
+        StdinHandle {
+            endpoint,
+            quite,
+            token: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// the token to show the daemon, if it wants one
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// tags to keep with the session
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
     }
 
     pub fn run(&self, name: Option<String>) -> Result<(), Box<dyn Error>> {
-        let mut stream = UnixStream::connect(&self.socket)
-            .map_err(|err| format!("Error connecting to socket: {}", err))?;
+        let mut producer = Producer::new(self.endpoint.clone())
+            .with_token(self.token.clone())
+            .with_tags(self.tags.clone());
 
-        let id = make_id_string(name)?;
-        let conect_id = format!("connect -ID- {}\n", id);
-        let content_id = format!("{} -ENDID- ", id);
+        if let Some(name) = name {
+            producer = producer.with_name(name);
+        }
 
-        // make initial connection
-        stream.write_all(&conect_id.as_bytes())?;
+        let mut handle = producer.connect()?;
 
         for line in stdin().lock().lines() {
-            let mut line = line.unwrap();
-            line.insert_str(0, &content_id);
+            let line = line?;
 
-            // add the \n back in as lines will return without one
-            line += "\n";
-
-            stream.write_all(line.as_bytes())?;
+            handle.send(&line)?;
 
             if !self.quite {
                 println!("line: {}", line);
             }
         }
 
-        stream.write_all(b"end\n")?;
+        handle.close()?;
 
         Ok(())
     }
 }
-
-fn make_id_string(name: Option<String>) -> Result<String, Box<dyn Error>> {
-    let since_epoch = SystemTime::now()
-        .duration_since(UNIX_EPOCH)?
-        .as_secs()
-        .to_string();
-
-    let log_file = if name.is_some() {
-        name.unwrap()
-    } else {
-        iter::repeat_with(|| thread_rng().sample(Alphanumeric))
-            .take(10)
-            .collect::<String>()
-    };
-
-    Ok(format!("{}_{}", log_file, since_epoch))
-}
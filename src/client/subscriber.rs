@@ -0,0 +1,382 @@
+use std::error::Error;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+
+use crate::protocol::{self, Handshake, ViewerLine};
+use crate::storage::retention::glob_match;
+use crate::transport::{BoxConnection, Endpoint};
+
+/// something that happened to a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// a producer connected
+    Started {
+        session: String,
+    },
+    /// a session started, with the unix millis the daemon opened it at.
+    /// sessions start as these rather than Started when the subscriber
+    /// asked for times, running ones in the history too
+    Opened {
+        session: String,
+        at: u64,
+    },
+    /// the tags the producer gave its session, sent after it started or
+    /// with the history of a running session
+    Tagged {
+        session: String,
+        tags: Vec<String>,
+    },
+    Line {
+        session: String,
+        text: String,
+    },
+    /// a line with the unix millis the daemon got it at, lines come as
+    /// these rather than Line when the subscriber asked for times
+    Stamped {
+        session: String,
+        at: u64,
+        text: String,
+    },
+    /// lines were dropped before they reached this viewer
+    Gap {
+        session: String,
+        dropped: u64,
+    },
+    /// the producer is gone, with how its command exited if it said
+    Ended {
+        session: String,
+        exit: Option<i32>,
+    },
+    /// the connection to the daemon failed, nothing comes after this
+    Error(String),
+}
+
+impl Event {
+    /// the session it is about, none for an error
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            Event::Started { session }
+            | Event::Opened { session, .. }
+            | Event::Tagged { session, .. }
+            | Event::Line { session, .. }
+            | Event::Stamped { session, .. }
+            | Event::Gap { session, .. }
+            | Event::Ended { session, .. } => Some(session),
+            Event::Error(_) => None,
+        }
+    }
+}
+
+/// what to watch and how much history to start with, `connect` gives the
+/// events
+///
+/// ```no_run
+/// use spellhold::client::subscriber::{Event, Subscriber};
+/// use spellhold::transport::Endpoint;
+///
+/// let endpoint = Endpoint::Unix("/tmp/spellholdd_socket".into());
+/// let events = Subscriber::new(endpoint)
+///     .with_sessions(vec!["deploy_*"])
+///     .with_history(Some(100))
+///     .connect()?;
+///
+/// for event in events {
+///     if let Event::Ended { session, exit: Some(code) } = event {
+///         println!("{} exited with {}", session, code);
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Subscriber {
+    endpoint: Endpoint,
+    token: Option<String>,
+    history: Option<u64>,
+    sessions: Vec<String>,
+    times: bool,
+    finished: bool,
+}
+
+impl Subscriber {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Subscriber {
+            endpoint,
+            token: None,
+            history: None,
+            sessions: Vec::new(),
+            times: false,
+            finished: false,
+        }
+    }
+
+    /// the token to show the daemon, if it wants one
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// start with the last lines of each running session
+    pub fn with_history(mut self, lines: Option<u64>) -> Self {
+        self.history = lines;
+        self
+    }
+
+    /// with history, start with the last lines of each finished session too,
+    /// imported ones as well. they come oldest first, each one ended, before
+    /// the running ones
+    pub fn with_finished(mut self, finished: bool) -> Self {
+        self.finished = finished;
+        self
+    }
+
+    /// only sessions matching one of these globs, every session if empty
+    pub fn with_sessions<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sessions = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// get lines as Event::Stamped, with when the daemon got each one, to
+    /// put lines from several sessions in order
+    pub fn with_times(mut self, times: bool) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// the first line to send the daemon
+    pub fn handshake(&self) -> String {
+        Handshake::Client {
+            token: self.token.clone(),
+            history: self.history,
+            times: self.times,
+            finished: self.finished,
+        }
+        .to_line()
+    }
+
+    /// turns what the daemon sends into events for this subscriber
+    pub fn decoder(&self) -> Decoder {
+        Decoder {
+            sessions: self.sessions.clone(),
+            exits: HashMap::new(),
+        }
+    }
+
+    /// connect as a viewer, an error here is a refused token or no daemon
+    pub fn connect(self) -> Result<Subscription, Box<dyn Error>> {
+        let mut reader = BufReader::new(self.endpoint.connect()?);
+
+        reader.get_mut().write_all(self.handshake().as_bytes())?;
+        reader.get_mut().flush()?;
+
+        protocol::read_reply(&mut reader)?;
+
+        Ok(Subscription {
+            reader,
+            decoder: self.decoder(),
+            done: false,
+        })
+    }
+}
+
+/// the events from the daemons lines, filtered to the sessions wanted
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    sessions: Vec<String>,
+    /// the exit said for a session that hasnt ended yet
+    exits: HashMap<String, i32>,
+}
+
+impl Decoder {
+    /// the event for a line, none if it is filtered out, only fills in
+    /// another event or isnt understood
+    pub fn decode(&mut self, line: &str) -> Option<Event> {
+        let line = ViewerLine::parse(line)?;
+
+        let wanted = self.sessions.is_empty()
+            || self
+                .sessions
+                .iter()
+                .any(|pattern| glob_match(pattern, line.session()));
+
+        if !wanted {
+            return None;
+        }
+
+        match line {
+            ViewerLine::Line { session, text } => {
+                Some(Event::Line { session, text })
+            }
+            ViewerLine::Stamped { session, at, text } => {
+                Some(Event::Stamped { session, at, text })
+            }
+            ViewerLine::Gap { session, dropped } => {
+                Some(Event::Gap { session, dropped })
+            }
+            ViewerLine::Started(session) => Some(Event::Started { session }),
+            ViewerLine::Opened(session, at) => {
+                Some(Event::Opened { session, at })
+            }
+            ViewerLine::Tagged(session, tags) => {
+                Some(Event::Tagged { session, tags })
+            }
+            ViewerLine::Exited(session, code) => {
+                self.exits.insert(session, code);
+                None
+            }
+            ViewerLine::Ended(session) => {
+                let exit = self.exits.remove(&session);
+                Some(Event::Ended { session, exit })
+            }
+        }
+    }
+}
+
+/// the events from a connected subscriber, ending with the connection
+pub struct Subscription {
+    reader: BufReader<BoxConnection>,
+    decoder: Decoder,
+    done: bool,
+}
+
+impl Iterator for Subscription {
+    type Item = Event;
+
+    /// an error is the last event
+    fn next(&mut self) -> Option<Event> {
+        let mut line = String::new();
+
+        while !self.done {
+            line.clear();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => self.done = true,
+                Ok(_) => {
+                    if let Some(event) = self.decoder.decode(&line) {
+                        return Some(event);
+                    }
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Event::Error(err.to_string()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(feature = "async")]
+pub use self::asynchronous::AsyncSubscription;
+
+/// the subscriber for tokio
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::error::Error;
+    use std::future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures_core::Stream;
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::{Decoder, Event, Subscriber};
+    use crate::protocol;
+    use crate::transport::BoxAsyncConnection;
+
+    impl Subscriber {
+        /// connect without holding up the thread, like connect
+        pub async fn connect_async(
+            self,
+        ) -> Result<AsyncSubscription, Box<dyn Error + Send + Sync>> {
+            let mut reader =
+                BufReader::new(self.endpoint.connect_async().await?);
+
+            let stream = reader.get_mut();
+            stream.write_all(self.handshake().as_bytes()).await?;
+            stream.flush().await?;
+
+            let mut reply = String::new();
+            reader.read_line(&mut reply).await?;
+            protocol::parse_reply(&reply)?;
+
+            Ok(AsyncSubscription {
+                reader,
+                decoder: self.decoder(),
+                line: Vec::new(),
+                done: false,
+            })
+        }
+    }
+
+    /// the events from a subscriber connected with connect_async, as a
+    /// Stream or through next
+    pub struct AsyncSubscription {
+        reader: BufReader<BoxAsyncConnection>,
+        decoder: Decoder,
+        /// the start of a line still being read
+        line: Vec<u8>,
+        done: bool,
+    }
+
+    impl AsyncSubscription {
+        /// the next event, none once the connection is gone
+        pub async fn next(&mut self) -> Option<Event> {
+            future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+        }
+    }
+
+    impl Stream for AsyncSubscription {
+        type Item = Event;
+
+        /// an error is the last event
+        fn poll_next(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Event>> {
+            let this = self.get_mut();
+
+            while !this.done {
+                let read = match Pin::new(&mut this.reader).poll_fill_buf(cx) {
+                    Poll::Ready(Ok(val)) => val,
+                    Poll::Ready(Err(err)) => {
+                        this.done = true;
+                        return Poll::Ready(Some(Event::Error(
+                            err.to_string(),
+                        )));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                };
+
+                // the end of the connection ends the last line too
+                let (used, ended) = match read.iter().position(|b| *b == b'\n')
+                {
+                    Some(index) => (index + 1, true),
+                    None => (read.len(), read.is_empty()),
+                };
+
+                this.line.extend_from_slice(&read[..used]);
+                Pin::new(&mut this.reader).consume(used);
+
+                if used == 0 {
+                    this.done = true;
+                }
+
+                if ended && !this.line.is_empty() {
+                    let line = String::from_utf8_lossy(&this.line).into_owned();
+                    this.line.clear();
+
+                    if let Some(event) = this.decoder.decode(&line) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+            }
+
+            Poll::Ready(None)
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::error::Error;
+
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+use crate::config::ThemeConfig;
+
+/// the themes a config can start from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    /// the terminals own colours with cyan and yellow picked out, fine on
+    /// light and dark terminals
+    #[default]
+    Default,
+    /// black on white
+    Light,
+    /// white on black
+    Dark,
+    /// no colours at all, bold and reverse pick things out
+    None,
+}
+
+/// the styles the tui draws with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// the lines and everything behind them
+    pub base: Style,
+    /// tab names and the bar
+    pub accent: Style,
+    /// the current tab and the border of the focused pane
+    pub highlight: Style,
+    /// search matches
+    pub matched: Style,
+    /// the sidebar cursor
+    pub selected: Style,
+    /// if the colours programs print and the session colours are shown
+    pub colour: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::named(ThemeName::Default)
+    }
+}
+
+/// the colours a theme is made from
+struct Colours {
+    text: Color,
+    background: Color,
+    accent: Color,
+    highlight: Color,
+    matched: Color,
+    selected: Color,
+}
+
+impl Colours {
+    fn named(name: ThemeName) -> Option<Self> {
+        let colours = match name {
+            ThemeName::Default => Colours {
+                text: Color::Reset,
+                background: Color::Reset,
+                accent: Color::Cyan,
+                highlight: Color::Yellow,
+                matched: Color::Yellow,
+                selected: Color::Cyan,
+            },
+            ThemeName::Light => Colours {
+                text: Color::Black,
+                background: Color::White,
+                accent: Color::Blue,
+                highlight: Color::Magenta,
+                matched: Color::Yellow,
+                selected: Color::LightBlue,
+            },
+            ThemeName::Dark => Colours {
+                text: Color::White,
+                background: Color::Black,
+                accent: Color::Cyan,
+                highlight: Color::Yellow,
+                matched: Color::Yellow,
+                selected: Color::Cyan,
+            },
+            ThemeName::None => return None,
+        };
+
+        Some(colours)
+    }
+}
+
+impl Theme {
+    pub fn named(name: ThemeName) -> Self {
+        Colours::named(name).map_or_else(Theme::plain, Theme::from_colours)
+    }
+
+    /// a theme without colours, for NO_COLOR
+    pub fn plain() -> Self {
+        let base = Style::default();
+
+        Theme {
+            base,
+            accent: base,
+            highlight: base.modifier(Modifier::Bold),
+            matched: base.modifier(Modifier::Invert),
+            selected: base.modifier(Modifier::Invert),
+            colour: false,
+        }
+    }
+
+    fn from_colours(colours: Colours) -> Self {
+        let base = Style::default().fg(colours.text).bg(colours.background);
+
+        Theme {
+            base,
+            accent: base.fg(colours.accent),
+            highlight: base.fg(colours.highlight),
+            matched: base.fg(Color::Black).bg(colours.matched),
+            selected: base.fg(Color::Black).bg(colours.selected),
+            colour: true,
+        }
+    }
+
+    /// the named theme with the configs colours over it, or no colours
+    /// when no_color is set
+    pub fn from_config(
+        config: &ThemeConfig,
+        no_color: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut colours = match Colours::named(config.name) {
+            Some(colours) if !no_color => colours,
+            _ => return Ok(Theme::plain()),
+        };
+
+        let set = |colour: &mut Color, name: &Option<String>| {
+            if let Some(name) = name {
+                *colour = parse_colour(name)?;
+            }
+
+            Ok::<(), String>(())
+        };
+
+        set(&mut colours.text, &config.text)?;
+        set(&mut colours.background, &config.background)?;
+        set(&mut colours.accent, &config.accent)?;
+        set(&mut colours.highlight, &config.highlight)?;
+        set(&mut colours.matched, &config.matched)?;
+        set(&mut colours.selected, &config.selected)?;
+
+        Ok(Theme::from_colours(colours))
+    }
+}
+
+/// if NO_COLOR is set to anything, see no-color.org
+pub fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// a colour as the config writes it, a terminal colour name like
+/// `lightblue`, `reset` or `#rrggbb`
+pub fn parse_colour(name: &str) -> Result<Color, String> {
+    let bad = || format!("No colour called {:?}", name);
+
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(bad());
+        }
+
+        let part = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16);
+
+        return match (part(0), part(2), part(4)) {
+            (Ok(r), Ok(g), Ok(b)) => Ok(Color::Rgb(r, g, b)),
+            _ => Err(bad()),
+        };
+    }
+
+    let lower = name.to_lowercase().replace(['_', '-', ' '], "");
+
+    let colour = match lower.as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return Err(bad()),
+    };
+
+    Ok(colour)
+}
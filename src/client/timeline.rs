@@ -0,0 +1,126 @@
+use std::cmp;
+
+use crate::ansi;
+
+/// a line of a session with the unix millis the daemon got it at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub at: u64,
+    pub session: String,
+    pub text: String,
+}
+
+/// lines from several sessions put in the order the daemon got them, each
+/// with the time and a label in the sessions colour
+///
+/// only the times are kept, the lines go wherever the caller keeps them
+/// at the place insert gives
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    times: Vec<u64>,
+    /// the longest session name so far, labels are padded to it
+    width: usize,
+    colour: bool,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Timeline::default()
+    }
+
+    /// colour the labels with ansi escapes, off by default
+    pub fn with_colour(mut self, colour: bool) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    /// pad the labels to at least this, they grow as longer names come
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// where a line got at goes, after any got at the same time, and the
+    /// line as the timeline shows it
+    pub fn insert(&mut self, entry: &Entry) -> (usize, String) {
+        let at = self.times.partition_point(|time| *time <= entry.at);
+
+        self.times.insert(at, entry.at);
+        (at, self.line(entry))
+    }
+
+    /// a line as the timeline shows it, like `13:04:05.123 build | text`
+    ///
+    /// a line that moves the cursor back, like a progress bar, would draw
+    /// over the time and label so it goes in as it was left, without its
+    /// colours
+    pub fn line(&mut self, entry: &Entry) -> String {
+        self.width = cmp::max(self.width, entry.session.chars().count());
+
+        let drawn = ansi::plain(&entry.text);
+        let text = if drawn == ansi::strip(&entry.text) {
+            &entry.text
+        } else {
+            drawn.as_ref()
+        };
+
+        format!(
+            "{} {} | {}",
+            time_of_day(entry.at),
+            label(&entry.session, self.width, self.colour),
+            text
+        )
+    }
+}
+
+/// the colour a session is labelled in, the same each time for a name.
+/// one of the 16 ansi colours without black, white and the greys
+pub fn colour(session: &str) -> u8 {
+    const COLOURS: [u8; 12] = [1, 2, 3, 4, 5, 6, 9, 10, 11, 12, 13, 14];
+
+    // fnv-1a, so it doesnt change between runs or builds
+    let hash = session.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    COLOURS[(hash % COLOURS.len() as u64) as usize]
+}
+
+/// the session name padded to width, in its colour if colour is on
+pub fn label(session: &str, width: usize, colour: bool) -> String {
+    let padded = format!("{:<width$}", session, width = width);
+
+    if !colour {
+        return padded;
+    }
+
+    let index = self::colour(session);
+    let sgr = if index < 8 {
+        30 + index
+    } else {
+        90 + index - 8
+    };
+
+    format!("\x1b[{}m{}\x1b[0m", sgr, padded)
+}
+
+/// unix millis as the time of day in utc, like `13:04:05.123`
+pub fn time_of_day(at: u64) -> String {
+    let secs = at / 1000 % 86400;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        at % 1000
+    )
+}
@@ -1,8 +1,9 @@
+use std::cmp;
 use std::error::Error;
+use std::ops::Range;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::path::PathBuf;
-use std::collections::HashMap;
-use std::os::unix::net::UnixStream;
-use std::io::{self, BufRead, BufReader, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -14,10 +15,22 @@ use termion::screen::AlternateScreen;
 use tui::Terminal;
 use tui::style::{Color, Style, Modifier};
 use tui::backend::TermionBackend;
-use tui::layout::{Alignment, Constraint, Direction, Layout};
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::widgets::{Block, Borders, Tabs, Widget, Paragraph, Text};
 
-use crate::events::event::{Event, Events};
+use crate::ansi;
+use crate::terminal::Screen;
+use crate::transport::Endpoint;
+use crate::client::filter::{Filter, Filters};
+use crate::client::keymap::{self, Action, Keymap};
+use crate::client::pager::{Pager, Scroll};
+use crate::client::panes::{self, Panes, Side, Split};
+use crate::client::search::{self, Search};
+use crate::client::sidebar::{self, Row, Session, Sidebar, State};
+use crate::client::subscriber::{self, Subscriber};
+use crate::client::theme::Theme;
+use crate::client::timeline::{Entry, Timeline};
+use crate::events::event::{Config, Event, Events};
 
 use std::fmt::Display;
 
@@ -47,10 +60,21 @@ impl TuiErr {
     }
 }
 
-fn listener(socket: &PathBuf, app_state: &Arc<Mutex<AppState>>) {
-    let mut stream = UnixStream::connect(socket).unwrap();
+fn listener(
+    endpoint: &Endpoint,
+    token: Option<String>,
+    history: Option<u64>,
+    finished: bool,
+    app_state: &Arc<Mutex<AppState>>,
+) {
+    let events = Subscriber::new(endpoint.clone())
+        .with_token(token)
+        .with_history(history)
+        .with_finished(finished)
+        .with_times(true)
+        .connect();
 
-    match stream.write_all(b"client\n") {
+    let events = match events {
         Ok(val) => val,
         Err(err) => {
             let mut app_state = app_state.lock().unwrap();
@@ -59,40 +83,62 @@ fn listener(socket: &PathBuf, app_state: &Arc<Mutex<AppState>>) {
         }
     };
 
-    for line in BufReader::new(stream).lines() {
+    for event in events {
         let mut app_state = app_state.lock().unwrap();
         if app_state.end {
             break;
         }
 
-        let line = match line {
-            Ok(val) => val,
-            Err(err) => {
+        let stamp = app_state.stamp;
+
+        let (id, contents, at) = match event {
+            subscriber::Event::Started { session } => {
+                app_state.heard(&session).state = State::Running;
+                app_state.add_tab(&session);
+                continue;
+            }
+            subscriber::Event::Opened { session, at } => {
+                let heard = app_state.heard(&session);
+                heard.state = State::Running;
+                heard.started = at;
+                app_state.add_tab(&session);
+                continue;
+            }
+            subscriber::Event::Tagged { session, tags } => {
+                app_state.heard(&session).tags = tags;
+                app_state.add_tab(&session);
+                continue;
+            }
+            subscriber::Event::Line { session, text } => {
+                app_state.heard(&session);
+                (session, text, stamp)
+            }
+            subscriber::Event::Stamped { session, at, text } => {
+                app_state.heard(&session);
+                app_state.stamp = cmp::max(stamp, at);
+                (session, text, at)
+            }
+            subscriber::Event::Gap { session, dropped } => {
+                app_state.heard(&session);
+                (session, format!("-- {} lines dropped --", dropped), stamp)
+            }
+            subscriber::Event::Ended { session, exit } => {
+                let contents = match exit {
+                    Some(code) => format!("-- ended, exited with {} --", code),
+                    None => "-- ended --".to_string(),
+                };
+
+                app_state.heard(&session).state = State::ended(exit);
+                (session, contents, stamp)
+            }
+            subscriber::Event::Error(err) => {
                 app_state.update_from_err(TuiErr::new(&err));
                 return;
             }
         };
 
-        let split_vec = line.split(' ').collect::<Vec<&str>>();
-        let (id, contents) = (split_vec[0], split_vec[2]);
-        let mut contents = contents.to_string();
-        contents.push('\n');
-
-        if !app_state.tabs.contains(&id.to_string()) {
-            app_state.tabs.push(id.to_owned());
-        }
-
-        let current_vec = app_state
-            .data_map
-            .entry(String::from(id))
-            .or_insert_with(Vec::new);
-
-        current_vec.push(contents);
-
-        // initial receive go to first tab
-        if app_state.current.is_empty() {
-            app_state.current = id.to_owned();
-        }
+        app_state.add_tab(&id);
+        app_state.push_line(id, contents + "\n", at);
     }
 }
 
@@ -101,9 +147,124 @@ struct AppState {
     current: String,
     tabs: Vec<String>,
     data_map: HashMap<String, Vec<String>>,
+    /// where each tab is scrolled to
+    pagers: HashMap<String, Pager>,
+    /// the lines each tab is filtered down to
+    filters: HashMap<String, Filters>,
+    /// how many lines the text view had room for when it was last drawn
+    height: usize,
+    /// the search that is highlighted and stepped through with n and N
+    search: Option<Search>,
+    /// a search being typed
+    prompt: Option<Prompt>,
+    /// search every tab rather than only the current one
+    search_all: bool,
+    /// how escapes in the lines are shown
+    view: View,
+    /// tabs shown as the screen a terminal would have drawn, rather than
+    /// as a log of lines
+    screen_tabs: HashMap<String, bool>,
+    /// if a tab is shown as a screen before t is pressed on it
+    screen_default: bool,
+    /// the terminal each pane showing a screen plays its tab into, with
+    /// the tab and how many of its lines it has had
+    screens: HashMap<usize, (String, Screen, usize)>,
+    /// the last line a search jumped to, by tab
+    hit: Option<(String, usize)>,
+    /// what the sidebar shows of each session
+    sessions: HashMap<String, Session>,
+    /// how many events have been heard, the clock recent sessions are
+    /// sorted by
+    clock: u64,
+    sidebar: Sidebar,
+    sidebar_open: bool,
+    /// the sidebar has the keys
+    sidebar_focus: bool,
+    /// the sidebar row the cursor is on, kept as the row so it stays on it
+    /// as sessions come and go
+    selected: Option<Row>,
+    /// what has been typed in the sidebar to jump to a session
+    query: String,
+    /// the panes the text area is split into, the focused one shows the
+    /// current tab with the scroll and search above
+    panes: Panes,
+    /// what each pane without the focus is showing
+    views: HashMap<usize, PaneView>,
+    /// the session name a pane from a saved layout is waiting for
+    wanted: HashMap<usize, String>,
+    /// where the panes were last drawn, to find the pane on a side
+    area: Rect,
+    /// the file the layout is saved to and restored from
+    layout: Option<PathBuf>,
+    /// ctrl-w was pressed, the next key is for the panes
+    pane_prefix: bool,
+    /// said in the status bar until the next key
+    notice: Option<String>,
+    /// the unix millis the daemon got each line of a tab at
+    times: HashMap<String, Vec<u64>>,
+    /// the newest time heard from the daemon, markers for gaps and ends
+    /// are put at it
+    stamp: u64,
+    /// the sessions picked for the merged timeline, every one if none are
+    merge: HashSet<String>,
+    /// where lines go in the merged timeline tab, once it has been opened
+    timeline: Option<Timeline>,
+    theme: Theme,
+    /// the help is showing, scrolled down this many lines
+    help: Option<u16>,
     end: bool,
 }
 
+/// a pane without the focus, with its own tab, scroll and search
+#[derive(Clone, Default)]
+struct PaneView {
+    tab: String,
+    pager: Pager,
+    search: Option<Search>,
+    hit: Option<(String, usize)>,
+}
+
+/// how the colours and other escapes programs print are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// in colour, with \r and cursor moves along a line played out
+    Rendered,
+    /// as rendered but without the colours
+    Stripped,
+    /// every escape shown as it was sent, like `^[[31m`
+    Raw,
+}
+
+impl View {
+    fn next(self) -> Self {
+        match self {
+            View::Rendered => View::Stripped,
+            View::Stripped => View::Raw,
+            View::Raw => View::Rendered,
+        }
+    }
+}
+
+/// what is being typed at the bottom
+#[derive(Clone, Copy)]
+enum Asking {
+    Search(search::Direction),
+    Filter,
+}
+
+/// a search or filter being typed, a search jumps as it goes and esc puts
+/// the view back
+struct Prompt {
+    text: String,
+    asking: Asking,
+    /// why the text isnt a regex yet
+    error: Option<String>,
+    index: usize,
+    pagers: HashMap<String, Pager>,
+    hit: Option<(String, usize)>,
+    search: Option<Search>,
+}
+
 impl AppState {
     fn new() -> Self {
         AppState {
@@ -111,15 +272,933 @@ impl AppState {
             tabs: Vec::new(),
             current: String::new(),
             data_map: HashMap::new(),
+            pagers: HashMap::new(),
+            filters: HashMap::new(),
+            height: 0,
+            search: None,
+            prompt: None,
+            search_all: false,
+            view: View::Rendered,
+            screen_tabs: HashMap::new(),
+            screen_default: false,
+            screens: HashMap::new(),
+            hit: None,
+            sessions: HashMap::new(),
+            clock: 0,
+            sidebar: Sidebar::new(),
+            sidebar_open: false,
+            sidebar_focus: false,
+            selected: None,
+            query: String::new(),
+            panes: Panes::new(),
+            views: HashMap::new(),
+            wanted: HashMap::new(),
+            area: Rect::default(),
+            layout: None,
+            pane_prefix: false,
+            notice: None,
+            times: HashMap::new(),
+            stamp: 0,
+            merge: HashSet::new(),
+            timeline: None,
+            theme: Theme::default(),
+            help: None,
             end: false,
         }
     }
 
+    /// show the error, in a tab of its own when there are sessions to keep
     fn update_from_err(&mut self, err_obj: TuiErr) {
-        self.index = err_obj.index;
-        self.current = err_obj.current;
-        self.tabs = err_obj.tabs;
-        self.data_map = err_obj.data_map;
+        for session in self.sessions.values_mut() {
+            if session.state == State::Running {
+                session.state = State::Disconnected;
+            }
+        }
+
+        if self.tabs.is_empty() {
+            self.index = err_obj.index;
+            self.current = err_obj.current;
+            self.tabs = err_obj.tabs;
+            self.data_map = err_obj.data_map;
+            return;
+        }
+
+        self.data_map.extend(err_obj.data_map);
+        self.add_tab(&err_obj.current);
+        self.show(&err_obj.current);
+    }
+
+    /// the session an event was for, moved to the front of recent
+    fn heard(&mut self, id: &str) -> &mut Session {
+        self.clock += 1;
+
+        let clock = self.clock;
+        let stamp = self.stamp;
+        let session =
+            self.sessions.entry(id.to_string()).or_insert_with(|| {
+                let mut session = Session::new(id, clock);
+                session.started = stamp;
+                session
+            });
+
+        session.active = clock;
+        session
+    }
+
+    /// switch to the tab of a session
+    fn show(&mut self, id: &str) {
+        if let Some(index) = self.tabs.iter().position(|tab| tab == id) {
+            let _ = self.update_state(Some(index));
+        }
+    }
+
+    fn sidebar_rows(&self) -> Vec<Row> {
+        let sessions = self.sessions.values().cloned().collect::<Vec<_>>();
+
+        self.sidebar.rows(&sessions)
+    }
+
+    /// where the cursor is in rows, on the current session if it was on a
+    /// row that is gone
+    fn selected_index(&self, rows: &[Row]) -> usize {
+        let current = |row: &Row| matches!(row, Row::Session { name, .. } if *name == self.current);
+
+        self.selected
+            .as_ref()
+            .and_then(|selected| rows.iter().position(|row| row.is(selected)))
+            .or_else(|| rows.iter().position(current))
+            .unwrap_or(0)
+    }
+
+    /// a key while the sidebar has them, typing jumps to a session
+    fn sidebar_key(&mut self, key: Key) {
+        let rows = self.sidebar_rows();
+        let at = self.selected_index(&rows);
+        let row = rows.get(at).cloned();
+
+        let group = match &row {
+            Some(Row::Group { name, .. }) => Some(name.clone()),
+            Some(Row::Session { group, .. }) => Some(group.clone()),
+            None => None,
+        };
+
+        match key {
+            Key::Up => self.selected = rows.get(at.saturating_sub(1)).cloned(),
+            Key::Down => {
+                self.selected = rows.get(at + 1).cloned().or(row);
+            }
+            Key::Char('\n') => match row {
+                Some(Row::Group { name, .. }) => self.sidebar.toggle(&name),
+                Some(Row::Session { name, .. }) => {
+                    self.show(&name);
+                    self.sidebar_focus = false;
+                }
+                None => {}
+            },
+            Key::Left | Key::Right => {
+                let group = match group {
+                    Some(val) => val,
+                    None => return,
+                };
+
+                if self.sidebar.is_collapsed(&group) == (key == Key::Right) {
+                    self.sidebar.toggle(&group);
+                }
+
+                self.selected = self
+                    .sidebar_rows()
+                    .into_iter()
+                    .find(|row| matches!(row, Row::Group { name, .. } if *name == group));
+            }
+            Key::Ctrl('o') => self.sidebar.sort = self.sidebar.sort.next(),
+            Key::Ctrl('g') => {
+                self.sidebar.group = self.sidebar.group.next();
+                self.selected = None;
+            }
+            Key::Esc if !self.query.is_empty() => self.query.clear(),
+            Key::Esc | Key::Char('\t') => self.sidebar_focus = false,
+            Key::Backspace => {
+                self.query.pop();
+                self.jump_to_query();
+            }
+            Key::Char(c) => {
+                self.query.push(c);
+                self.jump_to_query();
+            }
+            _ => {}
+        }
+    }
+
+    /// show the session that best matches what was typed, opening its
+    /// group if it was collapsed
+    fn jump_to_query(&mut self) {
+        if self.query.is_empty() {
+            return;
+        }
+
+        let mut names = self.sessions.keys().collect::<Vec<&String>>();
+        names.sort();
+
+        let name = match sidebar::fuzzy_jump(
+            &self.query,
+            names.into_iter().map(String::as_str),
+        ) {
+            Some(val) => val.to_string(),
+            None => return,
+        };
+
+        let find = |rows: Vec<Row>| {
+            rows.into_iter().find(
+                |row| matches!(row, Row::Session { name: found, .. } if *found == name),
+            )
+        };
+
+        if find(self.sidebar_rows()).is_none() {
+            let session = self.sessions[&name].clone();
+            let rows = self.sidebar.rows(&[session]);
+
+            if let Some(Row::Group { name, .. }) = rows.first() {
+                self.sidebar.toggle(name);
+            }
+        }
+
+        self.selected = find(self.sidebar_rows());
+        self.show(&name);
+    }
+
+    /// a tab for the session if it has none, the first one is shown
+    fn add_tab(&mut self, id: &str) {
+        if !self.tabs.iter().any(|tab| tab == id) {
+            self.tabs.push(id.to_owned());
+            self.claim(id);
+        }
+
+        if self.current.is_empty() {
+            self.current = id.to_owned();
+        }
+    }
+
+    /// add a line the daemon got at, to its tab and the merged timeline
+    fn push_line(&mut self, id: String, line: String, at: u64) {
+        self.times.entry(id.clone()).or_default().push(at);
+
+        let lines = self.data_map.entry(id.clone()).or_default();
+        lines.push(line.clone());
+
+        if let Some(session) = self.sessions.get_mut(&id) {
+            session.lines = lines.len();
+        }
+
+        if let Some(filters) = self.filters.get_mut(&id) {
+            filters.update(lines);
+        }
+
+        let timeline = match &mut self.timeline {
+            Some(val) if self.merge.is_empty() || self.merge.contains(&id) => {
+                val
+            }
+            _ => return,
+        };
+
+        let entry = Entry {
+            at,
+            session: id,
+            text: line.strip_suffix('\n').unwrap_or(&line).to_string(),
+        };
+
+        let (index, line) = timeline.insert(&entry);
+        let merged = self.data_map.entry(MERGED.to_string()).or_default();
+        let between = index < merged.len();
+
+        merged.insert(index, line + "\n");
+
+        if let Some(filters) = self.filters.get_mut(MERGED) {
+            // a line from history can go before lines already filtered
+            if between {
+                filters.reset();
+            }
+
+            filters.update(merged);
+        }
+    }
+
+    /// put the current session in the merged timeline or take it out
+    fn pick_for_merge(&mut self) {
+        if self.current.is_empty() || self.current == MERGED {
+            return;
+        }
+
+        let picked = if self.merge.remove(&self.current) {
+            "taken out of"
+        } else {
+            self.merge.insert(self.current.clone());
+            "put in"
+        };
+
+        let sessions = match self.merge.len() {
+            0 => "every session".to_string(),
+            1 => "1 session".to_string(),
+            picked => format!("{} sessions", picked),
+        };
+
+        self.notice = Some(format!(
+            "{} {} the merged timeline, it has {}",
+            self.current, picked, sessions
+        ));
+
+        if self.timeline.is_some() {
+            self.build_timeline();
+        }
+    }
+
+    /// switch to the merged timeline, making it if it isnt open
+    fn show_merged(&mut self) {
+        if self.timeline.is_none() {
+            self.build_timeline();
+        }
+
+        self.add_tab(MERGED);
+        self.show(MERGED);
+    }
+
+    /// the picked sessions lines in the order the daemon got them
+    fn build_timeline(&mut self) {
+        let mut entries = Vec::new();
+
+        for tab in &self.tabs {
+            if !self.merge.is_empty() && !self.merge.contains(tab) {
+                continue;
+            }
+
+            let (lines, times) =
+                match (self.data_map.get(tab), self.times.get(tab)) {
+                    (Some(lines), Some(times)) => (lines, times),
+                    _ => continue,
+                };
+
+            entries.extend(lines.iter().zip(times).map(|(line, at)| Entry {
+                at: *at,
+                session: tab.clone(),
+                text: line.strip_suffix('\n').unwrap_or(line).to_string(),
+            }));
+        }
+
+        // stable, so lines got at the same time stay in tab order
+        entries.sort_by_key(|entry| entry.at);
+
+        let width = entries
+            .iter()
+            .map(|entry| entry.session.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut timeline = Timeline::new()
+            .with_colour(self.theme.colour)
+            .with_width(width);
+        let lines = entries
+            .iter()
+            .map(|entry| timeline.insert(entry).1 + "\n")
+            .collect::<Vec<String>>();
+
+        if let Some(filters) = self.filters.get_mut(MERGED) {
+            filters.reset();
+            filters.update(&lines);
+        }
+
+        self.data_map.insert(MERGED.to_string(), lines);
+        self.timeline = Some(timeline);
+    }
+
+    /// move the current tabs view
+    fn scroll(&mut self, scroll: Scroll) {
+        let total = self.lines(&self.current).len();
+        let height = self.height;
+
+        self.pagers
+            .entry(self.current.clone())
+            .or_default()
+            .scroll(scroll, total, height);
+    }
+
+    fn showing_screen(&self, tab: &str) -> bool {
+        self.screen_tabs
+            .get(tab)
+            .copied()
+            .unwrap_or(self.screen_default)
+    }
+
+    /// switch the current tab between its screen and its line log
+    fn toggle_screen(&mut self) {
+        let screen = !self.showing_screen(&self.current);
+        self.screen_tabs.insert(self.current.clone(), screen);
+    }
+
+    /// a tab played into a terminal the size of the pane, its lines before
+    /// the last screen are in the line log
+    fn screen_text(
+        &mut self,
+        pane: usize,
+        tab: &str,
+        search: Option<&Search>,
+        width: usize,
+        height: usize,
+        theme: &Theme,
+    ) -> Vec<Text<'static>> {
+        let size = (cmp::max(width, 1), cmp::max(height, 1));
+        let lines = self.data_map.get(tab).map_or(&[][..], Vec::as_slice);
+
+        let (shown, screen, fed) =
+            self.screens.entry(pane).or_insert_with(|| {
+                (
+                    tab.to_string(),
+                    Screen::new(width, height).with_scrollback(0),
+                    0,
+                )
+            });
+
+        // a new size means starting again, as a terminal would have wrapped
+        // the lines differently
+        if *shown != tab || screen.size() != size || *fed > lines.len() {
+            *shown = tab.to_string();
+            *screen = Screen::new(width, height).with_scrollback(0);
+            *fed = 0;
+        }
+
+        for line in &lines[*fed..] {
+            screen.feed_line(line.strip_suffix('\n').unwrap_or(line));
+        }
+
+        *fed = lines.len();
+
+        let mut text = Vec::new();
+
+        for row in screen.rows() {
+            let spans = row
+                .into_iter()
+                .map(|span| match self.view {
+                    View::Rendered => {
+                        (span.text, tui_style(span.style, theme.base))
+                    }
+                    _ => (span.text, theme.base),
+                })
+                .collect::<Vec<(String, Style)>>();
+
+            let ranges = match search {
+                Some(search) => {
+                    let plain = spans
+                        .iter()
+                        .map(|(text, _)| text.as_str())
+                        .collect::<String>();
+
+                    search.ranges(&plain)
+                }
+                None => Vec::new(),
+            };
+
+            text.extend(highlighted(spans, &ranges, theme.matched));
+            text.push(Text::raw("\n"));
+        }
+
+        text
+    }
+
+    /// the lines of a tab that are shown, once filtered
+    fn lines(&self, tab: &str) -> &[String] {
+        match self.filters.get(tab) {
+            Some(filters) if filters.active() => filters.shown(),
+            _ => self.data_map.get(tab).map_or(&[], Vec::as_slice),
+        }
+    }
+
+    /// change the current tabs filters, the lines they show are worked out
+    /// again and search hits on the tab are forgotten
+    fn filter<F>(&mut self, change: F)
+    where
+        F: FnOnce(&mut Filters),
+    {
+        let filters = self.filters.entry(self.current.clone()).or_default();
+        change(filters);
+
+        if let Some(lines) = self.data_map.get(&self.current) {
+            filters.update(lines);
+        }
+
+        if filters.filters().is_empty() {
+            self.filters.remove(&self.current);
+        }
+
+        if self.hit.as_ref().map(|(tab, _)| tab) == Some(&self.current) {
+            self.hit = None;
+        }
+    }
+
+    /// start typing a search or filter, from where the view is now
+    fn start_prompt(&mut self, asking: Asking) {
+        self.prompt = Some(Prompt {
+            text: String::new(),
+            asking,
+            error: None,
+            index: self.index,
+            pagers: self.pagers.clone(),
+            hit: self.hit.clone(),
+            search: self.search.clone(),
+        });
+    }
+
+    /// a key while a search is being typed, enter keeps it and esc drops it
+    fn prompt_key(&mut self, key: Key) {
+        let mut prompt = match self.prompt.take() {
+            Some(val) => val,
+            None => return,
+        };
+
+        let direction = match prompt.asking {
+            Asking::Search(direction) => direction,
+            Asking::Filter => return self.filter_key(prompt, key),
+        };
+
+        // go back to where the search started each time, it jumps from there
+        let _ = self.update_state(Some(prompt.index));
+        self.pagers = prompt.pagers.clone();
+        self.hit = prompt.hit.clone();
+
+        match key {
+            Key::Char('\n') if prompt.error.is_none() => {
+                if prompt.text.is_empty() {
+                    self.search = prompt.search;
+                } else {
+                    self.jump(false);
+                }
+
+                return;
+            }
+            Key::Esc | Key::Char('\n') => {
+                self.search = prompt.search;
+                return;
+            }
+            Key::Backspace => {
+                prompt.text.pop();
+            }
+            Key::Char(c) => prompt.text.push(c),
+            _ => {}
+        }
+
+        match Search::new(&prompt.text, direction) {
+            _ if prompt.text.is_empty() => {
+                self.search = None;
+                prompt.error = None;
+            }
+            Ok(search) => {
+                self.search = Some(search);
+                prompt.error = None;
+                self.jump(false);
+            }
+            Err(err) => {
+                self.search = None;
+                prompt.error = Some(err);
+            }
+        }
+
+        self.prompt = Some(prompt);
+    }
+
+    /// a key while a filter is being typed, enter adds it and enter on
+    /// nothing takes the tabs filters off
+    fn filter_key(&mut self, mut prompt: Prompt, key: Key) {
+        match key {
+            Key::Char('\n') if prompt.text.is_empty() => {
+                self.filter(Filters::clear);
+                return;
+            }
+            Key::Char('\n') => {
+                if let Ok(filter) = Filter::parse(&prompt.text) {
+                    self.filter(|filters| filters.push(filter));
+                }
+
+                return;
+            }
+            Key::Esc => return,
+            Key::Backspace => {
+                prompt.text.pop();
+            }
+            Key::Char(c) => prompt.text.push(c),
+            _ => {}
+        }
+
+        prompt.error = Filter::parse(&prompt.text).err();
+        self.prompt = Some(prompt);
+    }
+
+    /// go to the next line the search matches, in the current tab or the
+    /// next tab that has one
+    fn jump(&mut self, reverse: bool) {
+        let search = match &self.search {
+            Some(val) => val,
+            None => return,
+        };
+
+        let names = if self.search_all {
+            self.tabs.clone()
+        } else {
+            vec![self.current.clone()]
+        };
+
+        let tab = match names.iter().position(|name| *name == self.current) {
+            Some(val) => val,
+            None => return,
+        };
+
+        let line = match &self.hit {
+            Some((hit_tab, line)) if *hit_tab == self.current => *line,
+            _ => self
+                .pagers
+                .get(&self.current)
+                .cloned()
+                .unwrap_or_default()
+                .top(self.lines(&self.current).len(), self.height),
+        };
+
+        let tabs = names
+            .iter()
+            .map(|name| self.lines(name))
+            .collect::<Vec<&[String]>>();
+
+        let (tab, line) = match search.next_hit(&tabs, (tab, line), reverse) {
+            Some(val) => val,
+            None => return,
+        };
+
+        let name = names[tab].clone();
+
+        if let Some(index) = self.tabs.iter().position(|tab| *tab == name) {
+            let _ = self.update_state(Some(index));
+        }
+
+        self.scroll(Scroll::To(line));
+        self.hit = Some((name, line));
+    }
+
+    /// what the search is and how many lines it matches
+    fn search_status(&self) -> String {
+        let all = if self.search_all { " in all tabs" } else { "" };
+
+        if let Some(prompt) = &self.prompt {
+            let slash = match prompt.asking {
+                Asking::Search(direction) => slash(direction),
+                Asking::Filter => '&',
+            };
+
+            return match &prompt.error {
+                Some(_) if !prompt.text.is_empty() => {
+                    format!("{}{}  (not a regex yet)", slash, prompt.text)
+                }
+                _ => format!("{}{}{}", slash, prompt.text, all),
+            };
+        }
+
+        let search = match &self.search {
+            Some(val) => val,
+            None => return String::new(),
+        };
+
+        let matches: usize = if self.search_all {
+            self.tabs
+                .iter()
+                .map(|tab| search.count(self.lines(tab)))
+                .sum()
+        } else {
+            search.count(self.lines(&self.current))
+        };
+
+        format!(
+            "{}{}  {} matching lines{}",
+            slash(search.direction()),
+            search.pattern(),
+            matches,
+            all
+        )
+    }
+
+    /// what the focused pane shows, kept while another pane has the focus
+    fn focused_view(&self) -> PaneView {
+        PaneView {
+            tab: self.current.clone(),
+            pager: self.pagers.get(&self.current).cloned().unwrap_or_default(),
+            search: self.search.clone(),
+            hit: self.hit.clone(),
+        }
+    }
+
+    /// give a pane the focus, its tab becomes the current one
+    fn focus_pane(&mut self, id: usize) {
+        if id == self.panes.focus() {
+            return;
+        }
+
+        let view = self.focused_view();
+        self.views.insert(self.panes.focus(), view);
+        self.panes.focus_on(id);
+        self.load_view(id);
+    }
+
+    /// put what a pane was showing back as the current tab
+    fn load_view(&mut self, id: usize) {
+        let view = match self.views.remove(&id) {
+            Some(val) => val,
+            None => return,
+        };
+
+        let PaneView {
+            tab,
+            pager,
+            search,
+            hit,
+        } = view;
+
+        self.search = search;
+        self.hit = hit;
+
+        if let Some(index) = self.tabs.iter().position(|val| *val == tab) {
+            self.index = index;
+            self.current = tab.clone();
+            self.pagers.insert(tab, pager);
+        }
+    }
+
+    /// a key after ctrl-w, to split, close, move between and size panes
+    fn pane_key(&mut self, key: Key) {
+        let side = match key {
+            Key::Char('h') | Key::Left => Some(Side::Left),
+            Key::Char('l') | Key::Right => Some(Side::Right),
+            Key::Char('k') | Key::Up => Some(Side::Up),
+            Key::Char('j') | Key::Down => Some(Side::Down),
+            _ => None,
+        };
+
+        if let Some(side) = side {
+            if let Some(id) = self.panes.neighbour(self.area, side) {
+                self.focus_pane(id);
+            }
+
+            return;
+        }
+
+        match key {
+            Key::Char('v') => self.split(Split::Horizontal),
+            Key::Char('s') => self.split(Split::Vertical),
+            Key::Char('w') | Key::Ctrl('w') => {
+                self.focus_pane(self.panes.cycle(false))
+            }
+            Key::Char('W') => self.focus_pane(self.panes.cycle(true)),
+            Key::Char('c') | Key::Char('q') => {
+                if let Some(closed) = self.panes.close() {
+                    self.forget_pane(closed);
+                    self.load_view(self.panes.focus());
+                }
+            }
+            Key::Char('o') => {
+                for closed in self.panes.only() {
+                    self.forget_pane(closed);
+                }
+            }
+            Key::Char('>') => self.panes.resize(Split::Horizontal, 5),
+            Key::Char('<') => self.panes.resize(Split::Horizontal, -5),
+            Key::Char('+') => self.panes.resize(Split::Vertical, 5),
+            Key::Char('-') => self.panes.resize(Split::Vertical, -5),
+            Key::Char('=') => self.panes.even(),
+            Key::Char('S') => self.save_layout(),
+            Key::Char('L') => self.load_layout(),
+            _ => {}
+        }
+    }
+
+    /// split the focused pane, the new pane starts as a copy of it
+    fn split(&mut self, split: Split) {
+        let view = self.focused_view();
+        self.views.insert(self.panes.focus(), view);
+        self.panes.split(split);
+    }
+
+    fn forget_pane(&mut self, id: usize) {
+        self.views.remove(&id);
+        self.wanted.remove(&id);
+        self.screens.remove(&id);
+    }
+
+    /// the name of the session a pane shows, or is waiting for
+    fn pane_session(&self, id: usize) -> Option<String> {
+        if let Some(name) = self.wanted.get(&id) {
+            return Some(name.clone());
+        }
+
+        let tab = if id == self.panes.focus() {
+            &self.current
+        } else {
+            &self.views.get(&id)?.tab
+        };
+
+        if tab.is_empty() {
+            None
+        } else {
+            Some(panes::session_name(tab).to_string())
+        }
+    }
+
+    fn save_layout(&mut self) {
+        let path = match &self.layout {
+            Some(val) => val.clone(),
+            None => {
+                self.notice = Some(NO_LAYOUT.to_string());
+                return;
+            }
+        };
+
+        let layout = self.panes.layout(|id| self.pane_session(id));
+
+        self.notice = Some(match layout.save(&path) {
+            Ok(()) => format!("layout saved to {}", path.display()),
+            Err(err) => format!("cant save {}: {}", path.display(), err),
+        });
+    }
+
+    /// lay the panes out as saved, each one showing the newest session with
+    /// the name it had or waiting for one to start
+    fn load_layout(&mut self) {
+        let path = match &self.layout {
+            Some(val) => val.clone(),
+            None => {
+                self.notice = Some(NO_LAYOUT.to_string());
+                return;
+            }
+        };
+
+        let layout = match panes::Layout::load(&path) {
+            Ok(val) => val,
+            Err(err) => {
+                self.notice =
+                    Some(format!("cant load {}: {}", path.display(), err));
+                return;
+            }
+        };
+
+        let (restored, wanted) = Panes::restore(&layout);
+
+        self.panes = restored;
+        self.views.clear();
+        self.wanted.clear();
+        self.screens.clear();
+
+        for (id, session) in wanted {
+            if id != self.panes.focus() {
+                self.views.insert(id, PaneView::default());
+            }
+
+            if let Some(name) = session {
+                self.wanted.insert(id, name);
+            }
+        }
+
+        for tab in self.tabs.clone().iter().rev() {
+            self.claim(tab);
+        }
+
+        self.notice = Some(format!("layout loaded from {}", path.display()));
+    }
+
+    /// show a session in the panes waiting for its name
+    fn claim(&mut self, id: &str) {
+        let name = panes::session_name(id);
+        let waiting = self
+            .wanted
+            .iter()
+            .filter(|(_, wanted)| *wanted == name)
+            .map(|(pane, _)| *pane)
+            .collect::<Vec<usize>>();
+
+        for pane in waiting {
+            self.wanted.remove(&pane);
+
+            if pane != self.panes.focus() {
+                self.views.entry(pane).or_default().tab = id.to_string();
+            } else if let Some(index) = self.tabs.iter().position(|t| t == id) {
+                self.index = index;
+                self.current = id.to_string();
+            }
+        }
+    }
+
+    /// do what a key is bound to, quitting is left to the key loop
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Quit => {}
+            Action::Help => self.help = Some(0),
+            Action::Sidebar => {
+                self.sidebar_open = !self.sidebar_open;
+                self.sidebar_focus = self.sidebar_open;
+            }
+            Action::SidebarFocus => self.sidebar_focus = self.sidebar_open,
+            Action::NextTab => self.next(),
+            Action::PreviousTab => self.previous(),
+            Action::Panes => self.pane_prefix = true,
+            Action::SearchForward => {
+                self.start_prompt(Asking::Search(search::Direction::Forward))
+            }
+            Action::SearchBackward => {
+                self.start_prompt(Asking::Search(search::Direction::Backward))
+            }
+            Action::SearchAll => self.search_all = !self.search_all,
+            Action::NextMatch => self.jump(false),
+            Action::PreviousMatch => self.jump(true),
+            Action::ClearSearch => {
+                self.search = None;
+                self.hit = None;
+            }
+            Action::Filter => self.start_prompt(Asking::Filter),
+            Action::ToggleFilters => self.filter(Filters::toggle),
+            Action::DropFilter => self.filter(|filters| {
+                filters.pop();
+            }),
+            Action::View => self.view = self.view.next(),
+            Action::Screen => self.toggle_screen(),
+            Action::PickMerge => self.pick_for_merge(),
+            Action::ShowMerged => self.show_merged(),
+            Action::ScrollUp => self.scroll(Scroll::Up(1)),
+            Action::ScrollDown => self.scroll(Scroll::Down(1)),
+            Action::PageUp => self.scroll(Scroll::PageUp),
+            Action::PageDown => self.scroll(Scroll::PageDown),
+            Action::HalfUp => self.scroll(Scroll::HalfUp),
+            Action::HalfDown => self.scroll(Scroll::HalfDown),
+            Action::Top => self.scroll(Scroll::Top),
+            Action::Bottom => self.scroll(Scroll::Bottom),
+        }
+    }
+
+    /// the scroll keys move the help up and down, any other key shuts it
+    fn help_key(&mut self, action: Option<Action>, lines: usize) {
+        let at = match self.help {
+            Some(at) => at as usize,
+            None => return,
+        };
+
+        let page = cmp::max(self.height, 1);
+        let last = lines.saturating_sub(page);
+
+        let at = match action {
+            Some(Action::ScrollUp) => at.saturating_sub(1),
+            Some(Action::ScrollDown) => at + 1,
+            Some(Action::PageUp) | Some(Action::HalfUp) => {
+                at.saturating_sub(page)
+            }
+            Some(Action::PageDown) | Some(Action::HalfDown) => at + page,
+            Some(Action::Top) => 0,
+            Some(Action::Bottom) => last,
+            _ => {
+                self.help = None;
+                return;
+            }
+        };
+
+        self.help = Some(cmp::min(at, last) as u16);
     }
 
     fn update_state(
@@ -178,25 +1257,98 @@ impl AppState {
 }
 
 pub struct TuiApp {
-    socket_path: PathBuf,
+    endpoint: Endpoint,
+    token: Option<String>,
+    history: Option<u64>,
+    finished: bool,
+    keymap: Keymap,
     app: Arc<Mutex<AppState>>,
 }
 
 impl TuiApp {
-    pub fn new(socket_path: PathBuf) -> Self {
+    pub fn new(endpoint: Endpoint) -> Self {
         TuiApp {
-            socket_path,
+            endpoint,
+            token: None,
+            history: None,
+            finished: false,
+            keymap: Keymap::default(),
             app: Arc::new(Mutex::new(AppState::new())),
         }
     }
 
+    /// the token to show the daemon, if it wants one
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// start each running session with its last lines from the daemon
+    pub fn with_history(mut self, lines: Option<u64>) -> Self {
+        self.history = lines;
+        self
+    }
+
+    /// load the finished sessions too, imported ones as well, with the same
+    /// number of lines as the running ones. they come in ended
+    pub fn with_finished(mut self, finished: bool) -> Self {
+        self.finished = finished;
+        self
+    }
+
+    /// show every tab as the screen a terminal would have drawn, for
+    /// sessions captured from a pty. t still switches a tab to its lines
+    pub fn with_screens(self, screens: bool) -> Self {
+        self.app.lock().unwrap().screen_default = screens;
+        self
+    }
+
+    /// the file ctrl-w S saves the panes to, they are laid out from it on
+    /// start if it is there
+    pub fn with_layout(self, path: Option<PathBuf>) -> Self {
+        self.app.lock().unwrap().layout = path;
+        self
+    }
+
+    /// what the keys do, ? shows them
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// the colours to draw with. without colours the lines start with
+    /// theirs taken out too, v still puts them back
+    pub fn with_theme(self, theme: Theme) -> Self {
+        {
+            let mut app_state = self.app.lock().unwrap();
+            app_state.theme = theme;
+
+            if !theme.colour {
+                app_state.view = View::Stripped;
+            }
+        }
+
+        self
+    }
+
     pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        {
+            let mut app_state = self.app.lock().unwrap();
+
+            if app_state.layout.as_ref().is_some_and(|path| path.exists()) {
+                app_state.load_layout();
+            }
+        }
+
         // for the thread
-        let socket_path = self.socket_path.clone();
+        let endpoint = self.endpoint.clone();
+        let token = self.token.clone();
+        let history = self.history;
+        let finished = self.finished;
         let app_state = self.app.clone();
 
         thread::spawn(move || {
-            listener(&socket_path, &app_state);
+            listener(&endpoint, token, history, finished, &app_state);
         });
 
         if let Err(err) = self.tui_start() {
@@ -207,7 +1359,12 @@ impl TuiApp {
     }
 
     fn tui_start(&self) -> Result<(), Box<dyn Error>> {
-        let events = Events::new();
+        // q can be part of a search, so the input thread only stops on the
+        // exit key, which cant be typed
+        let events = Events::with_config(Config {
+            exit_key: self.keymap.exit(),
+            ..Config::default()
+        });
 
         let stdout = io::stdout().into_raw_mode()?;
         let stdout = MouseTerminal::from(stdout);
@@ -217,12 +1374,14 @@ impl TuiApp {
 
         terminal.hide_cursor()?;
 
-        let word_style = Style::default().fg(Color::Cyan);
-        let word_style_hl = Style::default().fg(Color::Yellow);
+        let theme = self.app.lock().unwrap().theme;
+        let help = self.help();
 
         let block = Block::default()
             .borders(Borders::ALL)
-            .title_style(Style::default().modifier(Modifier::Bold));
+            .style(theme.base)
+            .border_style(theme.base)
+            .title_style(theme.base.modifier(Modifier::Bold));
 
         loop {
             let size = terminal.size()?;
@@ -234,49 +1393,246 @@ impl TuiApp {
                     .direction(Direction::Vertical)
                     .margin(0)
                     .constraints(
-                        [Constraint::Length(3), Constraint::Min(0)].as_ref(),
+                        [
+                            Constraint::Length(3),
+                            Constraint::Min(0),
+                            Constraint::Length(1),
+                        ]
+                        .as_ref(),
                     )
                     .split(size);
 
-                Block::default()
-                    .style(Style::default().bg(Color::White))
-                    .render(&mut f, size);
+                Block::default().style(theme.base).render(&mut f, size);
 
                 let (tabs, index): (Vec<String>, usize) = self.get_tab_info();
 
                 Tabs::default()
-                    .block(Block::default().borders(Borders::ALL).title("Tabs"))
+                    .block(block.title("Tabs"))
                     .titles(tabs.as_ref())
                     .select(index)
-                    .style(word_style)
-                    .highlight_style(word_style_hl)
+                    .style(theme.accent)
+                    .highlight_style(theme.highlight)
                     .render(&mut f, chunks[0]);
 
-                let text = self.get_text_widgets();
+                let open = self.app.lock().unwrap().sidebar_open;
+
+                let main = if open {
+                    let parts = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Length(32), Constraint::Min(0)]
+                                .as_ref(),
+                        )
+                        .split(chunks[1]);
+
+                    let height = block.inner(parts[0]).height as usize;
+                    let (rows, title) = self.get_sidebar(height, &theme);
+
+                    Paragraph::new(rows.iter())
+                        .block(block.title(&title))
+                        .style(theme.base)
+                        .render(&mut f, parts[0]);
+
+                    parts[1]
+                } else {
+                    chunks[1]
+                };
+
+                let (rects, focus, help_at) = {
+                    let mut app_state = self.app.lock().unwrap();
+                    app_state.area = main;
+
+                    (
+                        app_state.panes.rects(main),
+                        app_state.panes.focus(),
+                        app_state.help,
+                    )
+                };
+
+                let mut status = String::new();
+
+                // the help goes over the panes until a key shuts it
+                if let Some(at) = help_at {
+                    let inner = block.inner(main);
+                    self.app.lock().unwrap().height = inner.height as usize;
 
-                Paragraph::new(text.iter())
-                    .block(block.title("stdin"))
-                    .alignment(Alignment::Left)
-                    .render(&mut f, chunks[1]);
+                    let text = help
+                        .iter()
+                        .map(|line| Text::raw(format!("{}\n", line)))
+                        .collect::<Vec<Text>>();
+
+                    Paragraph::new(text.iter())
+                        .block(block.title(HELP_TITLE))
+                        .style(theme.base)
+                        .scroll(at)
+                        .render(&mut f, main);
+                }
+
+                for (pane, rect) in rects.iter().filter(|_| help_at.is_none()) {
+                    let inner = block.inner(*rect);
+                    let (text, title, pane_status) = self.get_text_widgets(
+                        *pane,
+                        inner.width as usize,
+                        inner.height as usize,
+                        &theme,
+                    );
+
+                    let mut border = theme.base;
+
+                    // the bar is about the focused pane, marked out when
+                    // there are others
+                    if *pane == focus {
+                        status = pane_status;
+
+                        if rects.len() > 1 {
+                            border = theme.highlight;
+                        }
+                    }
+
+                    Paragraph::new(text.iter())
+                        .block(block.title(&title).border_style(border))
+                        .style(theme.base)
+                        .alignment(Alignment::Left)
+                        .render(&mut f, *rect);
+                }
+
+                Paragraph::new(
+                    [Text::styled(self.get_status(), theme.accent)].iter(),
+                )
+                .style(theme.base)
+                .alignment(Alignment::Left)
+                .render(&mut f, chunks[2]);
+
+                Paragraph::new([Text::styled(status, theme.accent)].iter())
+                    .alignment(Alignment::Right)
+                    .render(&mut f, chunks[2]);
             })?;
 
-            match events.next()? {
-                Event::Input(input) => match input {
-                    Key::Char('q') => {
-                        let mut app_state = self.app.lock().unwrap();
+            if let Event::Input(input) = events.next()? {
+                let mut app_state = self.app.lock().unwrap();
+
+                let typing = app_state.prompt.is_some();
+                app_state.notice = None;
+
+                match input {
+                    key if key == self.keymap.exit() => {
                         app_state.end = true;
                         break;
                     }
-                    Key::Right => self.next(),
-                    Key::Left => self.previous(),
-                    _ => {}
-                },
-                Event::Tick => {}
-            };
+                    key if app_state.help.is_some() => {
+                        app_state.help_key(self.keymap.action(key), help.len())
+                    }
+                    key if typing => app_state.prompt_key(key),
+                    key if app_state.pane_prefix => {
+                        app_state.pane_prefix = false;
+                        app_state.pane_key(key)
+                    }
+                    key if app_state.sidebar_focus => {
+                        app_state.sidebar_key(key)
+                    }
+                    key => match self.keymap.action(key) {
+                        Some(Action::Quit) => {
+                            app_state.end = true;
+                            break;
+                        }
+                        Some(action) => app_state.act(action),
+                        None => {}
+                    },
+                }
+            }
         }
         Ok(())
     }
 
+    /// the keys of the keymap, then the ones after the pane key and the
+    /// ones in the sidebar which cant be changed
+    fn help(&self) -> Vec<String> {
+        let mut lines = self.keymap.help();
+
+        lines.push(String::new());
+
+        if let Some(key) = self.keymap.keys(Action::Panes).first() {
+            lines.push(format!(
+                "after {}: {}",
+                keymap::key_name(*key),
+                PANE_KEYS
+            ));
+        }
+
+        lines.push(format!("in the sidebar: {}", SIDEBAR_KEYS));
+
+        lines
+    }
+
+    /// the sidebar rows that fit in height around the cursor, and its title
+    fn get_sidebar(
+        &self,
+        height: usize,
+        theme: &Theme,
+    ) -> (Vec<Text<'_>>, String) {
+        let app_state = self.app.lock().unwrap();
+        let rows = app_state.sidebar_rows();
+        let at = app_state.selected_index(&rows);
+        let start = (at + 1).saturating_sub(height);
+
+        let mut text = Vec::new();
+
+        for (index, row) in rows.iter().enumerate().skip(start).take(height) {
+            let line = match row {
+                Row::Group {
+                    name,
+                    sessions,
+                    collapsed,
+                } => {
+                    let arrow = if *collapsed { '▸' } else { '▾' };
+                    format!("{} {} ({})", arrow, name, sessions)
+                }
+                Row::Session { name, .. } => {
+                    let session = &app_state.sessions[name];
+                    let exit = match session.state {
+                        State::Failed(code) => format!(", exit {}", code),
+                        _ => String::new(),
+                    };
+
+                    format!(
+                        "  {} {} {}{}",
+                        session.state.icon(),
+                        name,
+                        session.lines,
+                        exit
+                    )
+                }
+            };
+
+            let current = matches!(
+                row,
+                Row::Session { name, .. } if *name == app_state.current
+            );
+
+            let style = if index == at && app_state.sidebar_focus {
+                theme.selected
+            } else if current {
+                theme.base.modifier(Modifier::Bold)
+            } else {
+                theme.base
+            };
+
+            text.push(Text::styled(line + "\n", style));
+        }
+
+        let title = if app_state.query.is_empty() {
+            format!(
+                "by {}, {}",
+                app_state.sidebar.group.name(),
+                app_state.sidebar.sort.name()
+            )
+        } else {
+            format!("jump: {}", app_state.query)
+        };
+
+        (text, title)
+    }
+
     fn get_tab_info(&self) -> (Vec<String>, usize) {
         let app_state = self.app.lock().unwrap();
 
@@ -289,31 +1645,247 @@ impl TuiApp {
         (tabs, index)
     }
 
-    fn get_text_widgets(&self) -> Vec<Text> {
+    /// what goes on the left of the bar, the search, filters and view of
+    /// the current tab or the keys that can be pressed
+    fn get_status(&self) -> String {
+        let app_state = self.app.lock().unwrap();
+
+        let current = app_state.current.to_owned();
+        let filter_status = app_state.filters.get(&current).map(|filters| {
+            filters.status(app_state.data_map.get(&current).map_or(0, Vec::len))
+        });
+
+        let view = match app_state.view {
+            View::Rendered => None,
+            View::Stripped => Some("stripped".to_string()),
+            View::Raw => Some("raw".to_string()),
+        };
+
+        if let Some(notice) = &app_state.notice {
+            return notice.clone();
+        }
+
+        match app_state.prompt {
+            Some(_) => app_state.search_status(),
+            None if app_state.pane_prefix => PANE_KEYS.to_string(),
+            None if app_state.sidebar_focus => SIDEBAR_KEYS.to_string(),
+            None => view
+                .into_iter()
+                .chain(filter_status)
+                .chain(Some(app_state.search_status()))
+                .filter(|status| !status.is_empty())
+                .collect::<Vec<String>>()
+                .join("  "),
+        }
+    }
+
+    /// the lines of a panes tab that fit in height with the search matches
+    /// picked out, its title and where its view is
+    fn get_text_widgets(
+        &self,
+        pane: usize,
+        width: usize,
+        height: usize,
+        theme: &Theme,
+    ) -> (Vec<Text<'_>>, String, String) {
         let mut app_state = self.app.lock().unwrap();
-        let current_key = app_state.current.to_owned();
-        let current_vec = app_state.data_map.get_mut(&current_key);
 
-        let mut none = vec!["None".to_string()];
+        let (current, pager, search) = if pane == app_state.panes.focus() {
+            app_state.height = height;
 
-        let text = if current_vec.is_some() {
-            current_vec.unwrap()
+            let view = app_state.focused_view();
+            (view.tab, view.pager, view.search)
         } else {
-            &mut none
+            let view = app_state.views.get(&pane).cloned().unwrap_or_default();
+            (view.tab, view.pager, view.search)
         };
 
-        text.iter()
-            .map(|val| Text::raw(val.to_string()))
-            .collect::<Vec<Text>>()
+        let title = match app_state.wanted.get(&pane) {
+            _ if app_state.panes.ids().len() == 1 => "stdin".to_string(),
+            Some(name) => format!("waiting for {}", name),
+            None if current.is_empty() => "None".to_string(),
+            None => current.clone(),
+        };
+
+        if !app_state.data_map.contains_key(&current) {
+            return (
+                vec![Text::styled("None", theme.base)],
+                title,
+                String::new(),
+            );
+        }
+
+        if app_state.showing_screen(&current) {
+            let text = app_state.screen_text(
+                pane,
+                &current,
+                search.as_ref(),
+                width,
+                height,
+                theme,
+            );
+            let status = format!("screen {} by {}", width, height);
+
+            return (text, title, status);
+        }
+
+        let lines = app_state.lines(&current);
+
+        let mut text = Vec::new();
+        let mut style = ansi::Style::default();
+
+        for line in &lines[pager.visible(lines.len(), height)] {
+            let line = line.strip_suffix('\n').unwrap_or(line);
+
+            // colours carry on from line to line, from the top of the view
+            let spans = match app_state.view {
+                View::Raw => vec![(ansi::escape(line), theme.base)],
+                view => {
+                    let (spans, next) = ansi::draw(line, style);
+                    style = next;
+
+                    spans
+                        .into_iter()
+                        .map(|span| match view {
+                            View::Rendered => {
+                                (span.text, tui_style(span.style, theme.base))
+                            }
+                            _ => (span.text, theme.base),
+                        })
+                        .collect()
+                }
+            };
+
+            let ranges = match &search {
+                Some(search) => {
+                    let plain = spans
+                        .iter()
+                        .map(|(text, _)| text.as_str())
+                        .collect::<String>();
+
+                    search.ranges(&plain)
+                }
+                None => Vec::new(),
+            };
+
+            text.extend(highlighted(spans, &ranges, theme.matched));
+            text.push(Text::raw("\n"));
+        }
+
+        (text, title, pager.status(lines.len(), height))
     }
+}
 
-    fn next(&self) {
-        let mut app_state = self.app.lock().unwrap();
-        app_state.next();
+/// spans of a line cut up where the search matches start and end, with
+/// the matches in the highlight style
+fn highlighted(
+    spans: Vec<(String, Style)>,
+    ranges: &[Range<usize>],
+    highlight: Style,
+) -> Vec<Text<'static>> {
+    let mut text = Vec::new();
+    let mut offset = 0;
+
+    for (span, style) in spans {
+        let end = offset + span.len();
+        let mut start = offset;
+
+        for range in ranges {
+            if range.end <= start || range.start >= end {
+                continue;
+            }
+
+            let from = cmp::max(range.start, start);
+            let to = cmp::min(range.end, end);
+
+            if from > start {
+                let part = &span[start - offset..from - offset];
+                text.push(Text::styled(part.to_string(), style));
+            }
+
+            let part = &span[from - offset..to - offset];
+            text.push(Text::styled(part.to_string(), highlight));
+            start = to;
+        }
+
+        if start < end {
+            text.push(Text::styled(span[start - offset..].to_string(), style));
+        }
+
+        offset = end;
     }
 
-    fn previous(&self) {
-        let mut app_state = self.app.lock().unwrap();
-        app_state.previous();
+    text
+}
+
+/// an ansi style as near as tui can show it, which is one modifier. what
+/// it doesnt colour is left in the base colours
+fn tui_style(style: ansi::Style, base: Style) -> Style {
+    let color = |color: Option<ansi::Color>, unset: Color| match color {
+        None => unset,
+        Some(ansi::Color::Indexed(index)) if index < 16 => [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::Gray,
+            Color::DarkGray,
+            Color::LightRed,
+            Color::LightGreen,
+            Color::LightYellow,
+            Color::LightBlue,
+            Color::LightMagenta,
+            Color::LightCyan,
+            Color::White,
+        ][index as usize],
+        Some(color) => {
+            let (r, g, b) = color.to_rgb();
+            Color::Rgb(r, g, b)
+        }
+    };
+
+    let (mut fg, mut bg) = (color(style.fg, base.fg), color(style.bg, base.bg));
+
+    if style.inverse {
+        fg = color(style.bg.or(Some(ansi::Color::Indexed(0))), Color::Reset);
+        bg = color(style.fg.or(Some(ansi::Color::Indexed(7))), Color::Reset);
+    }
+
+    let modifier = if style.bold {
+        Modifier::Bold
+    } else if style.underline {
+        Modifier::Underline
+    } else if style.italic {
+        Modifier::Italic
+    } else if style.dim {
+        Modifier::Faint
+    } else {
+        Modifier::Reset
+    };
+
+    Style::default().fg(fg).bg(bg).modifier(modifier)
+}
+
+/// the tab of the merged timeline, no session has a space in its name
+const MERGED: &str = "merged timeline";
+
+const PANE_KEYS: &str = "v and s split, hjkl move, w next, c closes, \
+o only, <>+- size, = even, S saves, L loads";
+
+const HELP_TITLE: &str = "keys, the scroll keys move and any other shuts this";
+
+const NO_LAYOUT: &str = "no layout file, start the tui with --layout FILE";
+
+const SIDEBAR_KEYS: &str = "type to jump, enter opens, left/right fold, \
+ctrl-o sorts, ctrl-g groups, tab goes back";
+
+/// the mark a search in that direction is shown with, like less
+fn slash(direction: search::Direction) -> char {
+    match direction {
+        search::Direction::Forward => '/',
+        search::Direction::Backward => '?',
     }
 }
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::client::keymap::Preset;
+use crate::client::theme::ThemeName;
+use crate::daemon::queue::Policy;
+use crate::storage::compress::Compression;
+
+/// where the daemon writes logs when the config doesnt say
+pub const DEFAULT_LOG_ROOT: &str = "/home/chris/proj/spellhold/log_files";
+
+/// the daemons config file, toml
+///
+/// ```toml
+/// log_root = "/var/log/spellhold"
+///
+/// [[tokens]]
+/// name = "build-agents"
+/// secret = "change me"
+/// role = "producer"
+/// prefix = "build_"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    #[serde(default = "default_log_root")]
+    pub log_root: PathBuf,
+    /// when empty anyone who can reach the daemon can use it
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    #[serde(default)]
+    pub queues: QueueConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// the first rule whose pattern matches a session applies to it
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+}
+
+impl DaemonConfig {
+    pub fn load(path: &Path) -> Result<DaemonConfig, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            format!("Error reading config {}: {}", path.display(), err)
+        })?;
+
+        toml::from_str(&text).map_err(|err| {
+            Box::from(format!("Bad config {}: {}", path.display(), err))
+        })
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            log_root: default_log_root(),
+            tokens: Vec::new(),
+            queues: QueueConfig::default(),
+            storage: StorageConfig::default(),
+            retention: Vec::new(),
+        }
+    }
+}
+
+fn default_log_root() -> PathBuf {
+    PathBuf::from(DEFAULT_LOG_ROOT)
+}
+
+/// the sizes of the queues between producers, the main loop and viewers,
+/// and how long a connection has to get into one
+///
+/// ```toml
+/// [queues]
+/// producer_capacity = 1024
+/// producer_policy = "block"
+/// viewer_capacity = 1024
+/// viewer_policy = "drop_oldest"
+/// handshake_timeout_ms = 10000
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    /// lines from all producers waiting on the main loop and the disk
+    pub producer_capacity: usize,
+    pub producer_policy: Policy,
+    /// lines waiting to be written to each viewer
+    pub viewer_capacity: usize,
+    pub viewer_policy: Policy,
+    /// a new connection that hasnt finished tls and sent its first line by
+    /// then is dropped
+    pub handshake_timeout_ms: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            producer_capacity: 1024,
+            producer_policy: Policy::Block,
+            viewer_capacity: 1024,
+            viewer_policy: Policy::DropOldest,
+            handshake_timeout_ms: 10_000,
+        }
+    }
+}
+
+/// how session files are laid out and how much room they get
+///
+/// ```toml
+/// [storage]
+/// segment_bytes = 16777216
+/// max_total_bytes = 10737418240
+/// gc_interval_secs = 300
+/// compression = "zstd"
+/// compress_after_secs = 3600
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// a session moves on to a new segment once this size is reached
+    pub segment_bytes: u64,
+    /// the oldest finished sessions go once the log root is bigger than this
+    pub max_total_bytes: Option<u64>,
+    /// how often the daemon applies the retention rules
+    pub gc_interval_secs: u64,
+    /// what finished sessions are compressed with
+    pub compression: Compression,
+    /// how long a finished session sits untouched before it is compressed
+    pub compress_after_secs: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            segment_bytes: 16 * 1024 * 1024,
+            max_total_bytes: None,
+            gc_interval_secs: 300,
+            compression: Compression::Zstd,
+            compress_after_secs: 60 * 60,
+        }
+    }
+}
+
+/// limits for the sessions whose name matches pattern
+///
+/// ```toml
+/// [[retention]]
+/// pattern = "nightly_*"
+/// max_age_days = 90
+///
+/// [[retention]]
+/// pattern = "*"
+/// max_age_days = 7
+/// max_session_bytes = 104857600
+/// max_sessions = 500
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionRule {
+    /// a glob, `*` is any run of characters and `?` is one
+    pub pattern: String,
+    pub max_age_days: Option<u64>,
+    /// the oldest segments of a session go past this
+    pub max_session_bytes: Option<u64>,
+    /// only keep the newest this many matching sessions
+    pub max_sessions: Option<usize>,
+}
+
+/// what a token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Producer,
+    Viewer,
+    /// both a producer and a viewer
+    Admin,
+}
+
+impl Role {
+    pub fn allows(self, wanted: Role) -> bool {
+        self == Role::Admin || self == wanted
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+    /// a name for the token, used in the daemons messages
+    pub name: String,
+    /// what the client sends
+    pub secret: String,
+    pub role: Role,
+    /// only sessions starting with this
+    pub prefix: Option<String>,
+}
+
+/// the tuis config file, toml, given with `spellcli tui --config`
+///
+/// ```toml
+/// [keymap]
+/// preset = "vim"
+/// exit = "ctrl-c"
+///
+/// [keymap.bind]
+/// search_all = ["A"]
+/// help = ["f1", "ctrl-h"]
+///
+/// [theme]
+/// name = "dark"
+/// highlight = "lightmagenta"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    pub keymap: KeymapConfig,
+    pub theme: ThemeConfig,
+}
+
+impl TuiConfig {
+    pub fn load(path: &Path) -> Result<TuiConfig, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            format!("Error reading config {}: {}", path.display(), err)
+        })?;
+
+        toml::from_str(&text).map_err(|err| {
+            Box::from(format!("Bad config {}: {}", path.display(), err))
+        })
+    }
+}
+
+/// the keys of the tui, a preset with keys bound over it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    /// the key that quits from anywhere, ctrl-c when not set
+    pub exit: Option<String>,
+    /// the keys for an action by its name, in place of the presets. an
+    /// empty list unbinds it
+    pub bind: BTreeMap<String, Vec<String>>,
+}
+
+/// the colours of the tui, a named theme with colours set over it. they
+/// are all left out when NO_COLOR is set
+///
+/// a colour is one of the 16 terminal colour names like `lightblue`,
+/// `reset` for the terminals own or `#rrggbb`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+    /// the lines and the background behind everything
+    pub text: Option<String>,
+    pub background: Option<String>,
+    /// the tab names and the bar
+    pub accent: Option<String>,
+    /// the current tab and the focused pane
+    pub highlight: Option<String>,
+    /// behind search matches
+    pub matched: Option<String>,
+    /// behind the sidebar cursor
+    pub selected: Option<String>,
+}
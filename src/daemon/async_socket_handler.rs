@@ -0,0 +1,353 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::net::SocketAddr;
+
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+
+use crate::daemon::SendEvt;
+use crate::daemon::queue::Queue;
+use crate::daemon::unix_socket_handler::{viewer_line, Incoming, Shared};
+use crate::protocol::{self, Handshake, MAX_FRAME, MAX_HANDSHAKE};
+use crate::transport::{self, BoxAsyncConnection, TlsListener};
+
+type AsyncError = Box<dyn Error + Send + Sync>;
+
+/// the listeners of SocketHandler as tokio tasks, each connection is a task
+/// of its own rather than a thread
+///
+/// the handshakes, queues and viewers are the same ones the threads use
+pub(crate) struct AsyncSocketHandler {
+    /// the bound tcp addr if a tcp listener was asked for
+    pub(crate) tcp_addr: Option<SocketAddr>,
+    socket_path: PathBuf,
+    listeners: Vec<JoinHandle<()>>,
+}
+
+impl AsyncSocketHandler {
+    pub(crate) fn bind(
+        socket_path: &Path,
+        tcp: Option<&TlsListener>,
+        shared: &Shared,
+    ) -> Result<Self, AsyncError> {
+        // remove old file
+        if socket_path.exists() {
+            fs::remove_file(socket_path)?;
+        }
+
+        let unix_listener = UnixListener::bind(socket_path)?;
+        let unix_shared = shared.clone();
+
+        let mut listeners = vec![tokio::spawn(async move {
+            loop {
+                match unix_listener.accept().await {
+                    Ok((stream, _)) => {
+                        spawn_stream_handler(Box::new(stream), &unix_shared)
+                    }
+                    Err(err) => {
+                        unix_shared
+                            .error(format!("Error accepting stream: {}", err));
+                        return;
+                    }
+                }
+            }
+        })];
+
+        let tcp_addr = match tcp {
+            Some(opts) => {
+                let (listener, config) =
+                    opts.bind().map_err(|err| err.to_string())?;
+
+                listener.set_nonblocking(true)?;
+
+                let listener = TcpListener::from_std(listener)?;
+                let addr = listener.local_addr()?;
+                let shared = shared.clone();
+
+                listeners.push(tokio::spawn(async move {
+                    loop {
+                        let stream = match listener.accept().await {
+                            Ok((val, _)) => val,
+                            Err(err) => {
+                                shared.error(format!(
+                                    "Error accepting tcp: {}",
+                                    err
+                                ));
+                                continue;
+                            }
+                        };
+
+                        let config = config.clone();
+                        let shared = shared.clone();
+
+                        // a slow tls handshake cant hold up the accept loop
+                        tokio::spawn(async move {
+                            let accepted = time::timeout(
+                                shared.handshake_timeout,
+                                transport::accept_tls_async(&config, stream),
+                            );
+
+                            match accepted.await {
+                                Ok(Ok(conn)) => handle(conn, shared).await,
+                                Ok(Err(err)) => {
+                                    shared.error(format!("Tls Error: {}", err))
+                                }
+                                Err(_) => {
+                                    shared.error("Tls Error: timed out".into())
+                                }
+                            }
+                        });
+                    }
+                }));
+
+                Some(addr)
+            }
+            None => None,
+        };
+
+        Ok(AsyncSocketHandler {
+            tcp_addr,
+            socket_path: socket_path.to_path_buf(),
+            listeners,
+        })
+    }
+
+    /// another task to stop along with the listeners
+    pub(crate) fn add_task(&mut self, task: JoinHandle<()>) {
+        self.listeners.push(task);
+    }
+}
+
+/// stop accepting and remove the socket, the connections already open run
+/// until they end
+impl Drop for AsyncSocketHandler {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            listener.abort();
+        }
+
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+fn spawn_stream_handler(stream: BoxAsyncConnection, shared: &Shared) {
+    tokio::spawn(handle(stream, shared.clone()));
+}
+
+async fn handle(stream: BoxAsyncConnection, shared: Shared) {
+    if let Err(err) = stream_handler(stream, &shared).await {
+        shared.error(format!("Error handling stream: {}", err));
+    }
+}
+
+/// read the first line from a new connection, check its token then take in
+/// a producers lines, send lines to a viewer or answer a report
+async fn stream_handler(
+    stream: BoxAsyncConnection,
+    shared: &Shared,
+) -> Result<(), AsyncError> {
+    // keep the reader, it may have buffered lines past the first one
+    let mut reader = BufReader::new(stream);
+
+    let first =
+        time::timeout(shared.handshake_timeout, read_handshake(&mut reader));
+
+    let buffer = first
+        .await
+        .map_err(|_| "cant get initial line: timed out".to_string())?
+        .map_err(|err| format!("cant get initial line: {}", err))?;
+
+    let handshake = Handshake::parse(&buffer);
+    let allowed = shared.authorize(&handshake);
+
+    let stream = reader.get_mut();
+    stream
+        .write_all(protocol::reply(&allowed).as_bytes())
+        .await?;
+    stream.flush().await?;
+
+    allowed?;
+
+    match handshake? {
+        Handshake::Connect { id, token, tags } => {
+            let connect = SendEvt::Connect(id.clone());
+
+            if shared.main_queue.push_async(connect).await.is_err() {
+                return Ok(());
+            }
+
+            if !tags.is_empty() {
+                let tags = SendEvt::Tags(id.clone(), tags);
+                let _ = shared.main_queue.push_async(tags).await;
+            }
+
+            receiver_handler(reader, &id, token, shared).await;
+        }
+        Handshake::Client {
+            token,
+            history,
+            times,
+            finished,
+        } => {
+            // the history comes off the disk
+            let viewers = shared.clone();
+            let (ended, queue) = task::spawn_blocking(move || {
+                viewers.add_viewer(token, history, times, finished)
+            })
+            .await?;
+
+            client_handler(reader.into_inner(), ended, &queue, times).await?;
+        }
+        report => {
+            let reports = shared.clone();
+            let lines = task::spawn_blocking(move || {
+                reports.report(&report).map_err(|err| err.to_string())
+            })
+            .await??;
+
+            let stream = reader.get_mut();
+
+            for line in lines {
+                stream.write_all(format!("{}\n", line).as_bytes()).await?;
+            }
+
+            stream.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the next line of at most cap bytes, 0 once the connection is closed
+async fn read_capped<R>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    cap: u64,
+) -> io::Result<usize>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let read = reader.take(cap).read_until(b'\n', buf).await?;
+    protocol::check_capped(buf, read, cap)?;
+
+    Ok(read)
+}
+
+/// the first line from a new connection, an error if it is too long
+async fn read_handshake<R>(reader: &mut R) -> io::Result<String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut buf = Vec::new();
+    read_capped(reader, &mut buf, MAX_HANDSHAKE).await?;
+
+    String::from_utf8(buf)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// pass a producers lines to the main loop until it ends or is dropped
+///
+/// with the block policy a full main queue stops this reading, which pushes
+/// back on the producer through the socket
+async fn receiver_handler(
+    mut reader: BufReader<BoxAsyncConnection>,
+    session: &str,
+    token: Option<String>,
+    shared: &Shared,
+) {
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+
+        let line = match read_capped(&mut reader, &mut buf, MAX_FRAME).await {
+            Ok(0) => break,
+            Ok(_) => protocol::decode_frame(&buf),
+            Err(err) => {
+                shared.error(format!("Error reading from cli: {}", err));
+                break;
+            }
+        };
+
+        let evt = match Incoming::new(
+            line,
+            session,
+            token.as_deref(),
+            &shared.auth,
+        ) {
+            Incoming::Send(evt) => evt,
+            Incoming::End(exit) => {
+                if let Some(exit) = exit {
+                    let _ = shared.main_queue.push_async(exit).await;
+                }
+
+                break;
+            }
+            Incoming::Refused(why) => {
+                shared.error(format!("Dropping producer: {}", why));
+                break;
+            }
+        };
+
+        if let Err(err) = shared.main_queue.push_async(evt).await {
+            shared.error(format!("Dropping producer {}: {:?}", session, err));
+            break;
+        }
+    }
+
+    let closed = SendEvt::Closed(session.to_string());
+    let _ = shared.main_queue.push_async(closed).await;
+}
+
+/// send the finished sessions, then lines from the viewers own queue until
+/// it is closed
+async fn client_handler(
+    mut stream: BoxAsyncConnection,
+    ended: Vec<SendEvt>,
+    queue: &Queue<SendEvt>,
+    times: bool,
+) -> Result<(), AsyncError> {
+    let mut result = Ok(());
+    let mut ended = ended.into_iter();
+
+    loop {
+        let evt = match ended.next() {
+            Some(val) => val,
+            None => match queue.pop_async().await {
+                Some(val) => val,
+                None => break,
+            },
+        };
+
+        if let SendEvt::Kill = evt {
+            break;
+        }
+
+        let line = match viewer_line(evt, times) {
+            Some(val) => val,
+            None => continue,
+        };
+
+        let written = match stream.write_all(line.as_bytes()).await {
+            Ok(()) => stream.flush().await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = written {
+            result =
+                Err(AsyncError::from(format!("cant write to client: {}", err)));
+            break;
+        }
+    }
+
+    // let the main loop know this viewer is gone
+    queue.close();
+
+    result
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::sync::Mutex;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::{DaemonConfig, Role, TokenConfig};
+use crate::daemon::{print_events, DaemonEvent, OnEvent};
+
+/// how often the config file is looked at for changes
+const RELOAD_CHECK: Duration = Duration::from_secs(1);
+
+/// the tokens from the daemons config
+///
+/// the config file is checked for changes as tokens are used so a token can
+/// be revoked by editing the file, no restart needed. once there have been
+/// tokens auth stays on, taking the last one out lets nobody in rather than
+/// everyone
+pub struct Auth {
+    path: Option<PathBuf>,
+    state: Mutex<AuthState>,
+    /// where a config that cant be reloaded is reported
+    events: OnEvent,
+}
+
+struct AuthState {
+    tokens: Vec<TokenConfig>,
+    /// tokens are needed, set by the first token seen and never unset
+    enforced: bool,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl Auth {
+    /// tokens from the config at path, it will be reloaded when it changes
+    pub fn new(path: Option<PathBuf>, config: &DaemonConfig) -> Self {
+        let modified = path.as_ref().and_then(modified);
+
+        Auth {
+            path,
+            state: Mutex::new(AuthState {
+                tokens: config.tokens.clone(),
+                enforced: !config.tokens.is_empty(),
+                modified,
+                checked: Instant::now(),
+            }),
+            events: print_events(true),
+        }
+    }
+
+    /// report reload errors to the daemons events rather than stderr
+    pub fn with_events(mut self, events: OnEvent) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// no tokens and no config, everyone is let in
+    pub fn open() -> Self {
+        Auth::new(None, &DaemonConfig::default())
+    }
+
+    /// check a handshake, the session is the producers id or none for viewers
+    pub fn authorize(
+        &self,
+        token: Option<&str>,
+        wanted: Role,
+        session: Option<&str>,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "auth lock poisoned")?;
+        self.reload(&mut state);
+
+        if !state.enforced {
+            return Ok(());
+        }
+
+        let token = token.ok_or("a token is needed")?;
+
+        let found = find(&state.tokens, token).ok_or("unknown token")?;
+
+        if !found.role.allows(wanted) {
+            return Err(format!("token {} cant do that", found.name));
+        }
+
+        match session {
+            Some(session) if !prefix_matches(found, session) => {
+                Err(format!("token {} doesnt cover {}", found.name, session))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// if the token still exists and covers the session
+    ///
+    /// this is checked for every line so revoking a token cuts off clients
+    /// that are already connected
+    pub fn covers(&self, token: Option<&str>, session: &str) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(val) => val,
+            Err(_) => return false,
+        };
+
+        self.reload(&mut state);
+
+        if !state.enforced {
+            return true;
+        }
+
+        match token.and_then(|token| find(&state.tokens, token)) {
+            Some(found) => prefix_matches(found, session),
+            None => false,
+        }
+    }
+
+    /// if the token is still in the config, or auth is off
+    pub fn still_valid(&self, token: Option<&str>) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(val) => val,
+            Err(_) => return false,
+        };
+
+        self.reload(&mut state);
+
+        !state.enforced
+            || token.and_then(|token| find(&state.tokens, token)).is_some()
+    }
+
+    fn reload(&self, state: &mut AuthState) {
+        let path = match &self.path {
+            Some(val) => val,
+            None => return,
+        };
+
+        if state.checked.elapsed() < RELOAD_CHECK {
+            return;
+        }
+
+        state.checked = Instant::now();
+
+        let now_modified = modified(path);
+
+        if now_modified == state.modified {
+            return;
+        }
+
+        // a bad edit keeps the old tokens rather than locking everyone out
+        match DaemonConfig::load(path) {
+            Ok(config) => {
+                state.enforced |= !config.tokens.is_empty();
+                state.tokens = config.tokens;
+                state.modified = now_modified;
+            }
+            Err(err) => (self.events)(&DaemonEvent::Error(format!(
+                "Error reloading tokens: {}",
+                err
+            ))),
+        }
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn find<'a>(
+    tokens: &'a [TokenConfig],
+    secret: &str,
+) -> Option<&'a TokenConfig> {
+    tokens
+        .iter()
+        .find(|token| same(token.secret.as_bytes(), secret.as_bytes()))
+}
+
+fn prefix_matches(token: &TokenConfig, session: &str) -> bool {
+    match &token.prefix {
+        Some(prefix) => session.starts_with(prefix.as_str()),
+        None => true,
+    }
+}
+
+/// compare without bailing on the first different byte
+fn same(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
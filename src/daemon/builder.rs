@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+use crate::config::DaemonConfig;
+use crate::daemon::{DaemonEvent, SendEvt};
+use crate::daemon::main_loop::Daemon;
+use crate::daemon::queue::Queue;
+use crate::transport::{Endpoint, TlsListener};
+
+/// a daemon on its own socket and log root, for embedding and tests. the
+/// log root has to be given, there is no sensible place to default to
+///
+/// ```no_run
+/// use spellhold::daemon::builder::DaemonBuilder;
+///
+/// let daemon = DaemonBuilder::new("/tmp/my_socket")
+///     .with_log_root("/tmp/my_logs")
+///     .with_events(|evt| eprintln!("daemon: {}", evt))
+///     .start()?;
+///
+/// // hand daemon.endpoint() to producers and subscribers
+///
+/// daemon.shutdown();
+/// daemon.join()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct DaemonBuilder {
+    daemon: Daemon,
+    config_path: Option<PathBuf>,
+    log_root: Option<PathBuf>,
+}
+
+impl DaemonBuilder {
+    /// nothing is printed but errors, which go to stderr
+    pub fn new<P: AsRef<Path>>(socket: P) -> Self {
+        let socket = socket.as_ref().to_string_lossy().into_owned();
+
+        DaemonBuilder {
+            daemon: Daemon::new(Some(socket), true),
+            config_path: None,
+            log_root: None,
+        }
+    }
+
+    /// where sessions are written, over whatever the config says. starting
+    /// without one is an error
+    pub fn with_log_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.log_root = Some(root.into());
+        self
+    }
+
+    /// load a config file at start, tokens are reloaded from it as it
+    /// changes
+    pub fn with_config<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// tokens, queues, storage and retention as they are given
+    pub fn with_options(mut self, config: DaemonConfig) -> Self {
+        self.daemon = self.daemon.with_options(config);
+        self.config_path = None;
+        self
+    }
+
+    /// also listen for tls connections, an addr with port 0 gets a free one
+    pub fn with_tcp(mut self, tcp: TlsListener) -> Self {
+        self.daemon = self.daemon.with_tcp(tcp);
+        self
+    }
+
+    /// everything the daemon would print goes here instead
+    pub fn with_events<F>(mut self, on_event: F) -> Self
+    where
+        F: Fn(&DaemonEvent) + Send + Sync + 'static,
+    {
+        self.daemon = self.daemon.with_events(on_event);
+        self
+    }
+
+    fn build(self) -> Result<Daemon, Box<dyn Error>> {
+        let mut daemon = self.daemon;
+
+        if let Some(path) = self.config_path {
+            daemon = daemon.with_config(path)?;
+        }
+
+        let root = self
+            .log_root
+            .ok_or("no log root, give one with with_log_root")?;
+
+        Ok(daemon.with_log_root(root))
+    }
+
+    /// bind the sockets and run the daemon on a thread of its own, once this
+    /// returns it is taking connections
+    pub fn start(self) -> Result<DaemonHandle, Box<dyn Error>> {
+        self.build()?.start()
+    }
+
+    /// like start but the tokio daemon, on a runtime of its own
+    #[cfg(feature = "async")]
+    pub fn start_tokio(self) -> Result<DaemonHandle, Box<dyn Error>> {
+        self.build()?.start_tokio()
+    }
+}
+
+/// a running daemon, dropping it shuts it down and waits
+pub struct DaemonHandle {
+    socket: PathBuf,
+    tcp_addr: Option<SocketAddr>,
+    main_queue: Queue<SendEvt>,
+    thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl DaemonHandle {
+    pub(crate) fn new(
+        socket: PathBuf,
+        tcp_addr: Option<SocketAddr>,
+        main_queue: Queue<SendEvt>,
+        thread: JoinHandle<Result<(), String>>,
+    ) -> Self {
+        DaemonHandle {
+            socket,
+            tcp_addr,
+            main_queue,
+            thread: Some(thread),
+        }
+    }
+
+    /// the bound tls addr, none without a tcp listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.tcp_addr
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// the unix socket to connect producers and subscribers to
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::Unix(self.socket.clone())
+    }
+
+    /// ask the daemon to stop. what is queued
+    /// before it is still written, then sessions are closed, viewers are
+    /// dropped and the socket is removed
+    pub fn shutdown(&self) {
+        // a closed queue means it already stopped
+        let _ = self.main_queue.push(SendEvt::Kill);
+    }
+
+    /// wait for the daemon to stop, after shutdown
+    pub fn join(mut self) -> Result<(), Box<dyn Error>> {
+        self.wait()
+    }
+
+    /// shutdown then join, for when nothing is left to do in between
+    pub fn close(self) -> Result<(), Box<dyn Error>> {
+        self.shutdown();
+        self.join()
+    }
+
+    fn wait(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.thread.take() {
+            Some(thread) => match thread.join() {
+                Ok(val) => Ok(val?),
+                Err(_) => Err(Box::from("the daemon thread panicked")),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DaemonHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.shutdown();
+
+            if let Err(err) = self.wait() {
+                eprintln!("Error stopping daemon: {}", err);
+            }
+        }
+    }
+}
@@ -1,40 +1,285 @@
-use std::sync::Arc;
-use std::io::Write;
+use std::io;
+use std::thread;
 use std::error::Error;
 use std::path::PathBuf;
-use std::sync::atomic::Ordering;
-use std::fs::{self, OpenOptions};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::collections::HashSet;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::daemon::SendEvt;
+use crate::config::DaemonConfig;
+use crate::daemon::{print_events, DaemonEvent, OnEvent, SendEvt};
+use crate::daemon::auth::Auth;
+use crate::daemon::builder::DaemonHandle;
+use crate::daemon::queue::Queue;
+use crate::daemon::viewers::Viewers;
+use crate::transport::TlsListener;
 use crate::daemon::unix_socket_handler::SocketHandler;
+#[cfg(feature = "async")]
+use crate::daemon::unix_socket_handler::Shared;
+#[cfg(feature = "async")]
+use crate::daemon::async_socket_handler::AsyncSocketHandler;
+use crate::protocol;
+use crate::storage::{fsck, now_millis, Storage};
+use crate::storage::record::Stream;
+use crate::storage::retention::{ActiveSessions, Retention};
 
-// open a file and append the given string to it,
-// the file will be made but not the directory's
-// nothing is added to the string
-// it would be nice to have easy async for theses
-fn append_to_file(
-    the_file_path: &PathBuf,
-    to_write: &str,
-) -> Result<(), Box<dyn Error>> {
-    if !the_file_path.exists() {
-        fs::File::create(&the_file_path)
-            .map_err(|err| format!("Append File Error: {}", err))?;
+/// apply the retention rules and compress idle sessions every interval
+/// until the sender it gives back is dropped
+fn spawn_gc(retention: Retention, events: OnEvent) -> mpsc::Sender<()> {
+    let (stop, stopped) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        match stopped.recv_timeout(retention.interval()) {
+            Err(RecvTimeoutError::Timeout) => collect(&retention, &events),
+            _ => return,
+        }
+    });
+
+    stop
+}
+
+/// one pass of the retention rules and compression
+pub(crate) fn collect(retention: &Retention, events: &OnEvent) {
+    match retention.run(false) {
+        Ok(actions) => actions
+            .iter()
+            .for_each(|action| events(&DaemonEvent::Gc(action.to_string()))),
+        Err(err) => events(&DaemonEvent::Error(format!("Gc Error: {}", err))),
     }
 
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(the_file_path)?;
+    match retention.compact(false, None) {
+        Ok(actions) => actions.iter().for_each(|action| {
+            events(&DaemonEvent::Compacted(action.to_string()))
+        }),
+        Err(err) => {
+            events(&DaemonEvent::Error(format!("Compact Error: {}", err)))
+        }
+    }
+}
 
-    writeln!(file, "{}", to_write)?;
+/// pop what the producers sent until the daemon is killed or the queue is
+/// closed
+fn record(
+    queue: &Queue<SendEvt>,
+    recorder: &mut Recorder,
+) -> Result<(), Box<dyn Error>> {
+    while let Some(next) = queue.pop() {
+        if recorder.handle(next)? {
+            break;
+        }
+    }
 
     Ok(())
 }
 
+/// writes what the producers send and passes it on to the viewers
+pub(crate) struct Recorder {
+    storage: Storage,
+    viewers: Viewers,
+    active: ActiveSessions,
+    events: OnEvent,
+    /// sessions whose log couldnt be written, what they send is dropped
+    /// until they go
+    failed: HashSet<String>,
+}
+
+/// note a session whose log couldnt be written so the rest of what it sends
+/// is dropped, the other sessions carry on
+fn drop_session(
+    failed: &mut HashSet<String>,
+    events: &OnEvent,
+    log_id: &str,
+    err: io::Error,
+) {
+    events(&DaemonEvent::Error(format!("Dropping {}: {}", log_id, err)));
+    failed.insert(log_id.to_string());
+}
+
+impl Recorder {
+    /// handle one event from the producers, true once the daemon is killed
+    pub(crate) fn handle(
+        &mut self,
+        next: SendEvt,
+    ) -> Result<bool, Box<dyn Error>> {
+        let storage = &mut self.storage;
+        let viewers = &self.viewers;
+        let active = &self.active;
+        let events = &self.events;
+        let failed = &mut self.failed;
+
+        let comes_or_goes =
+            matches!(next, SendEvt::Connect(_) | SendEvt::Closed(_));
+
+        if let Some(log_id) = next.session() {
+            if failed.contains(log_id) && !comes_or_goes {
+                return Ok(false);
+            }
+        }
+
+        match next {
+            SendEvt::Connect(log_id) => {
+                let since_epoch = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)?
+                    .as_secs()
+                    .to_string();
+
+                events(&DaemonEvent::Connected(log_id.clone()));
+
+                // waits out gc or compaction working on it
+                active.open(&log_id);
+
+                let connected = format!("{} - connected", since_epoch);
+                let evt = SendEvt::Opened(log_id.clone(), now_millis());
+
+                failed.remove(&log_id);
+
+                let opened = viewers.publish(&evt, || {
+                    storage.append(&log_id, Stream::Meta, connected.as_bytes())
+                });
+
+                if let Err(err) = opened {
+                    drop_session(failed, events, &log_id, err);
+                }
+            }
+            SendEvt::SendString(val) => {
+                let (log_id, record) = match protocol::parse_producer_line(&val)
+                {
+                    Some(split) => split,
+                    None => return Ok(false),
+                };
+
+                // viewers get the plain line, attributes are only stored
+                let line = if record.attrs.is_empty() {
+                    val.clone()
+                } else {
+                    format!("{} -ENDID- {}", log_id, record.line)
+                };
+
+                let at = now_millis();
+                let evt = SendEvt::Stamped(line, at);
+
+                let written = viewers.publish(&evt, || {
+                    storage.append_with(
+                        log_id,
+                        Stream::Out,
+                        at,
+                        record.line.as_bytes(),
+                        record.attrs,
+                    )
+                });
+
+                match written {
+                    // only this line is too big to keep, the session is fine
+                    Err(err) if err.kind() == io::ErrorKind::InvalidInput => {
+                        events(&DaemonEvent::Error(format!(
+                            "Dropping a line of {}: {}",
+                            log_id, err
+                        )));
+                        return Ok(false);
+                    }
+                    Err(err) => {
+                        drop_session(failed, events, log_id, err);
+                        return Ok(false);
+                    }
+                    Ok(_) => {}
+                }
+
+                events(&DaemonEvent::Line(val));
+            }
+            SendEvt::Gap(log_id, dropped) => {
+                let marker = format!("-- {} lines dropped --", dropped);
+                let evt = SendEvt::Gap(log_id.clone(), dropped);
+
+                let written = viewers.publish(&evt, || {
+                    storage.append(&log_id, Stream::Meta, marker.as_bytes())
+                });
+
+                if let Err(err) = written {
+                    drop_session(failed, events, &log_id, err);
+                    return Ok(false);
+                }
+
+                events(&DaemonEvent::Dropped(log_id, dropped));
+            }
+            SendEvt::Tags(log_id, tags) => {
+                let evt = SendEvt::Tags(log_id.clone(), tags.clone());
+
+                let tagged = viewers.publish(&evt, || {
+                    storage.update_meta(&log_id, |meta| meta.tags = tags)
+                });
+
+                if let Err(err) = tagged {
+                    events(&DaemonEvent::Error(format!(
+                        "Error tagging {}: {}",
+                        log_id, err
+                    )));
+                }
+            }
+            SendEvt::Exit(log_id, code) => {
+                let marker = format!("-- exited with {} --", code);
+                let evt = SendEvt::Exit(log_id.clone(), code);
+
+                let written = viewers.publish(&evt, || {
+                    storage.append(&log_id, Stream::Meta, marker.as_bytes())
+                });
+
+                if let Err(err) = written {
+                    drop_session(failed, events, &log_id, err);
+                    return Ok(false);
+                }
+
+                let exited =
+                    storage.update_meta(&log_id, |meta| meta.exit = Some(code));
+
+                if let Err(err) = exited {
+                    events(&DaemonEvent::Error(format!(
+                        "Error saving exit of {}: {}",
+                        log_id, err
+                    )));
+                }
+            }
+            SendEvt::Closed(log_id) => {
+                let evt = SendEvt::Closed(log_id.clone());
+
+                failed.remove(&log_id);
+
+                if let Err(err) =
+                    viewers.publish(&evt, || storage.close(&log_id))
+                {
+                    events(&DaemonEvent::Error(format!(
+                        "Error closing {}: {}",
+                        log_id, err
+                    )));
+                }
+
+                active.close(&log_id);
+            }
+            SendEvt::Kill => {
+                storage.close_all()?;
+
+                viewers.broadcast(&SendEvt::Kill);
+                viewers.close_all();
+
+                return Ok(true);
+            }
+            // stamped lines only go out to viewers
+            SendEvt::End
+            | SendEvt::None
+            | SendEvt::Stamped(_, _)
+            | SendEvt::Opened(_, _) => {}
+        }
+
+        Ok(false)
+    }
+}
+
 pub struct Daemon {
-    quiet: bool,
     socket: PathBuf,
+    tcp: Option<TlsListener>,
+    config: DaemonConfig,
+    config_path: Option<PathBuf>,
+    events: OnEvent,
 }
 
 impl Daemon {
@@ -45,86 +290,291 @@ impl Daemon {
             None => PathBuf::from("/tmp/spellholdd_socket"),
         };
 
-        Daemon { quiet, socket }
+        Daemon {
+            socket,
+            tcp: None,
+            config: DaemonConfig::default(),
+            config_path: None,
+            events: print_events(quiet),
+        }
+    }
+
+    /// use the config file at path, tokens are reloaded from it as it changes
+    pub fn with_config(
+        mut self,
+        path: PathBuf,
+    ) -> Result<Self, Box<dyn Error>> {
+        self.config = DaemonConfig::load(&path)?;
+        self.config_path = Some(path);
+
+        Ok(self)
+    }
+
+    /// use this config as is, nothing is reloaded
+    pub fn with_options(mut self, config: DaemonConfig) -> Self {
+        self.config = config;
+        self.config_path = None;
+        self
+    }
+
+    /// write the sessions under root rather than the configs log_root
+    pub fn with_log_root(mut self, root: PathBuf) -> Self {
+        self.config.log_root = root;
+        self
+    }
+
+    /// also listen for tls connections from other hosts
+    pub fn with_tcp(mut self, tcp: TlsListener) -> Self {
+        self.tcp = Some(tcp);
+        self
+    }
+
+    /// give what the daemon does to on_event rather than printing it
+    pub fn with_events<F>(mut self, on_event: F) -> Self
+    where
+        F: Fn(&DaemonEvent) + Send + Sync + 'static,
+    {
+        self.events = Arc::new(on_event);
+        self
     }
 
     /// main run loop
     /// start the main threads and wait for input from the cli
     pub fn run(&mut self) -> Result<bool, Box<dyn Error>> {
-        let main_path = Arc::new(self.socket.to_owned());
-        let mut main_socket = SocketHandler::new(&main_path);
+        let (main_socket, mut recorder, _gc) = self.bind()?;
 
-        let log_root = PathBuf::from("/home/chris/proj/spellhold/log_files");
+        let recorded = record(&main_socket.receiver, &mut recorder);
+        main_socket.close();
+        recorded?;
 
-        let (client_accept, client_sender) =
-            main_socket.get_client_handles()?;
+        Ok(false)
+    }
 
-        for next in main_socket {
-            match next {
-                SendEvt::Connect(val) => {
-                    let log_id: &str = &val.split(' ').last().unwrap().trim();
+    /// bind the sockets then run on a thread of its own, the handle stops it
+    pub fn start(&self) -> Result<DaemonHandle, Box<dyn Error>> {
+        let (main_socket, mut recorder, gc) = self.bind()?;
 
-                    let log_file = log_root.join(log_id);
+        let main_queue = main_socket.receiver.clone();
+        let tcp_addr = main_socket.tcp_addr;
 
-                    let since_epoch = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)?
-                        .as_secs()
-                        .to_string();
+        let thread = thread::spawn(move || {
+            let recorded = record(&main_socket.receiver, &mut recorder)
+                .map_err(|err| err.to_string());
 
-                    if !self.quiet {
-                        println!("connecting");
-                    }
+            main_socket.close();
+            drop(gc);
 
-                    append_to_file(
-                        &log_file,
-                        &format!("{} - connected\n", since_epoch),
-                    )?;
-                }
-                SendEvt::SendString(val) => {
-                    let str_split = &val.split(' ').collect::<Vec<&str>>();
+            recorded
+        });
 
-                    let (log_id, content) = (str_split[0], str_split[2]);
+        Ok(DaemonHandle::new(
+            self.socket.clone(),
+            tcp_addr,
+            main_queue,
+            thread,
+        ))
+    }
 
-                    let log_file = log_root.join(log_id);
+    /// bind the sockets and start gc, dropping the sender stops gc
+    fn bind(
+        &self,
+    ) -> Result<(SocketHandler, Recorder, mpsc::Sender<()>), Box<dyn Error>>
+    {
+        let main_path = Arc::new(self.socket.to_owned());
+        let (auth, retention, active) = self.prepare()?;
 
-                    append_to_file(&log_file, &content)?;
+        let main_socket = SocketHandler::new(
+            &main_path,
+            self.tcp.as_ref(),
+            auth,
+            &self.config.queues,
+            Some(retention.clone()),
+            self.events.clone(),
+        )?;
 
-                    if !self.quiet {
-                        println!("{}", val);
-                    }
+        if let Some(addr) = main_socket.tcp_addr {
+            (self.events)(&DaemonEvent::Listening(addr));
+        }
 
-                    if client_accept.load(Ordering::Relaxed) {
-                        // send the whole string to be processed by the client
-                        client_sender.send(SendEvt::SendString(val)).map_err(
-                            |err| format!("Error sending to client: {}", err),
-                        )?;
-                    }
-                }
-                SendEvt::Kill => {
-                    if client_accept.load(Ordering::Relaxed) {
-                        client_sender.send(SendEvt::Kill).map_err(|err| {
-                            format!("Error killing the client: {}", err)
-                        })?;
+        let gc = spawn_gc(retention, self.events.clone());
+        let recorder = self.recorder(main_socket.viewers.clone(), active);
 
-                        client_accept.store(false, Ordering::Relaxed);
-                    }
+        Ok((main_socket, recorder, gc))
+    }
 
-                    break;
-                }
-                SendEvt::End | SendEvt::None => continue,
-            }
+    /// recover what was cut off last time and set up the shared state
+    pub(crate) fn prepare(
+        &self,
+    ) -> Result<(Arc<Auth>, Retention, ActiveSessions), Box<dyn Error>> {
+        let auth = Auth::new(self.config_path.clone(), &self.config)
+            .with_events(self.events.clone());
+        let auth = Arc::new(auth);
+        let log_root = self.config.log_root.clone();
+
+        // nothing is open yet, so whatever says it is was cut off
+        for check in fsck::recover(&log_root)? {
+            (self.events)(&DaemonEvent::Recovered(check.to_string()));
         }
 
+        let active = ActiveSessions::default();
+
+        let retention = Retention::new(
+            log_root,
+            self.config.storage.clone(),
+            self.config.retention.clone(),
+            active.clone(),
+        );
+
+        Ok((auth, retention, active))
+    }
+
+    pub(crate) fn recorder(
+        &self,
+        viewers: Viewers,
+        active: ActiveSessions,
+    ) -> Recorder {
+        Recorder {
+            storage: Storage::new(
+                self.config.log_root.clone(),
+                self.config.storage.segment_bytes,
+            ),
+            viewers,
+            active,
+            events: self.events.clone(),
+            failed: HashSet::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+type AsyncError = Box<dyn Error + Send + Sync>;
+
+#[cfg(feature = "async")]
+impl Daemon {
+    /// run on a tokio runtime, each connection is a task rather than a
+    /// thread. writing to disk stays on a blocking thread of its own
+    pub async fn run_async(&mut self) -> Result<bool, AsyncError> {
+        let (handler, recorder, main_queue) = self.bind_async()?;
+
+        record_async(handler, recorder, main_queue).await?;
+
         Ok(false)
     }
+
+    /// like start but the tokio daemon, on a runtime of its own thread
+    pub fn start_tokio(&self) -> Result<DaemonHandle, Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        // the listeners are spawned onto it as they are bound
+        let (handler, recorder, main_queue) = {
+            let _entered = runtime.enter();
+            self.bind_async().map_err(|err| err.to_string())?
+        };
+
+        let tcp_addr = handler.tcp_addr;
+        let queue = main_queue.clone();
+
+        let thread = thread::spawn(move || {
+            runtime
+                .block_on(record_async(handler, recorder, main_queue))
+                .map_err(|err| err.to_string())
+        });
+
+        Ok(DaemonHandle::new(
+            self.socket.clone(),
+            tcp_addr,
+            queue,
+            thread,
+        ))
+    }
+
+    /// bind the listeners and start gc, both as tasks on the current runtime
+    fn bind_async(
+        &self,
+    ) -> Result<(AsyncSocketHandler, Recorder, Queue<SendEvt>), AsyncError>
+    {
+        let (auth, retention, active) =
+            self.prepare().map_err(|err| err.to_string())?;
+
+        let shared = Shared::new(
+            auth,
+            &self.config.queues,
+            Some(retention.clone()),
+            self.events.clone(),
+        );
+
+        let mut handler =
+            AsyncSocketHandler::bind(&self.socket, self.tcp.as_ref(), &shared)?;
+
+        if let Some(addr) = handler.tcp_addr {
+            (self.events)(&DaemonEvent::Listening(addr));
+        }
+
+        let events = self.events.clone();
+        handler.add_task(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(retention.interval()).await;
+
+                let retention = retention.clone();
+                let events = events.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    collect(&retention, &events)
+                })
+                .await;
+            }
+        }));
+
+        let recorder = self.recorder(shared.viewers.clone(), active);
+
+        Ok((handler, recorder, shared.main_queue))
+    }
+}
+
+/// what run_async and start_tokio wait on, dropping the handler stops its
+/// listeners and gc
+#[cfg(feature = "async")]
+async fn record_async(
+    handler: AsyncSocketHandler,
+    mut recorder: Recorder,
+    main_queue: Queue<SendEvt>,
+) -> Result<(), AsyncError> {
+    let events = main_queue.clone();
+    let _closing = CloseOnDrop(main_queue);
+
+    let recorded = tokio::task::spawn_blocking(move || {
+        record(&events, &mut recorder).map_err(|err| err.to_string())
+    })
+    .await;
+
+    drop(handler);
+    recorded??;
+
+    Ok(())
+}
+
+/// closes the main queue when run_async is dropped, so the thread writing
+/// to disk doesnt outlive it
+#[cfg(feature = "async")]
+struct CloseOnDrop(Queue<SendEvt>);
+
+#[cfg(feature = "async")]
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
 }
 
 /// default is true
 impl Default for Daemon {
     fn default() -> Self {
         Daemon {
-            quiet: true,
             socket: PathBuf::from("/tmp/spellholdd_socket"),
+            tcp: None,
+            config: DaemonConfig::default(),
+            config_path: None,
+            events: print_events(true),
         }
     }
 }
@@ -1,5 +1,69 @@
+#[cfg(feature = "async")]
+pub(crate) mod async_socket_handler;
+pub mod auth;
+pub mod builder;
 pub mod main_loop;
+pub mod queue;
 pub mod unix_socket_handler;
+pub mod viewers;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::daemon::queue::Gapped;
+use crate::protocol;
+
+/// something a daemon did, what it used to print
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonEvent {
+    /// the tls listener is bound
+    Listening(SocketAddr),
+    /// a session left open last time was cut back to its last whole record
+    Recovered(String),
+    /// a producer connected for a session
+    Connected(String),
+    /// a line from a producer, as it was sent
+    Line(String),
+    /// lines for a session were dropped before they were written
+    Dropped(String, u64),
+    /// what a retention pass removed
+    Gc(String),
+    /// a session that was compressed
+    Compacted(String),
+    /// something went wrong that didnt stop the daemon
+    Error(String),
+}
+
+impl fmt::Display for DaemonEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DaemonEvent::Listening(addr) => write!(f, "listening on {}", addr),
+            DaemonEvent::Recovered(check) => write!(f, "recovered: {}", check),
+            DaemonEvent::Connected(id) => write!(f, "connecting {}", id),
+            DaemonEvent::Line(line) => write!(f, "{}", line),
+            DaemonEvent::Dropped(id, dropped) => {
+                write!(f, "{}: {} lines dropped", id, dropped)
+            }
+            DaemonEvent::Gc(action) => write!(f, "gc: {}", action),
+            DaemonEvent::Compacted(action) => write!(f, "compact: {}", action),
+            DaemonEvent::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// where a daemon sends its events, called from whichever thread had them
+pub type OnEvent = Arc<dyn Fn(&DaemonEvent) + Send + Sync>;
+
+/// print events the way the daemon always has, errors to stderr even when
+/// quiet
+pub fn print_events(quiet: bool) -> OnEvent {
+    Arc::new(move |evt| match evt {
+        DaemonEvent::Error(err) => eprintln!("{}", err),
+        evt if !quiet => println!("{}", evt),
+        _ => {}
+    })
+}
 
 #[derive(Debug, Clone)]
 pub enum SendEvt {
@@ -8,6 +72,19 @@ pub enum SendEvt {
     None,
     Connect(String),
     SendString(String),
+    /// a line as viewers get it, with the unix millis the daemon got it at
+    Stamped(String, u64),
+    /// a session as viewers hear of it starting, with the unix millis the
+    /// daemon opened it at
+    Opened(String, u64),
+    /// lines for a session were dropped from a full queue
+    Gap(String, u64),
+    /// the tags a producer gave its session when it connected
+    Tags(String, Vec<String>),
+    /// how the command a producer was sending for exited
+    Exit(String, i32),
+    /// a producer is done with its session, cleanly or not
+    Closed(String),
 }
 
 impl SendEvt {
@@ -15,10 +92,11 @@ impl SendEvt {
         SendEvt::evt_dispatch(line)
     }
 
+    /// only whole kill and end lines, a session can be called killer
     fn evt_dispatch(line: String) -> SendEvt {
-        if line.starts_with("kill") {
+        if line == "kill" {
             SendEvt::Kill
-        } else if line.starts_with("end") {
+        } else if protocol::is_end_line(&line) {
             SendEvt::End
         } else if !line.is_empty() {
             SendEvt::SendString(line)
@@ -27,4 +105,41 @@ impl SendEvt {
             SendEvt::None
         }
     }
+
+    /// the session an event is for, if it is for one
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            SendEvt::Connect(id)
+            | SendEvt::Opened(id, _)
+            | SendEvt::Gap(id, _)
+            | SendEvt::Tags(id, _)
+            | SendEvt::Exit(id, _)
+            | SendEvt::Closed(id) => Some(id),
+            SendEvt::SendString(val) | SendEvt::Stamped(val, _) => {
+                val.split(' ').next()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Gapped for SendEvt {
+    fn gap_key(&self) -> Option<&str> {
+        self.session()
+    }
+
+    /// only lines can be dropped, losing a connect or close would leave the
+    /// main loop with the wrong idea of what is open
+    fn droppable(&self) -> bool {
+        matches!(
+            self,
+            SendEvt::SendString(_)
+                | SendEvt::Stamped(_, _)
+                | SendEvt::Gap(_, _)
+        )
+    }
+
+    fn gap(key: String, dropped: u64) -> Self {
+        SendEvt::Gap(key, dropped)
+    }
 }
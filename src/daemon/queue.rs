@@ -0,0 +1,382 @@
+use std::future;
+use std::sync::Arc;
+use std::time::Duration;
+use std::task::{Context, Poll, Waker};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Deserialize;
+
+/// what to do when a queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// wait for room, this pushes back on whoever is sending
+    Block,
+    /// throw away the oldest item and leave a gap marker in its place
+    DropOldest,
+    /// close the queue, the other end gets disconnected
+    Disconnect,
+}
+
+/// something that can stand in for the items dropped from a queue
+pub trait Gapped: Sized {
+    /// what the dropped items are counted under, the session for events
+    fn gap_key(&self) -> Option<&str>;
+
+    /// if the drop oldest policy may throw this away
+    fn droppable(&self) -> bool {
+        true
+    }
+
+    /// the marker for `dropped` items under `key`
+    fn gap(key: String, dropped: u64) -> Self;
+}
+
+/// counters for one queue, shared with whoever reports on it
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    pub capacity: AtomicUsize,
+    pub depth: AtomicUsize,
+    /// the deepest the queue has been
+    pub high_water: AtomicUsize,
+    pub dropped: AtomicU64,
+    /// how many times a push had to wait for room
+    pub blocked: AtomicU64,
+}
+
+impl QueueStats {
+    /// one line for `spellcli stats`
+    pub fn report(&self, name: &str) -> String {
+        format!(
+            "{} depth={} capacity={} high_water={} dropped={} blocked={}",
+            name,
+            self.depth.load(Ordering::Relaxed),
+            self.capacity.load(Ordering::Relaxed),
+            self.high_water.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+            self.blocked.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PushError {
+    /// the queue was closed, by the other end or an overflow
+    Closed,
+    /// the queue was full with the disconnect policy, it is now closed
+    Overflow,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    /// dropped counts that have not been handed out as gaps yet
+    gaps: BTreeMap<String, u64>,
+    closed: bool,
+    /// async pops waiting for an item and pushes waiting for room, woken
+    /// along with the condvars
+    pop_wakers: Vec<Waker>,
+    push_wakers: Vec<Waker>,
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: Policy,
+    stats: Arc<QueueStats>,
+}
+
+/// a bounded many to many queue, cloning it gives another handle to the same
+/// queue
+pub struct Queue<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Gapped> Queue<T> {
+    pub fn new(capacity: usize, policy: Policy) -> Self {
+        let capacity = capacity.max(1);
+
+        let stats = QueueStats::default();
+        stats.capacity.store(capacity, Ordering::Relaxed);
+
+        Queue {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    items: VecDeque::with_capacity(capacity),
+                    gaps: BTreeMap::new(),
+                    closed: false,
+                    pop_wakers: Vec::new(),
+                    push_wakers: Vec::new(),
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+                capacity,
+                policy,
+                stats: Arc::new(stats),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<QueueStats> {
+        self.inner.stats.clone()
+    }
+
+    pub fn push(&self, item: T) -> Result<(), PushError> {
+        let inner = &self.inner;
+        let mut state = self.lock();
+
+        if state.closed {
+            return Err(PushError::Closed);
+        }
+
+        if self.must_wait(&state) {
+            inner.stats.blocked.fetch_add(1, Ordering::Relaxed);
+
+            while self.must_wait(&state) {
+                state = inner
+                    .not_full
+                    .wait(state)
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+
+            if state.closed {
+                return Err(PushError::Closed);
+            }
+        }
+
+        self.admit(state, item)
+    }
+
+    /// push without holding up the thread, a full queue with the block
+    /// policy waits for room like push does
+    pub async fn push_async(&self, item: T) -> Result<(), PushError> {
+        let mut item = Some(item);
+        let mut waited = false;
+
+        future::poll_fn(|cx| self.poll_push(cx, &mut item, &mut waited)).await
+    }
+
+    fn poll_push(
+        &self,
+        cx: &mut Context<'_>,
+        item: &mut Option<T>,
+        waited: &mut bool,
+    ) -> Poll<Result<(), PushError>> {
+        let mut state = self.lock();
+
+        if state.closed {
+            return Poll::Ready(Err(PushError::Closed));
+        }
+
+        if self.must_wait(&state) {
+            if !*waited {
+                self.inner.stats.blocked.fetch_add(1, Ordering::Relaxed);
+                *waited = true;
+            }
+
+            add_waker(&mut state.push_wakers, cx.waker());
+            return Poll::Pending;
+        }
+
+        match item.take() {
+            Some(item) => Poll::Ready(self.admit(state, item)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    /// if a push has to wait for room
+    fn must_wait(&self, state: &State<T>) -> bool {
+        self.inner.policy == Policy::Block
+            && state.items.len() >= self.inner.capacity
+            && !state.closed
+    }
+
+    /// add an item to an open queue, making room by the policy if it is full
+    fn admit(
+        &self,
+        mut state: MutexGuard<'_, State<T>>,
+        item: T,
+    ) -> Result<(), PushError> {
+        let inner = &self.inner;
+
+        if state.items.len() >= inner.capacity {
+            match inner.policy {
+                // the callers have waited for room already
+                Policy::Block => {}
+                Policy::DropOldest => {
+                    // a queue of only undroppable items grows past capacity
+                    // by those items, they are rare
+                    let oldest =
+                        state.items.iter().position(|item| item.droppable());
+
+                    if let Some(old) =
+                        oldest.and_then(|index| state.items.remove(index))
+                    {
+                        let key = old.gap_key().unwrap_or_default().to_owned();
+                        *state.gaps.entry(key).or_insert(0) += 1;
+
+                        inner.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Policy::Disconnect => {
+                    inner.stats.dropped.fetch_add(1, Ordering::Relaxed);
+
+                    drop(state);
+                    self.close();
+
+                    return Err(PushError::Overflow);
+                }
+            }
+        }
+
+        state.items.push_back(item);
+        self.set_depth(&state);
+
+        inner.not_empty.notify_one();
+        wake_all(&mut state.pop_wakers);
+
+        Ok(())
+    }
+
+    /// wait for the next item, none once the queue is closed
+    ///
+    /// gap markers come out before the items that were left
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.lock();
+
+        loop {
+            if let Some(item) = self.take(&mut state) {
+                return Some(item);
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            state = self
+                .inner
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// like pop but give up after timeout
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let mut state = self.lock();
+
+        if let Some(item) = self.take(&mut state) {
+            return Some(item);
+        }
+
+        if state.closed {
+            return None;
+        }
+
+        let (mut state, _) = self
+            .inner
+            .not_empty
+            .wait_timeout(state, timeout)
+            .unwrap_or_else(|err| err.into_inner());
+
+        self.take(&mut state)
+    }
+
+    /// pop without holding up the thread
+    pub async fn pop_async(&self) -> Option<T> {
+        future::poll_fn(|cx| self.poll_pop(cx)).await
+    }
+
+    fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.lock();
+
+        if let Some(item) = self.take(&mut state) {
+            return Poll::Ready(Some(item));
+        }
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        add_waker(&mut state.pop_wakers, cx.waker());
+
+        Poll::Pending
+    }
+
+    /// stop the queue, anything waiting on it is woken up and anything left
+    /// in it is thrown away
+    pub fn close(&self) {
+        let mut state = self.lock();
+
+        state.closed = true;
+        state.items.clear();
+        state.gaps.clear();
+        self.set_depth(&state);
+
+        self.inner.not_empty.notify_all();
+        self.inner.not_full.notify_all();
+        wake_all(&mut state.pop_wakers);
+        wake_all(&mut state.push_wakers);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let key = state.gaps.keys().next().cloned();
+
+        if let Some(key) = key {
+            let dropped = state.gaps.remove(&key).unwrap_or_default();
+            return Some(T::gap(key, dropped));
+        }
+
+        let item = state.items.pop_front()?;
+
+        self.set_depth(state);
+        self.inner.not_full.notify_one();
+        wake_all(&mut state.push_wakers);
+
+        Some(item)
+    }
+
+    fn set_depth(&self, state: &State<T>) {
+        let depth = state.items.len();
+        let stats = &self.inner.stats;
+
+        stats.depth.store(depth, Ordering::Relaxed);
+        stats.high_water.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// keep a waker to wake later, once however often the same task polls
+fn add_waker(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|old| old.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+}
+
+/// wake every waiting task, the ones that lose the race wait again
+fn wake_all(wakers: &mut Vec<Waker>) {
+    for waker in wakers.drain(..) {
+        waker.wake();
+    }
+}
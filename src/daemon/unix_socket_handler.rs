@@ -1,69 +1,451 @@
-use std::{fs, thread};
+use std::{fs, iter, thread};
 use std::error::Error;
-use std::path::PathBuf;
-use std::sync::{self, Arc, Mutex};
-use std::sync::mpsc::{self, Receiver};
-use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::io::{self, BufReader, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
 
-use crate::daemon::SendEvt;
+use crate::config::{QueueConfig, Role};
+use crate::daemon::{DaemonEvent, OnEvent, SendEvt};
+use crate::daemon::auth::Auth;
+use crate::daemon::queue::Queue;
+use crate::daemon::viewers::Viewers;
+use crate::protocol::{self, Handshake, ViewerLine};
+use crate::storage::list_sessions;
+use crate::storage::meta::SessionMeta;
+use crate::storage::reader::SessionReader;
+use crate::storage::record::{Record, Stream};
+use crate::storage::retention::Retention;
+use crate::transport::{self, BoxConnection, TlsListener};
 
-type ClientTuple = (Arc<AtomicBool>, mpsc::Sender<SendEvt>);
+/// shuts down the socket under a connection from another thread
+type Hangup = Box<dyn Fn() + Send>;
 
-type ArcMutexReceiver = Arc<Mutex<mpsc::Receiver<SendEvt>>>;
+/// lifts the handshake timeout off the socket under a connection once its
+/// first line is in
+type Settle = Box<dyn FnOnce() + Send>;
+
+/// the connections still open, so a closed handler can hang up on them and
+/// their producers know to spool
+#[derive(Clone, Default)]
+pub(crate) struct Connections {
+    open: Arc<Mutex<(u64, HashMap<u64, Hangup>)>>,
+}
+
+impl Connections {
+    fn add(&self, hangup: Hangup) -> u64 {
+        let mut open = self.open.lock().unwrap_or_else(|err| err.into_inner());
+
+        open.0 += 1;
+        let key = open.0;
+        open.1.insert(key, hangup);
+
+        key
+    }
+
+    fn remove(&self, key: u64) {
+        let mut open = self.open.lock().unwrap_or_else(|err| err.into_inner());
+        open.1.remove(&key);
+    }
+
+    fn hang_up_all(&self) {
+        let mut open = self.open.lock().unwrap_or_else(|err| err.into_inner());
+
+        for (_, hangup) in open.1.drain() {
+            hangup();
+        }
+    }
+}
+
+/// the things every connection thread needs a copy of
+#[derive(Clone)]
+pub(crate) struct Shared {
+    pub(crate) main_queue: Queue<SendEvt>,
+    pub(crate) viewers: Viewers,
+    pub(crate) auth: Arc<Auth>,
+    pub(crate) retention: Option<Retention>,
+    pub(crate) connections: Connections,
+    /// to finish tls and send the first line
+    pub(crate) handshake_timeout: Duration,
+    /// where errors with a connection are reported
+    pub(crate) events: OnEvent,
+}
+
+impl Shared {
+    pub(crate) fn new(
+        auth: Arc<Auth>,
+        queues: &QueueConfig,
+        retention: Option<Retention>,
+        events: OnEvent,
+    ) -> Self {
+        let main_queue =
+            Queue::new(queues.producer_capacity, queues.producer_policy);
+
+        let viewers = Viewers::new(
+            auth.clone(),
+            queues.viewer_capacity,
+            queues.viewer_policy,
+        );
+
+        Shared {
+            main_queue,
+            viewers,
+            auth,
+            retention,
+            connections: Connections::default(),
+            handshake_timeout: Duration::from_millis(
+                queues.handshake_timeout_ms,
+            ),
+            events,
+        }
+    }
+
+    /// report something that went wrong with one connection
+    pub(crate) fn error(&self, err: String) {
+        (self.events)(&DaemonEvent::Error(err))
+    }
+
+    /// check the first line of a connection against the tokens
+    pub(crate) fn authorize(
+        &self,
+        handshake: &Result<Handshake, String>,
+    ) -> Result<(), String> {
+        match handshake {
+            Ok(Handshake::Connect { id, token, .. }) => {
+                self.auth
+                    .authorize(token.as_deref(), Role::Producer, Some(id))
+            }
+            Ok(Handshake::Client { token, .. })
+            | Ok(Handshake::Stats { token }) => {
+                self.auth.authorize(token.as_deref(), Role::Viewer, None)
+            }
+            Ok(Handshake::Gc { token, .. })
+            | Ok(Handshake::Compact { token, .. }) => match &self.retention {
+                Some(_) => {
+                    self.auth.authorize(token.as_deref(), Role::Admin, None)
+                }
+                None => Err("this daemon has no retention".to_string()),
+            },
+            Err(err) => Err(err.to_owned()),
+        }
+    }
+
+    /// add a viewer, with the history it asked for queued up first
+    ///
+    /// the finished sessions it asked for are given back to send before
+    /// what is queued, they dont fit in a queue the size of a viewers
+    pub(crate) fn add_viewer(
+        &self,
+        token: Option<String>,
+        history: Option<u64>,
+        times: bool,
+        finished: bool,
+    ) -> (Vec<SendEvt>, Queue<SendEvt>) {
+        match (history, &self.retention) {
+            (Some(lines), Some(retention)) => {
+                let (auth, events) = (&self.auth, &self.events);
+                let covers = token.clone();
+                let mut running = Vec::new();
+
+                // the disk is read before the viewers are locked, producers
+                // are held up only for what came in meanwhile
+                let tails = read_history(
+                    events,
+                    retention,
+                    auth,
+                    covers.as_deref(),
+                    lines,
+                );
+
+                let queue = self.viewers.add_with(token, |queue| {
+                    running = load_history(
+                        events,
+                        retention,
+                        auth,
+                        covers.as_deref(),
+                        (lines, times),
+                        tails,
+                        queue,
+                    );
+                });
+
+                let ended = if finished {
+                    let covers = covers.as_deref();
+                    finished_history(
+                        events, retention, auth, covers, lines, &running,
+                    )
+                } else {
+                    Vec::new()
+                };
+
+                (ended, queue)
+            }
+            _ => (Vec::new(), self.viewers.add(token)),
+        }
+    }
+
+    /// the answer to a stats, gc or compact handshake, a line each
+    pub(crate) fn report(
+        &self,
+        handshake: &Handshake,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let retention = self.retention.as_ref();
+
+        match handshake {
+            Handshake::Stats { .. } => {
+                let mut lines =
+                    vec![self.main_queue.stats().report("producers")];
+                lines.extend(self.viewers.report());
+
+                Ok(lines)
+            }
+            Handshake::Gc { dry_run, .. } => {
+                let retention = retention.ok_or("no retention")?;
+                let actions = retention.run(*dry_run)?;
+
+                Ok(actions.iter().map(ToString::to_string).collect())
+            }
+            Handshake::Compact { session, .. } => {
+                let retention = retention.ok_or("no retention")?;
+                let actions = retention.compact(true, session.as_deref())?;
+
+                Ok(actions.iter().map(ToString::to_string).collect())
+            }
+            _ => Err(Box::from("not a report handshake")),
+        }
+    }
+}
+
+/// what a line from a producer means for the main loop
+pub(crate) enum Incoming {
+    Send(SendEvt),
+    /// the producer is done, with the exit it gave if any
+    End(Option<SendEvt>),
+    /// the producer has to be dropped, and why
+    Refused(String),
+}
+
+impl Incoming {
+    /// a producer can only write to the session it connected as, and only
+    /// while its token still covers it. it cant stop the daemon, that is
+    /// left to whatever started it
+    pub(crate) fn new(
+        line: String,
+        session: &str,
+        token: Option<&str>,
+        auth: &Auth,
+    ) -> Incoming {
+        let exit = protocol::exit_status(&line);
+        let evt = SendEvt::new(line);
+
+        if let SendEvt::Kill = evt {
+            return Incoming::Refused(format!(
+                "{} tried to stop the daemon",
+                session
+            ));
+        }
+
+        if let SendEvt::End = evt {
+            let exit =
+                exit.map(|code| SendEvt::Exit(session.to_string(), code));
+
+            return Incoming::End(exit);
+        }
+
+        if let SendEvt::SendString(val) = &evt {
+            let id = val.split(' ').next().unwrap_or_default();
+
+            if id != session {
+                return Incoming::Refused(format!(
+                    "{} sent for {}",
+                    session, id
+                ));
+            }
+
+            if !auth.covers(token, id) {
+                return Incoming::Refused(format!("token doesnt cover {}", id));
+            }
+        }
+
+        Incoming::Send(evt)
+    }
+}
+
+/// the line a viewer is sent for an event, none for events it doesnt see.
+/// lines have when the daemon got them if the viewer asked for times
+pub(crate) fn viewer_line(evt: SendEvt, times: bool) -> Option<String> {
+    match evt {
+        SendEvt::SendString(val) => Some(val + "\n"),
+        SendEvt::Stamped(val, at) if times => {
+            let (id, text) = protocol::split_line(&val)?;
+
+            Some(format!("{} -AT- {} -ENDID- {}\n", id, at, text))
+        }
+        SendEvt::Stamped(val, _) => Some(val + "\n"),
+        SendEvt::Gap(id, dropped) => Some(protocol::gap_line(&id, dropped)),
+        SendEvt::Connect(id) => Some(ViewerLine::Started(id).to_line()),
+        SendEvt::Opened(id, at) if times => {
+            Some(ViewerLine::Opened(id, at).to_line())
+        }
+        SendEvt::Opened(id, _) => Some(ViewerLine::Started(id).to_line()),
+        SendEvt::Tags(id, tags) => Some(ViewerLine::Tagged(id, tags).to_line()),
+        SendEvt::Exit(id, code) => Some(ViewerLine::Exited(id, code).to_line()),
+        SendEvt::Closed(id) => Some(ViewerLine::Ended(id).to_line()),
+        _ => None,
+    }
+}
 
 pub struct SocketHandler {
-    pub receiver: Receiver<SendEvt>,
-    pub client_accept: Option<Arc<AtomicBool>>,
-    pub client_sender: Option<mpsc::Sender<SendEvt>>,
+    /// every producers lines, bounded by the producer queue config
+    pub receiver: Queue<SendEvt>,
+    pub viewers: Viewers,
+    /// the bound tcp addr if a tcp listener was asked for
+    pub tcp_addr: Option<SocketAddr>,
+    socket_path: PathBuf,
+    /// set by close, the accept loops end on the next connection
+    stopping: Arc<AtomicBool>,
+    connections: Connections,
 }
 
 impl SocketHandler {
-    /// will spawn a main thread for the stream and wait for a connection
+    /// bind the unix socket and the optional tls listener then spawn a thread
+    /// for each to wait for connections
     ///
     /// when a connection is accepted another thread will spawn and listen for
     /// the incoming lines or send lines to the client.
-    pub fn new(socket_path: &Arc<PathBuf>) -> Self {
-        let (main_sender, main_receiver) = mpsc::channel();
-        let (client_sender, client_receiver) = mpsc::channel();
+    pub fn new(
+        socket_path: &Arc<PathBuf>,
+        tcp: Option<&TlsListener>,
+        auth: Arc<Auth>,
+        queues: &QueueConfig,
+        retention: Option<Retention>,
+        events: OnEvent,
+    ) -> Result<Self, Box<dyn Error>> {
+        let shared = Shared::new(auth, queues, retention, events);
+
+        let receiver = shared.main_queue.clone();
+        let viewers = shared.viewers.clone();
+        let connections = shared.connections.clone();
+
+        // remove old file
+        if socket_path.exists() {
+            fs::remove_file(socket_path.as_ref())?;
+        }
 
-        let client_accept = Arc::new(AtomicBool::new(false));
-        let client_receiver = Arc::new(Mutex::new(client_receiver));
+        let unix_listener = UnixListener::bind(socket_path.as_ref())?;
 
-        // TODO: get rid of these clones for lifetimes right?
-        let main_sender_give = main_sender.clone();
-        let socket_path_give = socket_path.clone();
-        let client_accept_give = client_accept.clone();
+        let unix_shared = shared.clone();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let unix_stopping = stopping.clone();
 
         // spawn the main receiver thread
         thread::spawn(move || {
-            if let Err(err) = stream_handler(
-                &socket_path_give,
-                &main_sender_give,
-                &client_accept_give,
-                &client_receiver,
-            ) {
-                eprintln!("Error in the main receiver thread: {}", err);
+            let accepted =
+                unix_accept(&unix_listener, &unix_shared, &unix_stopping);
+
+            if let Err(err) = accepted {
+                unix_shared.error(format!(
+                    "Error in the main receiver thread: {}",
+                    err
+                ));
             };
         });
 
-        SocketHandler {
-            receiver: main_receiver,
-            client_sender: Some(client_sender),
-            client_accept: Some(client_accept),
-        }
+        let tcp_addr = match tcp {
+            Some(opts) => {
+                let (listener, config) = opts.bind()?;
+                let addr = listener.local_addr()?;
+                let stopping = stopping.clone();
+
+                thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        if stopping.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        let stream = match stream {
+                            Ok(val) => val,
+                            Err(err) => {
+                                shared.error(format!(
+                                    "Error accepting tcp: {}",
+                                    err
+                                ));
+                                continue;
+                            }
+                        };
+
+                        let hangup = stream.try_clone().ok().map(|stream| {
+                            Box::new(move || {
+                                let _ = stream.shutdown(Shutdown::Both);
+                            }) as Hangup
+                        });
+
+                        // the tls handshake happens on the first read, so
+                        // this covers it as well as the first line
+                        let _ = stream
+                            .set_read_timeout(Some(shared.handshake_timeout));
+
+                        let settle = stream.try_clone().ok().map(|stream| {
+                            Box::new(move || {
+                                let _ = stream.set_read_timeout(None);
+                            }) as Settle
+                        });
+
+                        match transport::accept_tls(&config, stream) {
+                            Ok(conn) => spawn_stream_handler(
+                                conn, hangup, settle, &shared,
+                            ),
+                            Err(err) => {
+                                shared.error(format!("Tls Error: {}", err))
+                            }
+                        }
+                    }
+                });
+
+                Some(addr)
+            }
+            None => None,
+        };
+
+        Ok(SocketHandler {
+            receiver,
+            viewers,
+            tcp_addr,
+            socket_path: socket_path.to_path_buf(),
+            stopping,
+            connections,
+        })
     }
 
-    /// return the client handles replacing them with None
-    pub fn get_client_handles(
-        &mut self,
-    ) -> Result<ClientTuple, Box<dyn Error>> {
-        let abool = self.client_accept.take().ok_or("no client atomic bool")?;
+    /// stop taking connections, remove the socket and hang up on the
+    /// connections still open
+    pub fn close(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.receiver.close();
+        self.connections.hang_up_all();
+
+        // the accept loops only look once they have a connection
+        let _ = UnixStream::connect(&self.socket_path);
+
+        if let Some(mut addr) = self.tcp_addr {
+            match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => {
+                    addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST))
+                }
+                IpAddr::V6(ip) if ip.is_unspecified() => {
+                    addr.set_ip(IpAddr::V6(Ipv6Addr::LOCALHOST))
+                }
+                _ => {}
+            }
 
-        let sender = self.client_sender.take().ok_or("no mpsc::Sender")?;
+            let _ = TcpStream::connect(addr);
+        }
 
-        Ok((abool, sender))
+        let _ = fs::remove_file(&self.socket_path);
     }
 }
 
@@ -71,98 +453,500 @@ impl Iterator for SocketHandler {
     type Item = SendEvt;
 
     fn next(&mut self) -> Option<SendEvt> {
-        match self.receiver.recv() {
-            Ok(val) => Some(val),
-            Err(err) => {
-                eprintln!("SocketHandler Error {}", err);
-                None
-            }
-        }
+        self.receiver.pop()
     }
 }
 
-/// waits for a connection to the main socket
-/// then spawns ether,
-/// a listener to take in lines from the socket
-/// or a sender to give line to a client
-fn stream_handler(
-    socket_path: &Arc<PathBuf>,
-    main_sender: &mpsc::Sender<SendEvt>,
-    client_accept: &Arc<AtomicBool>,
-    client_receiver: &Arc<Mutex<mpsc::Receiver<SendEvt>>>,
+/// waits for a connection to the unix socket, will only end on error or
+/// once the handler is closed
+fn unix_accept(
+    listener: &UnixListener,
+    shared: &Shared,
+    stopping: &AtomicBool,
 ) -> Result<(), Box<dyn Error>> {
-    // remove old file
-    if socket_path.exists() {
-        fs::remove_file(socket_path.as_ref())?;
-    }
-
-    // make a new buffer outside of the loop
-    let mut buffer = String::new();
-
-    let listener = UnixListener::bind(socket_path.as_ref())?;
-
-    // listener / accept loop, will only end on error
     loop {
         // get the stream, blocking, ignoring the socket addr
         let (stream, _) = listener
             .accept()
             .map_err(|err| format!("Error accepting stream: {}", err))?;
 
-        // TODO: this kinda sucks from here down
-        BufReader::new(&stream)
-            .read_line(&mut buffer)
-            .expect("cant get initial line");
+        if stopping.load(Ordering::SeqCst) {
+            return Ok(());
+        }
 
-        let stream_send = stream.try_clone()?;
+        let hangup = stream.try_clone().ok().map(|stream| {
+            Box::new(move || {
+                let _ = stream.shutdown(Shutdown::Both);
+            }) as Hangup
+        });
+
+        let _ = stream.set_read_timeout(Some(shared.handshake_timeout));
 
+        let settle = stream.try_clone().ok().map(|stream| {
+            Box::new(move || {
+                let _ = stream.set_read_timeout(None);
+            }) as Settle
+        });
+
+        spawn_stream_handler(Box::new(stream), hangup, settle, shared);
+    }
+}
+
+/// handle each connection on its own thread so a slow tls handshake cant hold
+/// up the accept loop
+fn spawn_stream_handler(
+    stream: BoxConnection,
+    hangup: Option<Hangup>,
+    settle: Option<Settle>,
+    shared: &Shared,
+) {
+    let shared = shared.clone();
+
+    thread::spawn(move || {
+        let key = hangup.map(|hangup| shared.connections.add(hangup));
+
+        if let Err(err) = stream_handler(stream, settle, &shared) {
+            shared.error(format!("Error handling stream: {}", err));
+        }
+
+        if let Some(key) = key {
+            shared.connections.remove(key);
+        }
+    });
+}
+
+/// read the first line from a new connection, check its token then ether,
+/// listen to take in lines from the socket
+/// or send lines to a client
+fn stream_handler(
+    stream: BoxConnection,
+    settle: Option<Settle>,
+    shared: &Shared,
+) -> Result<(), Box<dyn Error>> {
+    // keep the reader, it may have buffered lines past the first one
+    let mut reader = BufReader::new(stream);
+
+    let buffer = protocol::read_handshake(&mut reader)
+        .map_err(|err| format!("cant get initial line: {}", err))?;
+
+    // a producer can go quiet for as long as it likes after this
+    if let Some(settle) = settle {
+        settle();
+    }
+
+    let handshake = Handshake::parse(&buffer);
+
+    let allowed = shared.authorize(&handshake);
+
+    let stream = reader.get_mut();
+    stream.write_all(protocol::reply(&allowed).as_bytes())?;
+    stream.flush()?;
+
+    allowed?;
+
+    match handshake? {
         // get data from a cli tool, send to main loop
-        if buffer.starts_with("connect") {
+        Handshake::Connect { id, token, tags } => {
             // send first connect evt
-            main_sender.send(SendEvt::Connect(buffer.to_owned()))?;
+            if shared
+                .main_queue
+                .push(SendEvt::Connect(id.clone()))
+                .is_err()
+            {
+                return Ok(());
+            }
 
-            receiver_handler(stream_send, main_sender.clone());
+            if !tags.is_empty() {
+                let _ = shared.main_queue.push(SendEvt::Tags(id.clone(), tags));
+            }
 
+            receiver_handler(reader, &id, token, shared);
+        }
         // send data to a client
-        } else if buffer.starts_with("client") {
-            // let the main thread know to start sending
-            client_accept.store(true, Ordering::Relaxed);
+        Handshake::Client {
+            token,
+            history,
+            times,
+            finished,
+        } => {
+            let (ended, queue) =
+                shared.add_viewer(token, history, times, finished);
 
-            client_handler(stream_send, client_receiver.clone());
+            client_handler(reader.into_inner(), ended, &queue, times)?;
         }
+        report => {
+            let stream = reader.get_mut();
 
-        // clear the first line buffer
-        buffer.clear();
+            for line in shared.report(&report)? {
+                writeln!(stream, "{}", line)?;
+            }
+
+            stream.flush()?;
+        }
     }
+
+    Ok(())
 }
 
-/// spawn a thread to retrieve data from the cli handle
-/// send it to the main loop, the buffer stream will end on its own
-fn receiver_handler(share_stream: UnixStream, sender: mpsc::Sender<SendEvt>) {
-    thread::spawn(move || {
-        for line in BufReader::new(share_stream).lines() {
-            let evt = SendEvt::new(line.unwrap());
+/// retrieve data from the cli handle and send it to the main loop,
+/// the buffer stream will end on its own
+///
+/// with the block policy a full main queue stops this reading, which pushes
+/// back on the producer through the socket
+fn receiver_handler(
+    mut reader: BufReader<BoxConnection>,
+    session: &str,
+    token: Option<String>,
+    shared: &Shared,
+) {
+    let mut buf = Vec::new();
+
+    loop {
+        let line = match protocol::read_frame(&mut reader, &mut buf) {
+            Ok(Some(val)) => val,
+            Ok(None) => break,
+            Err(err) => {
+                shared.error(format!("Error reading from cli: {}", err));
+                break;
+            }
+        };
+
+        let evt = match Incoming::new(
+            line,
+            session,
+            token.as_deref(),
+            &shared.auth,
+        ) {
+            Incoming::Send(evt) => evt,
+            Incoming::End(exit) => {
+                if let Some(exit) = exit {
+                    let _ = shared.main_queue.push(exit);
+                }
+
+                break;
+            }
+            Incoming::Refused(why) => {
+                shared.error(format!("Dropping producer: {}", why));
+                break;
+            }
+        };
+
+        if let Err(err) = shared.main_queue.push(evt) {
+            shared.error(format!("Dropping producer {}: {:?}", session, err));
+            break;
+        }
+    }
 
-            sender.send(evt).expect("fucked sending cli event");
+    let _ = shared.main_queue.push(SendEvt::Closed(session.to_string()));
+}
+
+/// the last lines of a running session, read before the viewer is added
+struct Tail {
+    id: String,
+    /// none if it couldnt be read
+    lines: Option<Vec<SendEvt>>,
+    /// the last seq read, what comes after is caught up under the lock
+    seq: Option<u64>,
+}
+
+/// read the last lines of each running session the token covers
+fn read_history(
+    events: &OnEvent,
+    retention: &Retention,
+    auth: &Auth,
+    token: Option<&str>,
+    lines: u64,
+) -> Vec<Tail> {
+    let mut sessions = retention
+        .active()
+        .writing()
+        .into_iter()
+        .filter(|id| auth.covers(token, id))
+        .collect::<Vec<String>>();
+
+    sessions.sort();
+
+    sessions
+        .into_iter()
+        .map(|id| {
+            let session = retention.root().join(&id);
+
+            match read_tail(events, &session, &id, lines) {
+                Some((lines, seq)) => Tail {
+                    id,
+                    lines: Some(lines),
+                    seq,
+                },
+                None => Tail {
+                    id,
+                    lines: None,
+                    seq: None,
+                },
+            }
+        })
+        .collect()
+}
+
+/// queue the history read for each running session, with what they got
+/// since it was read, then the sessions that started since. a viewer that
+/// wants times also hears when each one opened. gives back the sessions it
+/// looked at, the viewer hears the rest of them live
+///
+/// this runs with the viewers locked, so only what came in since the read
+/// comes off the disk here. the viewer isnt reading yet so its queue cant be
+/// waited on, each session gets a share of the room and a gap for the lines
+/// that didnt fit
+fn load_history(
+    events: &OnEvent,
+    retention: &Retention,
+    auth: &Auth,
+    token: Option<&str>,
+    (lines, times): (u64, bool),
+    tails: Vec<Tail>,
+    queue: &Queue<SendEvt>,
+) -> Vec<String> {
+    let writing = retention.active().writing();
+
+    let read = tails.iter().map(|tail| &tail.id).collect::<HashSet<_>>();
+    let mut started = writing
+        .iter()
+        .filter(|id| !read.contains(id) && auth.covers(token, id))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    started.sort();
+
+    let looked = tails
+        .iter()
+        .map(|tail| tail.id.clone())
+        .chain(started.iter().cloned())
+        .collect();
+
+    let started = started.into_iter().map(|id| {
+        let session = retention.root().join(&id);
+        let read = read_tail(events, &session, &id, lines);
+
+        Tail {
+            id,
+            seq: read.as_ref().and_then(|(_, seq)| *seq),
+            lines: read.map(|(lines, _)| lines),
         }
     });
+
+    let sessions = tails.into_iter().chain(started).collect::<Vec<Tail>>();
+
+    let stats = queue.stats();
+    let capacity = stats.capacity.load(Ordering::Relaxed);
+    let room = || capacity.saturating_sub(stats.depth.load(Ordering::Relaxed));
+
+    let push = |evt| {
+        if room() > 0 {
+            let _ = queue.push(evt);
+        }
+    };
+
+    let count = sessions.len();
+
+    for (index, tail) in sessions.into_iter().enumerate() {
+        let session = retention.root().join(&tail.id);
+        let meta = SessionMeta::load(&session).ok().flatten();
+
+        // whats left is split between this and the sessions after it, less
+        // the opened, tags, gap, exit and end lines each of them can have
+        let left = count - index;
+        let share = room().saturating_sub(left * 5) / left;
+
+        if let Some(meta) = &meta {
+            if times {
+                push(SendEvt::Opened(tail.id.clone(), meta.opened));
+            }
+
+            if !meta.tags.is_empty() {
+                push(SendEvt::Tags(tail.id.clone(), meta.tags.clone()));
+            }
+        }
+
+        if let Some(mut lines) = tail.lines {
+            lines.extend(read_since(events, &session, &tail.id, tail.seq));
+
+            let cut = lines.len().saturating_sub(share);
+
+            if cut > 0 {
+                push(SendEvt::Gap(tail.id.clone(), cut as u64));
+            }
+
+            lines.into_iter().skip(cut).for_each(push);
+        }
+
+        // it ended between the read and now
+        if !writing.contains(&tail.id) {
+            if let Some(code) = meta.and_then(|meta| meta.exit) {
+                push(SendEvt::Exit(tail.id.clone(), code));
+            }
+
+            push(SendEvt::Closed(tail.id.clone()));
+        }
+    }
+
+    looked
 }
 
-fn client_handler(stream: UnixStream, receiver: ArcMutexReceiver) {
-    thread::spawn(move || {
-        let mut stream = stream;
-        let receiver = receiver.lock().expect("Client handler failed");
-
-        loop {
-            match receiver.recv().unwrap() {
-                SendEvt::SendString(mut val) => {
-                    val += "\n";
-                    stream
-                        .write_all(val.as_bytes())
-                        .expect("cant write to client");
-                }
-                SendEvt::Kill => break,
-                _ => continue,
-            };
+/// each finished session the token covers, imported ones too, oldest first.
+/// each one opens, has its tags and last lines from disk then ends
+fn finished_history(
+    events: &OnEvent,
+    retention: &Retention,
+    auth: &Auth,
+    token: Option<&str>,
+    lines: u64,
+    running: &[String],
+) -> Vec<SendEvt> {
+    let sessions = match list_sessions(retention.root()) {
+        Ok(val) => val,
+        Err(err) => {
+            let err = format!("Error listing finished sessions: {}", err);
+            events(&DaemonEvent::Error(err));
+            return Vec::new();
+        }
+    };
+
+    let mut history = Vec::new();
+
+    for info in sessions.iter().rev() {
+        let id = &info.name;
+
+        if running.contains(id)
+            || protocol::check_id(id).is_err()
+            || !auth.covers(token, id)
+        {
+            continue;
+        }
+
+        let tail = match read_tail(events, &info.path, id, lines) {
+            Some((val, _)) => val,
+            None => continue,
+        };
+
+        let meta = SessionMeta::load(&info.path).ok().flatten();
+
+        history.push(match &meta {
+            Some(meta) => SendEvt::Opened(id.clone(), meta.opened),
+            None => SendEvt::Connect(id.clone()),
+        });
+
+        if let Some(meta) = &meta {
+            if !meta.tags.is_empty() {
+                history.push(SendEvt::Tags(id.clone(), meta.tags.clone()));
+            }
+        }
+
+        history.extend(tail);
+
+        if let Some(code) = meta.and_then(|meta| meta.exit) {
+            history.push(SendEvt::Exit(id.clone(), code));
+        }
+
+        history.push(SendEvt::Closed(id.clone()));
+    }
+
+    history
+}
+
+/// the last lines of a session as viewers get them, with the seq of the
+/// last record read. none if it cant be read
+fn read_tail(
+    events: &OnEvent,
+    session: &Path,
+    id: &str,
+    lines: u64,
+) -> Option<(Vec<SendEvt>, Option<u64>)> {
+    let records =
+        SessionReader::open(session).and_then(|reader| reader.tail(lines));
+
+    match records {
+        Ok(records) => {
+            let seq = records.last().map(|record| record.seq);
+
+            Some((viewer_records(id, records), seq))
         }
+        Err(err) => {
+            let err = format!("Error loading history for {}: {}", id, err);
+            events(&DaemonEvent::Error(err));
+            None
+        }
+    }
+}
+
+/// the lines of a session after seq, every one with no seq
+fn read_since(
+    events: &OnEvent,
+    session: &Path,
+    id: &str,
+    seq: Option<u64>,
+) -> Vec<SendEvt> {
+    let records = SessionReader::open(session).and_then(|mut reader| {
+        if let Some(seq) = seq {
+            reader.seek_seq(seq + 1)?;
+        }
+
+        reader.collect::<io::Result<Vec<Record>>>()
     });
+
+    match records {
+        Ok(records) => viewer_records(id, records),
+        Err(err) => {
+            let err = format!("Error catching up history for {}: {}", id, err);
+            events(&DaemonEvent::Error(err));
+            Vec::new()
+        }
+    }
+}
+
+/// records as the lines viewers get, meta records arent sent
+fn viewer_records(id: &str, records: Vec<Record>) -> Vec<SendEvt> {
+    records
+        .into_iter()
+        .filter(|record| record.stream != Stream::Meta)
+        .map(|record| {
+            let line = format!("{} -ENDID- {}", id, record.text());
+            SendEvt::Stamped(line, record.ts)
+        })
+        .collect()
+}
+
+/// send the finished sessions, then lines from the viewers own queue until
+/// it is closed
+fn client_handler(
+    mut stream: BoxConnection,
+    ended: Vec<SendEvt>,
+    queue: &Queue<SendEvt>,
+    times: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut result = Ok(());
+    let events = ended.into_iter().chain(iter::from_fn(|| queue.pop()));
+
+    for evt in events {
+        if let SendEvt::Kill = evt {
+            break;
+        }
+
+        let line = match viewer_line(evt, times) {
+            Some(val) => val,
+            None => continue,
+        };
+
+        let written = stream
+            .write_all(line.as_bytes())
+            .and_then(|_| stream.flush());
+
+        if let Err(err) = written {
+            result = Err(Box::from(format!("cant write to client: {}", err)));
+            break;
+        }
+    }
+
+    // let the main loop know this viewer is gone
+    queue.close();
+
+    result
 }
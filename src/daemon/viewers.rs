@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::daemon::SendEvt;
+use crate::daemon::auth::Auth;
+use crate::daemon::queue::{Policy, PushError, Queue};
+
+struct Viewer {
+    queue: Queue<SendEvt>,
+    token: Option<String>,
+}
+
+/// every connected viewer, each with its own bounded queue so one slow viewer
+/// only costs its own memory
+#[derive(Clone)]
+pub struct Viewers {
+    viewers: Arc<Mutex<Vec<Viewer>>>,
+    auth: Arc<Auth>,
+    capacity: usize,
+    policy: Policy,
+    disconnected: Arc<AtomicU64>,
+}
+
+impl Viewers {
+    pub fn new(auth: Arc<Auth>, capacity: usize, policy: Policy) -> Self {
+        Viewers {
+            viewers: Arc::new(Mutex::new(Vec::new())),
+            auth,
+            capacity,
+            policy,
+            disconnected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// add a viewer, the queue is what its thread pops from
+    pub fn add(&self, token: Option<String>) -> Queue<SendEvt> {
+        self.add_with(token, |_| {})
+    }
+
+    /// add a viewer after preload has filled its queue
+    ///
+    /// nothing is published while preload runs, so a viewer loading history
+    /// from disk gets every line once and in order. producers wait on it, so
+    /// read the bulk of the history before and only catch up in here
+    pub fn add_with<F>(
+        &self,
+        token: Option<String>,
+        preload: F,
+    ) -> Queue<SendEvt>
+    where
+        F: FnOnce(&Queue<SendEvt>),
+    {
+        let queue = Queue::new(self.capacity, self.policy);
+        let mut viewers = self.lock();
+
+        preload(&queue);
+
+        viewers.push(Viewer {
+            queue: queue.clone(),
+            token,
+        });
+
+        queue
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// give an event to every viewer whose token covers its session
+    ///
+    /// viewers that are gone, overflowed or lost their token are removed
+    pub fn broadcast(&self, evt: &SendEvt) {
+        self.publish(evt, || ())
+    }
+
+    /// run write, storing the event, then broadcast it without letting a
+    /// viewer be added in between
+    pub fn publish<F, R>(&self, evt: &SendEvt, write: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let mut viewers = self.lock();
+        let written = write();
+
+        self.broadcast_to(&mut viewers, evt);
+
+        written
+    }
+
+    fn broadcast_to(&self, viewers: &mut Vec<Viewer>, evt: &SendEvt) {
+        let auth = &self.auth;
+
+        viewers.retain(|viewer| {
+            let token = viewer.token.as_deref();
+
+            if viewer.queue.is_closed() {
+                return false;
+            }
+
+            if let Some(session) = evt.session() {
+                if !auth.covers(token, session) {
+                    if auth.still_valid(token) {
+                        return true;
+                    }
+
+                    viewer.queue.close();
+                    return false;
+                }
+            }
+
+            match viewer.queue.push(evt.clone()) {
+                Ok(()) => true,
+                Err(PushError::Overflow) => {
+                    self.disconnected.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+                Err(PushError::Closed) => false,
+            }
+        });
+    }
+
+    /// close every viewers queue, their threads will end
+    pub fn close_all(&self) {
+        for viewer in self.lock().drain(..) {
+            viewer.queue.close();
+        }
+    }
+
+    /// a line per viewer queue and one for the disconnects
+    pub fn report(&self) -> Vec<String> {
+        let viewers = self.lock();
+
+        let mut lines = viewers
+            .iter()
+            .enumerate()
+            .map(|(index, viewer)| {
+                viewer.queue.stats().report(&format!("viewer.{}", index))
+            })
+            .collect::<Vec<String>>();
+
+        lines.push(format!(
+            "viewers connected={} disconnected_slow={}",
+            viewers.len(),
+            self.disconnected.load(Ordering::Relaxed)
+        ));
+
+        lines
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Viewer>> {
+        self.viewers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
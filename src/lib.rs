@@ -1,3 +1,9 @@
+pub mod ansi;
 pub mod client;
+pub mod config;
 pub mod daemon;
 pub mod events;
+pub mod protocol;
+pub mod storage;
+pub mod terminal;
+pub mod transport;
@@ -0,0 +1,529 @@
+use std::io;
+use std::error::Error;
+use std::io::{BufRead, Read};
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::record::Attrs;
+
+/// the first line a client sends after connecting
+///
+/// it is a kind followed by `-FLAG- value` pairs, like
+/// `connect -ID- build_1554 -TOKEN- abc`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Handshake {
+    /// a producer that will send lines for the given id, tagged with tags
+    Connect {
+        id: String,
+        token: Option<String>,
+        tags: Vec<String>,
+    },
+    /// a viewer that wants lines sent to it, after the last history lines
+    /// of each running session. with times each line comes with when the
+    /// daemon got it, with finished the history has the sessions that have
+    /// ended too
+    Client {
+        token: Option<String>,
+        history: Option<u64>,
+        times: bool,
+        finished: bool,
+    },
+    /// ask for the daemons queue metrics
+    Stats { token: Option<String> },
+    /// apply the retention rules now, or only list what they would remove
+    Gc {
+        token: Option<String>,
+        dry_run: bool,
+    },
+    /// compress every finished session now, or only the one named
+    Compact {
+        token: Option<String>,
+        session: Option<String>,
+    },
+}
+
+impl Handshake {
+    pub fn parse(line: &str) -> Result<Handshake, String> {
+        let mut words = line.split_whitespace();
+
+        let kind = words.next().ok_or("empty handshake")?;
+
+        let mut flags = HashMap::new();
+
+        while let Some(flag) = words.next() {
+            if flag.len() < 3 || !flag.starts_with('-') || !flag.ends_with('-')
+            {
+                return Err(format!("bad handshake flag: {}", flag));
+            }
+
+            let value = words
+                .next()
+                .ok_or_else(|| format!("no value for {}", flag))?;
+
+            flags.insert(flag, value.to_string());
+        }
+
+        let token = flags.remove("-TOKEN-");
+
+        match kind {
+            "connect" => {
+                let id = flags.remove("-ID-").ok_or("connect without -ID-")?;
+
+                check_id(&id)?;
+
+                let tags = match flags.remove("-TAGS-") {
+                    Some(val) => val.split(',').map(String::from).collect(),
+                    None => Vec::new(),
+                };
+
+                check_tags(&tags)?;
+
+                Ok(Handshake::Connect { id, token, tags })
+            }
+            "client" => {
+                let history = match flags.remove("-HISTORY-") {
+                    Some(val) => Some(
+                        val.parse()
+                            .map_err(|_| format!("bad -HISTORY-: {}", val))?,
+                    ),
+                    None => None,
+                };
+
+                let times = flags.remove("-TIMES-").as_deref() == Some("yes");
+                let finished =
+                    flags.remove("-FINISHED-").as_deref() == Some("yes");
+
+                Ok(Handshake::Client {
+                    token,
+                    history,
+                    times,
+                    finished,
+                })
+            }
+            "stats" => Ok(Handshake::Stats { token }),
+            "gc" => {
+                let dry_run =
+                    flags.remove("-DRYRUN-").as_deref() == Some("yes");
+
+                Ok(Handshake::Gc { token, dry_run })
+            }
+            "compact" => {
+                let session = flags.remove("-SESSION-");
+
+                if let Some(id) = &session {
+                    check_id(id)?;
+                }
+
+                Ok(Handshake::Compact { token, session })
+            }
+            _ => Err(format!("unknown handshake: {}", kind)),
+        }
+    }
+
+    /// the line to send, with the \n
+    pub fn to_line(&self) -> String {
+        let (mut line, token) = match self {
+            Handshake::Connect { id, token, tags } => {
+                let mut line = format!("connect -ID- {}", id);
+
+                if !tags.is_empty() {
+                    line.push_str(" -TAGS- ");
+                    line.push_str(&tags.join(","));
+                }
+
+                (line, token)
+            }
+            Handshake::Client {
+                token,
+                history,
+                times,
+                finished,
+            } => {
+                let mut line = "client".to_string();
+
+                if let Some(lines) = history {
+                    line.push_str(&format!(" -HISTORY- {}", lines));
+                }
+
+                if *times {
+                    line.push_str(" -TIMES- yes");
+                }
+
+                if *finished {
+                    line.push_str(" -FINISHED- yes");
+                }
+
+                (line, token)
+            }
+            Handshake::Stats { token } => ("stats".to_string(), token),
+            Handshake::Gc { token, dry_run } => {
+                let dry_run = if *dry_run { "yes" } else { "no" };
+
+                (format!("gc -DRYRUN- {}", dry_run), token)
+            }
+            Handshake::Compact { token, session } => match session {
+                Some(id) => (format!("compact -SESSION- {}", id), token),
+                None => ("compact".to_string(), token),
+            },
+        };
+
+        if let Some(token) = token {
+            line.push_str(" -TOKEN- ");
+            line.push_str(token);
+        }
+
+        line.push('\n');
+
+        line
+    }
+}
+
+/// the id is used as a file name on the daemons side and as one word of
+/// every line, every session name goes through this
+pub fn check_id(id: &str) -> Result<(), String> {
+    if id.is_empty()
+        || id.starts_with('.')
+        || id.contains(|c: char| c == '/' || c.is_whitespace())
+    {
+        return Err(format!("bad id: {}", id));
+    }
+
+    Ok(())
+}
+
+/// tags go comma separated in one handshake word
+pub fn check_tags(tags: &[String]) -> Result<(), String> {
+    for tag in tags {
+        if tag.is_empty()
+            || tag.contains(|c: char| c == ',' || c.is_whitespace())
+        {
+            return Err(format!("bad tag: {:?}", tag));
+        }
+    }
+
+    Ok(())
+}
+
+/// the last line a producer sends, with how its command exited if it knows
+pub fn end_line(exit: Option<i32>) -> String {
+    match exit {
+        Some(code) => format!("end -EXIT- {}\n", code),
+        None => "end\n".to_string(),
+    }
+}
+
+/// if a line from a producer is its end line, with or without an exit
+pub fn is_end_line(line: &str) -> bool {
+    line == "end" || line.starts_with("end -EXIT- ")
+}
+
+/// the exit status from an end line, none if it has none
+pub fn exit_status(line: &str) -> Option<i32> {
+    line.strip_prefix("end -EXIT- ")?.trim().parse().ok()
+}
+
+/// a line off the wire without its \n, only that is taken off so a \r at
+/// the end is kept. bytes that arent utf8 are replaced rather than dropping
+/// the connection
+pub fn decode_frame(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// the longest first line the daemon reads, before it knows who is there
+pub const MAX_HANDSHAKE: u64 = 64 * 1024;
+/// the longest line a producer can send with its newline, well inside what
+/// a record holds
+pub const MAX_FRAME: u64 = 16 * 1024 * 1024;
+
+/// an error when a read of up to cap bytes stopped without reaching the end
+/// of the line, the connection should be dropped
+pub fn check_capped(buf: &[u8], read: usize, cap: u64) -> io::Result<()> {
+    if read as u64 >= cap && !buf.ends_with(b"\n") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line longer than {} bytes", cap),
+        ));
+    }
+
+    Ok(())
+}
+
+/// the next line of at most cap bytes, 0 once the connection is closed
+fn read_capped<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    cap: u64,
+) -> io::Result<usize> {
+    let read = reader.take(cap).read_until(b'\n', buf)?;
+    check_capped(buf, read, cap)?;
+
+    Ok(read)
+}
+
+/// the first line from a new connection, an error if it is too long
+pub fn read_handshake<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut buf = Vec::new();
+    read_capped(reader, &mut buf, MAX_HANDSHAKE)?;
+
+    String::from_utf8(buf)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// the next line from a connection, none once it is closed. buf is reused
+/// between calls. a line over MAX_FRAME is an error
+pub fn read_frame<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+) -> io::Result<Option<String>> {
+    buf.clear();
+
+    match read_capped(reader, buf, MAX_FRAME)? {
+        0 => Ok(None),
+        _ => Ok(Some(decode_frame(buf))),
+    }
+}
+
+/// the session and payload of a `<id> -ENDID- <payload>` line
+///
+/// the id is only ever the first word, the same one the daemon checks
+/// against the session a producer connected as
+pub fn split_line(line: &str) -> Option<(&str, &str)> {
+    let (id, rest) = line.split_once(' ')?;
+
+    if id.is_empty() {
+        return None;
+    }
+
+    match rest.strip_prefix("-ENDID-")? {
+        // an empty payload loses its trailing space on the way
+        "" => Some((id, "")),
+        payload => payload.strip_prefix(' ').map(|text| (id, text)),
+    }
+}
+
+/// the session, time and payload of a `<id> -AT- <millis> -ENDID- <payload>`
+/// line, the way a viewer that asked for times gets each line
+pub fn split_stamped(line: &str) -> Option<(&str, u64, &str)> {
+    let (id, rest) = line.split_once(" -AT- ")?;
+
+    if id.is_empty() || id.contains(' ') {
+        return None;
+    }
+
+    let (at, rest) = rest.split_once(' ')?;
+    let at = at.parse().ok()?;
+
+    match rest.strip_prefix("-ENDID-")? {
+        "" => Some((id, at, "")),
+        payload => payload.strip_prefix(' ').map(|text| (id, at, text)),
+    }
+}
+
+/// the json after `-RECORD-` in a producer line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordLine {
+    pub line: String,
+    #[serde(default)]
+    pub attrs: Attrs,
+}
+
+/// a producer line with attributes, `<id> -RECORD- {"line":..,"attrs":..}`
+///
+/// the text is json encoded so nothing in it or the attributes can be
+/// mistaken for the separator
+pub fn record_line(id: &str, line: &str, attrs: &Attrs) -> String {
+    let record = RecordLine {
+        line: line.to_string(),
+        attrs: attrs.clone(),
+    };
+
+    let json = serde_json::to_string(&record).unwrap_or_default();
+
+    format!("{} -RECORD- {}\n", id, json)
+}
+
+/// the session, text and attributes of a producer line of either kind
+pub fn parse_producer_line(line: &str) -> Option<(&str, RecordLine)> {
+    if let Some((id, json)) = line.split_once(" -RECORD- ") {
+        if !id.contains(' ') {
+            return serde_json::from_str(json).ok().map(|record| (id, record));
+        }
+    }
+
+    split_line(line).map(|(id, text)| {
+        let record = RecordLine {
+            line: text.to_string(),
+            attrs: Attrs::new(),
+        };
+
+        (id, record)
+    })
+}
+
+/// the line a viewer gets when lines for a session were dropped
+pub fn gap_line(id: &str, dropped: u64) -> String {
+    format!("gap -ID- {} -DROPPED- {}\n", id, dropped)
+}
+
+/// the session and count from a gap line, none if it isnt one
+pub fn parse_gap(line: &str) -> Option<(String, u64)> {
+    let words = line.split_whitespace().collect::<Vec<&str>>();
+
+    match words.as_slice() {
+        ["gap", "-ID-", id, "-DROPPED-", dropped] => {
+            Some((id.to_string(), dropped.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// a line a viewer is sent
+///
+/// session lines are `<id> -ENDID- <text>`, the rest say something about a
+/// session and are `<kind> -ID- <id> ...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViewerLine {
+    Line {
+        session: String,
+        text: String,
+    },
+    /// a line with the unix millis the daemon got it at, for viewers that
+    /// asked for times
+    Stamped {
+        session: String,
+        at: u64,
+        text: String,
+    },
+    Gap {
+        session: String,
+        dropped: u64,
+    },
+    /// a producer connected
+    Started(String),
+    /// a session started, with the unix millis the daemon opened it at, for
+    /// viewers that asked for times
+    Opened(String, u64),
+    /// the tags a producer gave its session
+    Tagged(String, Vec<String>),
+    /// the producer said how its command exited, its session ends next
+    Exited(String, i32),
+    /// the producer is gone
+    Ended(String),
+}
+
+impl ViewerLine {
+    pub fn parse(line: &str) -> Option<ViewerLine> {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+
+        if let Some((session, dropped)) = parse_gap(line) {
+            return Some(ViewerLine::Gap { session, dropped });
+        }
+
+        let words = line.split(' ').collect::<Vec<&str>>();
+
+        match words.as_slice() {
+            ["started", "-ID-", id] => {
+                Some(ViewerLine::Started(id.to_string()))
+            }
+            ["started", "-ID-", id, "-AT-", at] => {
+                Some(ViewerLine::Opened(id.to_string(), at.parse().ok()?))
+            }
+            ["ended", "-ID-", id] => Some(ViewerLine::Ended(id.to_string())),
+            ["tagged", "-ID-", id, "-TAGS-", tags] => {
+                let tags =
+                    tags.split(',').map(String::from).collect::<Vec<_>>();
+                check_tags(&tags).ok()?;
+
+                Some(ViewerLine::Tagged(id.to_string(), tags))
+            }
+            ["exited", "-ID-", id, "-CODE-", code] => {
+                Some(ViewerLine::Exited(id.to_string(), code.parse().ok()?))
+            }
+            _ => match split_stamped(line) {
+                Some((id, at, text)) => Some(ViewerLine::Stamped {
+                    session: id.to_string(),
+                    at,
+                    text: text.to_string(),
+                }),
+                None => split_line(line).map(|(id, text)| ViewerLine::Line {
+                    session: id.to_string(),
+                    text: text.to_string(),
+                }),
+            },
+        }
+    }
+
+    /// the line to send, with the \n
+    pub fn to_line(&self) -> String {
+        match self {
+            ViewerLine::Line { session, text } => {
+                format!("{} -ENDID- {}\n", session, text)
+            }
+            ViewerLine::Stamped { session, at, text } => {
+                format!("{} -AT- {} -ENDID- {}\n", session, at, text)
+            }
+            ViewerLine::Gap { session, dropped } => gap_line(session, *dropped),
+            ViewerLine::Started(id) => format!("started -ID- {}\n", id),
+            ViewerLine::Opened(id, at) => {
+                format!("started -ID- {} -AT- {}\n", id, at)
+            }
+            ViewerLine::Tagged(id, tags) => {
+                format!("tagged -ID- {} -TAGS- {}\n", id, tags.join(","))
+            }
+            ViewerLine::Exited(id, code) => {
+                format!("exited -ID- {} -CODE- {}\n", id, code)
+            }
+            ViewerLine::Ended(id) => format!("ended -ID- {}\n", id),
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        match self {
+            ViewerLine::Line { session, .. }
+            | ViewerLine::Stamped { session, .. }
+            | ViewerLine::Gap { session, .. }
+            | ViewerLine::Started(session)
+            | ViewerLine::Opened(session, _)
+            | ViewerLine::Tagged(session, _)
+            | ViewerLine::Exited(session, _)
+            | ViewerLine::Ended(session) => session,
+        }
+    }
+}
+
+/// the daemons answer to a handshake, `ok` or `error <why>`
+pub fn reply(result: &Result<(), String>) -> String {
+    match result {
+        Ok(()) => "ok\n".to_string(),
+        Err(err) => format!("error {}\n", err),
+    }
+}
+
+/// read the daemons answer to a handshake
+pub fn read_reply<R: BufRead>(reader: &mut R) -> Result<(), Box<dyn Error>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    Ok(parse_reply(&line)?)
+}
+
+/// what the daemon said to a handshake, an empty line is a closed connection
+pub fn parse_reply(line: &str) -> Result<(), String> {
+    if line.is_empty() {
+        return Err("daemon closed the connection".to_string());
+    }
+
+    let line = line.trim_end();
+
+    if line == "ok" {
+        return Ok(());
+    }
+
+    match line.strip_prefix("error ") {
+        Some(why) => Err(format!("Daemon refused: {}", why)),
+        None => Err(format!("bad reply from daemon: {}", line)),
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use flate2::Compression as GzLevel;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+
+use crate::storage::{list_sessions, SessionInfo};
+
+pub const ZSTD_LEVEL: i32 = 3;
+
+/// what a finished sessions segments are compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// the extension added after `.log`
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// what a segment file was compressed with, going by its name
+    pub fn of(path: &Path) -> Compression {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// read a segment, compressed or not
+pub fn open_segment(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path)?;
+
+    let reader: Box<dyn BufRead + Send> = match Compression::of(path) {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(GzDecoder::new(file))),
+        Compression::Zstd => {
+            Box::new(BufReader::new(zstd::Decoder::new(file)?))
+        }
+    };
+
+    Ok(reader)
+}
+
+/// compress a plain segment next to itself then remove the plain one,
+/// returns the new path
+///
+/// the compressed file is written under a hidden name and renamed into place
+/// so a reader never sees half of it
+pub fn compress_segment(
+    path: &Path,
+    compression: Compression,
+) -> io::Result<PathBuf> {
+    let ext = match compression.extension() {
+        Some(val) => val,
+        None => return Ok(path.to_owned()),
+    };
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::other("bad segment name"))?;
+
+    let done = path.with_file_name(format!("{}.{}", name, ext));
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, ext));
+
+    let mut input = File::open(path)?;
+    let output = BufWriter::new(File::create(&tmp)?);
+
+    let file = match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(output, GzLevel::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        _ => {
+            let mut encoder = zstd::Encoder::new(output, ZSTD_LEVEL)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+    };
+
+    let file = file.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+
+    // keep the age of the session the same
+    if let Ok(modified) = input.metadata().and_then(|meta| meta.modified()) {
+        file.set_modified(modified)?;
+    }
+
+    fs::rename(&tmp, &done)?;
+    fs::remove_file(path)?;
+
+    Ok(done)
+}
+
+/// one session that was or would be compressed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactAction {
+    pub name: String,
+    pub segments: usize,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl fmt::Display for CompactAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "compressed {} ({} segments, {} -> {} bytes)",
+            self.name, self.segments, self.before, self.after
+        )
+    }
+}
+
+/// the plain segments of a finished session, none for legacy single files
+fn plain_segments(session: &SessionInfo) -> Vec<&PathBuf> {
+    if !session.path.is_dir() {
+        return Vec::new();
+    }
+
+    session
+        .segments
+        .iter()
+        .filter(|path| Compression::of(path) == Compression::None)
+        .collect()
+}
+
+/// compress the finished sessions under root that have sat for idle, all of
+/// them now if idle is none, or only the one named
+pub fn compact(
+    root: &Path,
+    compression: Compression,
+    active: &HashSet<String>,
+    idle: Option<Duration>,
+    only: Option<&str>,
+) -> io::Result<Vec<CompactAction>> {
+    let mut actions = Vec::new();
+
+    if compression == Compression::None || !root.exists() {
+        return Ok(actions);
+    }
+
+    let now = SystemTime::now();
+
+    for session in list_sessions(root)? {
+        if active.contains(&session.name)
+            || only.is_some_and(|name| name != session.name)
+        {
+            continue;
+        }
+
+        if let Some(action) = compact_session(&session, compression, idle, now)?
+        {
+            actions.push(action);
+        }
+    }
+
+    Ok(actions)
+}
+
+/// compress the finished segments of one session if it has sat for idle,
+/// none if there was nothing to do. nothing may be writing to it
+pub fn compact_session(
+    session: &SessionInfo,
+    compression: Compression,
+    idle: Option<Duration>,
+    now: SystemTime,
+) -> io::Result<Option<CompactAction>> {
+    if compression == Compression::None {
+        return Ok(None);
+    }
+
+    let age = now.duration_since(session.modified).unwrap_or_default();
+
+    if idle.is_some_and(|idle| age < idle) {
+        return Ok(None);
+    }
+
+    let plain = plain_segments(session);
+
+    if plain.is_empty() {
+        return Ok(None);
+    }
+
+    let mut action = CompactAction {
+        name: session.name.clone(),
+        segments: plain.len(),
+        before: 0,
+        after: 0,
+    };
+
+    for path in plain {
+        action.before += fs::metadata(path)?.len();
+
+        let done = compress_segment(path, compression)?;
+        action.after += fs::metadata(done)?.len();
+    }
+
+    Ok(Some(action))
+}
+
+/// used by writers to never append to a compressed segment
+pub fn is_compressed(path: &Path) -> bool {
+    Compression::of(path) != Compression::None
+}
@@ -0,0 +1,399 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::json;
+
+use crate::ansi::{self, Style};
+use crate::storage::meta::{SessionMeta, SessionState};
+use crate::storage::reader::SessionReader;
+use crate::storage::record::{Record, Stream};
+use crate::terminal::Screen;
+
+/// what a session can be exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// the lines as they were sent
+    Txt,
+    /// an object per line with its seq, time, stream and attributes
+    Jsonl,
+    /// a page with the colours rendered and the session details on top
+    Html,
+    /// an asciinema v2 recording, played back at the recorded times
+    Asciicast,
+    /// the text as an 80 by 24 terminal would have drawn it, for sessions
+    /// captured from a pty that redraw themselves
+    Screen,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "txt" => Ok(Format::Txt),
+            "jsonl" => Ok(Format::Jsonl),
+            "html" => Ok(Format::Html),
+            "asciicast" => Ok(Format::Asciicast),
+            "screen" => Ok(Format::Screen),
+            _ => Err(format!("unknown format: {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::Txt => "txt",
+            Format::Jsonl => "jsonl",
+            Format::Html => "html",
+            Format::Asciicast => "asciicast",
+            Format::Screen => "screen",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// which records to export, every bound is inclusive and times are unix
+/// millis
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Range {
+    pub from_seq: Option<u64>,
+    pub to_seq: Option<u64>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl Range {
+    pub fn contains(&self, record: &Record) -> bool {
+        self.from_seq.is_none_or(|from| record.seq >= from)
+            && self.to_seq.is_none_or(|to| record.seq <= to)
+            && self.since.is_none_or(|since| record.ts >= since)
+            && self.until.is_none_or(|until| record.ts <= until)
+    }
+
+    /// past the end of the range, nothing after record can be in it
+    fn is_past(&self, record: &Record) -> bool {
+        self.to_seq.is_some_and(|to| record.seq > to)
+    }
+}
+
+/// write the records of the session at path in range out as format,
+/// returning how many were written
+pub fn export<W: Write>(
+    path: &Path,
+    format: Format,
+    range: &Range,
+    out: &mut W,
+) -> io::Result<u64> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut reader = SessionReader::open(path)?;
+
+    if let Some(from) = range.from_seq {
+        reader.seek_seq(from)?;
+    }
+
+    if let Some(since) = range.since {
+        reader.seek_ts(since)?;
+    }
+
+    let mut records = Vec::new();
+
+    for record in reader {
+        let record = record?;
+
+        if range.is_past(&record) {
+            break;
+        }
+
+        if range.contains(&record) {
+            records.push(record);
+        }
+    }
+
+    match format {
+        Format::Txt => write_txt(&records, out)?,
+        Format::Jsonl => write_jsonl(&records, out)?,
+        Format::Html => {
+            let meta = if path.is_dir() {
+                SessionMeta::load(path)?
+            } else {
+                None
+            };
+
+            write_html(&name, meta.as_ref(), &records, out)?
+        }
+        Format::Asciicast => write_asciicast(&name, &records, out)?,
+        Format::Screen => write_screen(&records, out)?,
+    }
+
+    out.flush()?;
+
+    Ok(records.len() as u64)
+}
+
+fn write_txt<W: Write>(records: &[Record], out: &mut W) -> io::Result<()> {
+    for record in records {
+        out.write_all(&record.payload)?;
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn write_jsonl<W: Write>(records: &[Record], out: &mut W) -> io::Result<()> {
+    for record in records {
+        let mut line = json!({
+            "seq": record.seq,
+            "ts": record.ts,
+            "stream": record.stream.name(),
+            "line": record.text(),
+        });
+
+        if !record.attrs.is_empty() {
+            line["attrs"] = json!(record.attrs);
+        }
+
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+/// asciicast v2, a header object then `[secs, "o", text]` per line
+///
+/// times are from the first exported line and meta lines are left out as
+/// they were never on the terminal
+fn write_asciicast<W: Write>(
+    name: &str,
+    records: &[Record],
+    out: &mut W,
+) -> io::Result<()> {
+    let start = records.first().map(|record| record.ts).unwrap_or_default();
+
+    let header = json!({
+        "version": 2,
+        "width": 80,
+        "height": 24,
+        "timestamp": start / 1000,
+        "title": name,
+    });
+
+    writeln!(out, "{}", header)?;
+
+    for record in records.iter().filter(|rec| rec.stream != Stream::Meta) {
+        let at = record.ts.saturating_sub(start) as f64 / 1000.0;
+        let event = json!([at, "o", format!("{}\r\n", record.text())]);
+
+        writeln!(out, "{}", event)?;
+    }
+
+    Ok(())
+}
+
+/// every line played into a terminal, then what scrolled off the top and
+/// what was left on the screen
+fn write_screen<W: Write>(records: &[Record], out: &mut W) -> io::Result<()> {
+    let mut screen = Screen::new(80, 24).with_scrollback(usize::MAX);
+
+    for record in records.iter().filter(|rec| rec.stream != Stream::Meta) {
+        screen.feed_line(&record.text());
+    }
+
+    for line in screen.text() {
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+fn write_html<W: Write>(
+    name: &str,
+    meta: Option<&SessionMeta>,
+    records: &[Record],
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape(name))?;
+    writeln!(
+        out,
+        "<style>\n\
+         body {{ background: #1e1e1e; color: #e5e5e5; \
+         font-family: sans-serif; }}\n\
+         table {{ margin-bottom: 1em; }}\n\
+         th {{ text-align: left; padding-right: 1em; }}\n\
+         pre {{ font-family: monospace; }}\n\
+         .meta {{ color: #888; font-style: italic; }}\n\
+         .err {{ color: #f14c4c; }}\n\
+         </style>"
+    )?;
+    writeln!(out, "</head>\n<body>")?;
+
+    let state = match meta.map(|meta| meta.state) {
+        Some(SessionState::Open) => "open",
+        Some(SessionState::Closed) => "closed",
+        Some(SessionState::Interrupted) => "interrupted",
+        None => "unknown",
+    };
+
+    let mut details =
+        vec![("session", name.to_string()), ("state", state.into())];
+
+    if let Some(meta) = meta {
+        details.push(("opened", utc(meta.opened)));
+
+        if let Some(closed) = meta.closed {
+            details.push(("closed", utc(closed)));
+        }
+    }
+
+    if let (Some(first), Some(last)) = (records.first(), records.last()) {
+        details
+            .push(("first line", format!("{} {}", first.seq, utc(first.ts))));
+        details.push(("last line", format!("{} {}", last.seq, utc(last.ts))));
+    }
+
+    details.push(("lines", records.len().to_string()));
+
+    writeln!(out, "<table>")?;
+
+    for (key, val) in details {
+        writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", key, escape(&val))?;
+    }
+
+    writeln!(out, "</table>\n<pre>")?;
+
+    // colours carry over from one line to the next like on a terminal
+    let mut style = Style::default();
+
+    for record in records {
+        let class = match record.stream {
+            Stream::Out => None,
+            Stream::Err => Some("err"),
+            Stream::Meta => Some("meta"),
+        };
+
+        if let Some(class) = class {
+            write!(out, "<span class=\"{}\">", class)?;
+        }
+
+        let (spans, next) = ansi::parse(&record.text(), style);
+        style = next;
+
+        for span in spans {
+            let css = css(&span.style);
+
+            if css.is_empty() {
+                write!(out, "{}", escape(&span.text))?;
+            } else {
+                write!(
+                    out,
+                    "<span style=\"{}\">{}</span>",
+                    css,
+                    escape(&span.text)
+                )?;
+            }
+        }
+
+        if class.is_some() {
+            write!(out, "</span>")?;
+        }
+
+        writeln!(out)?;
+    }
+
+    writeln!(out, "</pre>\n</body>\n</html>")?;
+
+    Ok(())
+}
+
+/// the inline css for a style, empty for the default one
+fn css(style: &Style) -> String {
+    let (fg, bg) = if style.inverse {
+        (
+            style.bg.or(Some(ansi::Color::Indexed(0))),
+            style.fg.or(Some(ansi::Color::Indexed(7))),
+        )
+    } else {
+        (style.fg, style.bg)
+    };
+
+    let mut rules = Vec::new();
+
+    if let Some(fg) = fg {
+        rules.push(format!("color: {}", fg.to_hex()));
+    }
+
+    if let Some(bg) = bg {
+        rules.push(format!("background: {}", bg.to_hex()));
+    }
+
+    if style.bold {
+        rules.push("font-weight: bold".to_string());
+    }
+
+    if style.dim {
+        rules.push("opacity: 0.6".to_string());
+    }
+
+    if style.italic {
+        rules.push("font-style: italic".to_string());
+    }
+
+    if style.underline {
+        rules.push("text-decoration: underline".to_string());
+    }
+
+    rules.join("; ")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// unix millis as `2019-04-01 12:00:00 UTC`
+pub fn utc(millis: u64) -> String {
+    let secs = millis / 1000;
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // days to a civil date, from howard hinnants date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
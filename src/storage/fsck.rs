@@ -0,0 +1,376 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression as GzLevel;
+use flate2::write::GzEncoder;
+
+use crate::storage::{index_path, list_sessions, now_millis, SessionInfo};
+use crate::storage::compress::{Compression, ZSTD_LEVEL};
+use crate::storage::index::{
+    Index, IndexEntry, INDEX_EVERY_BYTES, INDEX_EVERY_RECORDS,
+};
+use crate::storage::meta::{SessionMeta, SessionState};
+use crate::storage::reader::SegmentReader;
+use crate::storage::record::{Record, RecordError, SegmentHeader};
+
+/// something wrong with a segment
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// the segment ends part way through a record
+    Torn { offset: u64 },
+    /// a whole record that fails its checksum, the rest was still read
+    Corrupt { offset: u64, why: String },
+    /// the records stop making sense at offset, nothing after is readable
+    Framing { offset: u64, why: String },
+    /// the header or the compression is broken, nothing is readable
+    Unreadable { why: String },
+    /// an index entry doesnt point at its record
+    Index { why: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Torn { offset } => {
+                write!(f, "torn record at byte {}", offset)
+            }
+            Problem::Corrupt { offset, why } => {
+                write!(f, "bad record at byte {}: {}", offset, why)
+            }
+            Problem::Framing { offset, why } => {
+                write!(f, "unreadable from byte {}: {}", offset, why)
+            }
+            Problem::Unreadable { why } => write!(f, "unreadable: {}", why),
+            Problem::Index { why } => write!(f, "bad index: {}", why),
+        }
+    }
+}
+
+/// what was found in one segment
+#[derive(Debug, Clone)]
+pub struct SegmentCheck {
+    pub session: String,
+    pub path: PathBuf,
+    pub records: u64,
+    pub problems: Vec<Problem>,
+    pub repaired: bool,
+}
+
+impl SegmentCheck {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for SegmentCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+
+        for (index, problem) in self.problems.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}/{}: {}", self.session, name, problem)?;
+        }
+
+        if self.repaired {
+            write!(f, " (repaired, {} records kept)", self.records)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// read every record of a segment, the good ones and where they start
+fn scan(
+    path: &Path,
+) -> (Option<SegmentHeader>, Vec<(u64, Record)>, Vec<Problem>) {
+    let mut records = Vec::new();
+    let mut problems = Vec::new();
+
+    let mut reader = match SegmentReader::open(path) {
+        Ok(val) => val,
+        Err(err) => {
+            let why = err.to_string();
+            return (None, records, vec![Problem::Unreadable { why }]);
+        }
+    };
+
+    let header = match reader.header.clone() {
+        Some(val) => val,
+        // a legacy segment has no records to check
+        None => return (None, records, problems),
+    };
+
+    loop {
+        let offset = reader.offset;
+
+        match reader.next_record() {
+            Ok(Some(record)) => records.push((offset, record)),
+            Ok(None) => break,
+            Err(err) => {
+                let problem = match RecordError::of(&err) {
+                    Some(RecordError::Torn) => Problem::Torn { offset },
+                    Some(RecordError::Corrupt { why, .. }) => {
+                        // the bad record was read through, keep going
+                        problems.push(Problem::Corrupt {
+                            offset,
+                            why: why.clone(),
+                        });
+
+                        continue;
+                    }
+                    Some(RecordError::Framing(why)) => Problem::Framing {
+                        offset,
+                        why: why.clone(),
+                    },
+                    // a compressed segment cut short or damaged
+                    None => Problem::Framing {
+                        offset,
+                        why: err.to_string(),
+                    },
+                };
+
+                problems.push(problem);
+                break;
+            }
+        }
+    }
+
+    (Some(header), records, problems)
+}
+
+/// the index entries must each land on the record with the same seq
+fn check_index(path: &Path, records: &[(u64, Record)]) -> Option<Problem> {
+    let index = match Index::load(&index_path(path)) {
+        Ok(val) => val,
+        Err(err) => {
+            return Some(Problem::Index {
+                why: err.to_string(),
+            })
+        }
+    };
+
+    if index.entries.is_empty() && !records.is_empty() {
+        let why = "missing".to_string();
+        return Some(Problem::Index { why });
+    }
+
+    for entry in &index.entries {
+        let found = records.iter().any(|(offset, record)| {
+            *offset == entry.offset
+                && record.seq == entry.seq
+                && record.ts == entry.ts
+        });
+
+        if !found {
+            let why = format!("seq {} at byte {}", entry.seq, entry.offset);
+            return Some(Problem::Index { why });
+        }
+    }
+
+    None
+}
+
+/// check one segment, rewriting it with only its good records if repair
+pub fn check_segment(
+    session: &str,
+    path: &Path,
+    repair: bool,
+) -> io::Result<SegmentCheck> {
+    let (header, records, mut problems) = scan(path);
+
+    if header.is_some() {
+        problems.extend(check_index(path, &records));
+    }
+
+    let mut check = SegmentCheck {
+        session: session.to_string(),
+        path: path.to_owned(),
+        records: records.len() as u64,
+        problems,
+        repaired: false,
+    };
+
+    if let (Some(header), true, false) = (header, repair, check.is_ok()) {
+        rewrite_segment(path, &header, &records)?;
+        check.repaired = true;
+    }
+
+    Ok(check)
+}
+
+/// write a segment and its index again from the records kept
+///
+/// both are written under hidden names and moved into place
+fn rewrite_segment(
+    path: &Path,
+    header: &SegmentHeader,
+    records: &[(u64, Record)],
+) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::other("bad segment name"))?;
+
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    let index = index_path(path);
+    let index_tmp = index.with_file_name(format!(".{}.idx.tmp", name));
+
+    let mut bytes = Vec::new();
+    let mut entries = Vec::new();
+
+    let mut written = header.write(&mut bytes)?;
+    let (mut since_records, mut since_bytes) = (INDEX_EVERY_RECORDS, 0);
+
+    for (_, record) in records {
+        if since_records >= INDEX_EVERY_RECORDS
+            || since_bytes >= INDEX_EVERY_BYTES
+        {
+            entries.push(IndexEntry {
+                seq: record.seq,
+                ts: record.ts,
+                offset: written,
+            });
+
+            since_records = 0;
+            since_bytes = 0;
+        }
+
+        let size = record.write(&mut bytes)?;
+
+        written += size;
+        since_records += 1;
+        since_bytes += size;
+    }
+
+    let out = BufWriter::new(File::create(&tmp)?);
+
+    let file = match Compression::of(path) {
+        Compression::None => {
+            let mut out = out;
+            out.write_all(&bytes)?;
+            out.into_inner().map_err(|err| err.into_error())?
+        }
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(out, GzLevel::default());
+            encoder.write_all(&bytes)?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(out, ZSTD_LEVEL)?;
+            encoder.write_all(&bytes)?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+        }
+    };
+
+    file.sync_all()?;
+
+    let mut index_file = File::create(&index_tmp)?;
+
+    for entry in entries {
+        index_file.write_all(&entry.to_bytes())?;
+    }
+
+    index_file.sync_all()?;
+
+    fs::rename(&tmp, path)?;
+    fs::rename(&index_tmp, &index)
+}
+
+/// check every segment of a session
+pub fn check_session(
+    session: &SessionInfo,
+    repair: bool,
+) -> io::Result<Vec<SegmentCheck>> {
+    session
+        .segments
+        .iter()
+        .map(|path| check_segment(&session.name, path, repair))
+        .collect()
+}
+
+/// what fsck found across the log root
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub sessions: u64,
+    pub segments: u64,
+    /// sessions the daemon has open, left alone
+    pub skipped: Vec<String>,
+    pub damaged: Vec<SegmentCheck>,
+}
+
+impl FsckReport {
+    /// true if there is damage that wasnt repaired
+    pub fn has_damage(&self) -> bool {
+        self.damaged.iter().any(|check| !check.repaired)
+    }
+}
+
+/// check every session under root, repairing damage if asked
+///
+/// open sessions are skipped, the daemon may be writing to them and it
+/// recovers them itself when it starts
+pub fn fsck(root: &Path, repair: bool) -> io::Result<FsckReport> {
+    let mut report = FsckReport::default();
+
+    for session in list_sessions(root)? {
+        let meta = SessionMeta::load(&session.path).unwrap_or_default();
+
+        if meta.is_some_and(|meta| meta.state == SessionState::Open) {
+            report.skipped.push(session.name);
+            continue;
+        }
+
+        report.sessions += 1;
+        report.segments += session.segments.len() as u64;
+
+        let checks = check_session(&session, repair)?;
+        report
+            .damaged
+            .extend(checks.into_iter().filter(|check| !check.is_ok()));
+    }
+
+    Ok(report)
+}
+
+/// at startup, repair the sessions the daemon had open when it stopped and
+/// mark them interrupted
+pub fn recover(root: &Path) -> io::Result<Vec<SegmentCheck>> {
+    let mut repaired = Vec::new();
+
+    if !root.exists() {
+        return Ok(repaired);
+    }
+
+    for session in list_sessions(root)? {
+        let mut meta = match SessionMeta::load(&session.path) {
+            Ok(Some(meta)) if meta.state == SessionState::Open => meta,
+            _ => continue,
+        };
+
+        // only the newest segment was being written to
+        if let Some(last) = session.segments.last() {
+            let check = check_segment(&session.name, last, true)?;
+
+            if !check.is_ok() {
+                repaired.push(check);
+            }
+        }
+
+        meta.state = SessionState::Interrupted;
+        meta.closed = Some(now_millis());
+        meta.save(&session.path)?;
+    }
+
+    Ok(repaired)
+}
@@ -0,0 +1,282 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use crate::protocol;
+use crate::storage::export::Format;
+use crate::storage::meta::{SessionMeta, SessionState};
+use crate::storage::record::{Attrs, Stream};
+use crate::storage::{now_millis, Storage};
+
+/// what an import added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imported {
+    pub name: String,
+    pub lines: u64,
+    /// unix millis of the first and last line
+    pub first: u64,
+    pub last: u64,
+}
+
+/// the format of a file to import going by its extension, `.jsonl` and
+/// `.cast` or else plain text
+pub fn guess_format(path: &Path) -> Format {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("jsonl") | Some("json") => Format::Jsonl,
+        Some("cast") => Format::Asciicast,
+        _ => Format::Txt,
+    }
+}
+
+fn invalid(line: usize, why: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line + 1, why),
+    )
+}
+
+/// read file into a new finished session called name under root
+///
+/// text files have no times so every line gets the files modified time,
+/// jsonl and asciicast keep the ones they have. the session is written
+/// under a hidden name and moved into place once it is whole
+pub fn import(
+    root: &Path,
+    segment_bytes: u64,
+    file: &Path,
+    name: &str,
+    format: Format,
+) -> io::Result<Imported> {
+    protocol::check_id(name)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let dest = root.join(name);
+
+    if dest.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("session {} already exists", name),
+        ));
+    }
+
+    let input = BufReader::new(File::open(file)?);
+    let modified = fs::metadata(file)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_else(|_| now_millis());
+
+    let staging = root.join(format!(".import-{}", name));
+
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let mut storage = Storage::new(staging.clone(), segment_bytes);
+    let mut imported = Imported {
+        name: name.to_string(),
+        lines: 0,
+        first: 0,
+        last: 0,
+    };
+
+    let mut append = |stream: Stream, ts: u64, payload: &[u8], attrs| {
+        storage.append_with(name, stream, ts, payload, attrs)?;
+
+        if imported.lines == 0 {
+            imported.first = ts;
+        }
+
+        imported.lines += 1;
+        imported.last = imported.last.max(ts);
+
+        Ok(())
+    };
+
+    let read = match format {
+        Format::Txt => read_txt(input, modified, &mut append),
+        Format::Jsonl => read_jsonl(input, modified, &mut append),
+        Format::Asciicast => read_asciicast(input, &mut append),
+        Format::Html | Format::Screen => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} cant be imported", format),
+        )),
+    };
+
+    let read = read.and_then(|()| storage.close(name));
+
+    if let Err(err) = read {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(err);
+    }
+
+    let session = staging.join(name);
+
+    if imported.lines == 0 {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("nothing to import in {}", file.display()),
+        ));
+    }
+
+    let meta = SessionMeta {
+        state: SessionState::Closed,
+        opened: imported.first,
+        closed: Some(imported.last),
+        tags: Vec::new(),
+        exit: None,
+    };
+
+    meta.save(&session)?;
+
+    // dated by its last line so retention and compaction see its real age
+    let when = UNIX_EPOCH + Duration::from_millis(imported.last);
+    backdate(&session, when)?;
+
+    fs::rename(&session, &dest)?;
+    fs::remove_dir(&staging)?;
+
+    File::open(&dest)?.set_modified(when)?;
+
+    Ok(imported)
+}
+
+fn backdate(dir: &Path, when: SystemTime) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        File::options()
+            .append(true)
+            .open(entry?.path())?
+            .set_modified(when)?;
+    }
+
+    Ok(())
+}
+
+/// a line per record, all at the files time
+fn read_txt<R, F>(input: R, ts: u64, append: &mut F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Stream, u64, &[u8], Attrs) -> io::Result<()>,
+{
+    for line in input.split(b'\n') {
+        let mut line = line?;
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        append(Stream::Out, ts, &line, Attrs::new())?;
+    }
+
+    Ok(())
+}
+
+/// the objects spellcli export writes, only line is needed
+fn read_jsonl<R, F>(input: R, ts: u64, append: &mut F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Stream, u64, &[u8], Attrs) -> io::Result<()>,
+{
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let value: Value = serde_json::from_str(&line)
+            .map_err(|err| invalid(index, &err.to_string()))?;
+
+        let text = value["line"]
+            .as_str()
+            .ok_or_else(|| invalid(index, "no line"))?;
+
+        let stream = match value["stream"].as_str() {
+            Some(name) => Stream::from_name(name)
+                .ok_or_else(|| invalid(index, "unknown stream"))?,
+            None => Stream::Out,
+        };
+
+        let line_ts = value["ts"].as_u64().unwrap_or(ts);
+
+        let attrs = match value.get("attrs") {
+            Some(attrs) => serde_json::from_value(attrs.clone())
+                .map_err(|err| invalid(index, &err.to_string()))?,
+            None => Attrs::new(),
+        };
+
+        append(stream, line_ts, text.as_bytes(), attrs)?;
+    }
+
+    Ok(())
+}
+
+/// asciicast v2, output events are joined and cut into lines, each line
+/// stamped with the time of the event that ended it
+fn read_asciicast<R, F>(input: R, append: &mut F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Stream, u64, &[u8], Attrs) -> io::Result<()>,
+{
+    let mut lines = input.lines().enumerate();
+
+    let header: Value = match lines.next() {
+        Some((index, line)) => serde_json::from_str(&line?)
+            .map_err(|err| invalid(index, &err.to_string()))?,
+        None => return Ok(()),
+    };
+
+    if header["version"].as_u64() != Some(2) {
+        return Err(invalid(0, "only asciicast v2 can be imported"));
+    }
+
+    let start = header["timestamp"].as_u64().unwrap_or(0) * 1000;
+
+    let mut pending = String::new();
+    let mut ts = start;
+
+    for (index, line) in lines {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event: Value = serde_json::from_str(&line)
+            .map_err(|err| invalid(index, &err.to_string()))?;
+
+        let at = event[0]
+            .as_f64()
+            .ok_or_else(|| invalid(index, "no event time"))?;
+
+        // input and resize events never reached the terminal
+        if event[1].as_str() != Some("o") {
+            continue;
+        }
+
+        let data = event[2]
+            .as_str()
+            .ok_or_else(|| invalid(index, "no event data"))?;
+
+        ts = start + (at.max(0.0) * 1000.0) as u64;
+        pending.push_str(data);
+
+        while let Some(end) = pending.find('\n') {
+            let line = pending[..end].trim_end_matches('\r').to_string();
+            pending.drain(..=end);
+
+            append(Stream::Out, ts, line.as_bytes(), Attrs::new())?;
+        }
+    }
+
+    if !pending.is_empty() {
+        let line = pending.trim_end_matches('\r');
+        append(Stream::Out, ts, line.as_bytes(), Attrs::new())?;
+    }
+
+    Ok(())
+}
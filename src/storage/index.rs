@@ -0,0 +1,124 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// a record gets an index entry once this many records or bytes have gone
+/// by since the last one
+pub const INDEX_EVERY_RECORDS: u64 = 256;
+pub const INDEX_EVERY_BYTES: u64 = 64 * 1024;
+
+const ENTRY: usize = 24;
+
+/// where a record starts in its segment, offsets are into the uncompressed
+/// segment so they stay good after compaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    pub seq: u64,
+    pub ts: u64,
+    pub offset: u64,
+}
+
+impl IndexEntry {
+    pub fn to_bytes(self) -> [u8; ENTRY] {
+        let mut bytes = [0; ENTRY];
+
+        bytes[0..8].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.ts.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.offset.to_le_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> IndexEntry {
+        let mut words = [[0; 8]; 3];
+
+        for (index, word) in words.iter_mut().enumerate() {
+            word.copy_from_slice(&bytes[index * 8..index * 8 + 8]);
+        }
+
+        IndexEntry {
+            seq: u64::from_le_bytes(words[0]),
+            ts: u64::from_le_bytes(words[1]),
+            offset: u64::from_le_bytes(words[2]),
+        }
+    }
+}
+
+/// the sparse index of one segment, a `.idx` file next to it
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Index {
+    /// load an index, a missing one is empty and a torn last entry is left
+    /// off
+    pub fn load(path: &Path) -> io::Result<Index> {
+        let mut bytes = Vec::new();
+
+        match File::open(path) {
+            Ok(mut file) => file.read_to_end(&mut bytes)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        let entries = bytes
+            .chunks_exact(ENTRY)
+            .map(IndexEntry::from_bytes)
+            .collect();
+
+        Ok(Index { entries })
+    }
+
+    /// the last entry at or before seq
+    pub fn by_seq(&self, seq: u64) -> Option<IndexEntry> {
+        let at = self.entries.partition_point(|entry| entry.seq <= seq);
+
+        at.checked_sub(1).map(|at| self.entries[at])
+    }
+
+    /// the last entry before ts, so every record at or after ts is after it
+    pub fn by_ts(&self, ts: u64) -> Option<IndexEntry> {
+        let at = self.entries.partition_point(|entry| entry.ts < ts);
+
+        at.checked_sub(1).map(|at| self.entries[at])
+    }
+
+    pub fn first(&self) -> Option<IndexEntry> {
+        self.entries.first().copied()
+    }
+
+    pub fn last(&self) -> Option<IndexEntry> {
+        self.entries.last().copied()
+    }
+}
+
+/// appends entries to a segments index
+pub struct IndexWriter {
+    file: File,
+}
+
+impl IndexWriter {
+    pub fn open(path: &Path) -> io::Result<IndexWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // drop a torn entry so the next one lines up
+        let len = file.metadata()?.len();
+        let whole = len - len % ENTRY as u64;
+
+        if whole != len {
+            file.set_len(whole)?;
+        }
+
+        Ok(IndexWriter { file })
+    }
+
+    /// throw away every entry, for a segment being started over
+    pub fn truncate(&self) -> io::Result<()> {
+        self.file.set_len(0)
+    }
+
+    pub fn push(&mut self, entry: IndexEntry) -> io::Result<()> {
+        self.file.write_all(&entry.to_bytes())
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// the file in each session directory saying how it was left
+pub const META_FILE: &str = "session.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// the daemon has it open, or died with it open
+    Open,
+    Closed,
+    /// it was open when the daemon died and has been recovered
+    Interrupted,
+}
+
+/// the registry entry for a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMeta {
+    pub state: SessionState,
+    /// unix millis
+    pub opened: u64,
+    pub closed: Option<u64>,
+    /// given by the producer when it connected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// how the producers command exited, if it said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit: Option<i32>,
+}
+
+impl SessionMeta {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(META_FILE)
+    }
+
+    /// none for a session from before there was a registry
+    pub fn load(dir: &Path) -> io::Result<Option<SessionMeta>> {
+        let text = match fs::read_to_string(SessionMeta::path(dir)) {
+            Ok(val) => val,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// write it under a hidden name then move it into place
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let text = toml::to_string(self).map_err(io::Error::other)?;
+        let tmp = dir.join(format!(".{}.tmp", META_FILE));
+
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, SessionMeta::path(dir))
+    }
+}
@@ -0,0 +1,481 @@
+pub mod compress;
+pub mod export;
+pub mod fsck;
+pub mod import;
+pub mod index;
+pub mod meta;
+pub mod reader;
+pub mod record;
+pub mod retention;
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+
+use crate::storage::index::{
+    IndexEntry, IndexWriter, INDEX_EVERY_BYTES, INDEX_EVERY_RECORDS,
+};
+use crate::storage::meta::{SessionMeta, SessionState};
+use crate::storage::reader::SegmentReader;
+use crate::storage::record::{Attrs, Record, SegmentHeader, Stream};
+
+/// unix millis, what records are stamped with
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// the file name of segment n of a session
+pub fn segment_name(index: u32) -> String {
+    format!("{:05}.log", index)
+}
+
+/// the segment number from a segment file name, `00003.log.zst` is 3
+fn segment_index(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.split('.');
+
+    let index = parts.next()?.parse().ok()?;
+
+    match parts.next() {
+        Some("log") => Some(index),
+        _ => None,
+    }
+}
+
+/// the sparse index next to a segment, `00003.log.zst` has `00003.idx`
+pub fn index_path(segment: &Path) -> PathBuf {
+    match segment_index(segment) {
+        Some(index) => segment.with_file_name(format!("{:05}.idx", index)),
+        None => segment.with_extension("idx"),
+    }
+}
+
+/// a session on disk, a directory of numbered segments under the log root
+///
+/// logs from before sessions had directories are a single plain file
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub name: String,
+    pub path: PathBuf,
+    /// oldest first, the last one is the one being written to
+    pub segments: Vec<PathBuf>,
+    pub bytes: u64,
+    pub modified: SystemTime,
+}
+
+impl SessionInfo {
+    pub fn load(path: &Path) -> io::Result<SessionInfo> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let meta = fs::metadata(path)?;
+
+        let segments = if meta.is_dir() {
+            let mut segments = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| segment_index(path).is_some())
+                .collect::<Vec<PathBuf>>();
+
+            // a compressed segment wins over a plain one left behind by a
+            // compaction that stopped part way
+            segments.sort_by_key(|path| {
+                (segment_index(path), !compress::is_compressed(path))
+            });
+            segments.dedup_by_key(|path| segment_index(path));
+            segments
+        } else {
+            vec![path.to_owned()]
+        };
+
+        let mut bytes = 0;
+        let mut modified = meta.modified()?;
+
+        for segment in &segments {
+            let seg_meta = fs::metadata(segment)?;
+
+            bytes += seg_meta.len();
+            modified = modified.max(seg_meta.modified()?);
+        }
+
+        Ok(SessionInfo {
+            name,
+            path: path.to_owned(),
+            segments,
+            bytes,
+            modified,
+        })
+    }
+}
+
+/// every session under the log root, newest first
+pub fn list_sessions(root: &Path) -> io::Result<Vec<SessionInfo>> {
+    let mut sessions = Vec::new();
+
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_none_or(|name| name.starts_with('.'));
+
+        if hidden {
+            continue;
+        }
+
+        // a session can be removed by gc while we look at it
+        if let Ok(info) = SessionInfo::load(&path) {
+            sessions.push(info);
+        }
+    }
+
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.modified));
+
+    Ok(sessions)
+}
+
+struct SessionWriter {
+    file: File,
+    index_file: IndexWriter,
+    segment: u32,
+    /// where the first record goes, after the header
+    start: u64,
+    written: u64,
+    next_seq: u64,
+    /// records and bytes since the last index entry
+    since_records: u64,
+    since_bytes: u64,
+}
+
+impl SessionWriter {
+    fn needs_entry(&self) -> bool {
+        self.since_records >= INDEX_EVERY_RECORDS
+            || self.since_bytes >= INDEX_EVERY_BYTES
+    }
+}
+
+/// writes records to the sessions under the log root, rotating a sessions
+/// segment once it gets past segment_bytes
+pub struct Storage {
+    root: PathBuf,
+    segment_bytes: u64,
+    open: HashMap<String, SessionWriter>,
+}
+
+impl Storage {
+    pub fn new(root: PathBuf, segment_bytes: u64) -> Self {
+        Storage {
+            root,
+            segment_bytes: segment_bytes.max(1),
+            open: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// append a record stamped now, returns its seq
+    pub fn append(
+        &mut self,
+        id: &str,
+        stream: Stream,
+        payload: &[u8],
+    ) -> io::Result<u64> {
+        self.append_at(id, stream, now_millis(), payload)
+    }
+
+    /// append a record with a given time, returns its seq
+    pub fn append_at(
+        &mut self,
+        id: &str,
+        stream: Stream,
+        ts: u64,
+        payload: &[u8],
+    ) -> io::Result<u64> {
+        self.append_with(id, stream, ts, payload, Attrs::new())
+    }
+
+    /// append a record with a given time and attributes, returns its seq
+    pub fn append_with(
+        &mut self,
+        id: &str,
+        stream: Stream,
+        ts: u64,
+        payload: &[u8],
+        attrs: Attrs,
+    ) -> io::Result<u64> {
+        if !self.open.contains_key(id) {
+            let writer = self.open_session(id)?;
+            self.open.insert(id.to_string(), writer);
+        }
+
+        // a segment always gets at least one record
+        let needs_rotate = self.open.get(id).is_some_and(|writer| {
+            writer.written > writer.start
+                && writer.written >= self.segment_bytes
+        });
+
+        if needs_rotate {
+            let (segment, next_seq) = {
+                let old = &self.open[id];
+                (old.segment + 1, old.next_seq)
+            };
+
+            let writer = self.new_segment(id, segment, next_seq)?;
+            self.open.insert(id.to_string(), writer);
+        }
+
+        let writer = self
+            .open
+            .get_mut(id)
+            .ok_or_else(|| io::Error::other("session not open"))?;
+
+        let record = Record {
+            seq: writer.next_seq,
+            ts,
+            stream,
+            payload: payload.to_vec(),
+            attrs,
+        };
+
+        // a record too big to write leaves the segment and index as they were
+        let size = record.write(&mut writer.file)?;
+
+        if writer.needs_entry() {
+            writer.index_file.push(IndexEntry {
+                seq: record.seq,
+                ts: record.ts,
+                offset: writer.written,
+            })?;
+
+            writer.since_records = 0;
+            writer.since_bytes = 0;
+        }
+
+        writer.written += size;
+        writer.since_records += 1;
+        writer.since_bytes += size;
+        writer.next_seq += 1;
+
+        Ok(record.seq)
+    }
+
+    /// stop writing to a session and mark it closed, the next append opens
+    /// it again
+    pub fn close(&mut self, id: &str) -> io::Result<()> {
+        if self.open.remove(id).is_none() {
+            return Ok(());
+        }
+
+        let dir = self.root.join(id);
+
+        let mut meta = match SessionMeta::load(&dir)? {
+            Some(val) => val,
+            None => return Ok(()),
+        };
+
+        meta.state = SessionState::Closed;
+        meta.closed = Some(now_millis());
+
+        meta.save(&dir)
+    }
+
+    /// change the meta of a session that has one
+    pub fn update_meta<F>(&self, id: &str, update: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut SessionMeta),
+    {
+        let dir = self.root.join(id);
+
+        match SessionMeta::load(&dir)? {
+            Some(mut meta) => {
+                update(&mut meta);
+                meta.save(&dir)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// close every open session, for a clean shutdown
+    pub fn close_all(&mut self) -> io::Result<()> {
+        let ids = self.open.keys().cloned().collect::<Vec<String>>();
+
+        for id in ids {
+            self.close(&id)?;
+        }
+
+        Ok(())
+    }
+
+    /// open the newest segment of a session, making the directory if needed
+    ///
+    /// only a plain segment in the record format that ends cleanly is added
+    /// to, anything else is left alone and the next segment started
+    fn open_session(&self, id: &str) -> io::Result<SessionWriter> {
+        let dir = self.root.join(id);
+
+        if dir.is_file() {
+            move_legacy(&dir)?;
+        }
+
+        fs::create_dir_all(&dir)
+            .map_err(|err| annotate(err, "Error making session dir", &dir))?;
+
+        let meta = SessionMeta {
+            state: SessionState::Open,
+            opened: now_millis(),
+            closed: None,
+            tags: Vec::new(),
+            exit: None,
+        };
+
+        meta.save(&dir)?;
+
+        let last = match SessionInfo::load(&dir)?.segments.pop() {
+            Some(val) => val,
+            None => return self.new_segment(id, 0, 0),
+        };
+
+        let segment = segment_index(&last).unwrap_or(0);
+        let scan = scan_tail(&last)?;
+
+        match scan {
+            Some((next_seq, end)) if !compress::is_compressed(&last) => {
+                self.reopen_segment(&last, segment, next_seq, end)
+            }
+            Some((next_seq, _)) => self.new_segment(id, segment + 1, next_seq),
+            None => {
+                let next_seq = reader::SessionReader::open(&dir)?
+                    .last_seq()
+                    .unwrap_or_default()
+                    .map_or(0, |seq| seq + 1);
+
+                self.new_segment(id, segment + 1, next_seq)
+            }
+        }
+    }
+
+    fn new_segment(
+        &self,
+        id: &str,
+        segment: u32,
+        first_seq: u64,
+    ) -> io::Result<SessionWriter> {
+        let path = self.root.join(id).join(segment_name(segment));
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(|err| annotate(err, "Append File Error", &path))?;
+
+        let header = SegmentHeader {
+            session: id.to_string(),
+            segment,
+            first_seq,
+            created: now_millis(),
+        };
+
+        let written = header.write(&mut file)?;
+
+        let index_file = IndexWriter::open(&index_path(&path))?;
+        index_file.truncate()?;
+
+        Ok(SessionWriter {
+            file,
+            index_file,
+            segment,
+            start: written,
+            written,
+            next_seq: first_seq,
+            since_records: INDEX_EVERY_RECORDS,
+            since_bytes: 0,
+        })
+    }
+
+    fn reopen_segment(
+        &self,
+        path: &Path,
+        segment: u32,
+        next_seq: u64,
+        end: u64,
+    ) -> io::Result<SessionWriter> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|err| annotate(err, "Append File Error", path))?;
+
+        let start = SegmentReader::open(path)?.offset;
+
+        Ok(SessionWriter {
+            file,
+            index_file: IndexWriter::open(&index_path(path))?,
+            segment,
+            start,
+            written: end,
+            next_seq,
+            // the first record after a reopen always gets an entry
+            since_records: INDEX_EVERY_RECORDS,
+            since_bytes: 0,
+        })
+    }
+}
+
+/// a log from before sessions had directories becomes the first segment of
+/// the directory that takes its place, so a producer can carry on with it
+fn move_legacy(path: &Path) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    // hidden so list_sessions skips it while it moves
+    let moving = path.with_file_name(format!(".{}.moving", name));
+
+    fs::rename(path, &moving)
+        .map_err(|err| annotate(err, "Error moving legacy log", path))?;
+    fs::create_dir(path)
+        .map_err(|err| annotate(err, "Error making session dir", path))?;
+    fs::rename(&moving, path.join(segment_name(0)))
+        .map_err(|err| annotate(err, "Error moving legacy log", path))
+}
+
+/// read a record format segment from its last index entry to the end,
+/// returns the next seq and where the records end
+///
+/// none for a legacy segment or one that doesnt end on a whole record
+fn scan_tail(path: &Path) -> io::Result<Option<(u64, u64)>> {
+    let index = index::Index::load(&index_path(path))?;
+
+    let mut reader = match SegmentReader::open_at(path, index.last()) {
+        Ok(val) => val,
+        Err(_) => return Ok(None),
+    };
+
+    let mut next_seq = match &reader.header {
+        Some(header) => header.first_seq,
+        None => return Ok(None),
+    };
+
+    loop {
+        match reader.next_record() {
+            Ok(Some(record)) => next_seq = record.seq + 1,
+            Ok(None) => return Ok(Some((next_seq, reader.offset))),
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+fn annotate(err: io::Error, what: &str, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{} {}: {}", what, path.display(), err))
+}
@@ -0,0 +1,353 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::storage::{index_path, SessionInfo};
+use crate::storage::compress::{self, Compression};
+use crate::storage::index::{Index, IndexEntry};
+use crate::storage::record::{Record, RecordError, SegmentHeader, Stream, MAGIC};
+
+/// reads the records of one segment, compressed or not
+///
+/// a segment from before the record format is read a line per record, with
+/// no timestamps
+pub struct SegmentReader {
+    input: Box<dyn BufRead + Send>,
+    pub header: Option<SegmentHeader>,
+    /// how far into the uncompressed segment the next record is
+    pub offset: u64,
+    /// the seq the next legacy line gets
+    legacy_seq: u64,
+}
+
+impl SegmentReader {
+    pub fn open(path: &Path) -> io::Result<SegmentReader> {
+        SegmentReader::open_at(path, None)
+    }
+
+    /// open a segment and skip to an index entry
+    pub fn open_at(
+        path: &Path,
+        entry: Option<IndexEntry>,
+    ) -> io::Result<SegmentReader> {
+        let mut input = compress::open_segment(path)?;
+
+        let mut magic = [0; 4];
+        let read = read_up_to(&mut input, &mut magic)?;
+
+        if read < magic.len() || &magic != MAGIC {
+            // put back what was read, it is the start of the first line
+            let start = Cursor::new(magic[..read].to_vec());
+
+            return Ok(SegmentReader {
+                input: Box::new(BufReader::new(start.chain(input))),
+                header: None,
+                offset: 0,
+                legacy_seq: 0,
+            });
+        }
+
+        let (header, offset) = SegmentHeader::read_after_magic(&mut input)?;
+
+        let mut reader = SegmentReader {
+            input,
+            header: Some(header),
+            offset,
+            legacy_seq: 0,
+        };
+
+        if let Some(entry) = entry {
+            reader.skip_to(path, entry.offset)?;
+        }
+
+        Ok(reader)
+    }
+
+    /// move forward to offset, a plain segment seeks and a compressed one is
+    /// read through
+    fn skip_to(&mut self, path: &Path, offset: u64) -> io::Result<()> {
+        if offset <= self.offset {
+            return Ok(());
+        }
+
+        if Compression::of(path) == Compression::None {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+
+            self.input = Box::new(BufReader::new(file));
+        } else {
+            let skip = offset - self.offset;
+            let skipped =
+                io::copy(&mut (&mut self.input).take(skip), &mut io::sink())?;
+
+            if skipped != skip {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "index points past the segment",
+                ));
+            }
+        }
+
+        self.offset = offset;
+
+        Ok(())
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.header.is_none()
+    }
+
+    /// the next record, none at the end
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        if self.header.is_some() {
+            let record = Record::read(&mut self.input);
+
+            match &record {
+                Ok(Some(record)) => self.offset += record.size(),
+                // it was read through, the next record can still be read
+                Err(err) => {
+                    if let Some(RecordError::Corrupt { size, .. }) =
+                        RecordError::of(err)
+                    {
+                        self.offset += size;
+                    }
+                }
+                Ok(None) => {}
+            }
+
+            return record;
+        }
+
+        let mut line = Vec::new();
+
+        if self.input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+
+        self.offset += line.len() as u64;
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        let seq = self.legacy_seq;
+        self.legacy_seq += 1;
+
+        Ok(Some(Record::new(seq, 0, Stream::Out, line)))
+    }
+}
+
+/// read until buf is full or the input ends
+fn read_up_to<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+
+    Ok(filled)
+}
+
+#[derive(Clone)]
+struct Segment {
+    path: PathBuf,
+    index: Index,
+}
+
+/// reads a whole session in seq order, can jump to a seq or a time using the
+/// segment indexes
+pub struct SessionReader {
+    segments: Vec<Segment>,
+    current: Option<SegmentReader>,
+    /// the segment after current
+    next: usize,
+    min_seq: u64,
+    min_ts: u64,
+    /// the legacy seq carried from one legacy segment to the next
+    legacy_seq: u64,
+    failed: bool,
+}
+
+impl SessionReader {
+    pub fn open(path: &Path) -> io::Result<SessionReader> {
+        let info = SessionInfo::load(path)?;
+
+        SessionReader::from_info(&info)
+    }
+
+    pub fn from_info(info: &SessionInfo) -> io::Result<SessionReader> {
+        let mut segments = Vec::new();
+
+        for path in &info.segments {
+            segments.push(Segment {
+                index: Index::load(&index_path(path))?,
+                path: path.clone(),
+            });
+        }
+
+        Ok(SessionReader {
+            segments,
+            current: None,
+            next: 0,
+            min_seq: 0,
+            min_ts: 0,
+            legacy_seq: 0,
+            failed: false,
+        })
+    }
+
+    /// start at the first record with at least seq
+    pub fn seek_seq(&mut self, seq: u64) -> io::Result<()> {
+        self.min_seq = seq;
+
+        let found = self.segments.iter().rposition(|segment| {
+            segment.index.first().is_some_and(|entry| entry.seq <= seq)
+        });
+
+        let at = found.unwrap_or(0);
+        let entry = self.segments.get(at).and_then(|seg| seg.index.by_seq(seq));
+
+        self.start(at, entry)
+    }
+
+    /// start at the first record from ts on, unix millis
+    pub fn seek_ts(&mut self, ts: u64) -> io::Result<()> {
+        self.min_ts = ts;
+
+        let found = self.segments.iter().rposition(|segment| {
+            segment.index.first().is_some_and(|entry| entry.ts < ts)
+        });
+
+        let at = found.unwrap_or(0);
+        let entry = self.segments.get(at).and_then(|seg| seg.index.by_ts(ts));
+
+        self.start(at, entry)
+    }
+
+    /// the seq of the last record, none if the session is empty
+    pub fn last_seq(&self) -> io::Result<Option<u64>> {
+        for segment in self.segments.iter().rev() {
+            let mut reader =
+                SegmentReader::open_at(&segment.path, segment.index.last())?;
+
+            if reader.is_legacy() {
+                // no index to go on, count every line from the start
+                return self.rewound().try_fold(None, |_, record| {
+                    record.map(|record| Some(record.seq))
+                });
+            }
+
+            let mut last = None;
+
+            while let Some(record) = reader.next_record()? {
+                last = Some(record.seq);
+            }
+
+            if last.is_some() {
+                return Ok(last);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// a reader over the same segments from the start
+    fn rewound(&self) -> SessionReader {
+        SessionReader {
+            segments: self.segments.clone(),
+            current: None,
+            next: 0,
+            min_seq: 0,
+            min_ts: 0,
+            legacy_seq: 0,
+            failed: false,
+        }
+    }
+
+    /// the last count records
+    pub fn tail(mut self, count: u64) -> io::Result<Vec<Record>> {
+        let last = match self.last_seq()? {
+            Some(val) => val,
+            None => return Ok(Vec::new()),
+        };
+
+        self.seek_seq((last + 1).saturating_sub(count))?;
+
+        self.collect()
+    }
+
+    fn start(
+        &mut self,
+        at: usize,
+        entry: Option<IndexEntry>,
+    ) -> io::Result<()> {
+        self.current = None;
+        self.next = at + 1;
+        self.legacy_seq = 0;
+
+        if let Some(segment) = self.segments.get(at) {
+            self.current = Some(SegmentReader::open_at(&segment.path, entry)?);
+        }
+
+        Ok(())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Record>> {
+        loop {
+            if self.current.is_none() {
+                let segment = match self.segments.get(self.next) {
+                    Some(val) => val,
+                    None => return Ok(None),
+                };
+
+                let mut reader = SegmentReader::open(&segment.path)?;
+
+                // legacy lines carry on counting from the last segment
+                reader.legacy_seq = self.legacy_seq;
+
+                self.current = Some(reader);
+                self.next += 1;
+            }
+
+            let reader = match self.current.as_mut() {
+                Some(val) => val,
+                None => continue,
+            };
+
+            match reader.next_record()? {
+                Some(record) => {
+                    if reader.is_legacy() {
+                        self.legacy_seq = reader.legacy_seq;
+                    }
+
+                    if record.seq >= self.min_seq && record.ts >= self.min_ts {
+                        return Ok(Some(record));
+                    }
+                }
+                None => self.current = None,
+            }
+        }
+    }
+}
+
+impl Iterator for SessionReader {
+    type Item = io::Result<Record>;
+
+    /// stops after the first error
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
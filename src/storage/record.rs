@@ -0,0 +1,350 @@
+use std::fmt;
+use std::error::Error;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// the first bytes of every segment in the record format
+pub const MAGIC: &[u8; 4] = b"SPLH";
+pub const VERSION: u8 = 1;
+
+/// len and crc before each record body
+const FRAME: usize = 8;
+/// seq, ts and stream at the front of each record body
+const BODY_HEAD: usize = 17;
+/// the biggest record body, seq, ts, stream, attrs and payload together.
+/// write refuses anything bigger and read takes it for damage
+pub const MAX_RECORD: u32 = 64 * 1024 * 1024;
+/// set on the stream byte when attributes come before the payload
+const ATTRS_FLAG: u8 = 0x80;
+
+/// fields a line carries apart from its text, like the level and target of a
+/// log record
+pub type Attrs = BTreeMap<String, String>;
+
+/// why a record couldnt be read, inside the io error from Record::read
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    /// the input ended part way through a record
+    Torn,
+    /// the record was read whole but is wrong, the next one can still be read
+    /// size bytes on
+    Corrupt { why: String, size: u64 },
+    /// the frame makes no sense, nothing after it can be trusted
+    Framing(String),
+}
+
+impl RecordError {
+    /// the record error an io error carries, if it carries one
+    pub fn of(err: &io::Error) -> Option<&RecordError> {
+        err.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Torn => write!(f, "record cut short"),
+            RecordError::Corrupt { why, .. } | RecordError::Framing(why) => {
+                write!(f, "{}", why)
+            }
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+/// where a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Out,
+    Err,
+    /// written by the daemon, connects and gap markers
+    Meta,
+}
+
+impl Stream {
+    /// how it is written out in exports, `out`, `err` or `meta`
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Out => "out",
+            Stream::Err => "err",
+            Stream::Meta => "meta",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Stream> {
+        match name {
+            "out" => Some(Stream::Out),
+            "err" => Some(Stream::Err),
+            "meta" => Some(Stream::Meta),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Stream::Out => 0,
+            Stream::Err => 1,
+            Stream::Meta => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Stream> {
+        match byte {
+            0 => Some(Stream::Out),
+            1 => Some(Stream::Err),
+            2 => Some(Stream::Meta),
+            _ => None,
+        }
+    }
+}
+
+/// what a segment starts with, after the magic and version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentHeader {
+    pub session: String,
+    pub segment: u32,
+    /// the seq of the first record in the segment
+    pub first_seq: u64,
+    /// unix millis
+    pub created: u64,
+}
+
+impl SegmentHeader {
+    /// write the magic, version and header, returns the bytes written
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<u64> {
+        let body = toml::to_string(self).map_err(io::Error::other)?;
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&(body.len() as u32).to_le_bytes())?;
+        out.write_all(body.as_bytes())?;
+
+        Ok((MAGIC.len() + 1 + 4 + body.len()) as u64)
+    }
+
+    /// read a header after the magic has been checked, returns it and the
+    /// bytes read including the magic
+    pub fn read_after_magic<R: Read>(
+        input: &mut R,
+    ) -> io::Result<(SegmentHeader, u64)> {
+        let mut version = [0; 1];
+        input.read_exact(&mut version)?;
+
+        if version[0] != VERSION {
+            return Err(invalid(format!("unknown version {}", version[0])));
+        }
+
+        let len = read_u32(input)?;
+
+        if len > MAX_RECORD {
+            return Err(invalid(format!("header too big: {}", len)));
+        }
+
+        let mut body = vec![0; len as usize];
+        input.read_exact(&mut body)?;
+
+        let text = String::from_utf8(body).map_err(invalid)?;
+        let header = toml::from_str(&text).map_err(invalid)?;
+
+        Ok((header, (MAGIC.len() + 1 + 4) as u64 + len as u64))
+    }
+}
+
+/// one line in a session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub seq: u64,
+    /// unix millis when the daemon got it
+    pub ts: u64,
+    pub stream: Stream,
+    pub payload: Vec<u8>,
+    /// empty for most lines, only written when there are some
+    pub attrs: Attrs,
+}
+
+impl Record {
+    /// the bytes this takes on disk
+    pub fn size(&self) -> u64 {
+        let attrs = self.encoded_attrs().map_or(0, |attrs| 4 + attrs.len());
+
+        (FRAME + BODY_HEAD + attrs + self.payload.len()) as u64
+    }
+
+    /// a line with no attributes
+    pub fn new(seq: u64, ts: u64, stream: Stream, payload: Vec<u8>) -> Record {
+        Record {
+            seq,
+            ts,
+            stream,
+            payload,
+            attrs: Attrs::new(),
+        }
+    }
+
+    /// the attributes as json, none if there arent any
+    fn encoded_attrs(&self) -> Option<Vec<u8>> {
+        if self.attrs.is_empty() {
+            return None;
+        }
+
+        serde_json::to_vec(&self.attrs).ok()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.payload).into_owned()
+    }
+
+    /// write it as one frame, returns the bytes written. a body over
+    /// MAX_RECORD is refused and nothing is written
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<u64> {
+        let mut body = Vec::with_capacity(BODY_HEAD + self.payload.len());
+
+        body.extend_from_slice(&self.seq.to_le_bytes());
+        body.extend_from_slice(&self.ts.to_le_bytes());
+
+        match self.encoded_attrs() {
+            Some(attrs) => {
+                body.push(self.stream.to_byte() | ATTRS_FLAG);
+                body.extend_from_slice(&(attrs.len() as u32).to_le_bytes());
+                body.extend_from_slice(&attrs);
+            }
+            None => body.push(self.stream.to_byte()),
+        }
+
+        body.extend_from_slice(&self.payload);
+
+        // read would take it for damage and lose the records after it
+        if body.len() > MAX_RECORD as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("record too big: {} bytes", body.len()),
+            ));
+        }
+
+        let mut frame = Vec::with_capacity(FRAME + body.len());
+
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        frame.extend_from_slice(&body);
+
+        // one write so a crash leaves at most one torn record
+        out.write_all(&frame)?;
+
+        Ok(frame.len() as u64)
+    }
+
+    /// the next record, none at a clean end
+    ///
+    /// a record cut short is an UnexpectedEof error and a bad checksum or
+    /// frame is InvalidData
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Option<Record>> {
+        let mut frame = [0; FRAME];
+
+        match read_full(input, &mut frame)? {
+            0 => return Ok(None),
+            FRAME => {}
+            _ => return Err(torn()),
+        }
+
+        let len = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        let crc = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+
+        if (len as usize) < BODY_HEAD || len > MAX_RECORD {
+            let why = format!("bad record length {}", len);
+            return Err(invalid(RecordError::Framing(why)));
+        }
+
+        let mut body = vec![0; len as usize];
+
+        if read_full(input, &mut body)? != body.len() {
+            return Err(torn());
+        }
+
+        if crc32fast::hash(&body) != crc {
+            return Err(invalid(RecordError::Corrupt {
+                why: "checksum mismatch".to_string(),
+                size: (FRAME + body.len()) as u64,
+            }));
+        }
+
+        let size = (FRAME + body.len()) as u64;
+        let corrupt = |why: String| invalid(RecordError::Corrupt { why, size });
+
+        let stream = Stream::from_byte(body[16] & !ATTRS_FLAG)
+            .ok_or_else(|| corrupt(format!("bad stream {}", body[16])))?;
+
+        let mut seq = [0; 8];
+        let mut ts = [0; 8];
+        seq.copy_from_slice(&body[0..8]);
+        ts.copy_from_slice(&body[8..16]);
+
+        let has_attrs = body[16] & ATTRS_FLAG != 0;
+
+        body.drain(..BODY_HEAD);
+
+        let attrs = if has_attrs {
+            let len = body
+                .get(..4)
+                .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]))
+                .ok_or_else(|| corrupt("attributes cut short".to_string()))?;
+
+            let end = 4 + len as usize;
+            let json = body
+                .get(4..end)
+                .ok_or_else(|| corrupt("attributes cut short".to_string()))?;
+
+            let attrs = serde_json::from_slice(json)
+                .map_err(|err| corrupt(format!("bad attributes: {}", err)))?;
+
+            body.drain(..end);
+            attrs
+        } else {
+            Attrs::new()
+        };
+
+        Ok(Some(Record {
+            seq: u64::from_le_bytes(seq),
+            ts: u64::from_le_bytes(ts),
+            stream,
+            payload: body,
+            attrs,
+        }))
+    }
+}
+
+/// read until buf is full or the input ends, returns how much was read
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn torn() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, RecordError::Torn)
+}
+
+fn invalid<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
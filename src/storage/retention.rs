@@ -0,0 +1,462 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{RetentionRule, StorageConfig};
+use crate::storage::{index_path, list_sessions, SessionInfo};
+use crate::storage::compress::{self, CompactAction};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// the sessions the daemon is writing to, which gc leaves alone, and the
+/// ones gc or compaction is working on, which a producer has to wait for
+///
+/// the lock is only held to look at or change the sets, never for file work
+#[derive(Clone, Default)]
+pub struct ActiveSessions {
+    state: Arc<(Mutex<Sets>, Condvar)>,
+}
+
+#[derive(Default)]
+struct Sets {
+    writing: HashSet<String>,
+    claimed: HashSet<String>,
+}
+
+impl ActiveSessions {
+    fn lock(&self) -> MutexGuard<'_, Sets> {
+        self.state.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// mark a session as being written to, once gc is done with it
+    pub fn open(&self, id: &str) {
+        let mut sets = self.lock();
+
+        while sets.claimed.contains(id) {
+            sets = self
+                .state
+                .1
+                .wait(sets)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+
+        sets.writing.insert(id.to_string());
+    }
+
+    pub fn close(&self, id: &str) {
+        self.lock().writing.remove(id);
+    }
+
+    /// the sessions being written to right now
+    pub fn writing(&self) -> HashSet<String> {
+        self.lock().writing.clone()
+    }
+
+    /// hold a session that isnt being written to while its files are worked
+    /// on, none if it is being written to. dropping the claim lets it go
+    pub fn claim(&self, id: &str) -> Option<Claim> {
+        let mut sets = self.lock();
+
+        if sets.writing.contains(id) || !sets.claimed.insert(id.to_string()) {
+            return None;
+        }
+
+        Some(Claim {
+            sessions: self.clone(),
+            id: id.to_string(),
+        })
+    }
+}
+
+/// a session gc or compaction has, producers for it wait until it is dropped
+pub struct Claim {
+    sessions: ActiveSessions,
+    id: String,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.sessions.lock().claimed.remove(&self.id);
+        self.sessions.state.1.notify_all();
+    }
+}
+
+/// one thing gc will do or did
+#[derive(Debug, Clone, PartialEq)]
+pub enum GcAction {
+    RemoveSession {
+        name: String,
+        path: PathBuf,
+        bytes: u64,
+        reason: String,
+    },
+    RemoveSegment {
+        name: String,
+        path: PathBuf,
+        bytes: u64,
+        reason: String,
+    },
+}
+
+impl GcAction {
+    /// the session it is in
+    pub fn name(&self) -> &str {
+        match self {
+            GcAction::RemoveSession { name, .. }
+            | GcAction::RemoveSegment { name, .. } => name,
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        match self {
+            GcAction::RemoveSession { bytes, .. }
+            | GcAction::RemoveSegment { bytes, .. } => *bytes,
+        }
+    }
+
+    fn apply(&self) -> io::Result<()> {
+        match self {
+            GcAction::RemoveSession { path, .. } if path.is_dir() => {
+                fs::remove_dir_all(path)
+            }
+            GcAction::RemoveSession { path, .. } => fs::remove_file(path),
+            GcAction::RemoveSegment { path, .. } => {
+                fs::remove_file(path)?;
+
+                match fs::remove_file(index_path(path)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        Err(err)
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for GcAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GcAction::RemoveSession {
+                name,
+                bytes,
+                reason,
+                ..
+            } => write!(
+                f,
+                "remove session {} ({} bytes): {}",
+                name, bytes, reason
+            ),
+            GcAction::RemoveSegment {
+                name,
+                path,
+                bytes,
+                reason,
+            } => write!(
+                f,
+                "remove segment {}/{} ({} bytes): {}",
+                name,
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                bytes,
+                reason
+            ),
+        }
+    }
+}
+
+/// the retention rules for a log root
+#[derive(Clone)]
+pub struct Retention {
+    root: PathBuf,
+    storage: StorageConfig,
+    rules: Vec<RetentionRule>,
+    active: ActiveSessions,
+}
+
+impl Retention {
+    pub fn new(
+        root: PathBuf,
+        storage: StorageConfig,
+        rules: Vec<RetentionRule>,
+        active: ActiveSessions,
+    ) -> Self {
+        Retention {
+            root,
+            storage,
+            rules,
+            active,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// the sessions being written to right now
+    pub fn active(&self) -> &ActiveSessions {
+        &self.active
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.storage.gc_interval_secs.max(1))
+    }
+
+    /// work out what the rules want removed, then remove it unless dry_run
+    ///
+    /// each session is claimed as it is removed so nothing starts writing to
+    /// it meanwhile, one that started since the plan is left alone
+    pub fn run(&self, dry_run: bool) -> io::Result<Vec<GcAction>> {
+        let sessions = if self.root.exists() {
+            list_sessions(&self.root)?
+        } else {
+            Vec::new()
+        };
+
+        let actions = plan(
+            &sessions,
+            &self.rules,
+            &self.storage,
+            &self.active.writing(),
+            SystemTime::now(),
+        );
+
+        if dry_run {
+            return Ok(actions);
+        }
+
+        let mut done = Vec::new();
+
+        for action in actions {
+            let _claim = match self.active.claim(action.name()) {
+                Some(val) => val,
+                None => continue,
+            };
+
+            // it may be gone already, thats fine
+            if let Err(err) = action.apply() {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err);
+                }
+            }
+
+            done.push(action);
+        }
+
+        Ok(done)
+    }
+
+    /// compress the finished sessions that have been idle long enough, or
+    /// every finished one with force, or only the session named
+    pub fn compact(
+        &self,
+        force: bool,
+        only: Option<&str>,
+    ) -> io::Result<Vec<CompactAction>> {
+        let mut actions = Vec::new();
+
+        if !self.root.exists() {
+            return Ok(actions);
+        }
+
+        let idle = if force {
+            None
+        } else {
+            Some(Duration::from_secs(self.storage.compress_after_secs))
+        };
+
+        let now = SystemTime::now();
+
+        for session in list_sessions(&self.root)? {
+            if only.is_some_and(|name| name != session.name) {
+                continue;
+            }
+
+            let _claim = match self.active.claim(&session.name) {
+                Some(val) => val,
+                None => continue,
+            };
+
+            // look again now that nothing can write to it
+            let session = match SessionInfo::load(&session.path) {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+
+            let compression = self.storage.compression;
+
+            if let Some(action) =
+                compress::compact_session(&session, compression, idle, now)?
+            {
+                actions.push(action);
+            }
+        }
+
+        Ok(actions)
+    }
+}
+
+/// the first rule matching name
+pub fn rule_for<'a>(
+    rules: &'a [RetentionRule],
+    name: &str,
+) -> Option<&'a RetentionRule> {
+    rules.iter().find(|rule| glob_match(&rule.pattern, name))
+}
+
+/// decide what to remove, sessions must be newest first
+pub fn plan(
+    sessions: &[SessionInfo],
+    rules: &[RetentionRule],
+    storage: &StorageConfig,
+    active: &HashSet<String>,
+    now: SystemTime,
+) -> Vec<GcAction> {
+    let mut actions = Vec::new();
+    let mut kept = Vec::new();
+
+    // how many sessions each rule has kept so far, by pattern
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+
+    for session in sessions {
+        let rule = match rule_for(rules, &session.name) {
+            Some(val) => val,
+            None => {
+                kept.push((session, session.bytes));
+                continue;
+            }
+        };
+
+        let is_active = active.contains(&session.name);
+
+        let age = now
+            .duration_since(session.modified)
+            .unwrap_or_default()
+            .as_secs();
+
+        let too_old = rule.max_age_days.filter(|days| age > days * DAY);
+
+        let count = counts.entry(rule.pattern.as_str()).or_insert(0);
+        let too_many = rule.max_sessions.filter(|max| *count >= *max);
+
+        let reason = match (too_old, too_many) {
+            (Some(days), _) => Some(format!("older than {} days", days)),
+            (_, Some(max)) => Some(format!("more than {} sessions", max)),
+            _ => None,
+        };
+
+        if let (Some(reason), false) = (reason, is_active) {
+            actions.push(remove_session(session, reason));
+            continue;
+        }
+
+        *count += 1;
+
+        let bytes = match rule.max_session_bytes {
+            Some(max) => trim_session(session, max, &mut actions),
+            None => session.bytes,
+        };
+
+        kept.push((session, bytes));
+    }
+
+    if let Some(budget) = storage.max_total_bytes {
+        let mut total = kept.iter().map(|(_, bytes)| bytes).sum::<u64>();
+
+        // oldest first now
+        for (session, bytes) in kept.iter().rev() {
+            if total <= budget {
+                break;
+            }
+
+            if active.contains(&session.name) {
+                continue;
+            }
+
+            total -= bytes;
+
+            let reason = format!("log root over {} bytes", budget);
+            actions.push(remove_session(session, reason));
+        }
+    }
+
+    actions
+}
+
+fn remove_session(session: &SessionInfo, reason: String) -> GcAction {
+    GcAction::RemoveSession {
+        name: session.name.clone(),
+        path: session.path.clone(),
+        bytes: session.bytes,
+        reason,
+    }
+}
+
+/// remove the oldest segments until the session fits, never the newest one,
+/// returns the bytes left
+fn trim_session(
+    session: &SessionInfo,
+    max: u64,
+    actions: &mut Vec<GcAction>,
+) -> u64 {
+    let mut bytes = session.bytes;
+    let len = session.segments.len();
+
+    for segment in session.segments.iter().take(len.saturating_sub(1)) {
+        if bytes <= max {
+            break;
+        }
+
+        let size = segment_size(segment);
+        bytes -= size;
+
+        actions.push(GcAction::RemoveSegment {
+            name: session.name.clone(),
+            path: segment.clone(),
+            bytes: size,
+            reason: format!("session over {} bytes", max),
+        });
+    }
+
+    bytes
+}
+
+fn segment_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+/// match a name against a glob with `*` and `?`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+
+    let (mut p, mut n) = (0, 0);
+    // where to go back to after the last star
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,270 @@
+use std::cmp;
+use std::collections::VecDeque;
+
+use crate::ansi::{self, Span, Style, Token};
+
+/// a char on the screen and how it looks
+type Cell = (char, Style);
+
+fn blank() -> Cell {
+    (' ', Style::default())
+}
+
+/// a virtual terminal that output is played into, so a program that redraws
+/// itself with \r and cursor moves shows the way it was drawn
+///
+/// lines are fed in one at a time, as a producer sends them, and each one
+/// ends with a new line. rows pushed off the top are kept as scrollback
+#[derive(Debug, Clone)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    rows: Vec<Vec<Cell>>,
+    scrollback: VecDeque<Vec<Cell>>,
+    /// how many rows of scrollback to keep
+    keep: usize,
+    row: usize,
+    col: usize,
+    saved: (usize, usize),
+    style: Style,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        let width = cmp::max(width, 1);
+        let height = cmp::max(height, 1);
+
+        Screen {
+            width,
+            height,
+            rows: vec![vec![blank(); width]; height],
+            scrollback: VecDeque::new(),
+            keep: 10_000,
+            row: 0,
+            col: 0,
+            saved: (0, 0),
+            style: Style::default(),
+        }
+    }
+
+    /// keep this many rows that scrolled off the top, 10000 by default
+    pub fn with_scrollback(mut self, rows: usize) -> Self {
+        self.keep = rows;
+        self
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// the row and column the cursor is on
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// play a line of output and the new line after it
+    pub fn feed_line(&mut self, line: &str) {
+        self.feed(line);
+        self.line_feed();
+        self.col = 0;
+    }
+
+    /// play output into the screen
+    pub fn feed(&mut self, text: &str) {
+        for token in ansi::tokens(text) {
+            match token {
+                Token::Char('\t') => {
+                    self.col = cmp::min((self.col / 8 + 1) * 8, self.width - 1)
+                }
+                Token::Char(c) => self.put(c),
+                Token::Control('\r') => self.col = 0,
+                Token::Control('\n') => self.line_feed(),
+                Token::Control('\x08') => self.col = self.col.saturating_sub(1),
+                Token::Csi(body, last) => self.csi(&body, last),
+                Token::Control(_) => {}
+            }
+        }
+    }
+
+    /// the screen as rows of styled spans, without the blanks at the end of
+    /// each row
+    pub fn rows(&self) -> Vec<Vec<Span>> {
+        self.rows.iter().map(|row| spans(row)).collect()
+    }
+
+    /// the rows that scrolled off the top then the screen as text, without
+    /// the blank rows at the bottom
+    pub fn text(&self) -> Vec<String> {
+        let mut lines = self
+            .scrollback
+            .iter()
+            .chain(&self.rows)
+            .map(|row| {
+                let text = row.iter().map(|cell| cell.0).collect::<String>();
+                text.trim_end().to_string()
+            })
+            .collect::<Vec<String>>();
+
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+
+        lines
+    }
+
+    fn put(&mut self, c: char) {
+        if self.col >= self.width {
+            self.col = 0;
+            self.line_feed();
+        }
+
+        self.rows[self.row][self.col] = (c, self.style);
+        self.col += 1;
+    }
+
+    /// down a row, scrolling the screen up at the bottom
+    fn line_feed(&mut self) {
+        if self.row + 1 < self.height {
+            self.row += 1;
+            return;
+        }
+
+        let top = self.rows.remove(0);
+        self.rows.push(vec![blank(); self.width]);
+
+        if self.keep > 0 {
+            if self.scrollback.len() == self.keep {
+                self.scrollback.pop_front();
+            }
+
+            self.scrollback.push_back(top);
+        }
+    }
+
+    fn csi(&mut self, body: &str, last: char) {
+        // private modes, only going to and from the alternate screen matters
+        if let Some(mode) = body.strip_prefix('?') {
+            if matches!(mode, "1049" | "1047" | "47") {
+                self.clear(0..self.height);
+            }
+
+            return;
+        }
+
+        let params = ansi::params(body);
+        let first = params.first().copied().unwrap_or(0) as usize;
+        let count = cmp::max(first, 1);
+        let bottom = self.height - 1;
+        let right = self.width - 1;
+
+        match last {
+            'm' => self.style.apply(&params),
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.row = cmp::min(self.row + count, bottom),
+            'C' => self.col = cmp::min(self.col + count, right),
+            'D' => self.col = cmp::min(self.col, right).saturating_sub(count),
+            'E' => {
+                self.row = cmp::min(self.row + count, bottom);
+                self.col = 0;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(count);
+                self.col = 0;
+            }
+            'G' => self.col = cmp::min(count - 1, right),
+            'd' => self.row = cmp::min(count - 1, bottom),
+            'H' | 'f' => {
+                let col = params.get(1).copied().unwrap_or(0) as usize;
+
+                self.row = cmp::min(count - 1, bottom);
+                self.col = cmp::min(cmp::max(col, 1) - 1, right);
+            }
+            'J' => match first {
+                0 => {
+                    self.erase(self.col..self.width);
+                    self.clear(self.row + 1..self.height);
+                }
+                1 => {
+                    self.clear(0..self.row);
+                    self.erase(0..self.col + 1);
+                }
+                _ => self.clear(0..self.height),
+            },
+            'K' => match first {
+                0 => self.erase(self.col..self.width),
+                1 => self.erase(0..self.col + 1),
+                _ => self.erase(0..self.width),
+            },
+            'X' => self.erase(self.col..self.col + count),
+            'P' => {
+                let row = &mut self.rows[self.row];
+                let col = cmp::min(self.col, right);
+                let end = cmp::min(col + count, self.width);
+
+                row.drain(col..end);
+                row.resize(self.width, blank());
+            }
+            '@' => {
+                let row = &mut self.rows[self.row];
+                let col = cmp::min(self.col, right);
+
+                for _ in 0..cmp::min(count, self.width - col) {
+                    row.insert(col, blank());
+                }
+
+                row.truncate(self.width);
+            }
+            'L' => {
+                for _ in 0..cmp::min(count, self.height - self.row) {
+                    self.rows.insert(self.row, vec![blank(); self.width]);
+                    self.rows.pop();
+                }
+            }
+            'M' => {
+                for _ in 0..cmp::min(count, self.height - self.row) {
+                    self.rows.remove(self.row);
+                    self.rows.push(vec![blank(); self.width]);
+                }
+            }
+            's' => self.saved = (self.row, self.col),
+            'u' => (self.row, self.col) = self.saved,
+            _ => {}
+        }
+    }
+
+    /// blank out columns of the cursors row
+    fn erase(&mut self, cols: std::ops::Range<usize>) {
+        let end = cmp::min(cols.end, self.width);
+        let start = cmp::min(cols.start, end);
+
+        self.rows[self.row][start..end].fill(blank());
+    }
+
+    fn clear(&mut self, rows: std::ops::Range<usize>) {
+        for row in &mut self.rows[rows] {
+            row.fill(blank());
+        }
+    }
+}
+
+/// a row as spans of one style, the blanks at the end left off
+fn spans(row: &[Cell]) -> Vec<Span> {
+    let end = row
+        .iter()
+        .rposition(|cell| *cell != blank())
+        .map_or(0, |last| last + 1);
+
+    let mut spans: Vec<Span> = Vec::new();
+
+    for &(c, style) in &row[..end] {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(c),
+            _ => spans.push(Span {
+                style,
+                text: c.to_string(),
+            }),
+        }
+    }
+
+    spans
+}
@@ -0,0 +1,268 @@
+use std::fs;
+use std::sync::Arc;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::UnixStream;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig,
+    ServerConnection, StreamOwned,
+};
+
+/// a byte stream to or from the daemon, ether a unix socket or tls over tcp
+///
+/// the line protocol is the same whatever is underneath
+pub trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+pub type BoxConnection = Box<dyn Connection>;
+
+/// the files a client needs to reach a remote daemon
+#[derive(Debug, Clone, Default)]
+pub struct ClientTls {
+    /// the ca to trust the daemon with, the webpki roots if not given
+    pub ca: Option<PathBuf>,
+    /// a client certificate for daemons that ask for one
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+/// where a client should look for the daemon
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Remote { addr: String, tls: ClientTls },
+}
+
+impl Endpoint {
+    /// connect to the daemon, doing the tls handshake for remotes
+    pub fn connect(&self) -> Result<BoxConnection, Box<dyn Error>> {
+        match self {
+            Endpoint::Unix(path) => {
+                let stream = UnixStream::connect(path).map_err(|err| {
+                    format!("Error connecting to socket: {}", err)
+                })?;
+
+                Ok(Box::new(stream))
+            }
+            Endpoint::Remote { addr, tls } => {
+                let config = client_config(tls)?;
+                let name = server_name(addr)?;
+
+                let tcp = TcpStream::connect(addr.as_str()).map_err(|err| {
+                    format!("Error connecting to {}: {}", addr, err)
+                })?;
+
+                let mut conn = ClientConnection::new(config, name)?;
+
+                // finish the handshake here so bad certs show up on connect
+                let mut tcp = tcp;
+                while conn.is_handshaking() {
+                    conn.complete_io(&mut tcp)
+                        .map_err(|err| format!("Tls Error: {}", err))?;
+                }
+
+                Ok(Box::new(StreamOwned::new(conn, tcp)))
+            }
+        }
+    }
+}
+
+/// the daemon side of the tcp listener
+#[derive(Debug, Clone)]
+pub struct TlsListener {
+    /// the addr to bind to, like 0.0.0.0:7979
+    pub addr: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// when set every client has to show a cert signed by this ca
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsListener {
+    pub fn bind(
+        &self,
+    ) -> Result<(TcpListener, Arc<ServerConfig>), Box<dyn Error>> {
+        let config = server_config(self)?;
+
+        let listener = TcpListener::bind(self.addr.as_str())
+            .map_err(|err| format!("Error binding {}: {}", self.addr, err))?;
+
+        Ok((listener, config))
+    }
+}
+
+/// wrap an accepted tcp stream, the handshake is done on the first read
+pub fn accept_tls(
+    config: &Arc<ServerConfig>,
+    stream: TcpStream,
+) -> Result<BoxConnection, Box<dyn Error>> {
+    let conn = ServerConnection::new(config.clone())?;
+
+    Ok(Box::new(StreamOwned::new(conn, stream)))
+}
+
+fn server_config(
+    opts: &TlsListener,
+) -> Result<Arc<ServerConfig>, Box<dyn Error>> {
+    let certs = load_certs(&opts.cert)?;
+    let key = load_key(&opts.key)?;
+
+    let builder = ServerConfig::builder();
+
+    let builder = match &opts.client_ca {
+        Some(ca) => {
+            let roots = Arc::new(load_roots(ca)?);
+            let verifier = WebPkiClientVerifier::builder(roots)
+                .build()
+                .map_err(|err| format!("Client Ca Error: {}", err))?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(builder.with_single_cert(certs, key)?))
+}
+
+fn client_config(tls: &ClientTls) -> Result<Arc<ClientConfig>, Box<dyn Error>> {
+    let roots = match &tls.ca {
+        Some(ca) => load_roots(ca)?,
+        None => RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        },
+    };
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+
+    let config = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => {
+            builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(Box::from("a client cert needs both cert and key")),
+    };
+
+    Ok(Arc::new(config))
+}
+
+/// the host part of host:port, used to check the daemons cert
+fn server_name(addr: &str) -> Result<ServerName<'static>, Box<dyn Error>> {
+    let host = match addr.rfind(':') {
+        Some(index) => &addr[..index],
+        None => addr,
+    };
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    ServerName::try_from(host.to_string())
+        .map_err(|err| Box::from(format!("Bad Remote Host {}: {}", host, err)))
+}
+
+fn load_certs(
+    path: &PathBuf,
+) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+    let pem = fs::read(path)
+        .map_err(|err| format!("Error reading {}: {}", path.display(), err))?;
+
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        return Err(Box::from(format!("no certs in {}", path.display())));
+    }
+
+    Ok(certs)
+}
+
+fn load_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>, Box<dyn Error>> {
+    let pem = fs::read(path)
+        .map_err(|err| format!("Error reading {}: {}", path.display(), err))?;
+
+    rustls_pemfile::private_key(&mut pem.as_slice())?
+        .ok_or_else(|| Box::from(format!("no key in {}", path.display())))
+}
+
+fn load_roots(path: &PathBuf) -> Result<RootCertStore, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+
+    Ok(roots)
+}
+
+#[cfg(feature = "async")]
+pub use self::asynchronous::*;
+
+/// the same connections for tokio
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::error::Error;
+    use std::sync::Arc;
+
+    use rustls::ServerConfig;
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio::net::{TcpStream, UnixStream};
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    use super::{client_config, server_name, Endpoint};
+
+    pub trait AsyncConnection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+    impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncConnection for T {}
+
+    pub type BoxAsyncConnection = Box<dyn AsyncConnection>;
+
+    impl Endpoint {
+        /// connect without holding up the thread, like connect
+        pub async fn connect_async(
+            &self,
+        ) -> Result<BoxAsyncConnection, Box<dyn Error + Send + Sync>> {
+            match self {
+                Endpoint::Unix(path) => {
+                    let stream =
+                        UnixStream::connect(path).await.map_err(|err| {
+                            format!("Error connecting to socket: {}", err)
+                        })?;
+
+                    Ok(Box::new(stream))
+                }
+                Endpoint::Remote { addr, tls } => {
+                    let config =
+                        client_config(tls).map_err(|err| err.to_string())?;
+                    let name =
+                        server_name(addr).map_err(|err| err.to_string())?;
+
+                    let tcp = TcpStream::connect(addr.as_str()).await.map_err(
+                        |err| format!("Error connecting to {}: {}", addr, err),
+                    )?;
+
+                    let stream = TlsConnector::from(config)
+                        .connect(name, tcp)
+                        .await
+                        .map_err(|err| format!("Tls Error: {}", err))?;
+
+                    Ok(Box::new(stream))
+                }
+            }
+        }
+    }
+
+    /// do the tls handshake on an accepted tcp stream
+    pub async fn accept_tls_async(
+        config: &Arc<ServerConfig>,
+        stream: TcpStream,
+    ) -> Result<BoxAsyncConnection, Box<dyn Error + Send + Sync>> {
+        let stream = TlsAcceptor::from(config.clone()).accept(stream).await?;
+
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,68 @@
+use spellhold::ansi::{self, Color, Style};
+use spellhold::client::search::{Direction, Search};
+
+fn texts(line: &str) -> Vec<String> {
+    ansi::draw(line, Style::default())
+        .0
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+#[test]
+fn a_progress_bar_shows_where_it_got_to() {
+    let line = "[#   ] 25%\r[##  ] 50%\r[####] 100%";
+    assert_eq!(ansi::plain(line), "[####] 100%");
+
+    // shorter text only covers the start, like on a terminal
+    assert_eq!(ansi::plain("downloading\rdone"), "doneloading");
+    assert_eq!(ansi::plain("downloading\r\x1b[Kdone"), "done");
+    assert_eq!(ansi::plain("tick\x08\x08\x08\x08tock"), "tock");
+}
+
+#[test]
+fn cursor_moves_along_the_line_are_played_out() {
+    assert_eq!(ansi::plain("ab\x1b[3Cc"), "ab   c");
+    assert_eq!(ansi::plain("abcdef\x1b[2DX"), "abcdXf");
+    assert_eq!(ansi::plain("abcdef\x1b[2GX\x1b[K"), "aX");
+    assert_eq!(ansi::plain("abcdef\x1b[4G\x1b[1K"), "    ef");
+    assert_eq!(ansi::plain("abc\x1b[2Kx"), "   x");
+
+    // moves off the line are dropped
+    assert_eq!(ansi::plain("one\x1b[1Atwo"), "onetwo");
+}
+
+#[test]
+fn drawn_spans_keep_their_colours() {
+    let (spans, style) =
+        ansi::draw("\x1b[31m50%\r\x1b[32m100%\x1b[4m!", Style::default());
+
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[0].text, "100%");
+    assert_eq!(spans[0].style.fg, Some(Color::Indexed(2)));
+    assert!(spans[1].style.underline);
+    assert!(style.underline);
+
+    assert_eq!(texts("plain \x1b[1mbold\x1b[m\n"), vec!["plain ", "bold"]);
+}
+
+#[test]
+fn escapes_can_be_shown_as_sent() {
+    assert_eq!(
+        ansi::escape("\x1b[31mred\x1b[m\r\tx\x7f"),
+        "^[[31mred^[[m^M\tx^?"
+    );
+    assert_eq!(ansi::escape("plain"), "plain");
+}
+
+#[test]
+fn search_sees_the_line_as_drawn() {
+    let search = Search::new("^ok", Direction::Forward).unwrap();
+    let line = "\x1b[32mok\x1b[0m done\n".to_string();
+
+    assert!(search.is_match(&line));
+    assert_eq!(search.ranges(&line), vec![0..2]);
+    assert!(!Search::new("32m", Direction::Forward)
+        .unwrap()
+        .is_match(&line));
+}
@@ -0,0 +1,196 @@
+#![cfg(feature = "async")]
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+
+use futures::StreamExt;
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::storage::meta::SessionMeta;
+use spellhold::transport::Endpoint;
+
+mod common;
+
+use common::{start_daemon, WAIT};
+
+#[tokio::test]
+async fn a_session_goes_from_producer_to_subscriber() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut events = Subscriber::new(endpoint.clone())
+        .connect_async()
+        .await
+        .unwrap();
+
+    // the viewer is only registered once the daemon has looked at it
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut handle = Producer::new(endpoint)
+        .with_name("tokio")
+        .with_tags(vec!["async"])
+        .connect_async()
+        .await
+        .unwrap();
+
+    let id = handle.id().to_string();
+
+    handle.send("one\ntwo").await.unwrap();
+    handle.set_exit(4);
+    handle.close().await.unwrap();
+
+    let mut seen = Vec::new();
+    while let Some(event) = events.next().await {
+        let ended = matches!(event, Event::Ended { .. });
+        seen.push(event);
+
+        if ended {
+            break;
+        }
+    }
+
+    assert_eq!(
+        seen,
+        vec![
+            Event::Started {
+                session: id.clone()
+            },
+            Event::Tagged {
+                session: id.clone(),
+                tags: vec!["async".into()]
+            },
+            Event::Line {
+                session: id.clone(),
+                text: "one".into()
+            },
+            Event::Line {
+                session: id.clone(),
+                text: "two".into()
+            },
+            Event::Ended {
+                session: id.clone(),
+                exit: Some(4)
+            },
+        ]
+    );
+
+    let meta = SessionMeta::load(&root.join(&id)).unwrap().unwrap();
+    assert_eq!(meta.tags, vec!["async"]);
+    assert_eq!(meta.exit, Some(4));
+}
+
+#[tokio::test]
+async fn subscriptions_are_streams() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let events = Subscriber::new(endpoint.clone())
+        .with_sessions(vec!["wanted_*"])
+        .connect_async()
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    for name in &["other", "wanted"] {
+        let mut handle = Producer::new(endpoint.clone())
+            .with_name(*name)
+            .connect_async()
+            .await
+            .unwrap();
+
+        handle.send(name).await.unwrap();
+        handle.close().await.unwrap();
+    }
+
+    let lines = events
+        .filter_map(|event| async move {
+            match event {
+                Event::Line { text, .. } => Some(text),
+                _ => None,
+            }
+        })
+        .take(1)
+        .collect::<Vec<String>>()
+        .await;
+
+    assert_eq!(lines, vec!["wanted"]);
+}
+
+#[tokio::test]
+async fn lines_are_spooled_until_the_daemon_is_back() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("socket");
+
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    // answer the first connect then go away
+    let first = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        reader.get_mut().write_all(b"ok\n").unwrap();
+    });
+
+    let mut handle = Producer::new(Endpoint::Unix(socket.clone()))
+        .with_spool(2)
+        .with_retry(Duration::from_millis(0))
+        .connect_async()
+        .await
+        .unwrap();
+
+    first.join().unwrap();
+    fs::remove_file(&socket).unwrap();
+
+    // the first write can still land in the closed sockets buffer
+    let started = Instant::now();
+    let mut index = 0;
+    while handle.spooled() < 2 {
+        assert!(started.elapsed() < WAIT, "lines were never spooled");
+
+        handle.send(&format!("while down {}", index)).await.unwrap();
+        index += 1;
+    }
+
+    let listener = UnixListener::bind(&socket).unwrap();
+    let second = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        reader.get_mut().write_all(b"ok\n").unwrap();
+
+        reader.lines().map(Result::unwrap).collect::<Vec<String>>()
+    });
+
+    handle.set_exit(0);
+    handle.close().await.unwrap();
+
+    let lines = second.join().unwrap();
+    let sent = lines[lines.len() - 2].splitn(3, ' ').nth(2).unwrap();
+
+    assert_eq!(sent, format!("while down {}", index - 1));
+    assert_eq!(lines.last().unwrap(), "end -EXIT- 0");
+}
+
+#[tokio::test]
+async fn a_missing_daemon_is_an_error_at_connect() {
+    let dir = TempDir::new().unwrap();
+    let endpoint = Endpoint::Unix(dir.path().join("socket"));
+
+    assert!(Producer::new(endpoint.clone())
+        .connect_async()
+        .await
+        .is_err());
+    assert!(Subscriber::new(endpoint).connect_async().await.is_err());
+}
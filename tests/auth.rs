@@ -0,0 +1,348 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use std::io::{BufRead, BufReader, Write};
+
+use tempfile::TempDir;
+
+use spellhold::client::subscriber::{Event, Subscriber, Subscription};
+use spellhold::daemon::DaemonEvent;
+use spellhold::daemon::builder::{DaemonBuilder, DaemonHandle};
+use spellhold::protocol::{self, Handshake};
+use spellhold::transport::{BoxConnection, Endpoint};
+
+mod common;
+
+use common::WAIT;
+
+const TOKENS: &str = r#"
+[[tokens]]
+name = "builds"
+secret = "build-secret"
+role = "producer"
+prefix = "build_"
+
+[[tokens]]
+name = "watcher"
+secret = "watch-secret"
+role = "viewer"
+prefix = "build_"
+
+[[tokens]]
+name = "deploys"
+secret = "deploy-secret"
+role = "producer"
+prefix = "deploy_"
+"#;
+
+/// a daemon with the tokens in config, and what it would have printed
+fn start(dir: &Path, config: &str) -> (DaemonHandle, Receiver<DaemonEvent>) {
+    let config_path = dir.join("config.toml");
+    fs::write(&config_path, config).unwrap();
+
+    let (events, heard) = mpsc::channel();
+
+    let daemon = common::start(
+        DaemonBuilder::new(dir.join("socket"))
+            .with_log_root(dir.join("logs"))
+            .with_config(&config_path)
+            .with_events(move |evt| {
+                let _ = events.send(evt.clone());
+            }),
+    );
+
+    (daemon, heard)
+}
+
+fn handshake(
+    endpoint: &Endpoint,
+    handshake: Handshake,
+) -> (BufReader<BoxConnection>, Result<(), String>) {
+    let mut reader = BufReader::new(endpoint.connect().unwrap());
+
+    reader
+        .get_mut()
+        .write_all(handshake.to_line().as_bytes())
+        .unwrap();
+
+    let reply =
+        protocol::read_reply(&mut reader).map_err(|err| err.to_string());
+
+    (reader, reply)
+}
+
+/// change the config under a running daemon and wait for it to be seen
+fn rewrite(dir: &Path, config: &str) {
+    let config_path = dir.join("config.toml");
+    fs::write(&config_path, config).unwrap();
+
+    // make sure the mtime moves
+    fs::File::options()
+        .write(true)
+        .open(&config_path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(5))
+        .unwrap();
+
+    // the config is only looked at once a second
+    thread::sleep(Duration::from_millis(1100));
+}
+
+/// a viewer with the watcher token
+fn watch(endpoint: &Endpoint) -> Subscription {
+    let events = Subscriber::new(endpoint.clone())
+        .with_token(Some("watch-secret".into()))
+        .connect()
+        .unwrap();
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    events
+}
+
+/// a line sent as a producer of the session, which then ends
+fn send(endpoint: &Endpoint, token: &str, id: &str, line: &str) {
+    let (mut reader, reply) = handshake(
+        endpoint,
+        Handshake::Connect {
+            id: id.into(),
+            token: Some(token.into()),
+            tags: Vec::new(),
+        },
+    );
+    reply.unwrap();
+
+    let stream = reader.get_mut();
+    writeln!(stream, "{} -ENDID- {}", id, line).unwrap();
+    stream
+        .write_all(protocol::end_line(None).as_bytes())
+        .unwrap();
+
+    // one session at a time reaches the viewers in the order sent
+    thread::sleep(Duration::from_millis(100));
+}
+
+/// the next line a viewer gets, passing over sessions starting and ending
+fn next_line(events: &mut Subscription) -> Option<(String, String)> {
+    events.find_map(|event| match event {
+        Event::Line { session, text } => Some((session, text)),
+        Event::Error(err) => panic!("viewer failed: {}", err),
+        _ => None,
+    })
+}
+
+#[test]
+fn producers_need_a_token_covering_the_session() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, heard) = start(dir.path(), TOKENS);
+    let endpoint = daemon.endpoint();
+
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: None,
+            tags: Vec::new(),
+        },
+    );
+    assert!(reply.is_err());
+
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "deploy_1".into(),
+            token: Some("build-secret".into()),
+            tags: Vec::new(),
+        },
+    );
+    assert!(reply.unwrap_err().contains("doesnt cover"));
+
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: Some("watch-secret".into()),
+            tags: Vec::new(),
+        },
+    );
+    assert!(reply.is_err());
+
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: Some("build-secret".into()),
+            tags: Vec::new(),
+        },
+    );
+    assert!(reply.is_ok());
+
+    loop {
+        let evt = heard.recv_timeout(WAIT).expect("nothing connected");
+
+        if let DaemonEvent::Connected(id) = evt {
+            assert_eq!(id, "build_1");
+            break;
+        }
+    }
+
+    daemon.close().unwrap();
+
+    // nothing from the refused producers made it to the main loop
+    let others = heard
+        .try_iter()
+        .filter(|evt| matches!(evt, DaemonEvent::Connected(_)))
+        .collect::<Vec<DaemonEvent>>();
+    assert_eq!(others, Vec::new());
+}
+
+#[test]
+fn viewers_only_see_the_sessions_their_token_covers() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start(dir.path(), TOKENS);
+    let endpoint = daemon.endpoint();
+
+    let mut events = watch(&endpoint);
+
+    send(&endpoint, "build-secret", "build_1", "seen");
+    send(&endpoint, "deploy-secret", "deploy_1", "hidden");
+    send(&endpoint, "build-secret", "build_2", "also seen");
+
+    assert_eq!(
+        next_line(&mut events),
+        Some(("build_1".into(), "seen".into()))
+    );
+    assert_eq!(
+        next_line(&mut events),
+        Some(("build_2".into(), "also seen".into()))
+    );
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn removing_a_token_from_the_config_cuts_off_its_viewers() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start(dir.path(), TOKENS);
+    let endpoint = daemon.endpoint();
+
+    let mut events = watch(&endpoint);
+
+    send(&endpoint, "build-secret", "build_1", "before");
+
+    assert_eq!(
+        next_line(&mut events),
+        Some(("build_1".into(), "before".into()))
+    );
+
+    // keep only the producer token
+    let producer_only = TOKENS.split("[[tokens]]").nth(1).unwrap();
+    rewrite(dir.path(), &format!("[[tokens]]{}", producer_only));
+
+    send(&endpoint, "build-secret", "build_2", "after");
+
+    let rest = events.collect::<Vec<Event>>();
+    assert!(
+        rest.iter().all(|event| event.session() != Some("build_2")),
+        "revoked viewer still got {:?}",
+        rest
+    );
+
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Client {
+            token: Some("watch-secret".into()),
+            history: None,
+            times: false,
+            finished: false,
+        },
+    );
+    assert!(reply.is_err());
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn removing_the_last_token_lets_nobody_in() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start(dir.path(), TOKENS);
+    let endpoint = daemon.endpoint();
+
+    rewrite(dir.path(), "");
+
+    let handshakes = vec![
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: None,
+            tags: Vec::new(),
+        },
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: Some("build-secret".into()),
+            tags: Vec::new(),
+        },
+        Handshake::Client {
+            token: None,
+            history: None,
+            times: false,
+            finished: false,
+        },
+        Handshake::Gc {
+            token: None,
+            dry_run: false,
+        },
+        Handshake::Compact {
+            token: None,
+            session: None,
+        },
+    ];
+
+    for wanted in handshakes {
+        let (_, reply) = handshake(&endpoint, wanted.clone());
+        assert!(reply.is_err(), "{:?} was let in", wanted);
+    }
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn producers_cant_stop_the_daemon() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start(dir.path(), TOKENS);
+    let endpoint = daemon.endpoint();
+
+    let (mut reader, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "build_1".into(),
+            token: Some("build-secret".into()),
+            tags: Vec::new(),
+        },
+    );
+    reply.unwrap();
+
+    reader.get_mut().write_all(b"kill\n").unwrap();
+
+    // the producer is hung up on
+    let mut rest = String::new();
+    reader.read_line(&mut rest).unwrap();
+    assert_eq!(rest, "");
+
+    thread::sleep(Duration::from_millis(200));
+
+    // and the daemon is still there for the next one
+    let (_, reply) = handshake(
+        &endpoint,
+        Handshake::Connect {
+            id: "build_2".into(),
+            token: Some("build-secret".into()),
+            tags: Vec::new(),
+        },
+    );
+    reply.unwrap();
+
+    daemon.close().unwrap();
+}
@@ -0,0 +1,288 @@
+use std::{iter, thread};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use std::sync::atomic::Ordering;
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::stats::fetch_stats;
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::config::{DaemonConfig, QueueConfig};
+use spellhold::daemon::SendEvt;
+use spellhold::daemon::builder::{DaemonBuilder, DaemonHandle};
+use spellhold::daemon::queue::{Policy, Queue};
+use spellhold::protocol::Handshake;
+use spellhold::transport::Endpoint;
+
+mod common;
+
+const WAIT: Duration = Duration::from_secs(10);
+
+fn start(dir: &TempDir, queues: QueueConfig) -> DaemonHandle {
+    let config = DaemonConfig {
+        queues,
+        ..DaemonConfig::default()
+    };
+
+    common::start(
+        DaemonBuilder::new(dir.path().join("socket"))
+            .with_log_root(dir.path().join("logs"))
+            .with_options(config),
+    )
+}
+
+/// connect as the producer of a session and send every line given
+fn flood<I>(endpoint: Endpoint, id: &str, lines: I) -> thread::JoinHandle<()>
+where
+    I: Iterator<Item = String> + Send + 'static,
+{
+    let handshake = Handshake::Connect {
+        id: id.into(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    thread::spawn(move || {
+        let mut conn = endpoint.connect().unwrap();
+
+        conn.write_all(handshake.to_line().as_bytes()).unwrap();
+
+        for line in lines {
+            // the daemon hangs up on the producer once it is shut down
+            if writeln!(conn, "{}", line).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+fn line(id: &str, index: usize) -> SendEvt {
+    SendEvt::SendString(format!("{} -ENDID- {}", id, index))
+}
+
+#[test]
+fn drop_oldest_stays_bounded_and_leaves_a_gap() {
+    let queue = Queue::new(8, Policy::DropOldest);
+
+    for index in 0..10_000 {
+        queue.push(line("flood", index)).unwrap();
+    }
+
+    let stats = queue.stats();
+    assert_eq!(stats.high_water.load(Ordering::Relaxed), 8);
+    assert_eq!(stats.dropped.load(Ordering::Relaxed), 9_992);
+
+    match queue.pop() {
+        Some(SendEvt::Gap(id, dropped)) => {
+            assert_eq!((id.as_str(), dropped), ("flood", 9_992))
+        }
+        evt => panic!("expected a gap, got {:?}", evt),
+    }
+
+    // the newest lines are the ones kept
+    match queue.pop() {
+        Some(SendEvt::SendString(val)) => assert_eq!(val, "flood -ENDID- 9992"),
+        evt => panic!("expected a line, got {:?}", evt),
+    }
+}
+
+#[test]
+fn disconnect_policy_closes_the_queue() {
+    let queue = Queue::new(2, Policy::Disconnect);
+
+    queue.push(line("slow", 0)).unwrap();
+    queue.push(line("slow", 1)).unwrap();
+
+    assert!(queue.push(line("slow", 2)).is_err());
+    assert!(queue.is_closed());
+    assert!(queue.pop().is_none());
+}
+
+#[test]
+fn a_stalled_main_loop_blocks_the_producer_without_losing_lines() {
+    let dir = TempDir::new().unwrap();
+    let queues = QueueConfig {
+        producer_capacity: 16,
+        producer_policy: Policy::Block,
+        viewer_capacity: 4,
+        viewer_policy: Policy::Block,
+        ..QueueConfig::default()
+    };
+    let daemon = start(&dir, queues);
+    let endpoint = daemon.endpoint();
+
+    // a viewer that blocks the main loop until it reads, like a stuck disk
+    let events = Subscriber::new(endpoint.clone()).connect().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let total = 20_000;
+    let pad = "x".repeat(200);
+    let sent = pad.clone();
+
+    let producer = flood(
+        endpoint.clone(),
+        "flood",
+        (0..total)
+            .map(move |index| format!("flood -ENDID- {} {}", index, sent)),
+    );
+
+    // more than the sockets hold, so the producer is held up
+    thread::sleep(Duration::from_millis(500));
+    assert!(!producer.is_finished());
+
+    let mut lines = events.filter_map(|event| match event {
+        Event::Line { text, .. } => Some(text),
+        Event::Started { .. } => None,
+        other => panic!("expected a line, got {:?}", other),
+    });
+
+    for index in 0..total {
+        assert_eq!(lines.next(), Some(format!("{} {}", index, pad)));
+    }
+
+    producer.join().unwrap();
+
+    let stats = fetch_stats(&endpoint, None).unwrap();
+
+    assert!(stats[0].contains("capacity=16 high_water=16 dropped=0"));
+    assert!(!stats[0].ends_with("blocked=0"));
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn a_viewer_that_stops_reading_is_disconnected() {
+    let dir = TempDir::new().unwrap();
+    let queues = QueueConfig {
+        viewer_capacity: 4,
+        viewer_policy: Policy::Disconnect,
+        ..QueueConfig::default()
+    };
+    let daemon = start(&dir, queues);
+    let endpoint = daemon.endpoint();
+
+    // connect and then never read, the socket buffer fills then the queue
+    let mut conn = endpoint.connect().unwrap();
+    conn.write_all(
+        Handshake::Client {
+            token: None,
+            history: None,
+            times: false,
+            finished: false,
+        }
+        .to_line()
+        .as_bytes(),
+    )
+    .unwrap();
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    let big = "x".repeat(16 * 1024);
+    let producer = flood(
+        endpoint.clone(),
+        "slow",
+        (0..).map(move |_| format!("slow -ENDID- {}", big)),
+    );
+
+    let started = Instant::now();
+
+    loop {
+        assert!(started.elapsed() < WAIT, "slow viewer was never dropped");
+
+        let stats = fetch_stats(&endpoint, None).unwrap();
+
+        if stats.last().unwrap() == "viewers connected=0 disconnected_slow=1" {
+            assert!(stats[0].contains("capacity=1024"));
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    daemon.close().unwrap();
+    producer.join().unwrap();
+}
+
+#[test]
+fn history_bigger_than_the_queue_leaves_a_gap_for_each_session() {
+    let dir = TempDir::new().unwrap();
+    let queues = QueueConfig {
+        viewer_capacity: 32,
+        ..QueueConfig::default()
+    };
+    let daemon = start(&dir, queues);
+    let endpoint = daemon.endpoint();
+
+    let handles = ["first", "second"]
+        .iter()
+        .map(|name| {
+            let mut handle = Producer::new(endpoint.clone())
+                .with_name(*name)
+                .connect()
+                .unwrap();
+
+            for index in 0..100 {
+                handle.send(&format!("line {}", index)).unwrap();
+            }
+
+            handle
+        })
+        .collect::<Vec<_>>();
+
+    thread::sleep(Duration::from_millis(300));
+
+    let mut events = Subscriber::new(endpoint)
+        .with_history(Some(1000))
+        .connect()
+        .unwrap();
+
+    // the viewer has been added once its history starts coming
+    let first = events.next().unwrap();
+
+    let ids = handles
+        .iter()
+        .map(|handle| handle.id().to_string())
+        .collect::<Vec<String>>();
+
+    for handle in handles {
+        handle.close().unwrap();
+    }
+
+    let mut ended = 0;
+    let seen = iter::once(first)
+        .chain(events)
+        .take_while(|event| {
+            ended += matches!(event, Event::Ended { .. }) as usize;
+            ended < ids.len()
+        })
+        .collect::<Vec<Event>>();
+
+    // each session is cut short, not left out
+    for id in ids {
+        let mine = seen
+            .iter()
+            .filter(|event| event.session() == Some(id.as_str()))
+            .collect::<Vec<&Event>>();
+
+        let dropped = match mine[0] {
+            Event::Gap { dropped, .. } => *dropped as usize,
+            other => panic!("expected a gap, got {:?}", other),
+        };
+
+        let lines = mine
+            .iter()
+            .filter_map(|event| match event {
+                Event::Line { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<&str>>();
+
+        assert!(dropped > 0);
+        assert_eq!(dropped + lines.len(), 100);
+        assert_eq!(lines.last(), Some(&"line 99"));
+    }
+
+    daemon.close().unwrap();
+}
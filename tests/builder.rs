@@ -0,0 +1,133 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::daemon::DaemonEvent;
+use spellhold::daemon::builder::DaemonBuilder;
+
+#[test]
+fn what_the_daemon_did_goes_to_the_callback() {
+    let dir = TempDir::new().unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let events = seen.clone();
+
+    let daemon = DaemonBuilder::new(dir.path().join("socket"))
+        .with_log_root(dir.path().join("logs"))
+        .with_events(move |evt| events.lock().unwrap().push(evt.clone()))
+        .start()
+        .unwrap();
+
+    let mut viewer = Subscriber::new(daemon.endpoint()).connect().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut handle = Producer::new(daemon.endpoint())
+        .with_name("job")
+        .connect()
+        .unwrap();
+
+    writeln!(handle, "hello").unwrap();
+    let id = handle.id().to_string();
+    handle.close().unwrap();
+
+    // only what reached the main queue is written before it stops
+    assert!(viewer.any(|evt| matches!(evt, Event::Ended { .. })));
+
+    daemon.shutdown();
+    daemon.join().unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert!(seen.contains(&DaemonEvent::Connected(id.clone())));
+    assert!(seen.contains(&DaemonEvent::Line(format!("{} -ENDID- hello", id))));
+}
+
+#[test]
+fn shutdown_lets_go_of_viewers_and_the_socket() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("socket");
+
+    let daemon = DaemonBuilder::new(&socket)
+        .with_log_root(dir.path().join("logs"))
+        .start()
+        .unwrap();
+
+    // bound before start returns
+    assert!(socket.exists());
+    assert!(daemon.local_addr().is_none());
+
+    let events = Subscriber::new(daemon.endpoint()).connect().unwrap();
+
+    daemon.shutdown();
+    daemon.join().unwrap();
+
+    assert!(!socket.exists());
+    assert!(events.filter(|evt| !matches!(evt, Event::Error(_))).count() == 0);
+}
+
+#[test]
+fn a_bad_config_is_an_error_at_start() {
+    let dir = TempDir::new().unwrap();
+
+    let started = DaemonBuilder::new(dir.path().join("socket"))
+        .with_log_root(dir.path().join("logs"))
+        .with_config(dir.path().join("missing.toml"))
+        .start();
+
+    assert!(started.is_err());
+}
+
+#[test]
+fn a_log_root_has_to_be_given() {
+    let dir = TempDir::new().unwrap();
+
+    let started = DaemonBuilder::new(dir.path().join("socket")).start();
+
+    match started {
+        Err(err) => assert!(err.to_string().contains("with_log_root")),
+        Ok(_) => panic!("started without a log root"),
+    }
+}
+
+#[test]
+fn connection_errors_go_to_the_callback() {
+    let dir = TempDir::new().unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let events = seen.clone();
+
+    let daemon = DaemonBuilder::new(dir.path().join("socket"))
+        .with_log_root(dir.path().join("logs"))
+        .with_events(move |evt| events.lock().unwrap().push(evt.clone()))
+        .start()
+        .unwrap();
+
+    let mut conn = UnixStream::connect(daemon.socket()).unwrap();
+    conn.write_all(b"not a handshake\n").unwrap();
+
+    // the refusal is answered before it is reported
+    let mut reply = String::new();
+    BufReader::new(conn).read_line(&mut reply).unwrap();
+
+    let reported = || {
+        seen.lock().unwrap().iter().any(|evt| {
+            matches!(
+                evt,
+                DaemonEvent::Error(err) if err.starts_with("Error handling stream")
+            )
+        })
+    };
+
+    let started = Instant::now();
+
+    while !reported() {
+        assert!(started.elapsed() < Duration::from_secs(5), "never reported");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    daemon.shutdown();
+    daemon.join().unwrap();
+}
@@ -0,0 +1,90 @@
+// each test binary uses its own part of this
+#![allow(dead_code)]
+
+use std::thread;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use spellhold::daemon::builder::{DaemonBuilder, DaemonHandle};
+use spellhold::storage::Storage;
+use spellhold::storage::meta::{SessionMeta, SessionState};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Record, Stream};
+
+pub const WAIT: Duration = Duration::from_secs(5);
+
+/// a whole daemon on a socket in dir, logging under dir/logs. it is shut
+/// down when the handle is dropped
+///
+/// with the async feature it is the tokio one, so the same tests cover both
+pub fn start_daemon(dir: &Path) -> (DaemonHandle, PathBuf) {
+    let root = dir.join("logs");
+    let builder = DaemonBuilder::new(dir.join("socket")).with_log_root(&root);
+
+    (start(builder), root)
+}
+
+#[cfg(not(feature = "async"))]
+pub fn start(builder: DaemonBuilder) -> DaemonHandle {
+    builder.start().unwrap()
+}
+
+#[cfg(feature = "async")]
+pub fn start(builder: DaemonBuilder) -> DaemonHandle {
+    builder.start_tokio().unwrap()
+}
+
+/// the out lines a session has on disk, once the daemon has closed it
+pub fn stored(root: &Path, id: &str) -> Vec<String> {
+    let session = root.join(id);
+    let started = Instant::now();
+
+    loop {
+        let meta = SessionMeta::load(&session).unwrap_or_default();
+
+        if meta.map(|m| m.state == SessionState::Closed) == Some(true) {
+            break;
+        }
+
+        assert!(started.elapsed() < WAIT, "{} was never closed", id);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    SessionReader::open(&session)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|record| record.stream == Stream::Out)
+        .map(|record: Record| record.text())
+        .collect()
+}
+
+/// a session of count lines written straight to storage, line n is "line n"
+/// at the time ts gives for n, now when it gives none. a new segment starts
+/// every segment_bytes. it is left open, the storage is given back to close
+/// it
+pub fn write_session<F>(
+    root: &Path,
+    name: &str,
+    count: u64,
+    segment_bytes: u64,
+    ts: F,
+) -> Storage
+where
+    F: Fn(u64) -> Option<u64>,
+{
+    let mut storage = Storage::new(root.to_owned(), segment_bytes);
+
+    for seq in 0..count {
+        let line = format!("line {}", seq);
+
+        match ts(seq) {
+            Some(at) => {
+                storage.append_at(name, Stream::Out, at, line.as_bytes())
+            }
+            None => storage.append(name, Stream::Out, line.as_bytes()),
+        }
+        .unwrap();
+    }
+
+    storage
+}
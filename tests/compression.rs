@@ -0,0 +1,172 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use spellhold::client::stats::fetch_compact;
+use spellhold::storage::{SessionInfo, Storage};
+use spellhold::storage::compress::{compact, compress_segment, Compression};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::Stream;
+
+mod common;
+
+use common::{start_daemon, write_session};
+
+/// a session of count lines, a new segment every kilobyte, and the text it
+/// should read back as
+fn session(root: &Path, name: &str, count: u64) -> String {
+    write_session(root, name, count, 1024, |_| None);
+
+    (0..count).map(|seq| format!("line {}\n", seq)).collect()
+}
+
+fn read(root: &Path, name: &str) -> String {
+    let mut text = String::new();
+
+    for record in SessionReader::open(&root.join(name)).unwrap() {
+        text.push_str(&record.unwrap().text());
+        text.push('\n');
+    }
+
+    text
+}
+
+fn backdate(root: &Path, name: &str, age: Duration) {
+    let when = SystemTime::now() - age;
+
+    for entry in fs::read_dir(root.join(name)).unwrap() {
+        let file = File::options().append(true).open(entry.unwrap().path());
+        file.unwrap().set_modified(when).unwrap();
+    }
+
+    File::open(root.join(name))
+        .unwrap()
+        .set_modified(when)
+        .unwrap();
+}
+
+#[test]
+fn compressed_sessions_read_back_the_same() {
+    for compression in &[Compression::Gzip, Compression::Zstd] {
+        let dir = TempDir::new().unwrap();
+        let expected = session(dir.path(), "verbose", 200);
+
+        let active = HashSet::new();
+        let actions =
+            compact(dir.path(), *compression, &active, None, None).unwrap();
+
+        assert_eq!(actions.len(), 1);
+        assert!(actions[0].after < actions[0].before);
+
+        let info = SessionInfo::load(&dir.path().join("verbose")).unwrap();
+        let ext = compression.extension().unwrap();
+
+        assert!(info.segments.len() > 1);
+        assert!(info
+            .segments
+            .iter()
+            .all(|path| path.to_string_lossy().ends_with(ext)));
+
+        assert_eq!(read(dir.path(), "verbose"), expected);
+    }
+}
+
+#[test]
+fn only_idle_finished_sessions_are_compacted() {
+    let dir = TempDir::new().unwrap();
+
+    session(dir.path(), "idle", 10);
+    session(dir.path(), "recent", 10);
+    session(dir.path(), "running", 10);
+
+    backdate(dir.path(), "idle", Duration::from_secs(7200));
+    backdate(dir.path(), "running", Duration::from_secs(7200));
+
+    let active = ["running".to_string()].iter().cloned().collect();
+    let idle = Some(Duration::from_secs(3600));
+
+    let actions =
+        compact(dir.path(), Compression::Zstd, &active, idle, None).unwrap();
+
+    let names = actions
+        .iter()
+        .map(|action| action.name.as_str())
+        .collect::<Vec<&str>>();
+
+    assert_eq!(names, vec!["idle"]);
+
+    // nothing left to do the second time
+    let again =
+        compact(dir.path(), Compression::Zstd, &active, idle, None).unwrap();
+    assert!(again.is_empty());
+}
+
+#[test]
+fn a_reopened_session_starts_a_new_segment() {
+    let dir = TempDir::new().unwrap();
+    let mut expected = session(dir.path(), "again", 3);
+
+    compact(dir.path(), Compression::Gzip, &HashSet::new(), None, None)
+        .unwrap();
+
+    let mut storage = Storage::new(dir.path().to_owned(), 1024);
+    storage
+        .append("again", Stream::Out, b"after compaction")
+        .unwrap();
+    expected.push_str("after compaction\n");
+
+    let info = SessionInfo::load(&dir.path().join("again")).unwrap();
+    let names = info
+        .segments
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+
+    assert_eq!(names, vec!["00000.log.gz", "00001.log"]);
+    assert_eq!(read(dir.path(), "again"), expected);
+}
+
+#[test]
+fn a_half_done_compaction_reads_the_compressed_copy() {
+    let dir = TempDir::new().unwrap();
+    let expected = session(dir.path(), "torn", 3);
+
+    let plain = dir.path().join("torn/00000.log");
+    let kept = fs::read(&plain).unwrap();
+
+    compress_segment(&plain, Compression::Zstd).unwrap();
+
+    // as if the daemon died between the rename and the remove
+    fs::write(&plain, kept).unwrap();
+
+    let info = SessionInfo::load(&dir.path().join("torn")).unwrap();
+
+    assert_eq!(info.segments.len(), 1);
+    assert_eq!(read(dir.path(), "torn"), expected);
+}
+
+#[test]
+fn compact_over_the_socket_compresses_now() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let expected = session(&root, "build_1", 20);
+    session(&root, "build_2", 20);
+
+    let lines = fetch_compact(&endpoint, None, Some("build_1".into())).unwrap();
+
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("compressed build_1"));
+    assert_eq!(read(&root, "build_1"), expected);
+
+    let lines = fetch_compact(&endpoint, None, None).unwrap();
+
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("compressed build_2"));
+
+    daemon.close().unwrap();
+}
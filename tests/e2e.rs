@@ -0,0 +1,346 @@
+use std::fs;
+use std::thread;
+use std::path::Path;
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use std::io::{BufReader, Write};
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::config::DaemonConfig;
+use spellhold::daemon::builder::{DaemonBuilder, DaemonHandle};
+use spellhold::protocol::{self, Handshake};
+use spellhold::storage::meta::META_FILE;
+use spellhold::storage::segment_name;
+use spellhold::transport::Endpoint;
+
+mod common;
+
+use common::{stored, WAIT};
+
+const PRODUCERS: usize = 8;
+
+/// a daemon whose viewers have room for everything these tests send, so
+/// nothing is dropped and every line can be checked
+fn start(dir: &Path) -> DaemonHandle {
+    let mut options = DaemonConfig::default();
+    options.queues.viewer_capacity = 1 << 20;
+
+    common::start(
+        DaemonBuilder::new(dir.join("socket"))
+            .with_options(options)
+            .with_log_root(dir.join("logs")),
+    )
+}
+
+/// what producer n sends, every awkward kind of line then a run of numbered
+/// ones to show the order is kept
+fn content(n: usize) -> Vec<String> {
+    let mut lines = vec![
+        format!("producer {} starting", n),
+        "  spaces   inside and around  ".to_string(),
+        String::new(),
+        "unicode: héllo wörld ✓ 日本語 🚀".to_string(),
+        "\ttabs\tand a -ENDID- in the text".to_string(),
+        String::new(),
+        format!("{}{}", n, "x".repeat(256 * 1024)),
+    ];
+
+    lines.extend((0..200).map(|i| format!("{} line {}", n, i)));
+    lines
+}
+
+/// a viewer on a thread of its own, so waiting on it can time out
+fn watch(endpoint: Endpoint) -> Receiver<Event> {
+    let events = Subscriber::new(endpoint).connect().unwrap();
+    let (send, recv) = mpsc::channel();
+
+    thread::spawn(move || {
+        for event in events {
+            if send.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    recv
+}
+
+/// the lines each session got until count of them ended, with their exits
+fn until_ended(
+    events: &Receiver<Event>,
+    count: usize,
+) -> HashMap<String, (Vec<String>, Option<i32>)> {
+    let mut sessions: HashMap<String, (Vec<String>, Option<i32>)> =
+        HashMap::new();
+    let mut ended = 0;
+
+    while ended < count {
+        match events.recv_timeout(WAIT).expect("viewer stopped hearing") {
+            Event::Line { session, text }
+            | Event::Stamped { session, text, .. } => {
+                sessions.entry(session).or_default().0.push(text)
+            }
+            Event::Ended { session, exit } => {
+                sessions.entry(session).or_default().1 = exit;
+                ended += 1;
+            }
+            Event::Gap { session, dropped } => {
+                panic!("{} lines of {} were dropped", dropped, session)
+            }
+            Event::Error(err) => panic!("viewer failed: {}", err),
+            Event::Started { .. }
+            | Event::Opened { .. }
+            | Event::Tagged { .. } => {}
+        }
+    }
+
+    sessions
+}
+
+fn send_all(endpoint: Endpoint, name: &str, lines: &[String]) -> String {
+    let mut handle = Producer::new(endpoint).with_name(name).connect().unwrap();
+
+    for line in lines {
+        writeln!(handle, "{}", line).unwrap();
+    }
+
+    let id = handle.id().to_string();
+    handle.close().unwrap();
+
+    id
+}
+
+/// connect as exactly id, send lines and hang up
+fn send_as(socket: &Path, id: &str, lines: &[&str]) {
+    let mut reader = BufReader::new(UnixStream::connect(socket).unwrap());
+
+    let connect = Handshake::Connect {
+        id: id.to_string(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    reader
+        .get_mut()
+        .write_all(connect.to_line().as_bytes())
+        .unwrap();
+    protocol::read_reply(&mut reader).unwrap();
+
+    for line in lines {
+        writeln!(reader.get_mut(), "{} -ENDID- {}", id, line).unwrap();
+    }
+
+    writeln!(reader.get_mut(), "end").unwrap();
+}
+
+#[test]
+fn concurrent_producers_arrive_whole_and_in_order() {
+    let dir = TempDir::new().unwrap();
+    let daemon = start(dir.path());
+    let events = watch(daemon.endpoint());
+
+    let producers = (0..PRODUCERS)
+        .map(|n| {
+            let endpoint = daemon.endpoint();
+
+            thread::spawn(move || {
+                let mut handle = Producer::new(endpoint)
+                    .with_name(format!("p{}", n))
+                    .connect()
+                    .unwrap();
+
+                for line in content(n) {
+                    writeln!(handle, "{}", line).unwrap();
+                }
+
+                handle.set_exit(n as i32);
+
+                let id = handle.id().to_string();
+                handle.close().unwrap();
+
+                id
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let ids = producers
+        .into_iter()
+        .map(|producer| producer.join().unwrap())
+        .collect::<Vec<String>>();
+
+    let seen = until_ended(&events, PRODUCERS);
+
+    for (n, id) in ids.iter().enumerate() {
+        let (lines, exit) = &seen[id];
+
+        assert!(*lines == content(n), "{} was seen wrong", id);
+        assert_eq!(*exit, Some(n as i32));
+        assert!(stored(&dir.path().join("logs"), id) == content(n));
+    }
+}
+
+#[test]
+fn every_viewer_sees_every_line_even_when_one_leaves() {
+    let dir = TempDir::new().unwrap();
+    let daemon = start(dir.path());
+
+    let viewers = (0..3)
+        .map(|_| watch(daemon.endpoint()))
+        .collect::<Vec<Receiver<Event>>>();
+
+    // one that hangs up before anything is sent
+    drop(Subscriber::new(daemon.endpoint()).connect().unwrap());
+
+    let id = send_all(daemon.endpoint(), "shared", &content(0));
+
+    for events in &viewers {
+        let seen = until_ended(events, 1);
+        assert!(seen[&id].0 == content(0));
+    }
+}
+
+#[test]
+fn a_producer_that_vanishes_is_ended_with_what_it_sent() {
+    let dir = TempDir::new().unwrap();
+    let daemon = start(dir.path());
+    let events = watch(daemon.endpoint());
+
+    let id = "vanished_1".to_string();
+    let mut reader =
+        BufReader::new(UnixStream::connect(daemon.socket()).unwrap());
+
+    let connect = Handshake::Connect {
+        id: id.clone(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    reader
+        .get_mut()
+        .write_all(connect.to_line().as_bytes())
+        .unwrap();
+    protocol::read_reply(&mut reader).unwrap();
+
+    for line in ["one", "", "three"] {
+        writeln!(reader.get_mut(), "{} -ENDID- {}", id, line).unwrap();
+    }
+
+    // no end line, the connection is just gone
+    drop(reader);
+
+    let seen = until_ended(&events, 1);
+    assert_eq!(
+        seen[&id],
+        (vec!["one".into(), "".into(), "three".into()], None)
+    );
+    assert_eq!(
+        stored(&dir.path().join("logs"), &id),
+        vec!["one", "", "three"]
+    );
+}
+
+#[test]
+fn a_restarted_daemon_takes_back_its_producers() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+
+    let first = start(dir.path());
+    let events = watch(first.endpoint());
+
+    let mut handle = Producer::new(first.endpoint())
+        .with_name("long")
+        .with_retry(Duration::from_millis(10))
+        .connect()
+        .unwrap();
+
+    let id = handle.id().to_string();
+    let lines = content(1);
+    let (before, after) = lines.split_at(100);
+
+    for line in before {
+        writeln!(handle, "{}", line).unwrap();
+    }
+
+    // only lines the daemon has taken in are written before it stops
+    let started = Instant::now();
+    let mut heard = 0;
+
+    while heard < before.len() {
+        assert!(started.elapsed() < WAIT, "lines never reached the viewer");
+
+        if let Ok(Event::Line { .. }) = events.recv_timeout(WAIT) {
+            heard += 1;
+        }
+    }
+
+    first.shutdown();
+    first.join().unwrap();
+
+    assert_eq!(stored(&root, &id), before);
+
+    let second = start(dir.path());
+
+    for line in after {
+        writeln!(handle, "{}", line).unwrap();
+    }
+
+    handle.close().unwrap();
+
+    let started = Instant::now();
+    while stored(&root, &id).len() < lines.len() {
+        assert!(started.elapsed() < WAIT, "lines never reached the disk");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(stored(&root, &id) == lines);
+
+    drop(second);
+}
+
+#[test]
+fn a_legacy_log_is_carried_on_as_the_first_segment() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("old"), "from before\n").unwrap();
+
+    let daemon = start(dir.path());
+    let events = watch(daemon.endpoint());
+
+    send_as(daemon.socket(), "old", &["after"]);
+    until_ended(&events, 1);
+
+    assert!(root.join("old").join(segment_name(0)).is_file());
+    assert_eq!(stored(&root, "old"), vec!["from before", "after"]);
+}
+
+#[test]
+fn a_session_that_cant_be_written_is_dropped_alone() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+
+    // its meta cant be saved over a directory
+    fs::create_dir_all(root.join("broken").join(META_FILE)).unwrap();
+
+    let daemon = start(dir.path());
+    let events = watch(daemon.endpoint());
+
+    send_as(daemon.socket(), "broken", &["lost"]);
+    let seen = until_ended(&events, 1);
+    assert!(seen["broken"].0.is_empty());
+
+    // the daemon is still there for everyone else
+    let id = send_all(daemon.endpoint(), "fine", &["kept".to_string()]);
+    until_ended(&events, 1);
+
+    assert_eq!(stored(&root, &id), vec!["kept"]);
+}
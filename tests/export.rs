@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use spellhold::ansi::{self, Color, Style};
+use spellhold::storage::Storage;
+use spellhold::storage::export::{export, Format, Range};
+use spellhold::storage::record::Stream;
+
+mod common;
+
+use common::write_session;
+
+/// line n a second after line n - 1
+fn at(seq: u64) -> Option<u64> {
+    Some(1_554_000_000_000 + seq * 1000)
+}
+
+fn exported(root: &Path, name: &str, format: Format, range: Range) -> String {
+    let mut out = Vec::new();
+    export(&root.join(name), format, &range, &mut out).unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn sgr_sequences_become_styled_spans() {
+    let line = "plain \x1b[1;31mred\x1b[0m \x1b]0;title\x07\x1b[38;5;42mcube";
+    let (spans, style) = ansi::parse(line, Style::default());
+
+    let texts = spans
+        .iter()
+        .map(|span| span.text.as_str())
+        .collect::<Vec<&str>>();
+
+    assert_eq!(texts, vec!["plain ", "red", " ", "cube"]);
+    assert!(spans[1].style.bold);
+    assert_eq!(spans[1].style.fg, Some(Color::Indexed(1)));
+    assert_eq!(spans[2].style, Style::default());
+
+    // the colour is still set for the next line
+    assert_eq!(style.fg, Some(Color::Indexed(42)));
+    assert_eq!(ansi::strip("\x1b[2K\x1b[32mok\x1b[m"), "ok");
+}
+
+#[test]
+fn screen_shows_what_a_terminal_would() {
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 1 << 20);
+
+    for line in ["fetching", "10%\r55%\r100%", "\x1b[1A\x1b[2Kfetched"] {
+        storage.append("pty", Stream::Out, line.as_bytes()).unwrap();
+    }
+
+    storage.close("pty").unwrap();
+
+    let screen = exported(dir.path(), "pty", Format::Screen, Range::default());
+
+    assert_eq!(screen, "fetching\nfetched\n");
+}
+
+#[test]
+fn txt_and_jsonl_honour_the_ranges() {
+    let dir = TempDir::new().unwrap();
+    write_session(dir.path(), "build", 10, 1 << 20, at)
+        .close("build")
+        .unwrap();
+
+    let range = Range {
+        from_seq: Some(2),
+        to_seq: Some(7),
+        ..Range::default()
+    };
+
+    let text = exported(dir.path(), "build", Format::Txt, range);
+    assert_eq!(text, "line 2\nline 3\nline 4\nline 5\nline 6\nline 7\n");
+
+    let range = Range {
+        since: Some(1_554_000_004_000),
+        until: Some(1_554_000_005_500),
+        ..Range::default()
+    };
+
+    let jsonl = exported(dir.path(), "build", Format::Jsonl, range);
+    let lines = jsonl
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["seq"], 4);
+    assert_eq!(lines[0]["ts"], 1_554_000_004_000u64);
+    assert_eq!(lines[0]["stream"], "out");
+    assert_eq!(lines[1]["line"], "line 5");
+}
+
+#[test]
+fn html_has_the_details_and_colours() {
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 1 << 20);
+
+    storage
+        .append_at("colours", Stream::Out, 1_000, b"\x1b[31m<red>\x1b[0m ok")
+        .unwrap();
+    storage
+        .append_at("colours", Stream::Err, 2_000, b"oops")
+        .unwrap();
+    storage.close("colours").unwrap();
+
+    let html = exported(dir.path(), "colours", Format::Html, Range::default());
+
+    assert!(html.contains("<th>session</th><td>colours</td>"));
+    assert!(html.contains("<th>state</th><td>closed</td>"));
+    assert!(html.contains("<th>lines</th><td>2</td>"));
+    assert!(
+        html.contains("<span style=\"color: #cd3131\">&lt;red&gt;</span> ok")
+    );
+    assert!(html.contains("<span class=\"err\">oops</span>"));
+    assert!(!html.contains('\x1b'));
+}
+
+#[test]
+fn asciicast_replays_at_the_recorded_times() {
+    let dir = TempDir::new().unwrap();
+    write_session(dir.path(), "cast", 3, 1 << 20, at)
+        .close("cast")
+        .unwrap();
+
+    let cast =
+        exported(dir.path(), "cast", Format::Asciicast, Range::default());
+    let lines = cast
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+
+    assert_eq!(lines[0]["version"], 2);
+    assert_eq!(lines[0]["timestamp"], 1_554_000_000);
+    assert_eq!(lines[0]["title"], "cast");
+
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1], serde_json::json!([0.0, "o", "line 0\r\n"]));
+    assert_eq!(lines[3], serde_json::json!([2.0, "o", "line 2\r\n"]));
+}
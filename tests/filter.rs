@@ -0,0 +1,69 @@
+use spellhold::client::filter::{Filter, Filters};
+
+fn lines(text: &[&str]) -> Vec<String> {
+    text.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn a_bang_excludes() {
+    let include = Filter::parse("err").unwrap();
+    assert!(!include.excludes());
+    assert!(include.keeps("an error\n"));
+    assert!(!include.keeps("fine\n"));
+
+    let exclude = Filter::parse("!^debug").unwrap();
+    assert!(exclude.excludes());
+    assert_eq!(exclude.pattern(), "^debug");
+    assert!(!exclude.keeps("debug: noise\n"));
+    assert!(exclude.keeps("info: debug later\n"));
+
+    assert!(Filter::parse("!(").is_err());
+}
+
+#[test]
+fn stacked_filters_all_have_to_keep_a_line() {
+    let mut filters = Filters::new();
+    let mut tab = lines(&["error one", "debug error", "info", "error two"]);
+
+    filters.push(Filter::parse("error").unwrap());
+    filters.push(Filter::parse("!debug").unwrap());
+    filters.update(&tab);
+
+    assert!(filters.active());
+    assert_eq!(filters.shown(), &lines(&["error one", "error two"])[..]);
+
+    // new lines are filtered as they come
+    tab.extend(lines(&["debug error", "error three"]));
+    filters.update(&tab);
+    assert_eq!(filters.shown().len(), 3);
+    assert_eq!(filters.status(tab.len()), "3 of 6 lines, +error -debug");
+
+    filters.pop();
+    filters.update(&tab);
+    assert_eq!(filters.shown().len(), 5);
+}
+
+#[test]
+fn toggling_keeps_the_filters() {
+    let mut filters = Filters::new();
+    let mut tab = lines(&["a", "b"]);
+
+    assert!(!filters.active());
+
+    filters.push(Filter::parse("a").unwrap());
+    filters.toggle();
+    assert!(!filters.active());
+    assert_eq!(filters.status(2), "filters off, +a");
+
+    // lines that come in while off are there when it is back on
+    tab.extend(lines(&["aa"]));
+    filters.update(&tab);
+    filters.toggle();
+    assert!(filters.active());
+    assert_eq!(filters.shown(), &lines(&["a", "aa"])[..]);
+
+    filters.clear();
+    filters.update(&tab);
+    assert!(!filters.active());
+    assert_eq!(filters.status(3), "");
+}
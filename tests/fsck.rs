@@ -0,0 +1,151 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use spellhold::storage::{index_path, Storage};
+use spellhold::storage::fsck::{fsck, recover, Problem};
+use spellhold::storage::meta::{SessionMeta, SessionState};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Record, Stream};
+
+mod common;
+
+use common::write_session;
+
+fn texts(root: &Path, name: &str) -> Vec<String> {
+    SessionReader::open(&root.join(name))
+        .unwrap()
+        .map(|record| record.unwrap().text())
+        .collect()
+}
+
+fn state(root: &Path, name: &str) -> SessionState {
+    SessionMeta::load(&root.join(name)).unwrap().unwrap().state
+}
+
+#[test]
+fn startup_recovery_truncates_a_torn_tail() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+
+    write_session(root, "crashed", 3, 1 << 20, |_| None);
+    assert_eq!(state(root, "crashed"), SessionState::Open);
+
+    // half a record, as if the daemon died part way through a write
+    let mut bytes = Vec::new();
+    Record {
+        seq: 3,
+        ts: 0,
+        stream: Stream::Out,
+        payload: b"never finished".to_vec(),
+        attrs: Default::default(),
+    }
+    .write(&mut bytes)
+    .unwrap();
+
+    let segment = root.join("crashed/00000.log");
+    OpenOptions::new()
+        .append(true)
+        .open(&segment)
+        .unwrap()
+        .write_all(&bytes[..10])
+        .unwrap();
+
+    let repaired = recover(root).unwrap();
+
+    assert_eq!(repaired.len(), 1);
+    assert!(repaired[0].repaired);
+    assert!(matches!(repaired[0].problems[0], Problem::Torn { .. }));
+    assert_eq!(state(root, "crashed"), SessionState::Interrupted);
+
+    assert_eq!(texts(root, "crashed"), vec!["line 0", "line 1", "line 2"]);
+
+    // a clean segment is carried on with, not abandoned
+    let mut storage = Storage::new(root.to_owned(), 1 << 20);
+    let seq = storage.append("crashed", Stream::Out, b"back").unwrap();
+
+    assert_eq!(seq, 3);
+    assert_eq!(fs::read_dir(root.join("crashed")).unwrap().count(), 3);
+}
+
+#[test]
+fn fsck_finds_and_repairs_a_bad_checksum() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+
+    write_session(root, "flipped", 5, 1 << 20, |_| None)
+        .close("flipped")
+        .unwrap();
+
+    let segment = root.join("flipped/00000.log");
+    let mut bytes = fs::read(&segment).unwrap();
+
+    // the last byte of the third record, "line 2"
+    let at = bytes.windows(6).position(|win| win == b"line 2").unwrap();
+    bytes[at + 5] = b'X';
+    fs::write(&segment, bytes).unwrap();
+
+    let report = fsck(root, false).unwrap();
+
+    assert!(report.has_damage());
+    assert_eq!(report.damaged.len(), 1);
+    assert!(matches!(
+        report.damaged[0].problems[0],
+        Problem::Corrupt { .. }
+    ));
+
+    // the records around it are still readable
+    let report = fsck(root, true).unwrap();
+    assert!(!report.has_damage());
+    assert_eq!(report.damaged[0].records, 4);
+
+    assert_eq!(
+        texts(root, "flipped"),
+        vec!["line 0", "line 1", "line 3", "line 4"]
+    );
+    assert!(fsck(root, false).unwrap().damaged.is_empty());
+}
+
+#[test]
+fn fsck_rebuilds_a_bad_index() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+
+    write_session(root, "lost", 1_000, 1 << 20, |_| None)
+        .close("lost")
+        .unwrap();
+
+    let index = index_path(&root.join("lost/00000.log"));
+    fs::write(&index, [7; 48]).unwrap();
+
+    let report = fsck(root, true).unwrap();
+
+    assert!(matches!(
+        report.damaged[0].problems[0],
+        Problem::Index { .. }
+    ));
+    assert!(fsck(root, false).unwrap().damaged.is_empty());
+
+    let mut reader = SessionReader::open(&root.join("lost")).unwrap();
+    reader.seek_seq(900).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().text(), "line 900");
+}
+
+#[test]
+fn fsck_leaves_open_sessions_alone() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+
+    write_session(root, "done", 2, 1 << 20, |_| None)
+        .close("done")
+        .unwrap();
+    write_session(root, "running", 2, 1 << 20, |_| None);
+
+    let report = fsck(root, false).unwrap();
+
+    assert_eq!(report.sessions, 1);
+    assert_eq!(report.skipped, vec!["running"]);
+    assert!(report.damaged.is_empty());
+}
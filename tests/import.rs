@@ -0,0 +1,149 @@
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use tempfile::TempDir;
+
+use spellhold::storage::export::{export, Format, Range};
+use spellhold::storage::import::{guess_format, import};
+use spellhold::storage::meta::{SessionMeta, SessionState};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Record, Stream};
+use spellhold::storage::{list_sessions, Storage};
+
+fn records(root: &Path, name: &str) -> Vec<Record> {
+    SessionReader::open(&root.join(name))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn text_files_take_the_files_time() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+    let file = dir.path().join("old.log");
+
+    fs::write(&file, "first\r\nsecond\n\nlast").unwrap();
+
+    let when = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    File::options()
+        .append(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(when)
+        .unwrap();
+
+    let imported =
+        import(&root, 1 << 20, &file, "old", guess_format(&file)).unwrap();
+    assert_eq!(imported.lines, 4);
+
+    let records = records(&root, "old");
+    let texts = records.iter().map(Record::text).collect::<Vec<String>>();
+
+    assert_eq!(texts, vec!["first", "second", "", "last"]);
+    assert!(records.iter().all(|record| record.ts == 1_500_000_000_000));
+
+    let meta = SessionMeta::load(&root.join("old")).unwrap().unwrap();
+    assert_eq!(meta.state, SessionState::Closed);
+    assert_eq!(meta.closed, Some(1_500_000_000_000));
+
+    // old enough for retention and compaction to treat it as old
+    let sessions = list_sessions(&root).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].modified, when);
+}
+
+#[test]
+fn exported_jsonl_imports_back_the_same() {
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 1 << 20);
+
+    storage
+        .append_at("src", Stream::Meta, 1_000, b"connected")
+        .unwrap();
+    storage
+        .append_at("src", Stream::Out, 2_000, b"out line")
+        .unwrap();
+    storage
+        .append_at("src", Stream::Err, 3_000, b"err line")
+        .unwrap();
+    storage.close("src").unwrap();
+
+    let file = dir.path().join("src.jsonl");
+    let mut out = File::create(&file).unwrap();
+    export(
+        &dir.path().join("src"),
+        Format::Jsonl,
+        &Range::default(),
+        &mut out,
+    )
+    .unwrap();
+
+    import(dir.path(), 1 << 20, &file, "copy", Format::Jsonl).unwrap();
+
+    assert_eq!(records(dir.path(), "copy"), records(dir.path(), "src"));
+}
+
+#[test]
+fn asciicast_output_is_cut_into_lines() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("demo.cast");
+
+    fs::write(
+        &file,
+        "{\"version\": 2, \"width\": 80, \"height\": 24, \
+         \"timestamp\": 1600000000}\n\
+         [0.5, \"o\", \"$ make\\r\\n\"]\n\
+         [1.0, \"i\", \"q\"]\n\
+         [1.25, \"o\", \"build\"]\n\
+         [2.0, \"o\", \"ing\\r\\ndone\"]\n",
+    )
+    .unwrap();
+
+    assert_eq!(guess_format(&file), Format::Asciicast);
+    import(dir.path(), 1 << 20, &file, "demo", Format::Asciicast).unwrap();
+
+    let lines = records(dir.path(), "demo")
+        .iter()
+        .map(|record| (record.ts, record.text()))
+        .collect::<Vec<(u64, String)>>();
+
+    assert_eq!(
+        lines,
+        vec![
+            (1_600_000_000_500, "$ make".to_string()),
+            (1_600_000_002_000, "building".to_string()),
+            (1_600_000_002_000, "done".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn a_failed_import_leaves_nothing_behind() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+    let file = dir.path().join("bad.jsonl");
+
+    fs::write(&file, "{\"line\": \"ok\"}\nnot json\n").unwrap();
+
+    let err = import(&root, 1 << 20, &file, "bad", Format::Jsonl).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+
+    // and an existing session is never written over
+    fs::write(&file, "{\"line\": \"ok\"}\n").unwrap();
+    import(&root, 1 << 20, &file, "good", Format::Jsonl).unwrap();
+
+    let err = import(&root, 1 << 20, &file, "good", Format::Jsonl).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    // names go by the same rule as the ids producers connect with
+    for name in ["", ".hidden", "a/b", "two words", "tab\there"] {
+        let err = import(&root, 1 << 20, &file, name, Format::Jsonl);
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+}
@@ -0,0 +1,133 @@
+use std::fs;
+
+use tempfile::TempDir;
+use termion::event::Key;
+
+use spellhold::client::keymap::{self, Action, Keymap, Preset};
+use spellhold::config::TuiConfig;
+
+fn load(text: &str) -> Result<Keymap, String> {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tui.toml");
+    fs::write(&path, text).unwrap();
+
+    let config = TuiConfig::load(&path).map_err(|err| err.to_string())?;
+
+    Keymap::from_config(&config.keymap).map_err(|err| err.to_string())
+}
+
+#[test]
+fn keys_are_named_the_way_they_are_read() {
+    let keys = [
+        Key::Char('q'),
+        Key::Char('N'),
+        Key::Char(' '),
+        Key::Char('\t'),
+        Key::Char('\n'),
+        Key::Ctrl('w'),
+        Key::Alt('<'),
+        Key::F(12),
+        Key::Esc,
+        Key::PageDown,
+    ];
+
+    for key in keys.iter() {
+        assert_eq!(keymap::parse_key(&keymap::key_name(*key)), Ok(*key));
+    }
+
+    assert_eq!(keymap::parse_key("Ctrl-W"), Ok(Key::Ctrl('w')));
+    assert_eq!(keymap::parse_key("PageUp"), Ok(Key::PageUp));
+    assert!(keymap::parse_key("ctrl-").is_err());
+    assert!(keymap::parse_key("f13").is_err());
+    assert!(keymap::parse_key("hyper-x").is_err());
+}
+
+#[test]
+fn presets_change_only_some_keys() {
+    let default = Preset::Default.keymap();
+    let vim = Preset::Vim.keymap();
+    let emacs = Preset::Emacs.keymap();
+
+    assert_eq!(Keymap::default(), default);
+    assert_eq!(default.action(Key::Char('?')), Some(Action::Help));
+    assert_eq!(default.action(Key::Char('j')), Some(Action::ScrollDown));
+    assert_eq!(default.action(Key::Char('h')), None);
+
+    assert_eq!(vim.action(Key::Char('?')), Some(Action::SearchBackward));
+    assert_eq!(vim.keys(Action::Help), vec![Key::F(1)]);
+    assert_eq!(vim.action(Key::Char('h')), Some(Action::PreviousTab));
+
+    assert_eq!(emacs.action(Key::Ctrl('s')), Some(Action::SearchForward));
+    assert_eq!(emacs.action(Key::Ctrl('x')), Some(Action::Panes));
+    assert_eq!(emacs.action(Key::Ctrl('w')), None);
+    assert_eq!(emacs.action(Key::Ctrl('f')), Some(Action::NextTab));
+
+    for keymap in [default, vim, emacs].iter() {
+        assert_eq!(keymap.exit(), Key::Ctrl('c'));
+        assert!(!keymap.keys(Action::Quit).is_empty());
+    }
+}
+
+#[test]
+fn the_config_binds_over_a_preset() {
+    let keymap = load(
+        r#"
+[keymap]
+preset = "vim"
+exit = "ctrl-q"
+
+[keymap.bind]
+search_all = ["A"]
+help = ["f1", "?"]
+view = []
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(keymap.exit(), Key::Ctrl('q'));
+    assert_eq!(keymap.action(Key::Char('A')), Some(Action::SearchAll));
+    assert_eq!(keymap.action(Key::Char('a')), None);
+
+    // a key does one thing, so ? isnt a search any more
+    assert_eq!(keymap.action(Key::Char('?')), Some(Action::Help));
+    assert!(keymap.keys(Action::SearchBackward).is_empty());
+    assert_eq!(keymap.action(Key::Char('v')), None);
+
+    assert_eq!(load("").unwrap(), Keymap::default());
+}
+
+#[test]
+fn bad_keymaps_are_refused() {
+    let refused = |text: &str, why: &str| {
+        let err = load(text).unwrap_err();
+        assert!(err.contains(why), "{} in {}", why, err);
+    };
+
+    refused("[keymap.bind]\njump = [\"j\"]\n", "No action called jump");
+    refused("[keymap.bind]\nquit = [\"ctrl-\"]\n", "No key called");
+    refused("[keymap]\nexit = \"x\"\n", "would quit while typing");
+    refused("[keymap]\npreset = \"nano\"\n", "Bad config");
+}
+
+#[test]
+fn the_help_lists_the_keys_of_each_action() {
+    let mut keymap = Keymap::default();
+    keymap.bind(Action::ShowMerged, &[]);
+
+    let help = keymap.help();
+
+    assert!(help[0].starts_with("ctrl-c "));
+    assert!(help[0].ends_with("  quit from anywhere (exit)"));
+    assert!(help.iter().any(|line| line.starts_with("? f1 ")));
+    assert!(help.iter().any(|line| {
+        line.starts_with("pagedown ctrl-f space ")
+            && line.ends_with("down a page (page_down)")
+    }));
+    assert!(help.iter().any(|line| {
+        line.starts_with("- ") && line.ends_with("(show_merged)")
+    }));
+
+    // the keys are padded so what they do lines up
+    let at = help[0].find("quit").unwrap();
+    assert!(help.iter().all(|line| line.len() > at));
+}
@@ -0,0 +1,66 @@
+use spellhold::client::pager::{Pager, Scroll};
+
+#[test]
+fn it_follows_new_lines_until_scrolled_up() {
+    let mut pager = Pager::new();
+
+    assert_eq!(pager.visible(100, 10), 90..100);
+    assert_eq!(pager.visible(150, 10), 140..150);
+
+    pager.scroll(Scroll::Up(1), 150, 10);
+    assert!(!pager.following());
+    assert_eq!(pager.visible(150, 10), 139..149);
+
+    // more lines dont move it any more
+    assert_eq!(pager.visible(500, 10), 139..149);
+
+    pager.scroll(Scroll::Bottom, 500, 10);
+    assert!(pager.following());
+    assert_eq!(pager.visible(600, 10), 590..600);
+}
+
+#[test]
+fn pages_and_half_pages_are_the_view_height() {
+    let mut pager = Pager::new();
+
+    pager.scroll(Scroll::Top, 100, 10);
+    assert_eq!(pager.visible(100, 10), 0..10);
+
+    pager.scroll(Scroll::PageDown, 100, 10);
+    assert_eq!(pager.top(100, 10), 10);
+
+    pager.scroll(Scroll::HalfDown, 100, 10);
+    assert_eq!(pager.top(100, 10), 15);
+
+    pager.scroll(Scroll::HalfUp, 100, 10);
+    pager.scroll(Scroll::PageUp, 100, 10);
+    assert_eq!(pager.top(100, 10), 0);
+
+    // it stops at the ends
+    pager.scroll(Scroll::Up(5), 100, 10);
+    assert_eq!(pager.top(100, 10), 0);
+
+    pager.scroll(Scroll::Down(1000), 100, 10);
+    assert_eq!(pager.top(100, 10), 90);
+    assert!(pager.following());
+}
+
+#[test]
+fn fewer_lines_than_the_view_are_all_shown() {
+    let mut pager = Pager::new();
+
+    pager.scroll(Scroll::Up(3), 4, 10);
+    assert_eq!(pager.visible(4, 10), 0..4);
+    assert_eq!(pager.visible(0, 10), 0..0);
+}
+
+#[test]
+fn the_status_says_where_it_is() {
+    let mut pager = Pager::new();
+    assert_eq!(pager.status(0, 10), "no lines");
+    assert_eq!(pager.status(200, 10), "line 200 of 200, 100%, following");
+
+    pager.scroll(Scroll::Top, 200, 10);
+    pager.scroll(Scroll::Down(30), 200, 10);
+    assert_eq!(pager.status(200, 10), "line 40 of 200, 20%");
+}
@@ -0,0 +1,129 @@
+use tempfile::TempDir;
+use tui::layout::Rect;
+
+use spellhold::client::panes::{self, Layout, Panes, Side, Split};
+
+fn area() -> Rect {
+    Rect::new(0, 0, 100, 40)
+}
+
+#[test]
+fn splits_share_out_the_area() {
+    let mut panes = Panes::new();
+
+    // a server log on the left, its tests and a shell stacked on the right
+    let right = panes.split(Split::Horizontal);
+    let bottom = panes.split(Split::Vertical);
+
+    assert_eq!(panes.focus(), bottom);
+    assert_eq!(panes.ids(), vec![0, right, bottom]);
+    assert_eq!(
+        panes.rects(area()),
+        vec![
+            (0, Rect::new(0, 0, 50, 40)),
+            (right, Rect::new(50, 0, 50, 20)),
+            (bottom, Rect::new(50, 20, 50, 20)),
+        ]
+    );
+
+    panes.resize(Split::Horizontal, -20);
+    panes.resize(Split::Vertical, 10);
+
+    assert_eq!(
+        panes.rects(area()),
+        vec![
+            (0, Rect::new(0, 0, 70, 40)),
+            (right, Rect::new(70, 0, 30, 16)),
+            (bottom, Rect::new(70, 16, 30, 24)),
+        ]
+    );
+
+    // splits dont go past 10 and 90 percent
+    panes.resize(Split::Horizontal, -100);
+    assert_eq!(panes.rects(area())[0].1.width, 90);
+
+    panes.even();
+    assert_eq!(panes.rects(area())[0].1.width, 50);
+}
+
+#[test]
+fn focus_moves_to_the_pane_on_that_side() {
+    let mut panes = Panes::new();
+    let right = panes.split(Split::Horizontal);
+    let bottom = panes.split(Split::Vertical);
+
+    assert_eq!(panes.neighbour(area(), Side::Left), Some(0));
+    assert_eq!(panes.neighbour(area(), Side::Up), Some(right));
+    assert_eq!(panes.neighbour(area(), Side::Down), None);
+    assert_eq!(panes.neighbour(area(), Side::Right), None);
+
+    panes.focus_on(0);
+    assert_eq!(panes.neighbour(area(), Side::Right), Some(right));
+    assert_eq!(panes.cycle(false), right);
+    assert_eq!(panes.cycle(true), bottom);
+}
+
+#[test]
+fn closing_a_pane_gives_its_space_back() {
+    let mut panes = Panes::new();
+    let right = panes.split(Split::Horizontal);
+    let bottom = panes.split(Split::Vertical);
+
+    panes.focus_on(right);
+    assert_eq!(panes.close(), Some(right));
+    assert_eq!(panes.focus(), bottom);
+    assert_eq!(
+        panes.rects(area()),
+        vec![
+            (0, Rect::new(0, 0, 50, 40)),
+            (bottom, Rect::new(50, 0, 50, 40)),
+        ]
+    );
+
+    panes.split(Split::Vertical);
+    panes.focus_on(0);
+    assert_eq!(panes.only().len(), 2);
+    assert_eq!(panes.close(), None);
+    assert_eq!(panes.rects(area()), vec![(0, area())]);
+}
+
+#[test]
+fn layouts_are_saved_and_restored() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("layout.json");
+
+    let mut panes = Panes::new();
+    let right = panes.split(Split::Horizontal);
+    panes.resize(Split::Horizontal, -10);
+
+    let layout = panes.layout(|id| {
+        let session = if id == right {
+            "tests_1700000000"
+        } else {
+            "server"
+        };
+        Some(panes::session_name(session).to_string())
+    });
+
+    layout.save(&path).unwrap();
+    let loaded = Layout::load(&path).unwrap();
+    assert_eq!(loaded, layout);
+
+    let (restored, wanted) = Panes::restore(&loaded);
+    assert_eq!(restored.rects(area()), panes.rects(area()));
+    assert_eq!(restored.focus(), 1);
+    assert_eq!(
+        wanted,
+        vec![(0, Some("server".into())), (1, Some("tests".into()))]
+    );
+
+    assert!(Layout::load(&dir.path().join("none")).is_err());
+}
+
+#[test]
+fn session_names_drop_the_start_time() {
+    assert_eq!(panes::session_name("build_1700000000"), "build");
+    assert_eq!(panes::session_name("build_web_1700000000"), "build_web");
+    assert_eq!(panes::session_name("build_web"), "build_web");
+    assert_eq!(panes::session_name("_1700000000"), "_1700000000");
+}
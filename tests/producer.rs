@@ -0,0 +1,160 @@
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::protocol::Handshake;
+use spellhold::storage::meta::{SessionMeta, SessionState};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Record, Stream};
+use spellhold::transport::Endpoint;
+
+mod common;
+
+use common::{start_daemon, WAIT};
+
+/// accept one producer, answer ok and hand back its handshake and up to
+/// count lines
+fn fake_daemon(
+    listener: UnixListener,
+    count: usize,
+) -> thread::JoinHandle<(Handshake, Vec<String>)> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        reader.get_mut().write_all(b"ok\n").unwrap();
+
+        let lines = reader
+            .lines()
+            .take(count)
+            .map(Result::unwrap)
+            .collect::<Vec<String>>();
+
+        (Handshake::parse(&line).unwrap(), lines)
+    })
+}
+
+#[test]
+fn lines_tags_and_exit_reach_the_session() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut handle = Producer::new(endpoint)
+        .with_name("nightly")
+        .with_tags(vec!["ci", "linux"])
+        .connect()
+        .unwrap();
+
+    let id = handle.id().to_string();
+    assert!(id.starts_with("nightly_"));
+
+    handle.send("one\ntwo").unwrap();
+    write!(handle, "thr").unwrap();
+    writeln!(handle, "ee").unwrap();
+
+    handle.set_exit(3);
+    handle.close().unwrap();
+
+    let session = root.join(&id);
+    let started = Instant::now();
+
+    let meta = loop {
+        let meta = SessionMeta::load(&session).unwrap_or_default();
+
+        if let Some(meta) = meta.filter(|m| m.state == SessionState::Closed) {
+            break meta;
+        }
+
+        assert!(started.elapsed() < WAIT, "session was never closed");
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    assert_eq!(meta.tags, vec!["ci", "linux"]);
+    assert_eq!(meta.exit, Some(3));
+
+    let records = SessionReader::open(&session)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<Record>>();
+
+    let lines = records
+        .iter()
+        .filter(|record| record.stream == Stream::Out)
+        .map(Record::text)
+        .collect::<Vec<String>>();
+
+    assert_eq!(lines, vec!["one", "two", "three"]);
+    assert_eq!(records.last().unwrap().text(), "-- exited with 3 --");
+}
+
+#[test]
+fn lines_are_spooled_until_the_daemon_is_back() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("socket");
+
+    let first = fake_daemon(UnixListener::bind(&socket).unwrap(), 1);
+
+    let mut handle = Producer::new(Endpoint::Unix(socket.clone()))
+        .with_spool(2)
+        .with_retry(Duration::from_millis(0))
+        .connect()
+        .unwrap();
+
+    let id = handle.id().to_string();
+
+    handle.send("before").unwrap();
+
+    // the daemon goes away after the first line
+    let (_, lines) = first.join().unwrap();
+    assert_eq!(lines, vec![format!("{} -ENDID- before", id)]);
+    fs::remove_file(&socket).unwrap();
+
+    for index in 0..3 {
+        handle.send(&format!("while down {}", index)).unwrap();
+    }
+    assert_eq!(handle.spooled(), 2);
+
+    let second = fake_daemon(UnixListener::bind(&socket).unwrap(), 4);
+
+    handle.set_exit(0);
+    handle.close().unwrap();
+
+    let (handshake, lines) = second.join().unwrap();
+
+    match handshake {
+        Handshake::Connect { id: again, .. } => assert_eq!(again, id),
+        other => panic!("expected a connect, got {:?}", other),
+    }
+
+    assert_eq!(
+        lines,
+        vec![
+            format!("{} -ENDID- -- 1 lines dropped while disconnected --", id),
+            format!("{} -ENDID- while down 1", id),
+            format!("{} -ENDID- while down 2", id),
+            "end -EXIT- 0".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn a_refused_producer_is_told_at_connect() {
+    let dir = TempDir::new().unwrap();
+    let endpoint = Endpoint::Unix(dir.path().join("socket"));
+
+    assert!(Producer::new(endpoint.clone()).connect().is_err());
+
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let bad_tag = Producer::new(endpoint).with_tags(vec!["no spaces"]);
+    assert!(bad_tag.connect().is_err());
+}
@@ -0,0 +1,352 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::os::unix::net::UnixStream;
+
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::subscriber::Subscriber;
+use spellhold::daemon::SendEvt;
+use spellhold::protocol::{self, Handshake, RecordLine, ViewerLine};
+
+mod common;
+
+use common::{start_daemon, stored};
+
+/// one word, not a path and not hidden
+fn id() -> impl Strategy<Value = String> {
+    "[^\\s/.][^\\s/]{0,30}"
+}
+
+fn token() -> impl Strategy<Value = Option<String>> {
+    proptest::option::of("[^\\s]{1,40}")
+}
+
+/// anything that fits on one line
+fn text() -> impl Strategy<Value = String> {
+    "[^\n]{0,200}"
+}
+
+fn handshake() -> impl Strategy<Value = Handshake> {
+    prop_oneof![
+        (id(), token(), vec("[^\\s,]{1,10}", 0..4)).prop_map(
+            |(id, token, tags)| Handshake::Connect { id, token, tags }
+        ),
+        (token(), any::<Option<u64>>(), any::<bool>(), any::<bool>()).prop_map(
+            |(token, history, times, finished)| {
+                Handshake::Client {
+                    token,
+                    history,
+                    times,
+                    finished,
+                }
+            }
+        ),
+        token().prop_map(|token| Handshake::Stats { token }),
+        (token(), any::<bool>())
+            .prop_map(|(token, dry_run)| Handshake::Gc { token, dry_run }),
+        (token(), proptest::option::of(id()))
+            .prop_map(|(token, session)| Handshake::Compact { token, session }),
+    ]
+}
+
+fn viewer_line() -> impl Strategy<Value = ViewerLine> {
+    prop_oneof![
+        (id(), text())
+            .prop_map(|(session, text)| ViewerLine::Line { session, text }),
+        (id(), any::<u64>(), text()).prop_map(|(session, at, text)| {
+            ViewerLine::Stamped { session, at, text }
+        }),
+        (id(), any::<u64>()).prop_map(|(session, dropped)| ViewerLine::Gap {
+            session,
+            dropped
+        }),
+        id().prop_map(ViewerLine::Started),
+        (id(), any::<u64>()).prop_map(|(id, at)| ViewerLine::Opened(id, at)),
+        (id(), vec("[^\\s,]{1,10}", 1..4))
+            .prop_map(|(id, tags)| ViewerLine::Tagged(id, tags)),
+        (id(), any::<i32>())
+            .prop_map(|(id, code)| ViewerLine::Exited(id, code)),
+        id().prop_map(ViewerLine::Ended),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn handshakes_survive_the_wire(handshake in handshake()) {
+        let line = handshake.to_line();
+
+        prop_assert_eq!(Handshake::parse(&line), Ok(handshake));
+    }
+
+    #[test]
+    fn viewer_lines_survive_the_wire(line in viewer_line()) {
+        prop_assert_eq!(ViewerLine::parse(&line.to_line()), Some(line));
+    }
+
+    #[test]
+    fn plain_lines_survive_the_wire(id in id(), text in text()) {
+        let wire = format!("{} -ENDID- {}\n", id, text);
+        let frame = protocol::decode_frame(wire.as_bytes());
+
+        let (parsed, record) = protocol::parse_producer_line(&frame).unwrap();
+
+        prop_assert_eq!(parsed, id.as_str());
+        prop_assert_eq!(record.line, text);
+        prop_assert!(record.attrs.is_empty());
+    }
+
+    #[test]
+    fn records_survive_the_wire(
+        id in id(),
+        line in any::<String>(),
+        attrs in btree_map(any::<String>(), any::<String>(), 0..5),
+    ) {
+        let wire = protocol::record_line(&id, &line, &attrs);
+        let frame = protocol::decode_frame(wire.as_bytes());
+
+        prop_assert_eq!(
+            protocol::parse_producer_line(&frame),
+            Some((id.as_str(), RecordLine { line, attrs }))
+        );
+    }
+
+    #[test]
+    fn end_lines_survive_the_wire(exit in any::<Option<i32>>()) {
+        let frame = protocol::decode_frame(protocol::end_line(exit).as_bytes());
+
+        prop_assert!(protocol::is_end_line(&frame));
+        prop_assert_eq!(protocol::exit_status(&frame), exit);
+        prop_assert!(matches!(SendEvt::new(frame), SendEvt::End));
+    }
+
+    #[test]
+    fn frames_come_back_as_they_were_sent(lines in vec(text(), 0..20)) {
+        let wire = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+
+        let mut reader = Cursor::new(wire.into_bytes());
+        let mut buf = Vec::new();
+        let mut read = Vec::new();
+
+        while let Some(line) = protocol::read_frame(&mut reader, &mut buf).unwrap() {
+            read.push(line);
+        }
+
+        prop_assert_eq!(read, lines);
+    }
+
+    #[test]
+    fn no_line_breaks_a_parser(bytes in vec(any::<u8>(), 0..300)) {
+        let mut reader = Cursor::new(bytes);
+        let mut buf = Vec::new();
+
+        while let Some(line) = protocol::read_frame(&mut reader, &mut buf).unwrap() {
+            if let Ok(handshake) = Handshake::parse(&line) {
+                // whatever is let in says the same thing sent back out
+                prop_assert_eq!(Handshake::parse(&handshake.to_line()), Ok(handshake));
+            }
+
+            let _ = ViewerLine::parse(&line);
+            let _ = protocol::parse_producer_line(&line);
+            let _ = protocol::parse_reply(&line);
+            let _ = protocol::exit_status(&line);
+            let _ = SendEvt::new(line).session();
+        }
+    }
+
+    #[test]
+    fn only_a_whole_kill_line_kills(id in id(), text in text()) {
+        let line = format!("{} -ENDID- {}", id, text);
+
+        prop_assert!(matches!(SendEvt::new(line), SendEvt::SendString(_)));
+    }
+}
+
+/// sessions named killer_ or end_ used to be read as kill and end lines
+#[test]
+fn sessions_named_like_commands_are_only_lines() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+
+    for name in ["killer", "endgame", "kill", "end"] {
+        let mut handle = Producer::new(daemon.endpoint())
+            .with_name(name)
+            .connect()
+            .unwrap();
+
+        writeln!(handle, "still here").unwrap();
+
+        let id = handle.id().to_string();
+        handle.close().unwrap();
+
+        assert_eq!(stored(&root, &id), vec!["still here"]);
+    }
+
+    assert!(Subscriber::new(daemon.endpoint()).connect().is_ok());
+}
+
+/// a \r used to be lost and bytes that arent utf8 dropped the producer
+#[test]
+fn odd_bytes_are_kept_or_replaced() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+
+    let id = "raw_1";
+    let mut reader =
+        BufReader::new(UnixStream::connect(daemon.socket()).unwrap());
+
+    let connect = Handshake::Connect {
+        id: id.to_string(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    let stream = reader.get_mut();
+    stream.write_all(connect.to_line().as_bytes()).unwrap();
+    protocol::read_reply(&mut reader).unwrap();
+
+    let stream = reader.get_mut();
+    stream.write_all(b"raw_1 -ENDID- caf\xe9\n").unwrap();
+    stream.write_all(b"raw_1 -ENDID- windows\r\n").unwrap();
+    stream.write_all(b"raw_1 -ENDID- after\n").unwrap();
+    stream
+        .write_all(protocol::end_line(None).as_bytes())
+        .unwrap();
+
+    assert_eq!(stored(&root, id), vec!["caf\u{fffd}", "windows\r", "after"]);
+}
+
+#[test]
+fn handshakes_that_cant_be_sent_back_are_refused() {
+    for line in [
+        "connect -ID- ",
+        "connect -ID- .hidden",
+        "connect -ID- a/b",
+        "connect -ID- a -TAGS- x,,y",
+        "connect -ID-",
+        "client -HISTORY- lots",
+        "compact -SESSION- ../up",
+        "-ID- x",
+        "",
+    ] {
+        assert!(Handshake::parse(line).is_err(), "{:?} was let in", line);
+    }
+}
+
+/// found by the frames fuzz target, the id a line was stored under could be
+/// more than the word the daemon checked
+#[test]
+fn a_line_is_only_for_its_first_word() {
+    assert_eq!(protocol::parse_producer_line("a b -ENDID- x"), None);
+    assert_eq!(protocol::parse_producer_line(" -ENDID- x"), None);
+    assert_eq!(
+        protocol::split_line("a -ENDID- b -ENDID- c"),
+        Some(("a", "b -ENDID- c"))
+    );
+    assert_eq!(ViewerLine::parse("a b -ENDID- x\n"), None);
+}
+
+#[test]
+fn a_stamped_line_needs_a_whole_time() {
+    assert_eq!(
+        protocol::split_stamped("a -AT- 12 -ENDID- b -AT- 3 -ENDID- c"),
+        Some(("a", 12, "b -AT- 3 -ENDID- c"))
+    );
+    assert_eq!(
+        protocol::split_stamped("a -AT- 12 -ENDID-"),
+        Some(("a", 12, ""))
+    );
+    assert_eq!(protocol::split_stamped("a -AT- x -ENDID- b"), None);
+    assert_eq!(protocol::split_stamped("a -AT- -1 -ENDID- b"), None);
+    assert_eq!(protocol::split_stamped("a -AT- 12 -ENDIDb"), None);
+    assert_eq!(ViewerLine::parse("a -AT- 12 b -ENDID- x\n"), None);
+}
+
+#[test]
+fn a_producer_cant_write_outside_its_session() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+
+    let mut reader =
+        BufReader::new(UnixStream::connect(daemon.socket()).unwrap());
+
+    let connect = Handshake::Connect {
+        id: "raw_1".to_string(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    let stream = reader.get_mut();
+    stream.write_all(connect.to_line().as_bytes()).unwrap();
+    protocol::read_reply(&mut reader).unwrap();
+
+    let stream = reader.get_mut();
+    stream
+        .write_all(b"raw_1 ../../escaped -ENDID- x\n")
+        .unwrap();
+    stream.write_all(b"raw_1 -ENDID- kept\n").unwrap();
+    stream
+        .write_all(protocol::end_line(None).as_bytes())
+        .unwrap();
+
+    assert_eq!(stored(&root, "raw_1"), vec!["kept"]);
+    assert!(!dir.path().join("escaped").exists());
+    assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+}
+
+#[test]
+fn lines_over_the_cap_are_errors() {
+    let mut buf = Vec::new();
+
+    let mut fits = vec![b'x'; protocol::MAX_FRAME as usize - 1];
+    fits.push(b'\n');
+    let read = protocol::read_frame(&mut Cursor::new(fits), &mut buf);
+    assert_eq!(read.unwrap().map(|line| line.len()), Some(buf.len() - 1));
+
+    let mut over = vec![b'x'; protocol::MAX_FRAME as usize];
+    over.push(b'\n');
+    assert!(protocol::read_frame(&mut Cursor::new(over), &mut buf).is_err());
+
+    let mut over = vec![b'x'; protocol::MAX_HANDSHAKE as usize];
+    over.push(b'\n');
+    assert!(protocol::read_handshake(&mut Cursor::new(over)).is_err());
+}
+
+#[test]
+fn overlong_lines_hang_up_the_connection() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+
+    // a first line that never ends is cut off before any token is checked
+    let mut reader =
+        BufReader::new(UnixStream::connect(daemon.socket()).unwrap());
+    let endless = vec![b'x'; protocol::MAX_HANDSHAKE as usize + 1];
+    let _ = reader.get_mut().write_all(&endless);
+
+    let mut rest = String::new();
+    let read = reader.read_line(&mut rest);
+    assert!(read.map_or(true, |read| read == 0));
+
+    // and so is a producer sending a line past the frame cap
+    let mut reader =
+        BufReader::new(UnixStream::connect(daemon.socket()).unwrap());
+
+    let connect = Handshake::Connect {
+        id: "raw_1".to_string(),
+        token: None,
+        tags: Vec::new(),
+    };
+
+    let stream = reader.get_mut();
+    stream.write_all(connect.to_line().as_bytes()).unwrap();
+    protocol::read_reply(&mut reader).unwrap();
+
+    let stream = reader.get_mut();
+    stream.write_all(b"raw_1 -ENDID- before\n").unwrap();
+    let _ = stream.write_all(&vec![b'x'; protocol::MAX_FRAME as usize + 1]);
+    let _ = stream.write_all(b"\nraw_1 -ENDID- after\n");
+
+    assert_eq!(stored(&root, "raw_1"), vec!["before"]);
+}
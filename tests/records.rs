@@ -0,0 +1,235 @@
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use std::io::{BufRead, BufReader, Cursor, ErrorKind, Write};
+use std::collections::HashSet;
+
+use tempfile::TempDir;
+
+use spellhold::protocol::{self, Handshake};
+use spellhold::storage::{index_path, Storage};
+use spellhold::storage::compress::{compact, Compression};
+use spellhold::storage::index::Index;
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Record, Stream, MAX_RECORD};
+
+mod common;
+
+use common::{start_daemon, write_session};
+
+fn record(seq: u64, payload: &str) -> Record {
+    Record {
+        seq,
+        ts: 1_000 + seq,
+        stream: Stream::Out,
+        payload: payload.as_bytes().to_vec(),
+        attrs: Default::default(),
+    }
+}
+
+fn seqs(reader: SessionReader) -> Vec<u64> {
+    reader.map(|record| record.unwrap().seq).collect()
+}
+
+#[test]
+fn records_round_trip_and_catch_damage() {
+    let mut bytes = Vec::new();
+
+    record(0, "first").write(&mut bytes).unwrap();
+    record(1, "").write(&mut bytes).unwrap();
+
+    let mut input = Cursor::new(bytes.clone());
+    assert_eq!(Record::read(&mut input).unwrap(), Some(record(0, "first")));
+    assert_eq!(Record::read(&mut input).unwrap(), Some(record(1, "")));
+    assert_eq!(Record::read(&mut input).unwrap(), None);
+
+    // cut into the last record
+    let torn = &bytes[..bytes.len() - 3];
+    let mut input = Cursor::new(torn);
+    Record::read(&mut input).unwrap();
+
+    let err = Record::read(&mut input).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+    // flip a payload byte
+    let mut flipped = bytes.clone();
+    flipped[25] ^= 0xff;
+
+    let err = Record::read(&mut Cursor::new(flipped)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn records_over_the_limit_are_refused_not_written() {
+    let head = 17;
+    let mut biggest = record(0, "");
+    biggest.payload = vec![b'x'; MAX_RECORD as usize - head];
+
+    let mut bytes = Vec::new();
+    biggest.write(&mut bytes).unwrap();
+    let read = Record::read(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(
+        read.map(|record| record.payload.len()),
+        Some(MAX_RECORD as usize - head)
+    );
+
+    let mut too_big = biggest;
+    too_big.payload.push(b'x');
+
+    let mut bytes = Vec::new();
+    let err = too_big.write(&mut bytes).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(bytes.is_empty());
+
+    // the session carries on after a refused line, nothing torn behind it
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 1 << 20);
+
+    storage.append("big", Stream::Out, b"before").unwrap();
+    assert!(storage
+        .append("big", Stream::Out, &too_big.payload)
+        .is_err());
+    storage.append("big", Stream::Out, b"after").unwrap();
+
+    let texts = SessionReader::open(&dir.path().join("big"))
+        .unwrap()
+        .map(|record| record.unwrap().text())
+        .collect::<Vec<String>>();
+
+    assert_eq!(texts, vec!["before", "after"]);
+}
+
+#[test]
+fn the_index_is_sparse() {
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 1 << 30);
+
+    for _ in 0..10_000 {
+        storage.append("big", Stream::Out, b"some line").unwrap();
+    }
+
+    let index = Index::load(&index_path(&dir.path().join("big/00000.log")));
+    let entries = index.unwrap().entries;
+
+    assert_eq!(entries[0].seq, 0);
+    assert!(entries.len() > 10 && entries.len() < 100);
+    assert!(entries.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+}
+
+#[test]
+fn seeking_by_seq_and_time_across_segments() {
+    let dir = TempDir::new().unwrap();
+    write_session(dir.path(), "seek", 2_000, 4096, |seq| Some(seq * 10));
+
+    let path = dir.path().join("seek");
+    assert!(fs::read_dir(&path).unwrap().count() > 4);
+
+    let mut reader = SessionReader::open(&path).unwrap();
+    reader.seek_seq(1_234).unwrap();
+    assert_eq!(seqs(reader), (1_234..2_000).collect::<Vec<u64>>());
+
+    let mut reader = SessionReader::open(&path).unwrap();
+    reader.seek_ts(15_005).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().seq, 1_501);
+
+    // past the end is empty, not an error
+    let mut reader = SessionReader::open(&path).unwrap();
+    reader.seek_seq(5_000).unwrap();
+    assert!(seqs(reader).is_empty());
+
+    let tail = SessionReader::open(&path).unwrap().tail(3).unwrap();
+    let texts = tail.iter().map(Record::text).collect::<Vec<String>>();
+    assert_eq!(texts, vec!["line 1997", "line 1998", "line 1999"]);
+}
+
+#[test]
+fn the_index_still_works_once_compressed() {
+    let dir = TempDir::new().unwrap();
+    write_session(dir.path(), "packed", 2_000, 4096, |seq| Some(seq * 10));
+
+    compact(dir.path(), Compression::Zstd, &HashSet::new(), None, None)
+        .unwrap();
+
+    let mut reader = SessionReader::open(&dir.path().join("packed")).unwrap();
+    reader.seek_seq(777).unwrap();
+
+    let record = reader.next().unwrap().unwrap();
+    assert_eq!((record.seq, record.text()), (777, "line 777".to_string()));
+}
+
+#[test]
+fn legacy_plain_files_read_a_line_per_record() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("old"), "1554 - connected\nhello\n\nbye\n")
+        .unwrap();
+
+    let reader = SessionReader::open(&dir.path().join("old")).unwrap();
+    let records = reader.map(Result::unwrap).collect::<Vec<Record>>();
+
+    let texts = records.iter().map(Record::text).collect::<Vec<String>>();
+    assert_eq!(texts, vec!["1554 - connected", "hello", "", "bye"]);
+    assert_eq!(records[3].seq, 3);
+}
+
+#[test]
+fn a_viewer_gets_history_then_live_lines() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+
+    let mut storage = Storage::new(root, 1 << 20);
+    for line in &["one", "two", "three"] {
+        storage
+            .append("build_1", Stream::Out, line.as_bytes())
+            .unwrap();
+    }
+    storage
+        .append("build_1", Stream::Meta, b"-- marker --")
+        .unwrap();
+    storage.close("build_1").unwrap();
+
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    // the producer comes back, which adds a connected record
+    let mut producer = BufReader::new(endpoint.connect().unwrap());
+    let connect = Handshake::Connect {
+        id: "build_1".into(),
+        token: None,
+        tags: Vec::new(),
+    };
+    producer
+        .get_mut()
+        .write_all(connect.to_line().as_bytes())
+        .unwrap();
+    protocol::read_reply(&mut producer).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut conn = endpoint.connect().unwrap();
+    let handshake = Handshake::Client {
+        token: None,
+        history: Some(3),
+        times: false,
+        finished: false,
+    };
+    conn.write_all(handshake.to_line().as_bytes()).unwrap();
+
+    let mut reader = BufReader::new(conn);
+    protocol::read_reply(&mut reader).unwrap();
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    writeln!(producer.get_mut(), "build_1 -ENDID- four").unwrap();
+
+    let lines = reader
+        .lines()
+        .take(2)
+        .map(Result::unwrap)
+        .collect::<Vec<String>>();
+
+    // the marker and connected records are two of the three but arent sent
+    assert_eq!(lines, vec!["build_1 -ENDID- three", "build_1 -ENDID- four"]);
+
+    drop(producer);
+    daemon.close().unwrap();
+}
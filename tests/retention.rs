@@ -0,0 +1,313 @@
+use std::fs::{self, File};
+use std::thread;
+use std::path::Path;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use tempfile::TempDir;
+
+use spellhold::client::stats::fetch_gc;
+use spellhold::config::{DaemonConfig, RetentionRule, StorageConfig};
+use spellhold::daemon::builder::DaemonBuilder;
+use spellhold::storage::{list_sessions, segment_name, Storage};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::Stream;
+use spellhold::storage::retention::{glob_match, plan, ActiveSessions, GcAction};
+
+mod common;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn rule(pattern: &str) -> RetentionRule {
+    RetentionRule {
+        pattern: pattern.to_string(),
+        max_age_days: None,
+        max_session_bytes: None,
+        max_sessions: None,
+    }
+}
+
+/// write a session with a segment per line then backdate it
+fn session(root: &Path, name: &str, lines: &[&str], age: Duration) {
+    let mut storage = Storage::new(root.to_owned(), 1);
+
+    for line in lines {
+        storage.append(name, Stream::Out, line.as_bytes()).unwrap();
+    }
+
+    let when = SystemTime::now() - age;
+    let dir = root.join(name);
+
+    for entry in fs::read_dir(&dir).unwrap() {
+        let file = File::options().append(true).open(entry.unwrap().path());
+        file.unwrap().set_modified(when).unwrap();
+    }
+
+    File::open(&dir).unwrap().set_modified(when).unwrap();
+}
+
+fn removed(actions: &[GcAction]) -> Vec<String> {
+    let mut names = actions
+        .iter()
+        .map(|action| match action {
+            GcAction::RemoveSession { name, .. } => name.clone(),
+            GcAction::RemoveSegment { name, path, .. } => format!(
+                "{}/{}",
+                name,
+                path.file_name().unwrap().to_string_lossy()
+            ),
+        })
+        .collect::<Vec<String>>();
+
+    names.sort();
+    names
+}
+
+#[test]
+fn globs_match_names() {
+    assert!(glob_match("*", "anything"));
+    assert!(glob_match("build_*", "build_42"));
+    assert!(glob_match("build_??", "build_42"));
+    assert!(glob_match("*_night*", "deploy_nightly"));
+    assert!(!glob_match("build_*", "deploy_42"));
+    assert!(!glob_match("build_?", "build_42"));
+    assert!(!glob_match("", "build"));
+}
+
+#[test]
+fn storage_rotates_into_numbered_segments() {
+    let dir = TempDir::new().unwrap();
+    let mut storage = Storage::new(dir.path().to_owned(), 200);
+
+    let line = "x".repeat(50);
+
+    for _ in 0..10 {
+        storage
+            .append("rotating", Stream::Out, line.as_bytes())
+            .unwrap();
+    }
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let info = &sessions[0];
+
+    let names = info
+        .segments
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+
+    assert!(names.len() > 2);
+    assert_eq!(names[0], segment_name(0));
+    assert_eq!(names[1], segment_name(1));
+
+    // reopening carries on from the newest segment and the seq keeps going
+    storage.close("rotating").unwrap();
+    let seq = storage.append("rotating", Stream::Out, b"last").unwrap();
+    assert_eq!(seq, 10);
+
+    let seqs = SessionReader::open(&dir.path().join("rotating"))
+        .unwrap()
+        .map(|record| record.unwrap().seq)
+        .collect::<Vec<u64>>();
+
+    assert_eq!(seqs, (0..11).collect::<Vec<u64>>());
+}
+
+#[test]
+fn old_sessions_go_unless_active() {
+    let dir = TempDir::new().unwrap();
+
+    session(dir.path(), "fresh", &["a"], DAY);
+    session(dir.path(), "stale", &["a"], DAY * 10);
+    session(dir.path(), "running", &["a"], DAY * 10);
+
+    let rules = vec![RetentionRule {
+        max_age_days: Some(7),
+        ..rule("*")
+    }];
+    let active = ["running".to_string()].iter().cloned().collect();
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let actions = plan(
+        &sessions,
+        &rules,
+        &StorageConfig::default(),
+        &active,
+        SystemTime::now(),
+    );
+
+    assert_eq!(removed(&actions), vec!["stale"]);
+}
+
+#[test]
+fn the_first_matching_rule_wins() {
+    let dir = TempDir::new().unwrap();
+
+    session(dir.path(), "nightly_1", &["a"], DAY * 30);
+    session(dir.path(), "build_1", &["a"], DAY * 30);
+
+    let rules = vec![
+        RetentionRule {
+            max_age_days: Some(90),
+            ..rule("nightly_*")
+        },
+        RetentionRule {
+            max_age_days: Some(7),
+            ..rule("*")
+        },
+    ];
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let actions = plan(
+        &sessions,
+        &rules,
+        &StorageConfig::default(),
+        &HashSet::new(),
+        SystemTime::now(),
+    );
+
+    assert_eq!(removed(&actions), vec!["build_1"]);
+}
+
+#[test]
+fn only_the_newest_sessions_are_kept() {
+    let dir = TempDir::new().unwrap();
+
+    for index in 0..5 {
+        let name = format!("build_{}", index);
+        session(dir.path(), &name, &["a"], DAY * (5 - index));
+    }
+
+    let rules = vec![RetentionRule {
+        max_sessions: Some(2),
+        ..rule("build_*")
+    }];
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let actions = plan(
+        &sessions,
+        &rules,
+        &StorageConfig::default(),
+        &HashSet::new(),
+        SystemTime::now(),
+    );
+
+    assert_eq!(removed(&actions), vec!["build_0", "build_1", "build_2"]);
+}
+
+#[test]
+fn big_sessions_lose_their_oldest_segments() {
+    let dir = TempDir::new().unwrap();
+
+    // each line is its own segment
+    session(dir.path(), "big", &["aaa", "bbb", "ccc", "ddd"], DAY);
+    session(dir.path(), "one", &["0123456789"], DAY);
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let big = sessions.iter().find(|info| info.name == "big").unwrap();
+    let segment = big.bytes / 4;
+
+    let rules = vec![RetentionRule {
+        max_session_bytes: Some(segment * 2),
+        ..rule("*")
+    }];
+
+    let actions = plan(
+        &sessions,
+        &rules,
+        &StorageConfig::default(),
+        &HashSet::new(),
+        SystemTime::now(),
+    );
+
+    // the newest segment is never removed, even if it is too big alone
+    assert_eq!(removed(&actions), vec!["big/00000.log", "big/00001.log"]);
+}
+
+#[test]
+fn the_total_budget_removes_the_oldest_sessions() {
+    let dir = TempDir::new().unwrap();
+
+    session(dir.path(), "old", &["0123456789"], DAY * 3);
+    session(dir.path(), "older", &["0123456789"], DAY * 4);
+    session(dir.path(), "new", &["0123456789"], DAY);
+
+    let sessions = list_sessions(dir.path()).unwrap();
+    let each = sessions[0].bytes;
+
+    let storage = StorageConfig {
+        max_total_bytes: Some(each * 2 + each / 2),
+        ..StorageConfig::default()
+    };
+
+    let actions =
+        plan(&sessions, &[], &storage, &HashSet::new(), SystemTime::now());
+
+    assert_eq!(removed(&actions), vec!["older"]);
+}
+
+#[test]
+fn gc_over_the_socket_honours_dry_run() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().join("logs");
+
+    let config = DaemonConfig {
+        retention: vec![RetentionRule {
+            max_age_days: Some(1),
+            ..rule("*")
+        }],
+        ..DaemonConfig::default()
+    };
+
+    let daemon = common::start(
+        DaemonBuilder::new(dir.path().join("socket"))
+            .with_log_root(&root)
+            .with_options(config),
+    );
+    let endpoint = daemon.endpoint();
+
+    session(&root, "stale", &["a"], DAY * 10);
+
+    let planned = fetch_gc(&endpoint, None, true).unwrap();
+    assert_eq!(planned.len(), 1);
+    assert!(planned[0].starts_with("remove session stale"));
+    assert!(root.join("stale").exists());
+
+    let done = fetch_gc(&endpoint, None, false).unwrap();
+    assert_eq!(done, planned);
+    assert!(!root.join("stale").exists());
+
+    assert!(fetch_gc(&endpoint, None, false).unwrap().is_empty());
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn a_producer_waits_only_for_its_own_session_to_be_let_go() {
+    let active = ActiveSessions::default();
+
+    active.open("writing");
+    assert!(active.claim("writing").is_none());
+
+    let claim = active.claim("quiet").unwrap();
+    assert!(active.claim("quiet").is_none());
+
+    let opener = active.clone();
+    let opened = thread::spawn(move || {
+        opener.open("other");
+        opener.open("quiet");
+    });
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(active.writing().contains("other"));
+    assert!(!active.writing().contains("quiet"));
+
+    drop(claim);
+    opened.join().unwrap();
+
+    assert!(active.writing().contains("quiet"));
+    assert!(active.claim("quiet").is_none());
+
+    active.close("quiet");
+    assert!(active.claim("quiet").is_some());
+}
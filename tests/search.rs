@@ -0,0 +1,75 @@
+use spellhold::client::search::{Direction, Search};
+
+fn lines(text: &[&str]) -> Vec<String> {
+    text.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn it_counts_and_picks_out_matches() {
+    let search = Search::new("err(or)?", Direction::Forward).unwrap();
+    let tab = lines(&["ok", "error: one", "err and error", "fine"]);
+
+    assert_eq!(search.count(&tab), 2);
+    assert_eq!(search.ranges(&tab[2]), vec![0..3, 8..13]);
+    assert!(search.ranges(&tab[0]).is_empty());
+
+    // the line break isnt part of the line
+    let end = Search::new("one$", Direction::Forward).unwrap();
+    assert_eq!(end.ranges(&tab[1]), vec![7..10]);
+}
+
+#[test]
+fn empty_matches_arent_highlighted() {
+    let search = Search::new("x*", Direction::Forward).unwrap();
+
+    assert_eq!(search.ranges("abxxc\n"), vec![2..4]);
+}
+
+#[test]
+fn a_bad_regex_says_why() {
+    assert!(Search::new("(unclosed", Direction::Forward).is_err());
+}
+
+#[test]
+fn n_goes_the_way_of_the_search_and_wraps() {
+    let tab = lines(&["a", "hit", "b", "hit", "c"]);
+    let tabs = [tab.as_slice()];
+
+    let forward = Search::new("hit", Direction::Forward).unwrap();
+    assert_eq!(forward.next_hit(&tabs, (0, 0), false), Some((0, 1)));
+    assert_eq!(forward.next_hit(&tabs, (0, 1), false), Some((0, 3)));
+    assert_eq!(forward.next_hit(&tabs, (0, 3), false), Some((0, 1)));
+    assert_eq!(forward.next_hit(&tabs, (0, 3), true), Some((0, 1)));
+    assert_eq!(forward.next_hit(&tabs, (0, 1), true), Some((0, 3)));
+
+    let backward = Search::new("hit", Direction::Backward).unwrap();
+    assert_eq!(backward.next_hit(&tabs, (0, 4), false), Some((0, 3)));
+    assert_eq!(backward.next_hit(&tabs, (0, 3), false), Some((0, 1)));
+    assert_eq!(backward.next_hit(&tabs, (0, 1), true), Some((0, 3)));
+
+    // the only hit is found again from itself
+    let once = lines(&["a", "hit"]);
+    assert_eq!(forward.next_hit(&[&once], (0, 1), false), Some((0, 1)));
+    assert_eq!(backward.next_hit(&[&once], (0, 1), false), Some((0, 1)));
+
+    let none = Search::new("miss", Direction::Forward).unwrap();
+    assert_eq!(none.next_hit(&tabs, (0, 0), false), None);
+}
+
+#[test]
+fn it_moves_on_to_the_next_tab_with_a_hit() {
+    let first = lines(&["hit", "a"]);
+    let second = lines(&["b", "c"]);
+    let third = lines(&["d", "hit"]);
+    let tabs = [first.as_slice(), second.as_slice(), third.as_slice()];
+
+    let search = Search::new("hit", Direction::Forward).unwrap();
+    assert_eq!(search.next_hit(&tabs, (0, 0), false), Some((2, 1)));
+    assert_eq!(search.next_hit(&tabs, (2, 1), false), Some((0, 0)));
+    assert_eq!(search.next_hit(&tabs, (1, 0), false), Some((2, 1)));
+    assert_eq!(search.next_hit(&tabs, (1, 0), true), Some((0, 0)));
+
+    let backward = Search::new("hit", Direction::Backward).unwrap();
+    assert_eq!(backward.next_hit(&tabs, (0, 0), false), Some((2, 1)));
+    assert_eq!(backward.next_hit(&tabs, (2, 1), false), Some((0, 0)));
+}
@@ -0,0 +1,224 @@
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::sidebar::{self, Group, Row, Session, Sidebar, Sort, State};
+use spellhold::client::subscriber::{Event, Subscriber};
+
+mod common;
+
+use common::start_daemon;
+
+fn session(name: &str, started: u64, active: u64) -> Session {
+    let mut session = Session::new(name, started);
+    session.active = active;
+    session
+}
+
+fn names(rows: &[Row]) -> Vec<String> {
+    rows.iter()
+        .map(|row| match row {
+            Row::Group {
+                name,
+                sessions,
+                collapsed,
+            } => format!(
+                "{}{} ({})",
+                if *collapsed { "+" } else { "" },
+                name,
+                sessions
+            ),
+            Row::Session { name, .. } => format!("  {}", name),
+        })
+        .collect()
+}
+
+fn sessions() -> Vec<Session> {
+    let mut build = session("build_1", 1, 9);
+    build.tags = vec!["ci".into()];
+    build.state = State::ended(Some(2));
+
+    let mut deploy = session("deploy-api", 2, 5);
+    deploy.tags = vec!["ci".into(), "prod".into()];
+    deploy.state = State::ended(Some(0));
+
+    vec![
+        build,
+        deploy,
+        session("build_2", 3, 7),
+        session("shell", 4, 4),
+    ]
+}
+
+#[test]
+fn sessions_are_grouped_by_prefix_tag_or_state() {
+    let mut sidebar = Sidebar::new();
+
+    assert_eq!(
+        names(&sidebar.rows(&sessions())),
+        vec![
+            "build (2)",
+            "  build_1",
+            "  build_2",
+            "deploy (1)",
+            "  deploy-api",
+            "shell (1)",
+            "  shell",
+        ]
+    );
+
+    sidebar.group = Group::Tag;
+    assert_eq!(
+        names(&sidebar.rows(&sessions())),
+        vec![
+            "ci (2)",
+            "  build_1",
+            "  deploy-api",
+            "prod (1)",
+            "  deploy-api",
+            "untagged (2)",
+            "  build_2",
+            "  shell",
+        ]
+    );
+
+    sidebar.group = Group::State;
+    assert_eq!(
+        names(&sidebar.rows(&sessions())),
+        vec![
+            "failed (1)",
+            "  build_1",
+            "ok (1)",
+            "  deploy-api",
+            "running (2)",
+            "  build_2",
+            "  shell",
+        ]
+    );
+}
+
+#[test]
+fn sorting_and_folding() {
+    let mut sidebar = Sidebar::new();
+    sidebar.sort = Sort::Started;
+    sidebar.group = Group::State;
+
+    sidebar.toggle("running");
+    assert_eq!(
+        names(&sidebar.rows(&sessions()))[4..],
+        ["+running (2)".to_string()]
+    );
+
+    // folding is kept for each way of grouping
+    sidebar.group = Group::Prefix;
+    assert!(!sidebar.is_collapsed("running"));
+    sidebar.group = Group::State;
+    assert!(sidebar.is_collapsed("running"));
+
+    sidebar.toggle("running");
+    assert_eq!(
+        names(&sidebar.rows(&sessions()))[5..],
+        ["  build_2", "  shell"]
+    );
+
+    sidebar.sort = Sort::Recent;
+    assert_eq!(
+        names(&sidebar.rows(&sessions()))[5..],
+        ["  build_2", "  shell"]
+    );
+
+    let mut recent = sessions();
+    recent[3].active = 10;
+    assert_eq!(names(&sidebar.rows(&recent))[5..], ["  shell", "  build_2"]);
+
+    let folded = Row::Group {
+        name: "ok".into(),
+        sessions: 3,
+        collapsed: true,
+    };
+    assert!(sidebar.rows(&recent)[2].is(&folded));
+    assert!(!sidebar.rows(&recent)[3].is(&folded));
+}
+
+#[test]
+fn sessions_sort_by_when_the_daemon_opened_them() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let open = |name: &str| {
+        let handle = Producer::new(endpoint.clone())
+            .with_name(name)
+            .connect()
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        handle
+    };
+
+    // opened out of name order, the first two before the viewer came
+    let zeta = open("zeta");
+    let alpha = open("alpha");
+
+    let events = Subscriber::new(endpoint.clone())
+        .with_history(Some(10))
+        .with_times(true)
+        .connect()
+        .unwrap();
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    let beta = open("beta");
+
+    let sessions = events
+        .filter_map(|event| match event {
+            Event::Opened { session, at } => {
+                let mut heard = Session::new(&session, 0);
+                heard.started = at;
+                Some(heard)
+            }
+            Event::Error(err) => panic!("viewer failed: {}", err),
+            _ => None,
+        })
+        .take(3)
+        .collect::<Vec<_>>();
+
+    let mut sidebar = Sidebar::new();
+    sidebar.sort = Sort::Started;
+    sidebar.group = Group::State;
+
+    let ids = [&zeta, &alpha, &beta]
+        .iter()
+        .map(|handle| format!("  {}", handle.id()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(names(&sidebar.rows(&sessions))[1..], ids[..]);
+}
+
+#[test]
+fn states_have_icons() {
+    assert_eq!(State::ended(None), State::Ended);
+    assert_eq!(State::ended(Some(0)).icon(), '✓');
+    assert_eq!(State::ended(Some(1)), State::Failed(1));
+    assert_eq!(State::Disconnected.name(), "disconnected");
+}
+
+#[test]
+fn typing_jumps_to_the_best_match() {
+    assert_eq!(sidebar::prefix("deploy_api_3"), "deploy");
+    assert_eq!(sidebar::prefix("_hidden"), "_hidden");
+    assert_eq!(sidebar::prefix("plain"), "plain");
+
+    assert!(sidebar::fuzzy_score("dap", "deploy-api").is_some());
+    assert!(sidebar::fuzzy_score("pad", "deploy-api").is_none());
+
+    let names = ["build_1", "deploy-api", "build_api", "shell"];
+
+    assert_eq!(sidebar::fuzzy_jump("dapi", names), Some("deploy-api"));
+    assert_eq!(sidebar::fuzzy_jump("bapi", names), Some("build_api"));
+    assert_eq!(sidebar::fuzzy_jump("SH", names), Some("shell"));
+    assert_eq!(sidebar::fuzzy_jump("b1", names), Some("build_1"));
+    assert_eq!(sidebar::fuzzy_jump("zz", names), None);
+}
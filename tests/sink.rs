@@ -0,0 +1,179 @@
+use std::fs;
+use std::thread;
+use std::path::Path;
+use std::os::unix::net::UnixListener;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::sink::Shipper;
+use spellhold::storage::meta::{SessionMeta, SessionState};
+use spellhold::storage::reader::SessionReader;
+use spellhold::storage::record::{Attrs, Record, Stream};
+use spellhold::transport::Endpoint;
+
+mod common;
+
+use common::{start_daemon, WAIT};
+
+/// the out records of the one closed session under root
+fn closed_session(root: &Path) -> Vec<Record> {
+    let started = Instant::now();
+
+    loop {
+        let session = fs::read_dir(root)
+            .ok()
+            .and_then(|mut entries| entries.next())
+            .map(|entry| entry.unwrap().path());
+
+        let closed = session.as_ref().is_some_and(|path| {
+            SessionMeta::load(path)
+                .ok()
+                .flatten()
+                .is_some_and(|meta| meta.state == SessionState::Closed)
+        });
+
+        if let (Some(path), true) = (session, closed) {
+            return SessionReader::open(&path)
+                .unwrap()
+                .map(Result::unwrap)
+                .filter(|record| record.stream == Stream::Out)
+                .collect();
+        }
+
+        assert!(started.elapsed() < WAIT, "session was never closed");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn shipping_never_waits_for_a_missing_daemon() {
+    let dir = TempDir::new().unwrap();
+    let producer = Producer::new(Endpoint::Unix(dir.path().join("socket")))
+        .with_retry(Duration::from_millis(10));
+
+    let shipper = Shipper::spawn(producer, 4).unwrap();
+    let started = Instant::now();
+
+    for index in 0..10_000 {
+        shipper.ship(format!("line {}", index), Attrs::new());
+    }
+
+    assert!(started.elapsed() < Duration::from_secs(1));
+    shipper.close();
+}
+
+#[test]
+fn shipped_lines_keep_their_attributes() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let producer = Producer::new(endpoint).with_name("shipped");
+    let shipper = Shipper::spawn(producer, 16).unwrap();
+
+    let mut attrs = Attrs::new();
+    attrs.insert("level".into(), "WARN".into());
+    attrs.insert("odd key".into(), "a -ENDID- b".into());
+
+    shipper.ship("plain".into(), Attrs::new());
+    shipper.ship("with attrs".into(), attrs.clone());
+    shipper.close();
+
+    let records = closed_session(&root);
+
+    assert_eq!(records.len(), 2);
+    assert!(records[0].attrs.is_empty());
+    assert_eq!(records[1].text(), "with attrs");
+    assert_eq!(records[1].attrs, attrs);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_records_are_shipped_with_their_level() {
+    use log::{Level, Log};
+    use spellhold::client::sink::logger::SpellholdLogger;
+
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let producer = Producer::new(endpoint).with_name("logged");
+    let logger = SpellholdLogger::new(producer)
+        .unwrap()
+        .with_level(log::LevelFilter::Info);
+
+    for (level, text) in &[(Level::Debug, "hidden"), (Level::Warn, "slow")] {
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("{}", text))
+                .level(*level)
+                .target("app::db")
+                .build(),
+        );
+    }
+
+    drop(logger);
+
+    let records = closed_session(&root);
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].text(), "slow");
+    assert_eq!(records[0].attrs["level"], "WARN");
+    assert_eq!(records[0].attrs["target"], "app::db");
+    assert!(records[0].attrs.contains_key("time"));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_events_carry_their_span_fields() {
+    use spellhold::client::sink::layer::SpellholdLayer;
+    use tracing_subscriber::prelude::*;
+
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let producer = Producer::new(endpoint).with_name("traced");
+    let layer = SpellholdLayer::new(producer).unwrap();
+    let subscriber = tracing_subscriber::registry().with(layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("job", id = 7);
+        let _entered = span.enter();
+
+        tracing::warn!(rows = 3, "slow query");
+    });
+
+    let records = closed_session(&root);
+    let attrs = &records[0].attrs;
+
+    assert_eq!(records[0].text(), "slow query");
+    assert_eq!(attrs["level"], "WARN");
+    assert_eq!(attrs["rows"], "3");
+    assert_eq!(attrs["job.id"], "7");
+    assert_eq!(attrs["spans"], "job");
+}
+
+#[test]
+fn dropping_the_sink_doesnt_wait_on_a_stalled_daemon() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("socket");
+
+    // takes connections and never answers them
+    let _stalled = UnixListener::bind(&socket).unwrap();
+
+    let producer = Producer::new(Endpoint::Unix(socket)).with_name("stuck");
+    let shipper = Shipper::spawn(producer, 16).unwrap();
+
+    shipper.ship("never read".into(), Attrs::new());
+
+    // give the thread time to get stuck on the handshake
+    thread::sleep(Duration::from_millis(200));
+
+    let started = Instant::now();
+    drop(shipper);
+
+    assert!(started.elapsed() < WAIT);
+}
@@ -0,0 +1,382 @@
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use spellhold::client::producer::Producer;
+use spellhold::client::subscriber::{Event, Subscriber, Subscription};
+use spellhold::storage::export::Format;
+use spellhold::storage::import::import;
+use spellhold::transport::Endpoint;
+
+mod common;
+
+use common::start_daemon;
+
+/// the events up to and including the first ended one
+fn until_ended(events: &mut Subscription) -> Vec<Event> {
+    let mut seen = Vec::new();
+
+    for event in events {
+        let ended = matches!(event, Event::Ended { .. });
+        seen.push(event);
+
+        if ended {
+            break;
+        }
+    }
+
+    seen
+}
+
+#[test]
+fn a_session_is_seen_from_start_to_exit() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut events = Subscriber::new(endpoint.clone()).connect().unwrap();
+
+    // the viewer is only registered once the daemon has looked at it
+    thread::sleep(Duration::from_millis(200));
+
+    let mut handle =
+        Producer::new(endpoint).with_name("job").connect().unwrap();
+    let id = handle.id().to_string();
+
+    handle.send("one").unwrap();
+    handle.send("two words").unwrap();
+    handle.set_exit(2);
+    handle.close().unwrap();
+
+    assert_eq!(
+        until_ended(&mut events),
+        vec![
+            Event::Started {
+                session: id.clone()
+            },
+            Event::Line {
+                session: id.clone(),
+                text: "one".into()
+            },
+            Event::Line {
+                session: id.clone(),
+                text: "two words".into()
+            },
+            Event::Ended {
+                session: id,
+                exit: Some(2)
+            },
+        ]
+    );
+}
+
+#[test]
+fn only_matching_sessions_are_seen() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut events = Subscriber::new(endpoint.clone())
+        .with_sessions(vec!["wanted_*"])
+        .connect()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(200));
+
+    for name in &["other", "wanted"] {
+        let mut handle = Producer::new(endpoint.clone())
+            .with_name(*name)
+            .connect()
+            .unwrap();
+
+        handle.send(name).unwrap();
+        handle.close().unwrap();
+    }
+
+    let seen = until_ended(&mut events);
+
+    assert_eq!(seen.len(), 3);
+    assert!(seen
+        .iter()
+        .all(|event| event.session().unwrap().starts_with("wanted_")));
+}
+
+#[test]
+fn history_is_replayed_for_running_sessions() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut handle = Producer::new(endpoint.clone())
+        .with_name("long")
+        .connect()
+        .unwrap();
+    let id = handle.id().to_string();
+
+    for index in 0..5 {
+        handle.send(&format!("line {}", index)).unwrap();
+    }
+
+    // let the daemon write them before asking for them
+    thread::sleep(Duration::from_millis(300));
+
+    let events = Subscriber::new(endpoint)
+        .with_history(Some(2))
+        .connect()
+        .unwrap();
+
+    let replayed = events.take(2).collect::<Vec<Event>>();
+
+    assert_eq!(
+        replayed,
+        vec![
+            Event::Line {
+                session: id.clone(),
+                text: "line 3".into()
+            },
+            Event::Line {
+                session: id,
+                text: "line 4".into()
+            },
+        ]
+    );
+
+    handle.close().unwrap();
+}
+
+#[test]
+fn history_taken_while_a_producer_sends_has_every_line_once() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut handle = Producer::new(endpoint.clone())
+        .with_name("busy")
+        .connect()
+        .unwrap();
+
+    let sending = thread::spawn(move || {
+        for index in 0..20_000 {
+            handle.send(&format!("line {}", index)).unwrap();
+
+            if index % 1000 == 0 {
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+
+        handle.close().unwrap();
+    });
+
+    thread::sleep(Duration::from_millis(100));
+
+    // connects with the producer halfway through, lines come in while the
+    // history is read
+    let mut events = Subscriber::new(endpoint)
+        .with_history(Some(200))
+        .connect()
+        .unwrap();
+
+    let seen = until_ended(&mut events);
+
+    sending.join().unwrap();
+
+    let gaps = seen
+        .iter()
+        .filter(|event| matches!(event, Event::Gap { .. }))
+        .count();
+
+    let lines = seen
+        .iter()
+        .filter_map(|event| match event {
+            Event::Line { text, .. } => {
+                Some(text["line ".len()..].parse::<u64>().unwrap())
+            }
+            _ => None,
+        })
+        .collect::<Vec<u64>>();
+
+    // the last of the history and then everything after it, none twice.
+    // a viewer that falls behind can lose lines but is told it did
+    assert_eq!(lines.last(), Some(&19_999));
+    assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+
+    if gaps == 0 {
+        let first = lines[0];
+        assert_eq!(lines, (first..20_000).collect::<Vec<u64>>());
+    }
+}
+
+#[test]
+fn tags_reach_viewers_live_and_with_history() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut live = Subscriber::new(endpoint.clone()).connect().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut handle = Producer::new(endpoint.clone())
+        .with_name("tagged")
+        .with_tags(vec!["ci", "nightly"])
+        .connect()
+        .unwrap();
+    let id = handle.id().to_string();
+    let tags = vec!["ci".to_string(), "nightly".to_string()];
+
+    handle.send("one").unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let tagged = Event::Tagged {
+        session: id.clone(),
+        tags,
+    };
+
+    assert!(live.by_ref().take(3).any(|event| event == tagged));
+
+    let late = Subscriber::new(endpoint)
+        .with_history(Some(10))
+        .connect()
+        .unwrap();
+
+    assert_eq!(
+        late.take(2).collect::<Vec<Event>>(),
+        vec![
+            tagged,
+            Event::Line {
+                session: id,
+                text: "one".into()
+            }
+        ]
+    );
+
+    handle.close().unwrap();
+}
+
+#[test]
+fn finished_and_imported_sessions_come_when_asked_for() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, root) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let file = dir.path().join("old.log");
+    fs::write(&file, "from before\n").unwrap();
+    import(&root, 1 << 20, &file, "old", Format::Txt).unwrap();
+
+    let mut done = Producer::new(endpoint.clone())
+        .with_name("done")
+        .connect()
+        .unwrap();
+    let done_id = done.id().to_string();
+
+    done.send("bye").unwrap();
+    done.set_exit(3);
+    done.close().unwrap();
+
+    let mut running = Producer::new(endpoint.clone())
+        .with_name("running")
+        .connect()
+        .unwrap();
+    let running_id = running.id().to_string();
+
+    running.send("still here").unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let events = Subscriber::new(endpoint)
+        .with_history(Some(5))
+        .with_finished(true)
+        .connect()
+        .unwrap();
+
+    // oldest first, each one ended, then the running one as before
+    assert_eq!(
+        events.take(7).collect::<Vec<Event>>(),
+        vec![
+            Event::Started {
+                session: "old".into()
+            },
+            Event::Line {
+                session: "old".into(),
+                text: "from before".into()
+            },
+            Event::Ended {
+                session: "old".into(),
+                exit: None
+            },
+            Event::Started {
+                session: done_id.clone()
+            },
+            Event::Line {
+                session: done_id.clone(),
+                text: "bye".into()
+            },
+            Event::Ended {
+                session: done_id,
+                exit: Some(3)
+            },
+            Event::Line {
+                session: running_id,
+                text: "still here".into()
+            },
+        ]
+    );
+
+    running.close().unwrap();
+}
+
+#[test]
+fn no_daemon_is_an_error_at_connect() {
+    let dir = TempDir::new().unwrap();
+    let endpoint = Endpoint::Unix(dir.path().join("socket"));
+
+    assert!(Subscriber::new(endpoint).connect().is_err());
+}
+
+#[test]
+fn lines_can_come_with_the_time_they_were_got() {
+    let dir = TempDir::new().unwrap();
+    let (daemon, _) = start_daemon(dir.path());
+    let endpoint = daemon.endpoint();
+
+    let mut handle = Producer::new(endpoint.clone())
+        .with_name("timed")
+        .connect()
+        .unwrap();
+    let id = handle.id().to_string();
+
+    handle.send("old").unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let mut events = Subscriber::new(endpoint)
+        .with_history(Some(1))
+        .with_times(true)
+        .connect()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(200));
+    handle.send("new").unwrap();
+
+    // the history says when the session was opened before its lines
+    let opened = match events.next() {
+        Some(Event::Opened { session, at }) if session == id => at,
+        other => panic!("not opened: {:?}", other),
+    };
+
+    let seen = events
+        .by_ref()
+        .take(2)
+        .map(|event| match event {
+            Event::Stamped { session, at, text } => (session, at, text),
+            other => panic!("not stamped: {:?}", other),
+        })
+        .collect::<Vec<(String, u64, String)>>();
+
+    assert_eq!(seen[0].0, id);
+    assert_eq!(seen[0].2, "old");
+    assert_eq!(seen[1].2, "new");
+    assert!(opened > 0 && opened <= seen[0].1);
+    assert!(seen[0].1 + 200 <= seen[1].1);
+
+    handle.close().unwrap();
+}
@@ -0,0 +1,325 @@
+use std::fs;
+use std::thread;
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::io::{BufRead, BufReader, Read, Write};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use tempfile::TempDir;
+
+use spellhold::client::subscriber::{Event, Subscriber};
+use spellhold::config::DaemonConfig;
+use spellhold::daemon::builder::{DaemonBuilder, DaemonHandle};
+use spellhold::protocol::{self, Handshake};
+use spellhold::transport::{ClientTls, Endpoint, TlsListener};
+
+mod common;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// a throw away ca with a cert for localhost and one for a client
+struct Certs {
+    dir: TempDir,
+}
+
+impl Certs {
+    fn new() -> Certs {
+        let dir = tempfile::tempdir().unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let (server, server_key) = signed(names, &ca, &ca_key);
+        let (client, client_key) = signed(vec!["client".into()], &ca, &ca_key);
+
+        let write = |name: &str, pem: String| {
+            fs::write(dir.path().join(name), pem).unwrap();
+        };
+
+        write("ca.pem", ca.pem());
+        write("server.pem", server.pem());
+        write("server.key", server_key.serialize_pem());
+        write("client.pem", client.pem());
+        write("client.key", client_key.serialize_pem());
+
+        Certs { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn listener(&self, client_ca: bool) -> TlsListener {
+        TlsListener {
+            addr: "127.0.0.1:0".to_string(),
+            cert: self.path("server.pem"),
+            key: self.path("server.key"),
+            client_ca: if client_ca {
+                Some(self.path("ca.pem"))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn client(&self, with_cert: bool) -> ClientTls {
+        ClientTls {
+            ca: Some(self.path("ca.pem")),
+            cert: if with_cert {
+                Some(self.path("client.pem"))
+            } else {
+                None
+            },
+            key: if with_cert {
+                Some(self.path("client.key"))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+fn signed(
+    names: Vec<String>,
+    ca: &Certificate,
+    ca_key: &KeyPair,
+) -> (Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(names)
+        .unwrap()
+        .signed_by(&key, ca, ca_key)
+        .unwrap();
+
+    (cert, key)
+}
+
+/// a daemon on the unix socket and tls, with the tls endpoint to reach it
+fn start(
+    dir: &Path,
+    tcp: TlsListener,
+    tls: ClientTls,
+) -> (DaemonHandle, Endpoint) {
+    let daemon = common::start(
+        DaemonBuilder::new(dir.join("socket"))
+            .with_log_root(dir.join("logs"))
+            .with_tcp(tcp),
+    );
+
+    let endpoint = Endpoint::Remote {
+        addr: daemon.local_addr().unwrap().to_string(),
+        tls,
+    };
+
+    (daemon, endpoint)
+}
+
+#[test]
+fn producer_lines_arrive_over_tls() {
+    let certs = Certs::new();
+    let (daemon, endpoint) =
+        start(certs.dir.path(), certs.listener(false), certs.client(false));
+
+    let mut viewer = Subscriber::new(daemon.endpoint()).connect().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut conn = endpoint.connect().unwrap();
+    conn.write_all(b"connect -ID- remote_1\nremote_1 -ENDID- hello\n")
+        .unwrap();
+    conn.flush().unwrap();
+
+    assert_eq!(
+        viewer.next(),
+        Some(Event::Started {
+            session: "remote_1".into()
+        })
+    );
+    assert_eq!(
+        viewer.next(),
+        Some(Event::Line {
+            session: "remote_1".into(),
+            text: "hello".into()
+        })
+    );
+
+    daemon.close().unwrap();
+}
+
+#[test]
+fn viewer_gets_lines_over_tls() {
+    let certs = Certs::new();
+    let (daemon, endpoint) =
+        start(certs.dir.path(), certs.listener(true), certs.client(true));
+
+    let mut conn = endpoint.connect().unwrap();
+    conn.write_all(b"client\n").unwrap();
+    conn.flush().unwrap();
+
+    let mut reader = BufReader::new(conn);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "ok\n");
+
+    // the daemon adds the viewer just after it answers
+    thread::sleep(Duration::from_millis(200));
+
+    let mut producer = BufReader::new(daemon.endpoint().connect().unwrap());
+    let connect = Handshake::Connect {
+        id: "a".into(),
+        token: None,
+        tags: Vec::new(),
+    };
+    producer
+        .get_mut()
+        .write_all(connect.to_line().as_bytes())
+        .unwrap();
+    protocol::read_reply(&mut producer).unwrap();
+    writeln!(producer.get_mut(), "a -ENDID- from afar").unwrap();
+
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "started -ID- a\n");
+
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "a -ENDID- from afar\n");
+
+    drop(producer);
+    daemon.close().unwrap();
+}
+
+#[test]
+fn client_cert_is_required_when_configured() {
+    let certs = Certs::new();
+    let (daemon, endpoint) =
+        start(certs.dir.path(), certs.listener(true), certs.client(false));
+
+    // tls 1.3 clients only learn about the rejection on the next read
+    if let Ok(mut conn) = endpoint.connect() {
+        let _ = conn.write_all(b"connect -ID- sneaky\n");
+        let _ = conn.flush();
+
+        let mut buf = [0; 1];
+        match conn.read(&mut buf) {
+            Ok(0) | Err(_) => {}
+            Ok(_) => panic!("daemon talked to a client without a cert"),
+        }
+    }
+
+    daemon.close().unwrap();
+
+    // nothing from it made it to the main loop
+    assert!(!certs.dir.path().join("logs").join("sneaky").exists());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn the_tokio_daemon_and_clients_speak_tls() {
+    use spellhold::client::producer::Producer;
+    use spellhold::client::subscriber::{Event, Subscriber};
+    use spellhold::daemon::builder::DaemonBuilder;
+
+    let certs = Certs::new();
+    let dir = certs.dir.path();
+
+    let daemon = DaemonBuilder::new(dir.join("socket"))
+        .with_log_root(dir.join("logs"))
+        .with_tcp(certs.listener(false))
+        .start_tokio()
+        .unwrap();
+
+    let endpoint = Endpoint::Remote {
+        addr: daemon.local_addr().unwrap().to_string(),
+        tls: certs.client(false),
+    };
+
+    let mut events = Subscriber::new(endpoint.clone())
+        .connect_async()
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut handle = Producer::new(endpoint)
+        .with_name("remote")
+        .connect_async()
+        .await
+        .unwrap();
+
+    handle.send("hello").await.unwrap();
+
+    assert!(matches!(events.next().await, Some(Event::Started { .. })));
+    assert_eq!(
+        events.next().await,
+        Some(Event::Line {
+            session: handle.id().to_string(),
+            text: "hello".into()
+        })
+    );
+
+    handle.close().await.unwrap();
+    daemon.close().unwrap();
+}
+
+#[test]
+fn peers_that_never_get_past_the_handshake_are_dropped() {
+    let certs = Certs::new();
+    let dir = TempDir::new().unwrap();
+
+    let mut options = DaemonConfig::default();
+    options.queues.handshake_timeout_ms = 200;
+
+    let daemon = common::start(
+        DaemonBuilder::new(dir.path().join("socket"))
+            .with_options(options)
+            .with_log_root(dir.path().join("logs"))
+            .with_tcp(certs.listener(false)),
+    );
+    let addr = daemon.local_addr().unwrap();
+
+    let hung_up = |conn: &mut dyn Read| {
+        let started = Instant::now();
+        let mut byte = [0; 1];
+
+        assert!(conn.read(&mut byte).map_or(true, |read| read == 0));
+        assert!(started.elapsed() < WAIT);
+    };
+
+    // one that never starts tls, and one that never sends its first line
+    let mut silent = TcpStream::connect(addr).unwrap();
+    silent.set_read_timeout(Some(WAIT)).unwrap();
+    hung_up(&mut silent);
+
+    let mut silent = UnixStream::connect(daemon.socket()).unwrap();
+    silent.set_read_timeout(Some(WAIT)).unwrap();
+    hung_up(&mut silent);
+
+    // a producer past its first line can go quiet for longer than that
+    let mut viewer = Subscriber::new(daemon.endpoint()).connect().unwrap();
+
+    let endpoint = Endpoint::Remote {
+        addr: addr.to_string(),
+        tls: certs.client(false),
+    };
+
+    let mut conn = endpoint.connect().unwrap();
+    conn.write_all(b"connect -ID- quiet_1\n").unwrap();
+    conn.flush().unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+
+    conn.write_all(b"quiet_1 -ENDID- still here\n").unwrap();
+    conn.flush().unwrap();
+
+    assert!(viewer.any(|evt| match evt {
+        Event::Line { text, .. } => text == "still here",
+        _ => false,
+    }));
+
+    daemon.close().unwrap();
+}
@@ -0,0 +1,90 @@
+use spellhold::ansi::Color;
+use spellhold::terminal::Screen;
+
+fn played(width: usize, height: usize, lines: &[&str]) -> Screen {
+    let mut screen = Screen::new(width, height);
+
+    for line in lines {
+        screen.feed_line(line);
+    }
+
+    screen
+}
+
+#[test]
+fn a_progress_bar_is_one_line() {
+    let screen = played(
+        20,
+        5,
+        &["building", "[#   ] 1/4\r[##  ] 2/4\r[####] 4/4", "done"],
+    );
+
+    assert_eq!(screen.text(), vec!["building", "[####] 4/4", "done"]);
+    assert_eq!(screen.cursor(), (3, 0));
+}
+
+#[test]
+fn cursor_moves_redraw_earlier_rows() {
+    // two bars redrawn in place, the way cargo and docker draw them
+    let screen = played(
+        20,
+        5,
+        &[
+            "a: 10%",
+            "b: 10%",
+            "\x1b[2A\x1b[2Ka: 90%",
+            "\x1b[2Kb: 50%\x1b[1B",
+        ],
+    );
+
+    assert_eq!(screen.text(), vec!["a: 90%", "b: 50%"]);
+}
+
+#[test]
+fn rows_scroll_off_the_top_into_scrollback() {
+    let lines = (0..6).map(|n| format!("line {}", n)).collect::<Vec<_>>();
+    let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let screen = played(10, 3, &lines);
+    assert_eq!(screen.text(), lines);
+
+    let rows = screen.rows();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0][0].text, "line 4");
+    assert_eq!(rows[1][0].text, "line 5");
+    assert!(rows[2].is_empty());
+
+    let mut none = Screen::new(10, 3).with_scrollback(0);
+    for line in &lines {
+        none.feed_line(line);
+    }
+    assert_eq!(none.text(), vec!["line 4", "line 5"]);
+}
+
+#[test]
+fn long_lines_wrap_at_the_width() {
+    let screen = played(4, 5, &["abcdefghij"]);
+
+    assert_eq!(screen.text(), vec!["abcd", "efgh", "ij"]);
+}
+
+#[test]
+fn clearing_and_moving_to_a_place() {
+    let screen =
+        played(10, 4, &["old", "old", "\x1b[2J\x1b[1;1Htop\x1b[3;5Hmid"]);
+
+    assert_eq!(screen.text(), vec!["top", "", "    mid"]);
+
+    let screen = played(10, 2, &["abcdef\x1b[1;3H\x1b[2P\x1b[1@"]);
+    assert_eq!(screen.text(), vec!["ab ef"]);
+}
+
+#[test]
+fn colours_are_kept_on_the_screen() {
+    let screen = played(10, 2, &["\x1b[31mred\x1b[0m plain"]);
+    let row = &screen.rows()[0];
+
+    assert_eq!(row[0].text, "red");
+    assert_eq!(row[0].style.fg, Some(Color::Indexed(1)));
+    assert_eq!(row[1].text, " plain");
+}
@@ -0,0 +1,61 @@
+use tui::style::{Color, Modifier, Style};
+
+use spellhold::client::theme::{self, Theme, ThemeName};
+use spellhold::config::ThemeConfig;
+
+#[test]
+fn themes_pick_things_out() {
+    let default = Theme::default();
+    assert_eq!(default.base, Style::default());
+    assert_eq!(default.accent.fg, Color::Cyan);
+    assert_eq!(default.matched.bg, Color::Yellow);
+    assert!(default.colour);
+
+    let light = Theme::named(ThemeName::Light);
+    assert_eq!(light.base.bg, Color::White);
+    assert_eq!(light.base.fg, Color::Black);
+    assert_eq!(light.accent.bg, Color::White);
+
+    let none = Theme::named(ThemeName::None);
+    assert_eq!(none, Theme::plain());
+    assert_eq!(none.highlight.modifier, Modifier::Bold);
+    assert_eq!(none.matched.modifier, Modifier::Invert);
+    assert_eq!(none.matched.bg, Color::Reset);
+    assert!(!none.colour);
+}
+
+#[test]
+fn the_config_colours_a_theme() {
+    let config = ThemeConfig {
+        name: ThemeName::Dark,
+        background: Some("#102030".into()),
+        highlight: Some("light_magenta".into()),
+        ..ThemeConfig::default()
+    };
+
+    let theme = Theme::from_config(&config, false).unwrap();
+    assert_eq!(theme.base.bg, Color::Rgb(0x10, 0x20, 0x30));
+    assert_eq!(theme.accent.bg, Color::Rgb(0x10, 0x20, 0x30));
+    assert_eq!(theme.highlight.fg, Color::LightMagenta);
+    assert_eq!(theme.accent.fg, Color::Cyan);
+
+    // NO_COLOR wins over any theme
+    assert_eq!(Theme::from_config(&config, true).unwrap(), Theme::plain());
+
+    let bad = ThemeConfig {
+        accent: Some("chartreuse".into()),
+        ..ThemeConfig::default()
+    };
+    assert!(Theme::from_config(&bad, false).is_err());
+}
+
+#[test]
+fn colours_are_named_or_hex() {
+    assert_eq!(theme::parse_colour("Grey"), Ok(Color::Gray));
+    assert_eq!(theme::parse_colour("dark-gray"), Ok(Color::DarkGray));
+    assert_eq!(theme::parse_colour("reset"), Ok(Color::Reset));
+    assert_eq!(theme::parse_colour("#ff0080"), Ok(Color::Rgb(255, 0, 128)));
+    assert!(theme::parse_colour("#ff008").is_err());
+    assert!(theme::parse_colour("#gg0000").is_err());
+    assert!(theme::parse_colour("#ff00é").is_err());
+}
@@ -0,0 +1,70 @@
+use spellhold::client::timeline::{self, Entry, Timeline};
+
+fn entry(at: u64, session: &str, text: &str) -> Entry {
+    Entry {
+        at,
+        session: session.into(),
+        text: text.into(),
+    }
+}
+
+#[test]
+fn lines_go_in_by_time_and_after_ties() {
+    let mut timeline = Timeline::new();
+
+    let places = [
+        entry(2_000, "web", "listening"),
+        entry(1_000, "db", "ready"),
+        entry(3_000, "web", "GET /"),
+        entry(2_000, "db", "query"),
+    ]
+    .iter()
+    .map(|entry| timeline.insert(entry).0)
+    .collect::<Vec<usize>>();
+
+    // the db query came at the same time as listening, so it goes after it
+    assert_eq!(places, vec![0, 0, 2, 2]);
+    assert_eq!(timeline.len(), 4);
+}
+
+#[test]
+fn lines_have_the_time_and_a_padded_label() {
+    let mut timeline = Timeline::new().with_width(4);
+
+    assert_eq!(
+        timeline.line(&entry(45_296_789, "db", "ready")),
+        "12:34:56.789 db   | ready"
+    );
+    assert_eq!(
+        timeline.line(&entry(86_400_001, "webserver", "up")),
+        "00:00:00.001 webserver | up"
+    );
+    // labels dont shrink back once a longer name was seen
+    assert_eq!(
+        timeline.line(&entry(0, "db", "x")),
+        "00:00:00.000 db        | x"
+    );
+
+    // colours are kept, a progress bar is shown where it got to
+    assert_eq!(
+        timeline.line(&entry(0, "db", "\x1b[32mok\x1b[0m")),
+        "00:00:00.000 db        | \x1b[32mok\x1b[0m"
+    );
+    assert_eq!(
+        timeline.line(&entry(0, "db", "10%\r50%\r100%")),
+        "00:00:00.000 db        | 100%"
+    );
+}
+
+#[test]
+fn a_session_keeps_its_colour() {
+    assert_eq!(timeline::colour("build"), timeline::colour("build"));
+    assert!(![0, 7, 8, 15].contains(&timeline::colour("build")));
+
+    let label = timeline::label("build", 7, true);
+    let index = timeline::colour("build");
+    let sgr = if index < 8 { 30 + index } else { 82 + index };
+
+    assert_eq!(label, format!("\x1b[{}mbuild  \x1b[0m", sgr));
+    assert_eq!(timeline::label("build", 7, false), "build  ");
+}